@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+
+use models::{asset::canonical_ticker, income::IncomeRecord, InputTransaction};
+use rust_decimal::Decimal;
+
+pub use models::{
+    binance::{
+        BinanceOperation, BinanceTransactionRecord, CSV_HEADERS, DATE_FORMAT, OPERATIONS,
+        SHORT_DATE_FORMAT,
+    },
+    ActiveAssetValues, HistoricalPrice, InputTransactions, RecordsByAsset, StakingIncome,
+    StakingRewards, UnknownTransactionType, UnknownTransactionTypes,
+};
+
+/// Operations that pay out staking, savings or Earn rewards.
+pub const STAKING_OPERATIONS: &[BinanceOperation] = &[
+    BinanceOperation::StakingRewards,
+    BinanceOperation::Eth2StakingRewards,
+    BinanceOperation::SimpleEarnFlexibleInterest,
+    BinanceOperation::SimpleEarnLockedRewards,
+    BinanceOperation::SavingsInterest,
+    BinanceOperation::PosSavingsInterest,
+    BinanceOperation::LaunchpoolInterest,
+];
+
+/// Operations that move a holding between Binance wallets. The coin is still held afterwards, and exports do not
+/// always include both sides of the move, so these are left out of balances.
+pub const WALLET_MOVE_OPERATIONS: &[BinanceOperation] = &[
+    BinanceOperation::SimpleEarnFlexibleSubscription,
+    BinanceOperation::SimpleEarnFlexibleRedemption,
+    BinanceOperation::SimpleEarnLockedSubscription,
+    BinanceOperation::SimpleEarnLockedRedemption,
+    BinanceOperation::StakingPurchase,
+    BinanceOperation::StakingRedemption,
+    BinanceOperation::WalletTransfer,
+];
+
+pub struct BinanceParser<T> {
+    data: Vec<T>,
+}
+
+impl<T> BinanceParser<T> {
+    pub fn new(data: Vec<T>) -> Self {
+        Self { data }
+    }
+}
+
+impl BinanceParser<BinanceTransactionRecord> {
+    /// The rows that paid out a staking, savings or Earn reward.
+    pub fn staking_reward_records(&self) -> Vec<&BinanceTransactionRecord> {
+        self.data
+            .iter()
+            .filter(|record| is_staking_reward(record))
+            .collect()
+    }
+}
+
+fn is_staking_reward(record: &BinanceTransactionRecord) -> bool {
+    STAKING_OPERATIONS.contains(&record.operation) && record.change.is_sign_positive()
+}
+
+impl StakingRewards for BinanceParser<BinanceTransactionRecord> {
+    /// ```
+    /// # use chrono::{TimeZone, Utc};
+    /// # use models::{
+    /// #   binance::{BinanceOperation, BinanceTransactionRecord, DATE_FORMAT as BINANCE_DATE_FORMAT},
+    /// #   StakingRewards,
+    /// # };
+    /// # use rust_decimal::Decimal;
+    /// # use binance_parser::BinanceParser;
+    /// #
+    /// let reward = BinanceTransactionRecord {
+    ///     user_id: "12345678".to_string(),
+    ///     time: Utc
+    ///         .datetime_from_str("2021-09-29 15:18:30", BINANCE_DATE_FORMAT)
+    ///         .unwrap(),
+    ///     account: "Spot".to_string(),
+    ///     operation: BinanceOperation::StakingRewards,
+    ///     coin: "DOT".to_string(),
+    ///     change: Decimal::new(51002, 4),
+    ///     remark: "".to_string(),
+    /// };
+    /// let subscription = BinanceTransactionRecord {
+    ///     operation: BinanceOperation::StakingPurchase,
+    ///     change: Decimal::new(-10, 0),
+    ///     ..reward.clone()
+    /// };
+    ///
+    /// let binance_parser = BinanceParser::new(vec![reward, subscription]);
+    /// let reward_map = binance_parser.staking_rewards();
+    /// assert_eq!(reward_map.len(), 1);
+    /// assert_eq!(*reward_map.get("DOT").unwrap(), Decimal::new(51002, 4));
+    /// ```
+    fn staking_rewards(&self) -> HashMap<String, Decimal> {
+        self.staking_reward_records()
+            .into_iter()
+            .fold(HashMap::new(), |mut reward_map, record| {
+                *reward_map
+                    .entry(canonical_ticker(&record.coin))
+                    .or_insert(Decimal::ZERO) += record.change;
+
+                reward_map
+            })
+    }
+}
+
+impl ActiveAssetValues for BinanceParser<BinanceTransactionRecord> {
+    /// Sums the change of every coin across all accounts. Moves between wallets and operations that are not
+    /// understood are left out.
+    /// ```
+    /// # use chrono::{TimeZone, Utc};
+    /// # use models::{
+    /// #   binance::{BinanceOperation, BinanceTransactionRecord, DATE_FORMAT as BINANCE_DATE_FORMAT},
+    /// #   ActiveAssetValues,
+    /// # };
+    /// # use rust_decimal::Decimal;
+    /// # use binance_parser::BinanceParser;
+    /// #
+    /// let bought = BinanceTransactionRecord {
+    ///     user_id: "12345678".to_string(),
+    ///     time: Utc
+    ///         .datetime_from_str("2021-09-29 15:18:30", BINANCE_DATE_FORMAT)
+    ///         .unwrap(),
+    ///     account: "Spot".to_string(),
+    ///     operation: BinanceOperation::TransactionBuy,
+    ///     coin: "DOT".to_string(),
+    ///     change: Decimal::new(10, 0),
+    ///     remark: "".to_string(),
+    /// };
+    /// let fee = BinanceTransactionRecord {
+    ///     operation: BinanceOperation::TransactionFee,
+    ///     change: Decimal::new(-1, 2),
+    ///     ..bought.clone()
+    /// };
+    /// let subscribed = BinanceTransactionRecord {
+    ///     operation: BinanceOperation::SimpleEarnFlexibleSubscription,
+    ///     change: Decimal::new(-5, 0),
+    ///     ..bought.clone()
+    /// };
+    ///
+    /// let binance_parser = BinanceParser::new(vec![bought, fee, subscribed]);
+    /// let active_assets = binance_parser.active_assets();
+    /// assert_eq!(*active_assets.get("DOT").unwrap(), Decimal::new(999, 2));
+    /// ```
+    fn active_assets(&self) -> HashMap<String, Decimal> {
+        self.data
+            .iter()
+            .filter(|record| !record.operation.is_unknown())
+            .filter(|record| !WALLET_MOVE_OPERATIONS.contains(&record.operation))
+            .fold(HashMap::new(), |mut map, record| {
+                *map.entry(canonical_ticker(&record.coin))
+                    .or_insert(Decimal::ZERO) += record.change;
+
+                map
+            })
+    }
+}
+
+impl RecordsByAsset<BinanceTransactionRecord> for BinanceParser<BinanceTransactionRecord> {
+    fn by_asset(&self) -> HashMap<String, Vec<&BinanceTransactionRecord>> {
+        self.data
+            .iter()
+            .fold(HashMap::new(), |mut currency_map, record| {
+                currency_map
+                    .entry(canonical_ticker(&record.coin))
+                    .or_insert_with(Vec::new)
+                    .push(record);
+
+                currency_map
+            })
+    }
+}
+
+impl InputTransactions<BinanceTransactionRecord> for BinanceParser<BinanceTransactionRecord> {
+    /// Rows that added to a balance, leaving out coins moved in from another Binance wallet.
+    fn input_transactions(&self) -> Vec<&BinanceTransactionRecord> {
+        self.data
+            .iter()
+            .filter(|record| record.is_input_transaction())
+            .filter(|record| !WALLET_MOVE_OPERATIONS.contains(&record.operation))
+            .collect()
+    }
+}
+
+impl UnknownTransactionTypes for BinanceParser<BinanceTransactionRecord> {
+    fn unknown_transaction_types(&self) -> Vec<UnknownTransactionType> {
+        UnknownTransactionType::tally(
+            self.data
+                .iter()
+                .filter(|record| record.operation.is_unknown())
+                .map(|record| (record.operation.as_str(), record.coin.as_str())),
+        )
+    }
+}
+
+impl StakingIncome for BinanceParser<BinanceTransactionRecord> {
+    /// Binance does not export prices, so every reward is valued with the given prices. Rewards are identified by
+    /// their position in the export, as several can be paid in the same coin at the same second.
+    fn staking_income(&self, prices: &dyn HistoricalPrice) -> Vec<IncomeRecord> {
+        self.data
+            .iter()
+            .enumerate()
+            .filter(|(_, record)| is_staking_reward(record))
+            .map(|(index, record)| {
+                let asset = canonical_ticker(&record.coin);
+                let usd_price = prices.usd_price_at(&asset, &record.time);
+
+                IncomeRecord {
+                    id: format!("binance-{index}"),
+                    asset,
+                    quantity: record.change,
+                    received_at: record.time,
+                    usd_price,
+                    usd_value: usd_price.map(|price| price * record.change),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod binance_parser_should {
+    use chrono::{DateTime, TimeZone, Utc};
+    use models::binance::{BinanceOperation, BinanceTransactionRecord, DATE_FORMAT};
+    use rust_decimal::Decimal;
+
+    use crate::{
+        ActiveAssetValues, BinanceParser, HistoricalPrice, InputTransactions, RecordsByAsset,
+        StakingIncome, UnknownTransactionTypes,
+    };
+
+    fn record(
+        operation: BinanceOperation,
+        coin: &str,
+        change: Decimal,
+    ) -> BinanceTransactionRecord {
+        BinanceTransactionRecord {
+            user_id: "12345678".to_string(),
+            time: Utc
+                .datetime_from_str("2021-09-29 15:18:30", DATE_FORMAT)
+                .unwrap(),
+            account: "Spot".to_string(),
+            operation,
+            coin: coin.to_string(),
+            change,
+            remark: "".to_string(),
+        }
+    }
+
+    struct FixedPrice;
+
+    impl HistoricalPrice for FixedPrice {
+        fn usd_price_at(&self, _asset: &str, _time: &DateTime<Utc>) -> Option<Decimal> {
+            Some(Decimal::new(2, 0))
+        }
+    }
+
+    #[test]
+    fn read_the_transaction_history_export() {
+        let csv = "\"User_ID\",\"UTC_Time\",\"Account\",\"Operation\",\"Coin\",\"Change\",\"Remark\"\n\
+            \"12345678\",\"2021-09-29 15:18:30\",\"Spot\",\"ETH 2.0 Staking Rewards\",\"BETH\",\"1.2E-7\",\"\"\n\
+            \"12345678\",\"21-09-30 15:18:30\",\"Spot\",\"Deposit\",\"BTC\",\"0.5\",\"\"\n";
+
+        let records: Vec<BinanceTransactionRecord> = csv::Reader::from_reader(csv.as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(records[0].operation, BinanceOperation::Eth2StakingRewards);
+        assert_eq!(records[0].change, Decimal::new(12, 8));
+        assert_eq!(
+            records[1].time,
+            Utc.datetime_from_str("2021-09-30 15:18:30", DATE_FORMAT)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn leave_wallet_moves_out_of_inputs() {
+        let binance_parser = BinanceParser::new(vec![
+            record(BinanceOperation::Deposit, "BTC", Decimal::new(5, 1)),
+            record(
+                BinanceOperation::StakingRedemption,
+                "DOT",
+                Decimal::new(10, 0),
+            ),
+            record(BinanceOperation::Withdraw, "BTC", Decimal::new(-1, 1)),
+        ]);
+
+        let inputs = binance_parser.input_transactions();
+
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0].operation, BinanceOperation::Deposit);
+    }
+
+    #[test]
+    fn group_records_by_canonical_ticker() {
+        let binance_parser = BinanceParser::new(vec![
+            record(BinanceOperation::Deposit, "BTC", Decimal::new(5, 1)),
+            record(
+                BinanceOperation::TransactionSold,
+                "BTC",
+                Decimal::new(-1, 1),
+            ),
+            record(
+                BinanceOperation::TransactionRevenue,
+                "USDT",
+                Decimal::new(2000, 0),
+            ),
+        ]);
+
+        let by_asset = binance_parser.by_asset();
+
+        assert_eq!(by_asset.get("BTC").unwrap().len(), 2);
+        assert_eq!(by_asset.get("USDT").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn leave_unknown_operations_out_of_balances_and_report_them() {
+        let binance_parser = BinanceParser::new(vec![
+            record(BinanceOperation::Deposit, "BTC", Decimal::new(5, 1)),
+            record(
+                BinanceOperation::from("Crypto Box"),
+                "BTC",
+                Decimal::new(1, 1),
+            ),
+        ]);
+
+        let unknown = binance_parser.unknown_transaction_types();
+
+        assert_eq!(
+            *binance_parser.active_assets().get("BTC").unwrap(),
+            Decimal::new(5, 1)
+        );
+        assert_eq!(unknown.len(), 1);
+        assert_eq!(unknown[0].transaction_type, "Crypto Box");
+        assert_eq!(unknown[0].assets, ["BTC"]);
+    }
+
+    #[test]
+    fn price_staking_income() {
+        let binance_parser = BinanceParser::new(vec![
+            record(
+                BinanceOperation::SimpleEarnFlexibleInterest,
+                "ADA",
+                Decimal::new(3, 0),
+            ),
+            record(BinanceOperation::Distribution, "ADA", Decimal::new(1, 0)),
+        ]);
+
+        let income = binance_parser.staking_income(&FixedPrice);
+
+        assert_eq!(income.len(), 1);
+        assert_eq!(income[0].asset, "ADA");
+        assert_eq!(income[0].usd_value, Some(Decimal::new(6, 0)));
+    }
+
+    #[test]
+    fn tell_apart_rewards_paid_in_the_same_second() {
+        let binance_parser = BinanceParser::new(vec![
+            record(BinanceOperation::Deposit, "ADA", Decimal::new(5, 0)),
+            record(BinanceOperation::StakingRewards, "ADA", Decimal::new(1, 0)),
+            record(
+                BinanceOperation::SimpleEarnFlexibleInterest,
+                "ADA",
+                Decimal::new(2, 0),
+            ),
+        ]);
+
+        let ids: Vec<String> = binance_parser
+            .staking_income(&FixedPrice)
+            .into_iter()
+            .map(|income| income.id)
+            .collect();
+
+        assert_eq!(ids, ["binance-1", "binance-2"]);
+    }
+}
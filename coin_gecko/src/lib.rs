@@ -1,47 +1,56 @@
 pub mod coin_gecko {
+    extern crate chrono;
+    extern crate models;
     extern crate reqwest;
     extern crate rust_decimal;
+    extern crate serde;
 
-    use std::collections::HashMap;
+    use std::{cell::RefCell, collections::HashMap};
 
+    use self::chrono::{DateTime, NaiveDate, Utc};
+    use self::models::{asset::canonical_ticker, HistoricalPrice};
     use self::rust_decimal::Decimal;
+    use self::serde::Deserialize;
 
     // use rust_decimal::Decimal;
 
     pub const BASE_API_URL: &str = "https://api.coingecko.com/api/v3";
     pub const PRICE_ROUTE: &str = "/simple/price";
+    pub const HISTORY_ROUTE: &str = "/coins/{id}/history";
+    pub const HISTORY_DATE_FORMAT: &str = "%d-%m-%Y";
     pub const VS_CURRENCY: &str = "usd";
 
-    /// Converts exchange ticker into CoinGecko id.
+    /// Converts exchange ticker into CoinGecko id. Exchange specific codes such as `XXBT` or `DOT.S` are normalized first.
     /// ```
     /// use coin_gecko::coin_gecko::ticker_to_id;
     /// let currency = "btc";
     /// let id = ticker_to_id(currency).unwrap(); // unwrap shouldn't be used outside this test since this could return None.
     /// assert_eq!(id, "bitcoin".to_string());
+    /// assert_eq!(ticker_to_id("XXBT").unwrap(), "bitcoin".to_string());
     /// ```
     pub fn ticker_to_id(currency: &str) -> Option<String> {
-        let currency = currency.to_ascii_lowercase();
+        let currency = canonical_ticker(currency).to_ascii_lowercase();
         let currency_ref = currency.as_str();
 
         match currency_ref {
-            "eth" | "eth2" | "eth2.s" => Some("ethereum".to_string()),
+            "eth" => Some("ethereum".to_string()),
             "cgld" => Some("celo".to_string()),
             "btc" => Some("bitcoin".to_string()),
-            "algo" | "algo.s" => Some("algorand".to_string()),
+            "algo" => Some("algorand".to_string()),
             "near" => Some("near".to_string()),
             "amp" => Some("amp-token".to_string()),
             "icp" => Some("internet-computer".to_string()),
             "fil" => Some("filecoin".to_string()),
             "comp" => Some("compound-coin".to_string()),
             "fet" => Some("fetch-ai".to_string()),
-            "ada" | "ada.s" => Some("cardano".to_string()),
+            "ada" => Some("cardano".to_string()),
             "gtc" => Some("gitcoin".to_string()),
             "dnt" => Some("nucypher".to_string()),
-            "sol" | "sol.s" => Some("solana".to_string()),
+            "sol" => Some("solana".to_string()),
             "usdt" => Some("tether".to_string()),
             "gal" => Some("gallant".to_string()),
             "dai" => Some("dai".to_string()),
-            "dot" | "dot.s" => Some("polkadot".to_string()),
+            "dot" => Some("polkadot".to_string()),
             "usdc" => Some("usd-coin".to_string()),
             "storj" => Some("storj".to_string()),
             "xtz" => Some("tezos".to_string()),
@@ -52,9 +61,9 @@ pub mod coin_gecko {
             "grt" => Some("the-graph".to_string()),
             "xcn" => Some("chain-2".to_string()),
             "xlm" => Some("stellar".to_string()),
-            "atom" | "atom.s" => Some("cosmos".to_string()),
+            "atom" => Some("cosmos".to_string()),
             "pols" => Some("polkastarter".to_string()),
-            "scrt" | "scrt.s" => Some("secret".to_string()),
+            "scrt" => Some("secret".to_string()),
             _ => {
                 println!("{currency_ref} not matched");
                 None
@@ -90,6 +99,97 @@ pub mod coin_gecko {
             Err(e) => Err(e.to_string()),
         }
     }
+
+    #[derive(Deserialize)]
+    struct HistoryResponse {
+        market_data: Option<MarketData>,
+    }
+
+    #[derive(Deserialize)]
+    struct MarketData {
+        current_price: HashMap<String, Decimal>,
+    }
+
+    /// Gets the USD price of a coin at the start of the given day.
+    pub fn get_historical_price(coin_gecko_id: &str, date: &NaiveDate) -> Result<Decimal, String> {
+        let queries = format!(
+            "?date={}&localization=false",
+            date.format(HISTORY_DATE_FORMAT)
+        );
+        let url = format!(
+            "{}{}{}",
+            BASE_API_URL,
+            HISTORY_ROUTE.replace("{id}", coin_gecko_id),
+            queries
+        );
+
+        match reqwest::blocking::get(url) {
+            Ok(response) => match response.status().is_success() {
+                true => match response.json::<HistoryResponse>() {
+                    Ok(history) => history
+                        .market_data
+                        .and_then(|mut market_data| market_data.current_price.remove(VS_CURRENCY))
+                        .ok_or(format!("No {} price for {} on {}", VS_CURRENCY, coin_gecko_id, date)),
+                    Err(e) => Err(format!("Error attempting to convert http price request to object, original error: {}", e)),
+                },
+                false => Err(format!(
+                    "Pricing data response was not successful: {}",
+                    response.text().unwrap_or_default()
+                )),
+            },
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Looks up daily prices from CoinGecko, remembering each price so a day is only requested once per coin.
+    #[derive(Default)]
+    pub struct CoinGeckoPrices {
+        cache: RefCell<HashMap<(String, NaiveDate), Option<Decimal>>>,
+        errors: RefCell<Vec<String>>,
+    }
+
+    impl CoinGeckoPrices {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Why prices could not be looked up, once for each coin without an id and each coin and day that failed.
+        pub fn errors(&self) -> Vec<String> {
+            self.errors.borrow().clone()
+        }
+
+        fn add_error(&self, error: String) {
+            let mut errors = self.errors.borrow_mut();
+            if !errors.contains(&error) {
+                errors.push(error);
+            }
+        }
+    }
+
+    impl HistoricalPrice for CoinGeckoPrices {
+        fn usd_price_at(&self, asset: &str, time: &DateTime<Utc>) -> Option<Decimal> {
+            let Some(id) = ticker_to_id(asset) else {
+                self.add_error(format!(
+                    "{} has no CoinGecko id to look up its price",
+                    canonical_ticker(asset)
+                ));
+                return None;
+            };
+            let date = time.date_naive();
+
+            self.cache
+                .borrow_mut()
+                .entry((id.to_string(), date))
+                .or_insert_with(|| match get_historical_price(&id, &date) {
+                    Ok(price) => Some(price),
+                    Err(e) => {
+                        self.add_error(e);
+                        None
+                    }
+                })
+                .to_owned()
+        }
+    }
 }
 
 #[cfg(test)]
@@ -165,4 +265,50 @@ mod ticker_to_id {
 
         assert_eq!(ids, expected)
     }
+
+    #[test]
+    fn converts_kraken_codes_to_ids() {
+        let tickers = ["XXBT", "XBT.M", "XETH", "USDC.M", "SOL.F", "ADA.B"];
+
+        let expected = vec![
+            "bitcoin".to_string(),
+            "bitcoin".to_string(),
+            "ethereum".to_string(),
+            "usd-coin".to_string(),
+            "solana".to_string(),
+            "cardano".to_string(),
+        ];
+
+        let ids: Vec<String> = tickers
+            .iter()
+            .map(|ticker| ticker_to_id(ticker).unwrap())
+            .collect();
+
+        assert_eq!(ids, expected)
+    }
+}
+
+#[cfg(test)]
+mod usd_price_at {
+    extern crate chrono;
+    extern crate models;
+
+    use self::chrono::{DateTime, Utc};
+    use self::models::HistoricalPrice;
+
+    use crate::coin_gecko::CoinGeckoPrices;
+
+    #[test]
+    fn report_coins_without_an_id_once() {
+        let prices = CoinGeckoPrices::new();
+        let time = "2021-04-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+
+        assert_eq!(prices.usd_price_at("NOTACOIN", &time), None);
+        assert_eq!(prices.usd_price_at("NOTACOIN", &time), None);
+
+        assert_eq!(
+            prices.errors(),
+            ["NOTACOIN has no CoinGecko id to look up its price"]
+        );
+    }
 }
@@ -1,27 +1,84 @@
-use std::{collections::HashMap, slice::Iter, str::FromStr};
-
-use models::InputTransaction;
+use std::{collections::HashMap, fmt::Display, slice::Iter};
+
+use chrono::{DateTime, Utc};
+use models::{
+    asset::canonical_ticker,
+    cost_basis::{Acquisition, CostBasisEvent, Disposal},
+    income::IncomeRecord,
+    InputTransaction,
+};
 use models_db::CoinbaseTransaction;
 use rust_decimal::Decimal;
 
 pub use models::{
     coinbase::{
-        CoinbaseTransactionRecord, CSV_HEADERS, INCLUDE_TRANSACTIONS, INPUT_TRANSACTIONS,
-        OUTPUT_TRANSACTIONS,
+        CoinbaseTransactionRecord, CoinbaseTransactionType, Conversion, ConversionError,
+        CSV_HEADERS, INCLUDE_TRANSACTIONS, INPUT_TRANSACTIONS, OUTPUT_TRANSACTIONS, SHARED_HEADERS,
+        TRANSACTION_REPORT_DATE_FORMAT, TRANSACTION_REPORT_HEADERS,
     },
-    ActiveAssetValues, InputTransactions, StakingRewards,
+    ActiveAssetValues, CostBasisEvents, HistoricalPrice, InputTransactions, StakingIncome,
+    StakingRewards, UnknownTransactionType, UnknownTransactionTypes,
 };
 
+/// Transaction types that create a new tax lot at their USD value. Rewards become lots from their staking income,
+/// which is priced when Coinbase did not record a USD price.
+pub const ACQUISITION_TRANSACTIONS: &[CoinbaseTransactionType] = &[
+    CoinbaseTransactionType::Buy,
+    CoinbaseTransactionType::AdvancedTradeBuy,
+];
+
+/// Transaction types paid out for staking, Coinbase renamed Rewards Income to Staking Income in 2023.
+pub const STAKING_TRANSACTIONS: &[CoinbaseTransactionType] = &[
+    CoinbaseTransactionType::RewardsIncome,
+    CoinbaseTransactionType::StakingIncome,
+    CoinbaseTransactionType::InflationReward,
+];
+
+/// Transaction types that are taxed as income when received.
+pub const REWARD_TRANSACTIONS: &[CoinbaseTransactionType] = &[
+    CoinbaseTransactionType::RewardsIncome,
+    CoinbaseTransactionType::StakingIncome,
+    CoinbaseTransactionType::InflationReward,
+    CoinbaseTransactionType::LearningReward,
+];
+
+/// Transaction types that dispose of an asset at its USD value.
+pub const DISPOSAL_TRANSACTIONS: &[CoinbaseTransactionType] = &[
+    CoinbaseTransactionType::Sell,
+    CoinbaseTransactionType::AdvancedTradeSell,
+    CoinbaseTransactionType::CardSpend,
+];
+
 pub struct CoinbaseParser<T> {
     data: Vec<T>,
 }
 
+/// A Convert whose notes could not be read, only the side that was sent is counted.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct UnreadConversion {
+    pub asset: String,
+    pub quantity: Decimal,
+    pub time: DateTime<Utc>,
+    pub error: ConversionError,
+}
+
+/// A trade valued in a currency other than USD, it is left out of lots and disposals rather than given a basis or
+/// proceeds in the wrong currency.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ForeignCurrencyTransaction {
+    pub transaction_type: CoinbaseTransactionType,
+    pub asset: String,
+    pub quantity: Decimal,
+    pub time: DateTime<Utc>,
+    pub currency: String,
+}
+
 impl<T> CoinbaseParser<T> {
     pub fn new(data: Vec<T>) -> Self {
         Self { data }
     }
 
-    pub fn create_iter(&self) -> Iter<T> {
+    pub fn create_iter(&self) -> Iter<'_, T> {
         self.data.iter()
     }
 }
@@ -33,13 +90,14 @@ impl StakingRewards for CoinbaseParser<CoinbaseTransactionRecord> {
     /// # use rust_decimal::Decimal;
     /// # use std::collections::HashMap;
     /// # use chrono::{DateTime, Utc};
-    /// # use models::coinbase::CoinbaseTransactionRecord;
+    /// # use models::coinbase::{CoinbaseTransactionRecord, CoinbaseTransactionType};
     /// # use coinbase_parser::{CoinbaseParser, StakingRewards};
     /// let coinbase_parser = CoinbaseParser::new(
     ///     vec![
     ///         CoinbaseTransactionRecord {
+    ///             id: None,
     ///             time_of_transaction: "2021-04-01T21:38:01Z".parse::<DateTime<Utc>>().unwrap(),
-    ///             transaction_type: "Rewards Income".to_string(),
+    ///             transaction_type: CoinbaseTransactionType::RewardsIncome,
     ///             asset: "DOT".to_string(),
     ///             quantity_transacted: Decimal::new(22028, 6),
     ///             spot_price_currency: "USD".to_string(),
@@ -58,12 +116,13 @@ impl StakingRewards for CoinbaseParser<CoinbaseTransactionRecord> {
     fn staking_rewards(&self) -> HashMap<String, Decimal> {
         self.data
             .iter()
-            .filter(|transaction| transaction.transaction_type.eq("Rewards Income"))
+            .filter(|transaction| STAKING_TRANSACTIONS.contains(&transaction.transaction_type))
             .fold(HashMap::new(), |mut reward_map, record| {
-                if let Some(value) = reward_map.get(&record.asset) {
-                    reward_map.insert(record.asset.to_string(), value + record.quantity_transacted);
+                let asset = canonical_ticker(&record.asset);
+                if let Some(value) = reward_map.get(&asset) {
+                    reward_map.insert(asset, value + record.quantity_transacted);
                 } else {
-                    reward_map.insert(record.asset.to_string(), record.quantity_transacted);
+                    reward_map.insert(asset, record.quantity_transacted);
                 }
 
                 reward_map
@@ -78,13 +137,14 @@ impl InputTransactions<CoinbaseTransactionRecord> for CoinbaseParser<CoinbaseTra
     /// # use rust_decimal::Decimal;
     /// # use std::collections::HashMap;
     /// # use chrono::{DateTime, Utc};
-    /// # use models::coinbase::CoinbaseTransactionRecord;
+    /// # use models::coinbase::{CoinbaseTransactionRecord, CoinbaseTransactionType};
     /// # use coinbase_parser::{CoinbaseParser, InputTransactions};
     /// let coinbase_parser = CoinbaseParser::new(
     ///     vec![
     ///         CoinbaseTransactionRecord {
+    ///             id: None,
     ///             time_of_transaction: "2022-04-01T21:38:01Z".parse::<DateTime<Utc>>().unwrap(),
-    ///             transaction_type: "Buy".to_string(),
+    ///             transaction_type: CoinbaseTransactionType::Buy,
     ///             asset: "DOT".to_string(),
     ///             quantity_transacted: Decimal::new(22028, 6),
     ///             spot_price_currency: "USD".to_string(),
@@ -95,8 +155,9 @@ impl InputTransactions<CoinbaseTransactionRecord> for CoinbaseParser<CoinbaseTra
     ///             notes: "Bought 0.022028 DOT for $100.00 USD".to_string(),
     ///         },
     ///         CoinbaseTransactionRecord {
+    ///             id: None,
     ///             time_of_transaction: "2022-04-01T21:38:01Z".parse::<DateTime<Utc>>().unwrap(),
-    ///             transaction_type: "Sell".to_string(),
+    ///             transaction_type: CoinbaseTransactionType::Sell,
     ///             asset: "DOT".to_string(),
     ///             quantity_transacted: Decimal::new(22028, 6),
     ///             spot_price_currency: "USD".to_string(),
@@ -110,8 +171,9 @@ impl InputTransactions<CoinbaseTransactionRecord> for CoinbaseParser<CoinbaseTra
     /// );
     ///
     /// let expected = CoinbaseTransactionRecord {
+    ///     id: None,
     ///     time_of_transaction: "2022-04-01T21:38:01Z".parse::<DateTime<Utc>>().unwrap(),
-    ///     transaction_type: "Buy".to_string(),
+    ///     transaction_type: CoinbaseTransactionType::Buy,
     ///     asset: "DOT".to_string(),
     ///     quantity_transacted: Decimal::new(22028, 6),
     ///     spot_price_currency: "USD".to_string(),
@@ -121,7 +183,7 @@ impl InputTransactions<CoinbaseTransactionRecord> for CoinbaseParser<CoinbaseTra
     ///     fees: None,
     ///     notes: "Bought 0.022028 DOT for $100.00 USD".to_string(),
     /// };
-
+    ///
     /// let input_transactions = coinbase_parser.input_transactions();
     /// assert_eq!(input_transactions.len(), 1);
     /// assert_eq!(input_transactions.first(), Some(&&expected));
@@ -138,11 +200,7 @@ impl ActiveAssetValues for CoinbaseParser<CoinbaseTransactionRecord> {
     fn active_assets(&self) -> HashMap<String, Decimal> {
         self.data
             .iter()
-            .filter(|transaction| {
-                INCLUDE_TRANSACTIONS.iter().any(|included_transaction| {
-                    included_transaction.eq(&transaction.transaction_type)
-                })
-            })
+            .filter(|transaction| !transaction.transaction_type.is_unknown())
             .fold(HashMap::new(), |mut map, transaction| {
                 if is_gain_record(transaction) {
                     process_transaction(
@@ -156,22 +214,19 @@ impl ActiveAssetValues for CoinbaseParser<CoinbaseTransactionRecord> {
                         &transaction.asset,
                         &(transaction.quantity_transacted * Decimal::new(-1, 0)),
                     );
-                } else if transaction.transaction_type.eq("Convert") {
-                    if let Some(value) = transaction.notes.split("to").last() {
-                        let vec: Vec<&str> = value.trim().split(' ').collect();
-
-                        let amount =
-                            Decimal::from_str(&vec.first().unwrap().trim().replace(',', ""))
-                                .unwrap();
-                        let asset = vec.last().unwrap().to_string();
+                } else if transaction.transaction_type == CoinbaseTransactionType::Convert {
+                    process_transaction(
+                        &mut map,
+                        &transaction.asset,
+                        &(transaction.quantity_transacted * Decimal::new(-1, 0)),
+                    );
 
+                    if let Ok(conversion) = Conversion::try_from(transaction) {
                         process_transaction(
                             &mut map,
-                            &transaction.asset,
-                            &(transaction.quantity_transacted * Decimal::new(-1, 0)),
+                            &conversion.to_asset,
+                            &conversion.to_quantity,
                         );
-
-                        process_transaction(&mut map, &asset, &amount);
                     }
                 };
 
@@ -180,6 +235,101 @@ impl ActiveAssetValues for CoinbaseParser<CoinbaseTransactionRecord> {
     }
 }
 
+impl CoinbaseParser<CoinbaseTransactionRecord> {
+    /// Converts whose notes do not say what was received, so nothing is added to holdings or lots for them.
+    pub fn unread_conversions(&self) -> Vec<UnreadConversion> {
+        self.data.iter().filter_map(unread_conversion).collect()
+    }
+
+    /// Trades valued in another currency than USD, which are left out of [`CostBasisEvents::cost_basis_events`].
+    pub fn foreign_currency_transactions(&self) -> Vec<ForeignCurrencyTransaction> {
+        self.data
+            .iter()
+            .filter_map(foreign_currency_transaction)
+            .collect()
+    }
+}
+
+impl CoinbaseParser<CoinbaseTransaction> {
+    /// Converts whose notes do not say what was received, so nothing is added to holdings or lots for them.
+    pub fn unread_conversions(&self) -> Vec<UnreadConversion> {
+        self.data
+            .iter()
+            .filter_map(|transaction| {
+                unread_conversion(&CoinbaseTransactionRecord::from(transaction))
+            })
+            .collect()
+    }
+
+    /// Trades valued in another currency than USD, which are left out of [`CostBasisEvents::cost_basis_events`].
+    pub fn foreign_currency_transactions(&self) -> Vec<ForeignCurrencyTransaction> {
+        self.data
+            .iter()
+            .filter_map(|transaction| {
+                foreign_currency_transaction(&CoinbaseTransactionRecord::from(transaction))
+            })
+            .collect()
+    }
+}
+
+impl UnknownTransactionTypes for CoinbaseParser<CoinbaseTransactionRecord> {
+    /// Reports the transaction types that were left out of the analysis because they are not understood.
+    /// ```
+    /// # use rust_decimal::Decimal;
+    /// # use chrono::{DateTime, Utc};
+    /// # use coinbase_parser::{
+    /// #     CoinbaseParser, CoinbaseTransactionRecord, CoinbaseTransactionType, UnknownTransactionTypes,
+    /// # };
+    /// let record = CoinbaseTransactionRecord {
+    ///     id: None,
+    ///     time_of_transaction: "2023-04-01T21:38:01Z".parse::<DateTime<Utc>>().unwrap(),
+    ///     transaction_type: CoinbaseTransactionType::from("Pro Withdrawal"),
+    ///     asset: "BTC".to_string(),
+    ///     // ... the other properties
+    /// #   quantity_transacted: Decimal::new(1, 0),
+    /// #   spot_price_currency: "USD".to_string(),
+    /// #   spot_price_at_transaction: None,
+    /// #   subtotal: None,
+    /// #   total: None,
+    /// #   fees: None,
+    /// #   notes: "".to_string(),
+    /// };
+    ///
+    /// let unknown = CoinbaseParser::new(vec![record]).unknown_transaction_types();
+    /// assert_eq!(unknown.first().unwrap().transaction_type, "Pro Withdrawal");
+    /// assert_eq!(unknown.first().unwrap().occurrences, 1);
+    /// ```
+    fn unknown_transaction_types(&self) -> Vec<UnknownTransactionType> {
+        UnknownTransactionType::tally(
+            self.data
+                .iter()
+                .filter(|transaction| transaction.transaction_type.is_unknown())
+                .map(|transaction| {
+                    (
+                        transaction.transaction_type.as_str(),
+                        transaction.asset.as_str(),
+                    )
+                }),
+        )
+    }
+}
+
+impl UnknownTransactionTypes for CoinbaseParser<CoinbaseTransaction> {
+    fn unknown_transaction_types(&self) -> Vec<UnknownTransactionType> {
+        UnknownTransactionType::tally(
+            self.data
+                .iter()
+                .filter(|transaction| transaction.transaction_kind().is_unknown())
+                .map(|transaction| {
+                    (
+                        transaction.transaction_type.as_str(),
+                        transaction.asset.as_str(),
+                    )
+                }),
+        )
+    }
+}
+
 impl StakingRewards for CoinbaseParser<CoinbaseTransaction> {
     ///
     /// Generates rewards based on the vector of CoinbaseTransaction contained within the struct.
@@ -203,6 +353,8 @@ impl StakingRewards for CoinbaseParser<CoinbaseTransaction> {
     ///             total: Some(Decimal::new(100, 0)),
     ///             fees: None,
     ///             notes: "Bought 0.0016458 BTC for $100.00 USD".to_string(),
+    ///             coinbase_id: None,
+    ///             import_batch_id: None,
     ///         },
     ///     ]
     /// );
@@ -213,12 +365,13 @@ impl StakingRewards for CoinbaseParser<CoinbaseTransaction> {
     fn staking_rewards(&self) -> HashMap<String, Decimal> {
         self.data
             .iter()
-            .filter(|transaction| transaction.transaction_type.eq("Rewards Income"))
+            .filter(|transaction| STAKING_TRANSACTIONS.contains(&transaction.transaction_kind()))
             .fold(HashMap::new(), |mut reward_map, record| {
-                if let Some(value) = reward_map.get(&record.asset) {
-                    reward_map.insert(record.asset.to_string(), value + record.quantity_transacted);
+                let asset = canonical_ticker(&record.asset);
+                if let Some(value) = reward_map.get(&asset) {
+                    reward_map.insert(asset, value + record.quantity_transacted);
                 } else {
-                    reward_map.insert(record.asset.to_string(), record.quantity_transacted);
+                    reward_map.insert(asset, record.quantity_transacted);
                 }
 
                 reward_map
@@ -249,6 +402,8 @@ impl InputTransactions<CoinbaseTransaction> for CoinbaseParser<CoinbaseTransacti
     ///             total: Some(Decimal::new(100, 0)),
     ///             fees: None,
     ///             notes: "Bought 0.022028 DOT for $100.00 USD".to_string(),
+    ///             coinbase_id: None,
+    ///             import_batch_id: None,
     ///         },
     ///         CoinbaseTransaction {
     ///             id: 1022735,
@@ -262,6 +417,8 @@ impl InputTransactions<CoinbaseTransaction> for CoinbaseParser<CoinbaseTransacti
     ///             total: Some(Decimal::new(100, 0)),
     ///             fees: None,
     ///             notes: "Bought 0.022028 DOT for $100.00 USD".to_string(),
+    ///             coinbase_id: None,
+    ///             import_batch_id: None,
     ///         },
     ///     ]
     /// );
@@ -278,8 +435,10 @@ impl InputTransactions<CoinbaseTransaction> for CoinbaseParser<CoinbaseTransacti
     ///     total: Some(Decimal::new(100, 0)),
     ///     fees: None,
     ///     notes: "Bought 0.022028 DOT for $100.00 USD".to_string(),
+    ///     coinbase_id: None,
+    ///     import_batch_id: None,
     /// };
-
+    ///
     /// let input_transactions = coinbase_parser.input_transactions();
     /// assert_eq!(input_transactions.len(), 1);
     /// assert_eq!(input_transactions.first(), Some(&&expected));
@@ -287,11 +446,7 @@ impl InputTransactions<CoinbaseTransaction> for CoinbaseParser<CoinbaseTransacti
     fn input_transactions(&self) -> Vec<&CoinbaseTransaction> {
         self.data
             .iter()
-            .filter(|transaction| {
-                INPUT_TRANSACTIONS.iter().any(|received_transaction_type| {
-                    received_transaction_type.eq(&transaction.transaction_type)
-                })
-            })
+            .filter(|transaction| transaction.is_input_transaction())
             .collect()
     }
 }
@@ -300,11 +455,7 @@ impl ActiveAssetValues for CoinbaseParser<CoinbaseTransaction> {
     fn active_assets(&self) -> HashMap<String, Decimal> {
         self.data
             .iter()
-            .filter(|transaction| {
-                INCLUDE_TRANSACTIONS.iter().any(|included_transaction| {
-                    included_transaction.eq(&transaction.transaction_type)
-                })
-            })
+            .filter(|transaction| !transaction.transaction_kind().is_unknown())
             .fold(HashMap::new(), |mut map, transaction| {
                 if is_gain(transaction) {
                     process_transaction(
@@ -318,22 +469,19 @@ impl ActiveAssetValues for CoinbaseParser<CoinbaseTransaction> {
                         &transaction.asset,
                         &(transaction.quantity_transacted * Decimal::new(-1, 0)),
                     );
-                } else if transaction.transaction_type.eq("Convert") {
-                    if let Some(value) = transaction.notes.split("to").last() {
-                        let vec: Vec<&str> = value.trim().split(' ').collect();
-
-                        let amount =
-                            Decimal::from_str(&vec.first().unwrap().trim().replace(',', ""))
-                                .unwrap();
-                        let asset = vec.last().unwrap().to_string();
+                } else if transaction.transaction_kind() == CoinbaseTransactionType::Convert {
+                    process_transaction(
+                        &mut map,
+                        &transaction.asset,
+                        &(transaction.quantity_transacted * Decimal::new(-1, 0)),
+                    );
 
+                    if let Ok(conversion) = Conversion::try_from(transaction) {
                         process_transaction(
                             &mut map,
-                            &transaction.asset,
-                            &(transaction.quantity_transacted * Decimal::new(-1, 0)),
+                            &conversion.to_asset,
+                            &conversion.to_quantity,
                         );
-
-                        process_transaction(&mut map, &asset, &amount);
                     }
                 };
 
@@ -342,38 +490,256 @@ impl ActiveAssetValues for CoinbaseParser<CoinbaseTransaction> {
     }
 }
 
-fn process_transaction(map: &mut HashMap<String, Decimal>, asset: &String, amount: &Decimal) {
-    if let Some(value) = map.get(asset) {
-        Decimal::from_str(&value.to_string()).unwrap();
-        map.insert(asset.to_string(), value + *amount);
+impl CostBasisEvents for CoinbaseParser<CoinbaseTransactionRecord> {
+    /// Creates the acquisitions and disposals needed to track cost basis. Records are identified by their Coinbase id, or by their position in the export when they have none.
+    /// ```
+    /// # use rust_decimal::Decimal;
+    /// # use chrono::{DateTime, Utc};
+    /// # use models::{
+    /// #     coinbase::{CoinbaseTransactionRecord, CoinbaseTransactionType},
+    /// #     cost_basis::CostBasisEvent,
+    /// # };
+    /// # use coinbase_parser::{CoinbaseParser, CostBasisEvents};
+    /// let coinbase_parser = CoinbaseParser::new(vec![CoinbaseTransactionRecord {
+    ///     id: None,
+    ///     time_of_transaction: "2021-04-01T21:38:01Z".parse::<DateTime<Utc>>().unwrap(),
+    ///     transaction_type: CoinbaseTransactionType::Buy,
+    ///     asset: "BTC".to_string(),
+    ///     quantity_transacted: Decimal::new(2, 0),
+    ///     spot_price_currency: "USD".to_string(),
+    ///     spot_price_at_transaction: Some(Decimal::new(50, 0)),
+    ///     subtotal: Some(Decimal::new(100, 0)),
+    ///     total: Some(Decimal::new(102, 0)),
+    ///     fees: Some(Decimal::new(2, 0)),
+    ///     notes: "Bought 2 BTC for $102.00 USD".to_string(),
+    /// }]);
+    ///
+    /// let events = coinbase_parser.cost_basis_events();
+    /// match events.first() {
+    ///     Some(CostBasisEvent::Acquisition(acquisition)) => {
+    ///         assert_eq!(acquisition.cost, Decimal::new(102, 0));
+    ///     }
+    ///     _ => panic!("Buy was not seen as an acquisition"),
+    /// }
+    /// ```
+    fn cost_basis_events(&self) -> Vec<CostBasisEvent> {
+        self.data
+            .iter()
+            .enumerate()
+            .flat_map(|(index, record)| record_cost_basis_events(event_id(record, index), record))
+            .collect()
+    }
+}
+
+impl CostBasisEvents for CoinbaseParser<CoinbaseTransaction> {
+    fn cost_basis_events(&self) -> Vec<CostBasisEvent> {
+        self.data
+            .iter()
+            .flat_map(|transaction| {
+                let record = CoinbaseTransactionRecord::from(transaction);
+                record_cost_basis_events(event_id(&record, transaction.id), &record)
+            })
+            .collect()
+    }
+}
+
+impl StakingIncome for CoinbaseParser<CoinbaseTransactionRecord> {
+    /// Values every reward at the spot price Coinbase recorded, falling back to the given prices when it is missing.
+    /// ```
+    /// # use rust_decimal::Decimal;
+    /// # use chrono::{DateTime, Utc};
+    /// # use models::coinbase::{CoinbaseTransactionRecord, CoinbaseTransactionType};
+    /// # use coinbase_parser::{CoinbaseParser, HistoricalPrice, StakingIncome};
+    /// struct NoPrices;
+    /// impl HistoricalPrice for NoPrices {
+    ///     fn usd_price_at(&self, _: &str, _: &DateTime<Utc>) -> Option<Decimal> {
+    ///         None
+    ///     }
+    /// }
+    ///
+    /// let coinbase_parser = CoinbaseParser::new(vec![CoinbaseTransactionRecord {
+    ///     id: None,
+    ///     time_of_transaction: "2021-04-01T21:38:01Z".parse::<DateTime<Utc>>().unwrap(),
+    ///     transaction_type: CoinbaseTransactionType::RewardsIncome,
+    ///     asset: "DOT".to_string(),
+    ///     quantity_transacted: Decimal::new(2, 0),
+    ///     spot_price_currency: "USD".to_string(),
+    ///     spot_price_at_transaction: Some(Decimal::new(40, 0)),
+    ///     subtotal: None,
+    ///     total: None,
+    ///     fees: None,
+    ///     notes: "".to_string(),
+    /// }]);
+    ///
+    /// let income = coinbase_parser.staking_income(&NoPrices);
+    /// assert_eq!(income.first().unwrap().usd_value, Some(Decimal::new(80, 0)));
+    /// ```
+    fn staking_income(&self, prices: &dyn HistoricalPrice) -> Vec<IncomeRecord> {
+        self.data
+            .iter()
+            .enumerate()
+            .filter(|(_, record)| REWARD_TRANSACTIONS.contains(&record.transaction_type))
+            .map(|(index, record)| record_income(event_id(record, index), record, prices))
+            .collect()
+    }
+}
+
+impl StakingIncome for CoinbaseParser<CoinbaseTransaction> {
+    fn staking_income(&self, prices: &dyn HistoricalPrice) -> Vec<IncomeRecord> {
+        self.data
+            .iter()
+            .filter(|transaction| REWARD_TRANSACTIONS.contains(&transaction.transaction_kind()))
+            .map(|transaction| {
+                let record = CoinbaseTransactionRecord::from(transaction);
+                record_income(event_id(&record, transaction.id), &record, prices)
+            })
+            .collect()
+    }
+}
+
+fn process_transaction(map: &mut HashMap<String, Decimal>, asset: &str, amount: &Decimal) {
+    let asset = canonical_ticker(asset);
+    if let Some(value) = map.get(&asset) {
+        map.insert(asset, value + *amount);
     } else {
-        map.insert(asset.to_string(), *amount);
+        map.insert(asset, *amount);
+    }
+}
+
+/// Ids the events of a record by its Coinbase id, falling back to the given row id for records without one.
+fn event_id(record: &CoinbaseTransactionRecord, row_id: impl Display) -> String {
+    match record.id.as_deref().map(str::trim) {
+        Some(id) if !id.is_empty() => format!("coinbase-{id}"),
+        _ => format!("coinbase-{row_id}"),
+    }
+}
+
+fn record_cost_basis_events(id: String, record: &CoinbaseTransactionRecord) -> Vec<CostBasisEvent> {
+    if foreign_currency_transaction(record).is_some() {
+        return Vec::new();
+    }
+    let fees = record.fees.unwrap_or_default();
+    let asset = canonical_ticker(&record.asset);
+    let value = record.subtotal.unwrap_or_else(|| {
+        record.quantity_transacted * record.spot_price_at_transaction.unwrap_or_default()
+    });
+
+    if ACQUISITION_TRANSACTIONS.contains(&record.transaction_type) {
+        vec![CostBasisEvent::Acquisition(Acquisition {
+            id,
+            asset,
+            quantity: record.quantity_transacted,
+            cost: record.total.unwrap_or(value + fees),
+            fees,
+            time: record.time_of_transaction,
+        })]
+    } else if DISPOSAL_TRANSACTIONS.contains(&record.transaction_type) {
+        vec![CostBasisEvent::Disposal(Disposal {
+            id,
+            asset,
+            quantity: record.quantity_transacted,
+            proceeds: value,
+            fees,
+            time: record.time_of_transaction,
+        })]
+    } else if record.transaction_type == CoinbaseTransactionType::Convert {
+        let mut events = vec![CostBasisEvent::Disposal(Disposal {
+            id: id.to_string(),
+            asset,
+            quantity: record.quantity_transacted,
+            proceeds: value,
+            fees,
+            time: record.time_of_transaction,
+        })];
+
+        if let Ok(conversion) = Conversion::try_from(record) {
+            events.push(CostBasisEvent::Acquisition(Acquisition {
+                id: format!("{id}-to"),
+                asset: canonical_ticker(&conversion.to_asset),
+                quantity: conversion.to_quantity,
+                cost: value - fees,
+                fees: Decimal::ZERO,
+                time: record.time_of_transaction,
+            }));
+        }
+
+        events
+    } else {
+        Vec::new()
+    }
+}
+
+/// Trades whose values are in another currency than USD.
+fn foreign_currency_transaction(
+    record: &CoinbaseTransactionRecord,
+) -> Option<ForeignCurrencyTransaction> {
+    let valued = ACQUISITION_TRANSACTIONS.contains(&record.transaction_type)
+        || DISPOSAL_TRANSACTIONS.contains(&record.transaction_type)
+        || record.transaction_type == CoinbaseTransactionType::Convert;
+    if !valued || record.spot_price_currency.eq("USD") {
+        return None;
+    }
+
+    Some(ForeignCurrencyTransaction {
+        transaction_type: record.transaction_type.clone(),
+        asset: canonical_ticker(&record.asset),
+        quantity: record.quantity_transacted,
+        time: record.time_of_transaction,
+        currency: record.spot_price_currency.to_string(),
+    })
+}
+
+fn unread_conversion(record: &CoinbaseTransactionRecord) -> Option<UnreadConversion> {
+    if record.transaction_type != CoinbaseTransactionType::Convert {
+        return None;
+    }
+
+    Conversion::try_from(record)
+        .err()
+        .map(|error| UnreadConversion {
+            asset: canonical_ticker(&record.asset),
+            quantity: record.quantity_transacted,
+            time: record.time_of_transaction,
+            error,
+        })
+}
+
+fn record_income(
+    id: String,
+    record: &CoinbaseTransactionRecord,
+    prices: &dyn HistoricalPrice,
+) -> IncomeRecord {
+    let asset = canonical_ticker(&record.asset);
+    let usd_price = record
+        .spot_price_at_transaction
+        .filter(|_| record.spot_price_currency.eq("USD"))
+        .or_else(|| prices.usd_price_at(&asset, &record.time_of_transaction));
+
+    IncomeRecord {
+        id,
+        asset,
+        quantity: record.quantity_transacted,
+        received_at: record.time_of_transaction,
+        usd_price,
+        usd_value: usd_price.map(|price| price * record.quantity_transacted),
     }
 }
 
 fn is_gain_record(transaction: &CoinbaseTransactionRecord) -> bool {
-    INPUT_TRANSACTIONS
-        .iter()
-        .any(|receive_type| receive_type.eq(&transaction.transaction_type))
+    INPUT_TRANSACTIONS.contains(&transaction.transaction_type)
 }
 
 fn is_loss_record(transaction: &CoinbaseTransactionRecord) -> bool {
-    OUTPUT_TRANSACTIONS
-        .iter()
-        .any(|send_transaction| send_transaction.eq(&transaction.transaction_type))
+    OUTPUT_TRANSACTIONS.contains(&transaction.transaction_type)
 }
 
 // TODO: Combined these together?
 fn is_gain(transaction: &CoinbaseTransaction) -> bool {
-    INPUT_TRANSACTIONS
-        .iter()
-        .any(|receive_type| receive_type.eq(&transaction.transaction_type))
+    INPUT_TRANSACTIONS.contains(&transaction.transaction_kind())
 }
 
 fn is_loss(transaction: &CoinbaseTransaction) -> bool {
-    OUTPUT_TRANSACTIONS
-        .iter()
-        .any(|send_transaction| send_transaction.eq(&transaction.transaction_type))
+    OUTPUT_TRANSACTIONS.contains(&transaction.transaction_kind())
 }
 
 #[cfg(test)]
@@ -381,7 +747,7 @@ mod staking_reward_for {
     mod coinbase_transaction_record {
         use crate::{CoinbaseParser, StakingRewards};
 
-        use crate::CoinbaseTransactionRecord;
+        use crate::{CoinbaseTransactionRecord, CoinbaseTransactionType};
         use chrono::{DateTime, Utc};
         use rand::Rng;
         use rust_decimal::Decimal;
@@ -390,8 +756,9 @@ mod staking_reward_for {
         fn total_staking_rewards_returns_with_multiple_asset_types() {
             let sample_vec = vec![
                 CoinbaseTransactionRecord {
+                    id: None,
                     time_of_transaction: "2021-04-01T21:38:01Z".parse::<DateTime<Utc>>().unwrap(),
-                    transaction_type: "Rewards Income".to_string(),
+                    transaction_type: CoinbaseTransactionType::RewardsIncome,
                     asset: "DOT".to_string(),
                     quantity_transacted: Decimal::new(22028, 6),
                     spot_price_currency: "USD".to_string(),
@@ -402,8 +769,9 @@ mod staking_reward_for {
                     notes: "Bought 0.0016458 BTC for $100.00 USD".to_string(),
                 },
                 CoinbaseTransactionRecord {
+                    id: None,
                     time_of_transaction: "2021-04-01T21:38:02Z".parse::<DateTime<Utc>>().unwrap(),
-                    transaction_type: "Rewards Income".to_string(),
+                    transaction_type: CoinbaseTransactionType::RewardsIncome,
                     asset: "ALGO".to_string(),
                     quantity_transacted: Decimal::new(16458, 7),
                     spot_price_currency: "USD".to_string(),
@@ -423,7 +791,7 @@ mod staking_reward_for {
             assert_eq!(actual.len(), 2);
             expected_keys
                 .iter()
-                .for_each(|key| assert!(actual.contains_key(&key.to_string())));
+                .for_each(|key| assert!(actual.contains_key(*key)));
 
             // Values
             let mut values = actual.values().cloned().collect::<Vec<Decimal>>();
@@ -444,8 +812,9 @@ mod staking_reward_for {
 
             let sample_vec = vec![
                 CoinbaseTransactionRecord {
+                    id: None,
                     time_of_transaction: "2021-04-01T21:38:01Z".parse::<DateTime<Utc>>().unwrap(),
-                    transaction_type: "Rewards Income".to_string(),
+                    transaction_type: CoinbaseTransactionType::RewardsIncome,
                     asset: "DOT".to_string(),
                     quantity_transacted: given_transaction_1,
                     spot_price_currency: "USD".to_string(),
@@ -456,8 +825,9 @@ mod staking_reward_for {
                     notes: "Bought 0.0016458 BTC for $100.00 USD".to_string(),
                 },
                 CoinbaseTransactionRecord {
+                    id: None,
                     time_of_transaction: "2021-04-01T21:38:02Z".parse::<DateTime<Utc>>().unwrap(),
-                    transaction_type: "Rewards Income".to_string(),
+                    transaction_type: CoinbaseTransactionType::RewardsIncome,
                     asset: "DOT".to_string(),
                     quantity_transacted: given_transaction_2,
                     spot_price_currency: "USD".to_string(),
@@ -511,6 +881,8 @@ mod staking_reward_for {
                     total: Some(Decimal::new(100, 0)),
                     fees: Some(Decimal::new(299, 2)),
                     notes: "Bought 0.0016458 BTC for $100.00 USD".to_string(),
+                    coinbase_id: None,
+                    import_batch_id: None,
                 },
                 CoinbaseTransaction {
                     id: 37222,
@@ -524,6 +896,8 @@ mod staking_reward_for {
                     total: Some(Decimal::new(100, 0)),
                     fees: Some(Decimal::new(299, 2)),
                     notes: "Bought 0.0016458 BTC for $100.00 USD".to_string(),
+                    coinbase_id: None,
+                    import_batch_id: None,
                 },
             ];
 
@@ -535,7 +909,7 @@ mod staking_reward_for {
             assert_eq!(actual.len(), 2);
             expected_keys
                 .iter()
-                .for_each(|key| assert!(actual.contains_key(&key.to_string())));
+                .for_each(|key| assert!(actual.contains_key(*key)));
 
             // Values
             let mut values = actual.values().cloned().collect::<Vec<Decimal>>();
@@ -567,6 +941,8 @@ mod staking_reward_for {
                     total: Some(Decimal::new(100, 0)),
                     fees: Some(Decimal::new(299, 2)),
                     notes: "Bought 0.0016458 BTC for $100.00 USD".to_string(),
+                    coinbase_id: None,
+                    import_batch_id: None,
                 },
                 CoinbaseTransaction {
                     id: 101,
@@ -580,6 +956,8 @@ mod staking_reward_for {
                     total: Some(Decimal::new(100, 0)),
                     fees: Some(Decimal::new(299, 2)),
                     notes: "Bought 0.0016458 BTC for $100.00 USD".to_string(),
+                    coinbase_id: None,
+                    import_batch_id: None,
                 },
             ];
             let expected_transacted = given_transaction_1 + given_transaction_2;
@@ -607,7 +985,10 @@ mod input_transactions_for {
     #[cfg(test)]
     mod coinbase_transaction_record {
         use chrono::{DateTime, Utc};
-        use models::{coinbase::CoinbaseTransactionRecord, InputTransactions};
+        use models::{
+            coinbase::{CoinbaseTransactionRecord, CoinbaseTransactionType},
+            InputTransactions,
+        };
         use rust_decimal::Decimal;
 
         use crate::CoinbaseParser;
@@ -616,8 +997,9 @@ mod input_transactions_for {
         fn should_return_input_transactions_with_expected_content() {
             let sample_vec = vec![
                 CoinbaseTransactionRecord {
+                    id: None,
                     time_of_transaction: "2021-04-01T21:38:02Z".parse::<DateTime<Utc>>().unwrap(),
-                    transaction_type: "Buy".to_string(),
+                    transaction_type: CoinbaseTransactionType::Buy,
                     asset: "DOT".to_string(),
                     quantity_transacted: Decimal::new(2200024, 5),
                     spot_price_currency: "USD".to_string(),
@@ -628,8 +1010,9 @@ mod input_transactions_for {
                     notes: "Bought 0.0016458 BTC for $100.00 USD".to_string(),
                 },
                 CoinbaseTransactionRecord {
+                    id: None,
                     time_of_transaction: "2021-04-01T21:38:02Z".parse::<DateTime<Utc>>().unwrap(),
-                    transaction_type: "Receive".to_string(),
+                    transaction_type: CoinbaseTransactionType::Receive,
                     asset: "DOT".to_string(),
                     quantity_transacted: Decimal::new(2200024, 5),
                     spot_price_currency: "USD".to_string(),
@@ -645,7 +1028,7 @@ mod input_transactions_for {
             let actual = coinbase_parser.input_transactions();
 
             assert_eq!(actual.len(), 2);
-            assert_eq!(**actual.get(0).unwrap(), *sample_vec.get(0).unwrap());
+            assert_eq!(**actual.first().unwrap(), *sample_vec.first().unwrap());
             assert_eq!(**actual.get(1).unwrap(), *sample_vec.get(1).unwrap());
         }
 
@@ -653,8 +1036,9 @@ mod input_transactions_for {
         fn should_filter_out_non_input_transactions() {
             let sample_vec = vec![
                 CoinbaseTransactionRecord {
+                    id: None,
                     time_of_transaction: "2021-04-01T21:38:02Z".parse::<DateTime<Utc>>().unwrap(),
-                    transaction_type: "Buy".to_string(),
+                    transaction_type: CoinbaseTransactionType::Buy,
                     asset: "DOT".to_string(),
                     quantity_transacted: Decimal::new(2200024, 5),
                     spot_price_currency: "USD".to_string(),
@@ -665,8 +1049,9 @@ mod input_transactions_for {
                     notes: "Bought 0.0016458 BTC for $100.00 USD".to_string(),
                 },
                 CoinbaseTransactionRecord {
+                    id: None,
                     time_of_transaction: "2021-04-01T21:38:02Z".parse::<DateTime<Utc>>().unwrap(),
-                    transaction_type: "Send".to_string(),
+                    transaction_type: CoinbaseTransactionType::Send,
                     asset: "DOT".to_string(),
                     quantity_transacted: Decimal::new(2200024, 5),
                     spot_price_currency: "USD".to_string(),
@@ -682,7 +1067,7 @@ mod input_transactions_for {
             let actual = coinbase_parser.input_transactions();
 
             assert_eq!(actual.len(), 1);
-            assert_eq!(**actual.get(0).unwrap(), *sample_vec.get(0).unwrap());
+            assert_eq!(**actual.first().unwrap(), *sample_vec.first().unwrap());
         }
     }
 
@@ -710,6 +1095,8 @@ mod input_transactions_for {
                     total: Some(Decimal::new(100, 0)),
                     fees: Some(Decimal::new(299, 2)),
                     notes: "Bought 0.0016458 BTC for $100.00 USD".to_string(),
+                    coinbase_id: None,
+                    import_batch_id: None,
                 },
                 CoinbaseTransaction {
                     id: 32313,
@@ -723,6 +1110,8 @@ mod input_transactions_for {
                     total: Some(Decimal::new(100, 0)),
                     fees: Some(Decimal::new(299, 2)),
                     notes: "Bought 0.0016458 BTC for $100.00 USD".to_string(),
+                    coinbase_id: None,
+                    import_batch_id: None,
                 },
             ];
 
@@ -730,7 +1119,7 @@ mod input_transactions_for {
             let actual = coinbase_parser.input_transactions();
 
             assert_eq!(actual.len(), 2);
-            assert_eq!(**actual.get(0).unwrap(), *sample_vec.get(0).unwrap());
+            assert_eq!(**actual.first().unwrap(), *sample_vec.first().unwrap());
             assert_eq!(**actual.get(1).unwrap(), *sample_vec.get(1).unwrap());
         }
 
@@ -749,6 +1138,8 @@ mod input_transactions_for {
                     total: Some(Decimal::new(100, 0)),
                     fees: Some(Decimal::new(299, 2)),
                     notes: "Bought 0.0016458 BTC for $100.00 USD".to_string(),
+                    coinbase_id: None,
+                    import_batch_id: None,
                 },
                 CoinbaseTransaction {
                     id: 3773,
@@ -762,6 +1153,8 @@ mod input_transactions_for {
                     total: Some(Decimal::new(100, 0)),
                     fees: Some(Decimal::new(299, 2)),
                     notes: "Bought 0.0016458 BTC for $100.00 USD".to_string(),
+                    coinbase_id: None,
+                    import_batch_id: None,
                 },
             ];
 
@@ -769,7 +1162,7 @@ mod input_transactions_for {
             let actual = coinbase_parser.input_transactions();
 
             assert_eq!(actual.len(), 1);
-            assert_eq!(**actual.get(0).unwrap(), *sample_vec.get(0).unwrap());
+            assert_eq!(**actual.first().unwrap(), *sample_vec.first().unwrap());
         }
     }
 }
@@ -779,7 +1172,10 @@ mod active_assets_for {
     #[cfg(test)]
     mod coinbase_transaction_record {
         use chrono::{DateTime, Utc};
-        use models::{coinbase::CoinbaseTransactionRecord, ActiveAssetValues};
+        use models::{
+            coinbase::{CoinbaseTransactionRecord, CoinbaseTransactionType},
+            ActiveAssetValues,
+        };
         use rust_decimal::{prelude::Zero, Decimal};
 
         use crate::CoinbaseParser;
@@ -788,8 +1184,9 @@ mod active_assets_for {
         fn performs_addition_and_subtraction() {
             let sample_vec = vec![
                 CoinbaseTransactionRecord {
+                    id: None,
                     time_of_transaction: "2021-04-01T21:38:02Z".parse::<DateTime<Utc>>().unwrap(),
-                    transaction_type: "Buy".to_string(),
+                    transaction_type: CoinbaseTransactionType::Buy,
                     asset: "DOT".to_string(),
                     quantity_transacted: Decimal::new(2200024, 5),
                     spot_price_currency: "USD".to_string(),
@@ -800,8 +1197,9 @@ mod active_assets_for {
                     notes: "Bought 22.00024 DOT for $122.00 USD".to_string(),
                 },
                 CoinbaseTransactionRecord {
+                    id: None,
                     time_of_transaction: "2021-04-04T21:38:02Z".parse::<DateTime<Utc>>().unwrap(),
-                    transaction_type: "Buy".to_string(),
+                    transaction_type: CoinbaseTransactionType::Buy,
                     asset: "DOT".to_string(),
                     quantity_transacted: Decimal::new(602, 2),
                     spot_price_currency: "USD".to_string(),
@@ -812,8 +1210,9 @@ mod active_assets_for {
                     notes: "Bought 22.00024 DOT for $122.00 USD".to_string(),
                 },
                 CoinbaseTransactionRecord {
+                    id: None,
                     time_of_transaction: "2021-04-05T21:38:02Z".parse::<DateTime<Utc>>().unwrap(),
-                    transaction_type: "Sell".to_string(),
+                    transaction_type: CoinbaseTransactionType::Sell,
                     asset: "DOT".to_string(),
                     quantity_transacted: Decimal::new(3027, 3),
                     spot_price_currency: "USD".to_string(),
@@ -838,8 +1237,9 @@ mod active_assets_for {
         fn stores_multiple_assets() {
             let sample_vec = vec![
                 CoinbaseTransactionRecord {
+                    id: None,
                     time_of_transaction: "2021-04-01T21:38:02Z".parse::<DateTime<Utc>>().unwrap(),
-                    transaction_type: "Buy".to_string(),
+                    transaction_type: CoinbaseTransactionType::Buy,
                     asset: "DOT".to_string(),
                     quantity_transacted: Decimal::new(2200024, 5),
                     spot_price_currency: "USD".to_string(),
@@ -850,8 +1250,9 @@ mod active_assets_for {
                     notes: "Bought 22.00024 DOT for $122.00 USD".to_string(),
                 },
                 CoinbaseTransactionRecord {
+                    id: None,
                     time_of_transaction: "2021-04-01T21:38:02Z".parse::<DateTime<Utc>>().unwrap(),
-                    transaction_type: "Buy".to_string(),
+                    transaction_type: CoinbaseTransactionType::Buy,
                     asset: "BTC".to_string(),
                     quantity_transacted: Decimal::new(1802442, 5),
                     spot_price_currency: "USD".to_string(),
@@ -892,8 +1293,9 @@ mod active_assets_for {
         fn convert_asset() {
             let sample_vec = vec![
                 CoinbaseTransactionRecord {
+                    id: None,
                     time_of_transaction: "2021-04-01T21:38:02Z".parse::<DateTime<Utc>>().unwrap(),
-                    transaction_type: "Buy".to_string(),
+                    transaction_type: CoinbaseTransactionType::Buy,
                     asset: "BTC".to_string(),
                     quantity_transacted: Decimal::new(1802442, 5),
                     spot_price_currency: "USD".to_string(),
@@ -904,8 +1306,9 @@ mod active_assets_for {
                     notes: "Bought 22.00024 BTC for $122.00 USD".to_string(),
                 },
                 CoinbaseTransactionRecord {
+                    id: None,
                     time_of_transaction: "2021-04-01T21:38:02Z".parse::<DateTime<Utc>>().unwrap(),
-                    transaction_type: "Convert".to_string(),
+                    transaction_type: CoinbaseTransactionType::Convert,
                     asset: "BTC".to_string(),
                     quantity_transacted: Decimal::new(1802442, 5),
                     spot_price_currency: "USD".to_string(),
@@ -932,6 +1335,81 @@ mod active_assets_for {
             assert_eq!(*active_assets.get("BTC").unwrap(), Decimal::zero());
             assert_eq!(*active_assets.get("DOT").unwrap(), Decimal::new(3370245, 4));
         }
+
+        #[test]
+        fn convert_to_asset_containing_to() {
+            let convert = CoinbaseTransactionRecord {
+                id: None,
+                time_of_transaction: "2021-04-01T21:38:02Z".parse::<DateTime<Utc>>().unwrap(),
+                transaction_type: CoinbaseTransactionType::Convert,
+                asset: "BTC".to_string(),
+                quantity_transacted: Decimal::new(2, 0),
+                spot_price_currency: "USD".to_string(),
+                spot_price_at_transaction: Some(Decimal::new(48744, 0)),
+                subtotal: Some(Decimal::new(97488, 0)),
+                total: Some(Decimal::new(97488, 0)),
+                fees: Some(Decimal::zero()),
+                notes: "Converted 2 BTC to 1,337.0245 STORJ".to_string(),
+            };
+
+            let active_assets = CoinbaseParser::new(vec![convert]).active_assets();
+
+            assert_eq!(*active_assets.get("BTC").unwrap(), Decimal::new(-2, 0));
+            assert_eq!(
+                *active_assets.get("STORJ").unwrap(),
+                Decimal::new(13370245, 4)
+            );
+        }
+
+        #[test]
+        fn keep_the_sent_side_of_unreadable_converts() {
+            let convert = CoinbaseTransactionRecord {
+                id: None,
+                time_of_transaction: "2021-04-01T21:38:02Z".parse::<DateTime<Utc>>().unwrap(),
+                transaction_type: CoinbaseTransactionType::Convert,
+                asset: "BTC".to_string(),
+                quantity_transacted: Decimal::new(2, 0),
+                spot_price_currency: "USD".to_string(),
+                spot_price_at_transaction: None,
+                subtotal: None,
+                total: None,
+                fees: None,
+                notes: "Converted to something".to_string(),
+            };
+
+            let active_assets = CoinbaseParser::new(vec![convert]).active_assets();
+
+            assert_eq!(active_assets.len(), 1);
+            assert_eq!(*active_assets.get("BTC").unwrap(), Decimal::new(-2, 0));
+        }
+
+        #[test]
+        fn count_newer_transaction_types_and_skip_unknown_ones() {
+            let record = |transaction_type: &str, quantity: Decimal| CoinbaseTransactionRecord {
+                id: None,
+                time_of_transaction: "2023-04-01T21:38:02Z".parse::<DateTime<Utc>>().unwrap(),
+                transaction_type: CoinbaseTransactionType::from(transaction_type),
+                asset: "ETH".to_string(),
+                quantity_transacted: quantity,
+                spot_price_currency: "USD".to_string(),
+                spot_price_at_transaction: None,
+                subtotal: None,
+                total: None,
+                fees: None,
+                notes: "".to_string(),
+            };
+
+            let active_assets = CoinbaseParser::new(vec![
+                record("Advanced Trade Buy", Decimal::new(3, 0)),
+                record("Staking Income", Decimal::new(1, 1)),
+                record("Inflation Reward", Decimal::new(1, 2)),
+                record("Advanced Trade Sell", Decimal::new(1, 0)),
+                record("Pro Withdrawal", Decimal::new(2, 0)),
+            ])
+            .active_assets();
+
+            assert_eq!(*active_assets.get("ETH").unwrap(), Decimal::new(211, 2));
+        }
     }
 
     #[cfg(test)]
@@ -958,6 +1436,8 @@ mod active_assets_for {
                     total: Some(Decimal::new(2200024, 5) * Decimal::new(605, 2)),
                     fees: Some(Decimal::zero()),
                     notes: "Bought 22.00024 DOT for $122.00 USD".to_string(),
+                    coinbase_id: None,
+                    import_batch_id: None,
                 },
                 CoinbaseTransaction {
                     id: 2029,
@@ -971,6 +1451,8 @@ mod active_assets_for {
                     total: Some(Decimal::new(602, 2) * Decimal::new(605, 2)),
                     fees: Some(Decimal::zero()),
                     notes: "Bought 22.00024 DOT for $122.00 USD".to_string(),
+                    coinbase_id: None,
+                    import_batch_id: None,
                 },
                 CoinbaseTransaction {
                     id: 222,
@@ -984,6 +1466,8 @@ mod active_assets_for {
                     total: Some(Decimal::new(3027, 3)),
                     fees: Some(Decimal::zero()),
                     notes: "Bought 22.00024 DOT for $122.00 USD".to_string(),
+                    coinbase_id: None,
+                    import_batch_id: None,
                 },
             ];
 
@@ -1011,6 +1495,8 @@ mod active_assets_for {
                     total: Some(Decimal::new(2200024, 5) * Decimal::new(605, 2)),
                     fees: Some(Decimal::zero()),
                     notes: "Bought 22.00024 DOT for $122.00 USD".to_string(),
+                    coinbase_id: None,
+                    import_batch_id: None,
                 },
                 CoinbaseTransaction {
                     id: 301,
@@ -1024,6 +1510,8 @@ mod active_assets_for {
                     total: Some(Decimal::new(1802442, 5) * Decimal::new(48744, 0)),
                     fees: Some(Decimal::zero()),
                     notes: "Bought 22.00024 BTC for $122.00 USD".to_string(),
+                    coinbase_id: None,
+                    import_batch_id: None,
                 },
             ];
 
@@ -1067,6 +1555,8 @@ mod active_assets_for {
                     total: Some(Decimal::new(1802442, 5) * Decimal::new(48744, 0)),
                     fees: Some(Decimal::zero()),
                     notes: "Bought 22.00024 BTC for $122.00 USD".to_string(),
+                    coinbase_id: None,
+                    import_batch_id: None,
                 },
                 CoinbaseTransaction {
                     id: 2912,
@@ -1080,6 +1570,8 @@ mod active_assets_for {
                     total: Some(Decimal::new(1802442, 5) * Decimal::new(48744, 0)),
                     fees: Some(Decimal::zero()),
                     notes: "Converted 18.02442 BTC to 337.0245 DOT".to_string(),
+                    coinbase_id: None,
+                    import_batch_id: None,
                 },
             ];
 
@@ -1100,3 +1592,215 @@ mod active_assets_for {
         }
     }
 }
+
+#[cfg(test)]
+mod cost_basis_events_for {
+    mod coinbase_transaction_record {
+        use chrono::{DateTime, Utc};
+        use models::cost_basis::CostBasisEvent;
+        use rust_decimal::Decimal;
+
+        use crate::{
+            CoinbaseParser, CoinbaseTransactionRecord, CoinbaseTransactionType, ConversionError,
+            CostBasisEvents,
+        };
+
+        fn record(transaction_type: &str, asset: &str, notes: &str) -> CoinbaseTransactionRecord {
+            CoinbaseTransactionRecord {
+                id: None,
+                time_of_transaction: "2021-04-01T21:38:02Z".parse::<DateTime<Utc>>().unwrap(),
+                transaction_type: CoinbaseTransactionType::from(transaction_type),
+                asset: asset.to_string(),
+                quantity_transacted: Decimal::new(2, 0),
+                spot_price_currency: "USD".to_string(),
+                spot_price_at_transaction: Some(Decimal::new(50, 0)),
+                subtotal: Some(Decimal::new(100, 0)),
+                total: Some(Decimal::new(98, 0)),
+                fees: Some(Decimal::new(2, 0)),
+                notes: notes.to_string(),
+            }
+        }
+
+        #[test]
+        fn create_disposal_from_sell() {
+            let coinbase_parser =
+                CoinbaseParser::new(vec![record("Sell", "BTC", "Sold 2 BTC for $98.00 USD")]);
+
+            match coinbase_parser.cost_basis_events().as_slice() {
+                [CostBasisEvent::Disposal(disposal)] => {
+                    assert_eq!(disposal.id, "coinbase-0");
+                    assert_eq!(disposal.proceeds, Decimal::new(100, 0));
+                    assert_eq!(disposal.fees, Decimal::new(2, 0));
+                }
+                events => panic!("Unexpected events {events:?}"),
+            }
+        }
+
+        #[test]
+        fn id_events_by_their_coinbase_id() {
+            let coinbase_parser = CoinbaseParser::new(vec![
+                CoinbaseTransactionRecord {
+                    id: Some("65f1b8a2c1d4e3f0a9b8c7d6".to_string()),
+                    ..record("Sell", "BTC", "Sold 2 BTC for $98.00 USD")
+                },
+                record("Sell", "BTC", "Sold 2 BTC for $98.00 USD"),
+            ]);
+
+            match coinbase_parser.cost_basis_events().as_slice() {
+                [CostBasisEvent::Disposal(with_id), CostBasisEvent::Disposal(without_id)] => {
+                    assert_eq!(with_id.id, "coinbase-65f1b8a2c1d4e3f0a9b8c7d6");
+                    assert_eq!(without_id.id, "coinbase-1");
+                }
+                events => panic!("Unexpected events {events:?}"),
+            }
+        }
+
+        #[test]
+        fn create_disposal_and_acquisition_from_convert() {
+            let coinbase_parser = CoinbaseParser::new(vec![record(
+                "Convert",
+                "BTC",
+                "Converted 2 BTC to 1,337.0245 STORJ",
+            )]);
+
+            match coinbase_parser.cost_basis_events().as_slice() {
+                [CostBasisEvent::Disposal(disposal), CostBasisEvent::Acquisition(acquisition)] => {
+                    assert_eq!(disposal.asset, "BTC");
+                    assert_eq!(disposal.quantity, Decimal::new(2, 0));
+                    assert_eq!(acquisition.asset, "STORJ");
+                    assert_eq!(acquisition.quantity, Decimal::new(13370245, 4));
+                    assert_eq!(acquisition.cost, Decimal::new(98, 0));
+                }
+                events => panic!("Unexpected events {events:?}"),
+            }
+        }
+
+        #[test]
+        fn report_converts_whose_notes_can_not_be_read() {
+            let coinbase_parser = CoinbaseParser::new(vec![
+                record("Convert", "BTC", "Converted 2 BTC to 1,337.0245 STORJ"),
+                record("Convert", "BTC", "Swapped 2 BTC"),
+            ]);
+
+            match coinbase_parser.unread_conversions().as_slice() {
+                [unread] => {
+                    assert_eq!(unread.asset, "BTC");
+                    assert_eq!(unread.quantity, Decimal::new(2, 0));
+                    assert_eq!(
+                        unread.error,
+                        ConversionError::UnrecognizedNotes("Swapped 2 BTC".to_string())
+                    );
+                }
+                unread => panic!("Unexpected conversions {unread:?}"),
+            }
+        }
+
+        #[test]
+        fn leave_rewards_to_staking_income() {
+            let coinbase_parser = CoinbaseParser::new(vec![
+                record("Rewards Income", "DOT", "Received 2 DOT"),
+                record("Learning Reward", "GRT", "Received 2 GRT"),
+            ]);
+
+            assert!(coinbase_parser.cost_basis_events().is_empty());
+        }
+
+        #[test]
+        fn report_trades_valued_in_another_currency() {
+            let coinbase_parser = CoinbaseParser::new(vec![
+                record("Buy", "BTC", "Bought 2 BTC for $98.00 USD"),
+                CoinbaseTransactionRecord {
+                    spot_price_currency: "EUR".to_string(),
+                    ..record("Sell", "BTC", "Sold 2 BTC for €98.00 EUR")
+                },
+            ]);
+
+            match coinbase_parser.cost_basis_events().as_slice() {
+                [CostBasisEvent::Acquisition(acquisition)] => assert_eq!(acquisition.asset, "BTC"),
+                events => panic!("Unexpected events {events:?}"),
+            }
+            match coinbase_parser.foreign_currency_transactions().as_slice() {
+                [foreign] => {
+                    assert_eq!(foreign.transaction_type, CoinbaseTransactionType::Sell);
+                    assert_eq!(foreign.currency, "EUR");
+                }
+                foreign => panic!("Unexpected transactions {foreign:?}"),
+            }
+        }
+
+        #[test]
+        fn ignore_transfers() {
+            let coinbase_parser = CoinbaseParser::new(vec![
+                record("Send", "BTC", "Sent 2 BTC"),
+                record("Receive", "BTC", "Received 2 BTC"),
+            ]);
+
+            assert!(coinbase_parser.cost_basis_events().is_empty());
+        }
+    }
+}
+
+#[cfg(test)]
+mod unknown_transaction_types_for {
+    mod coinbase_transaction_record {
+        use chrono::{DateTime, Utc};
+        use rust_decimal::Decimal;
+
+        use crate::{
+            CoinbaseParser, CoinbaseTransactionRecord, CoinbaseTransactionType,
+            UnknownTransactionType, UnknownTransactionTypes,
+        };
+
+        fn record(transaction_type: &str, asset: &str) -> CoinbaseTransactionRecord {
+            CoinbaseTransactionRecord {
+                id: None,
+                time_of_transaction: "2023-04-01T21:38:02Z".parse::<DateTime<Utc>>().unwrap(),
+                transaction_type: CoinbaseTransactionType::from(transaction_type),
+                asset: asset.to_string(),
+                quantity_transacted: Decimal::ONE,
+                spot_price_currency: "USD".to_string(),
+                spot_price_at_transaction: None,
+                subtotal: None,
+                total: None,
+                fees: None,
+                notes: "".to_string(),
+            }
+        }
+
+        #[test]
+        fn report_each_unknown_type_once() {
+            let parser = CoinbaseParser::new(vec![
+                record("Buy", "BTC"),
+                record("Pro Withdrawal", "BTC"),
+                record("Vault Transfer", "ETH"),
+                record("Pro Withdrawal", "USDC"),
+            ]);
+
+            assert_eq!(
+                parser.unknown_transaction_types(),
+                vec![
+                    UnknownTransactionType {
+                        transaction_type: "Pro Withdrawal".to_string(),
+                        occurrences: 2,
+                        assets: vec!["BTC".to_string(), "USDC".to_string()],
+                    },
+                    UnknownTransactionType {
+                        transaction_type: "Vault Transfer".to_string(),
+                        occurrences: 1,
+                        assets: vec!["ETH".to_string()],
+                    },
+                ]
+            );
+        }
+
+        #[test]
+        fn report_nothing_when_every_type_is_known() {
+            let parser = CoinbaseParser::new(vec![
+                record("Staking Income", "ETH"),
+                record("Deposit", "USD"),
+            ]);
+
+            assert!(parser.unknown_transaction_types().is_empty());
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+
+use models::{asset::canonical_ticker, InputTransaction};
+use rust_decimal::Decimal;
+
+pub use models::{
+    coinbase_pro::{CoinbaseProFill, FillSide, CSV_HEADERS},
+    ActiveAssetValues, InputTransactions, RecordsByAsset, StakingRewards,
+};
+
+pub struct CoinbaseProParser<T> {
+    data: Vec<T>,
+}
+
+impl<T> CoinbaseProParser<T> {
+    pub fn new(data: Vec<T>) -> Self {
+        Self { data }
+    }
+}
+
+impl StakingRewards for CoinbaseProParser<CoinbaseProFill> {
+    /// The fills export only holds trades, rewards were paid to the Coinbase account.
+    fn staking_rewards(&self) -> HashMap<String, Decimal> {
+        HashMap::new()
+    }
+}
+
+impl ActiveAssetValues for CoinbaseProParser<CoinbaseProFill> {
+    /// Adds up what every fill bought and sold. The total of a fill already has the fee taken out.
+    /// ```
+    /// # use chrono::{TimeZone, Utc};
+    /// # use models::{
+    /// #   coinbase_pro::{CoinbaseProFill, FillSide},
+    /// #   ActiveAssetValues,
+    /// # };
+    /// # use rust_decimal::Decimal;
+    /// # use coinbase_pro_parser::CoinbaseProParser;
+    /// #
+    /// let bought = CoinbaseProFill {
+    ///     portfolio: "default".to_string(),
+    ///     trade_id: "1".to_string(),
+    ///     product: "BTC-USD".to_string(),
+    ///     side: FillSide::Buy,
+    ///     created_at: Utc.with_ymd_and_hms(2021, 3, 1, 15, 20, 30).unwrap(),
+    ///     size: Decimal::new(1, 1),
+    ///     size_unit: "BTC".to_string(),
+    ///     price: Decimal::new(50000, 0),
+    ///     fee: Decimal::new(25, 0),
+    ///     total: Decimal::new(-5025, 0),
+    ///     price_unit: "USD".to_string(),
+    /// };
+    /// let sold = CoinbaseProFill {
+    ///     trade_id: "2".to_string(),
+    ///     side: FillSide::Sell,
+    ///     size: Decimal::new(4, 2),
+    ///     price: Decimal::new(60000, 0),
+    ///     fee: Decimal::new(12, 0),
+    ///     total: Decimal::new(2388, 0),
+    ///     ..bought.clone()
+    /// };
+    ///
+    /// let active_assets = CoinbaseProParser::new(vec![bought, sold]).active_assets();
+    /// assert_eq!(*active_assets.get("BTC").unwrap(), Decimal::new(6, 2));
+    /// assert_eq!(*active_assets.get("USD").unwrap(), Decimal::new(-2637, 0));
+    /// ```
+    fn active_assets(&self) -> HashMap<String, Decimal> {
+        self.data.iter().fold(HashMap::new(), |mut map, fill| {
+            let size = match fill.side {
+                FillSide::Buy => fill.size,
+                FillSide::Sell => -fill.size,
+            };
+            *map.entry(canonical_ticker(&fill.size_unit))
+                .or_insert(Decimal::ZERO) += size;
+            *map.entry(canonical_ticker(&fill.price_unit))
+                .or_insert(Decimal::ZERO) += fill.total;
+
+            map
+        })
+    }
+}
+
+impl RecordsByAsset<CoinbaseProFill> for CoinbaseProParser<CoinbaseProFill> {
+    /// Fills collected by the asset that was bought or sold.
+    fn by_asset(&self) -> HashMap<String, Vec<&CoinbaseProFill>> {
+        self.data
+            .iter()
+            .fold(HashMap::new(), |mut currency_map, fill| {
+                currency_map
+                    .entry(canonical_ticker(&fill.size_unit))
+                    .or_insert_with(Vec::new)
+                    .push(fill);
+
+                currency_map
+            })
+    }
+}
+
+impl InputTransactions<CoinbaseProFill> for CoinbaseProParser<CoinbaseProFill> {
+    fn input_transactions(&self) -> Vec<&CoinbaseProFill> {
+        self.data
+            .iter()
+            .filter(|fill| fill.is_input_transaction())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod coinbase_pro_parser_should {
+    use rust_decimal::Decimal;
+
+    use crate::{
+        CoinbaseProFill, CoinbaseProParser, FillSide, InputTransactions, RecordsByAsset,
+        StakingRewards,
+    };
+
+    const FILLS: &str = "portfolio,trade id,product,side,created at,size,size unit,price,fee,total,price/fee/total unit\n\
+        default,1,ETH-USD,BUY,2021-03-01T15:20:30.123Z,0.5,ETH,1500.00,3.75,-753.75,USD\n\
+        default,2,ETH-BTC,SELL,2021-03-02T10:00:00.000Z,0.25,ETH,0.031,0.00001,0.00774,BTC\n";
+
+    fn fills() -> Vec<CoinbaseProFill> {
+        csv::Reader::from_reader(FILLS.as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn read_the_fills_export() {
+        let fills = fills();
+
+        assert_eq!(fills[0].side, FillSide::Buy);
+        assert_eq!(fills[0].total, Decimal::new(-75375, 2));
+        assert_eq!(fills[1].side, FillSide::Sell);
+        assert_eq!(fills[1].price_unit, "BTC");
+    }
+
+    #[test]
+    fn find_buys_as_inputs() {
+        let coinbase_pro_parser = CoinbaseProParser::new(fills());
+        let inputs = coinbase_pro_parser.input_transactions();
+
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0].trade_id, "1");
+    }
+
+    #[test]
+    fn group_fills_by_the_asset_traded() {
+        let coinbase_pro_parser = CoinbaseProParser::new(fills());
+
+        assert_eq!(coinbase_pro_parser.by_asset().get("ETH").unwrap().len(), 2);
+        assert!(coinbase_pro_parser.staking_rewards().is_empty());
+    }
+}
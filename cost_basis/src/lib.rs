@@ -0,0 +1,836 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+};
+
+use chrono::{Datelike, Duration, Months};
+use rust_decimal::Decimal;
+
+pub use models::{
+    cost_basis::{
+        Acquisition, AssetGainSummary, CostBasisEvent, CostBasisReport, CostBasisStrategy,
+        Disposal, GainTotals, HoldingPeriod, RealizedGain, TaxLot, TaxYearSummary,
+    },
+    income::{IncomePeriodTotal, IncomeRecord, IncomeReport},
+    CostBasisEvents, HistoricalPrice, StakingIncome,
+};
+
+pub struct CostBasisCalculator {
+    strategy: CostBasisStrategy,
+    /// Days a lot must be held before its gains are long term, more than one calendar year when not given.
+    long_term_threshold: Option<Duration>,
+}
+
+impl CostBasisCalculator {
+    pub fn new(strategy: CostBasisStrategy) -> Self {
+        Self {
+            strategy,
+            long_term_threshold: None,
+        }
+    }
+
+    /// Overrides the number of days a lot must be held before its gains are long term.
+    pub fn with_long_term_threshold(mut self, days: i64) -> Self {
+        self.long_term_threshold = Some(Duration::days(days));
+        self
+    }
+
+    /// Lots held for more than one year are long term, anything else is short term. The year is counted in calendar
+    /// dates so a lot sold on the anniversary of its purchase is still short term, leap day or not.
+    pub fn holding_period(&self, lot: &TaxLot, disposal: &Disposal) -> HoldingPeriod {
+        let long_term = match self.long_term_threshold {
+            Some(threshold) => disposal.time - lot.acquired_at > threshold,
+            None => lot
+                .acquired_at
+                .date_naive()
+                .checked_add_months(Months::new(12))
+                .is_some_and(|anniversary| disposal.time.date_naive() > anniversary),
+        };
+
+        match long_term {
+            true => HoldingPeriod::LongTerm,
+            false => HoldingPeriod::ShortTerm,
+        }
+    }
+
+    /// Replays the events in time order, creating lots from acquisitions and matching each disposal against them.
+    /// ```
+    /// # use chrono::{DateTime, Utc};
+    /// # use rust_decimal::Decimal;
+    /// # use cost_basis::{Acquisition, CostBasisCalculator, CostBasisEvent, CostBasisStrategy, Disposal};
+    /// let events = vec![
+    ///     CostBasisEvent::Acquisition(Acquisition {
+    ///         id: "buy".to_string(),
+    ///         asset: "BTC".to_string(),
+    ///         quantity: Decimal::new(2, 0),
+    ///         cost: Decimal::new(100, 0),
+    ///         fees: Decimal::ZERO,
+    ///         time: "2021-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap(),
+    ///     }),
+    ///     CostBasisEvent::Disposal(Disposal {
+    ///         id: "sell".to_string(),
+    ///         asset: "BTC".to_string(),
+    ///         quantity: Decimal::new(1, 0),
+    ///         proceeds: Decimal::new(80, 0),
+    ///         fees: Decimal::new(5, 0),
+    ///         time: "2021-06-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap(),
+    ///     }),
+    /// ];
+    ///
+    /// let report = CostBasisCalculator::new(CostBasisStrategy::Fifo).calculate(events);
+    /// let gain = report.realized_gains.first().unwrap();
+    /// assert_eq!(gain.cost_basis, Decimal::new(50, 0));
+    /// assert_eq!(gain.gain, Decimal::new(25, 0));
+    /// assert_eq!(report.open_lots.first().unwrap().remaining, Decimal::new(1, 0));
+    /// ```
+    pub fn calculate(&self, events: impl IntoIterator<Item = CostBasisEvent>) -> CostBasisReport {
+        let mut events: Vec<CostBasisEvent> = events.into_iter().collect();
+        // Acquisitions sort ahead of disposals made at the same instant so they can be matched.
+        events.sort_by(|a, b| {
+            a.time().cmp(b.time()).then_with(|| match (a, b) {
+                (CostBasisEvent::Acquisition(_), CostBasisEvent::Disposal(_)) => Ordering::Less,
+                (CostBasisEvent::Disposal(_), CostBasisEvent::Acquisition(_)) => Ordering::Greater,
+                _ => Ordering::Equal,
+            })
+        });
+
+        let mut report = CostBasisReport::default();
+        let mut lots: HashMap<String, Vec<TaxLot>> = HashMap::new();
+
+        for event in events {
+            match event {
+                CostBasisEvent::Acquisition(acquisition) => lots
+                    .entry(acquisition.asset.to_string())
+                    .or_default()
+                    .push(TaxLot::from(&acquisition)),
+                CostBasisEvent::Disposal(disposal) => {
+                    let asset_lots = lots.entry(disposal.asset.to_string()).or_default();
+                    self.dispose(asset_lots, disposal, &mut report);
+                }
+            }
+        }
+
+        report.open_lots = lots
+            .into_values()
+            .flatten()
+            .filter(|lot| lot.remaining > Decimal::ZERO)
+            .collect();
+        report.open_lots.sort_by_key(|lot| lot.acquired_at);
+
+        report
+    }
+
+    fn dispose(&self, lots: &mut [TaxLot], disposal: Disposal, report: &mut CostBasisReport) {
+        if disposal.quantity <= Decimal::ZERO {
+            return;
+        }
+
+        let mut remaining = disposal.quantity;
+        while remaining > Decimal::ZERO {
+            let Some(index) = self.next_lot(lots, &disposal) else {
+                break;
+            };
+            let holding_period = self.holding_period(&lots[index], &disposal);
+            let lot = &mut lots[index];
+
+            let quantity = remaining.min(lot.remaining);
+            let cost_basis = lot.cost * quantity / lot.quantity;
+            let proceeds = disposal.proceeds * quantity / disposal.quantity;
+            let fees = disposal.fees * quantity / disposal.quantity;
+
+            report.realized_gains.push(RealizedGain {
+                asset: disposal.asset.to_string(),
+                lot_id: lot.id.to_string(),
+                disposal_id: disposal.id.to_string(),
+                quantity,
+                acquired_at: lot.acquired_at,
+                disposed_at: disposal.time,
+                proceeds,
+                cost_basis,
+                fees,
+                gain: proceeds - fees - cost_basis,
+                holding_period,
+            });
+
+            lot.remaining -= quantity;
+            remaining -= quantity;
+        }
+
+        if remaining > Decimal::ZERO {
+            report.unmatched_disposals.push(Disposal {
+                quantity: remaining,
+                proceeds: disposal.proceeds * remaining / disposal.quantity,
+                fees: disposal.fees * remaining / disposal.quantity,
+                ..disposal
+            });
+        }
+    }
+
+    fn next_lot(&self, lots: &[TaxLot], disposal: &Disposal) -> Option<usize> {
+        let available = lots
+            .iter()
+            .enumerate()
+            .filter(|(_, lot)| lot.remaining > Decimal::ZERO);
+
+        match &self.strategy {
+            CostBasisStrategy::Fifo => available
+                .min_by_key(|(_, lot)| lot.acquired_at)
+                .map(|(index, _)| index),
+            CostBasisStrategy::Lifo => available
+                .max_by_key(|(_, lot)| lot.acquired_at)
+                .map(|(index, _)| index),
+            CostBasisStrategy::Hifo => available
+                .max_by(|(_, a), (_, b)| a.unit_cost().cmp(&b.unit_cost()))
+                .map(|(index, _)| index),
+            CostBasisStrategy::SpecificId(selections) => selections
+                .get(&disposal.id)
+                .and_then(|lot_ids| {
+                    available
+                        .clone()
+                        .find(|(_, lot)| lot_ids.contains(&lot.id))
+                        .map(|(index, _)| index)
+                })
+                .or_else(|| {
+                    available
+                        .min_by_key(|(_, lot)| lot.acquired_at)
+                        .map(|(index, _)| index)
+                }),
+        }
+    }
+}
+
+/// Totals realized gains by the year they were disposed, split into short and long term and broken down per asset.
+/// ```
+/// # use chrono::{DateTime, Utc};
+/// # use rust_decimal::Decimal;
+/// # use cost_basis::{tax_year_summaries, HoldingPeriod, RealizedGain};
+/// let gain = RealizedGain {
+///     asset: "BTC".to_string(),
+///     lot_id: "buy".to_string(),
+///     disposal_id: "sell".to_string(),
+///     quantity: Decimal::new(1, 0),
+///     acquired_at: "2020-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap(),
+///     disposed_at: "2021-06-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap(),
+///     proceeds: Decimal::new(80, 0),
+///     cost_basis: Decimal::new(50, 0),
+///     fees: Decimal::ZERO,
+///     gain: Decimal::new(30, 0),
+///     holding_period: HoldingPeriod::LongTerm,
+/// };
+///
+/// let summaries = tax_year_summaries(&[gain]);
+/// let summary = summaries.first().unwrap();
+/// assert_eq!(summary.tax_year, 2021);
+/// assert_eq!(summary.long_term.gain, Decimal::new(30, 0));
+/// assert_eq!(summary.short_term.gain, Decimal::ZERO);
+/// assert_eq!(summary.assets.first().unwrap().asset, "BTC");
+/// ```
+pub fn tax_year_summaries(gains: &[RealizedGain]) -> Vec<TaxYearSummary> {
+    gains
+        .iter()
+        .fold(
+            BTreeMap::new(),
+            |mut years: BTreeMap<i32, BTreeMap<&str, AssetGainSummary>>, gain| {
+                let asset_summary = years
+                    .entry(gain.disposed_at.year())
+                    .or_default()
+                    .entry(gain.asset.as_str())
+                    .or_insert_with(|| AssetGainSummary {
+                        asset: gain.asset.to_string(),
+                        ..Default::default()
+                    });
+
+                match gain.holding_period {
+                    HoldingPeriod::ShortTerm => asset_summary.short_term.add(gain),
+                    HoldingPeriod::LongTerm => asset_summary.long_term.add(gain),
+                }
+
+                years
+            },
+        )
+        .into_iter()
+        .map(|(tax_year, assets)| {
+            let assets: Vec<AssetGainSummary> = assets.into_values().collect();
+            let mut summary = TaxYearSummary {
+                tax_year,
+                ..Default::default()
+            };
+
+            for asset in &assets {
+                summary.short_term.combine(&asset.short_term);
+                summary.long_term.combine(&asset.long_term);
+            }
+            summary.assets = assets;
+
+            summary
+        })
+        .collect()
+}
+
+/// Orders rewards by the time they were received and totals their USD value per month and per year.
+/// ```
+/// # use chrono::{DateTime, Utc};
+/// # use rust_decimal::Decimal;
+/// # use cost_basis::{income_report, IncomeRecord};
+/// let reward = |received_at: &str, usd_value: Option<Decimal>| IncomeRecord {
+///     id: received_at.to_string(),
+///     asset: "DOT".to_string(),
+///     quantity: Decimal::new(1, 0),
+///     received_at: received_at.parse::<DateTime<Utc>>().unwrap(),
+///     usd_price: usd_value,
+///     usd_value,
+/// };
+///
+/// let report = income_report(vec![
+///     reward("2021-05-02T00:00:00Z", Some(Decimal::new(3, 0))),
+///     reward("2021-04-01T00:00:00Z", Some(Decimal::new(2, 0))),
+///     reward("2021-04-03T00:00:00Z", None),
+/// ]);
+///
+/// assert_eq!(report.records.len(), 3);
+/// assert_eq!(report.monthly_totals.len(), 2);
+/// assert_eq!(report.monthly_totals.first().unwrap().period, "2021-04");
+/// assert_eq!(report.monthly_totals.first().unwrap().usd_value, Decimal::new(2, 0));
+/// assert_eq!(report.yearly_totals.first().unwrap().usd_value, Decimal::new(5, 0));
+/// assert_eq!(report.unpriced.len(), 1);
+/// ```
+pub fn income_report(mut records: Vec<IncomeRecord>) -> IncomeReport {
+    records.sort_by_key(|record| record.received_at);
+
+    let (monthly, yearly) = records.iter().fold(
+        (BTreeMap::new(), BTreeMap::new()),
+        |(mut monthly, mut yearly), record| {
+            let usd_value = record.usd_value.unwrap_or_default();
+            *monthly
+                .entry(record.received_at.format("%Y-%m").to_string())
+                .or_insert(Decimal::ZERO) += usd_value;
+            *yearly
+                .entry(record.received_at.format("%Y").to_string())
+                .or_insert(Decimal::ZERO) += usd_value;
+
+            (monthly, yearly)
+        },
+    );
+
+    IncomeReport {
+        unpriced: records
+            .iter()
+            .filter(|record| record.usd_value.is_none())
+            .cloned()
+            .collect(),
+        records,
+        monthly_totals: period_totals(monthly),
+        yearly_totals: period_totals(yearly),
+    }
+}
+
+fn period_totals(totals: BTreeMap<String, Decimal>) -> Vec<IncomePeriodTotal> {
+    totals
+        .into_iter()
+        .map(|(period, usd_value)| IncomePeriodTotal { period, usd_value })
+        .collect()
+}
+
+pub mod form_8949 {
+    use std::io::Write;
+
+    use csv::WriterBuilder;
+    use rust_decimal::Decimal;
+    use serde::Serialize;
+
+    use crate::{HoldingPeriod, RealizedGain};
+
+    pub const DATE_FORMAT: &str = "%m/%d/%Y";
+
+    /// Adjustment code for selling expenses that were not already subtracted from proceeds.
+    pub const SELLING_EXPENSE_CODE: &str = "E";
+
+    #[derive(Debug, Serialize, PartialEq, Eq, Clone, Copy)]
+    pub enum Form8949Part {
+        /// Short term transactions.
+        #[serde(rename = "Part I")]
+        PartI,
+        /// Long term transactions.
+        #[serde(rename = "Part II")]
+        PartII,
+        /// Totals carried over to Schedule D.
+        #[serde(rename = "Schedule D")]
+        ScheduleD,
+    }
+
+    #[derive(Debug, Serialize, PartialEq, Eq, Clone)]
+    pub struct Form8949Row {
+        #[serde(rename = "Part")]
+        pub part: Form8949Part,
+        #[serde(rename = "(a) Description of property")]
+        pub description: String,
+        #[serde(rename = "(b) Date acquired")]
+        pub date_acquired: String,
+        #[serde(rename = "(c) Date sold or disposed of")]
+        pub date_sold: String,
+        #[serde(rename = "(d) Proceeds")]
+        pub proceeds: Decimal,
+        #[serde(rename = "(e) Cost or other basis")]
+        pub cost_basis: Decimal,
+        #[serde(rename = "(f) Code")]
+        pub adjustment_code: String,
+        #[serde(rename = "(g) Amount of adjustment")]
+        pub adjustment: Decimal,
+        #[serde(rename = "(h) Gain or (loss)")]
+        pub gain: Decimal,
+    }
+
+    impl From<&RealizedGain> for Form8949Row {
+        fn from(gain: &RealizedGain) -> Self {
+            let proceeds = to_cents(gain.proceeds);
+            let cost_basis = to_cents(gain.cost_basis);
+            let adjustment = to_cents(Decimal::ZERO - gain.fees);
+
+            Self {
+                part: match gain.holding_period {
+                    HoldingPeriod::ShortTerm => Form8949Part::PartI,
+                    HoldingPeriod::LongTerm => Form8949Part::PartII,
+                },
+                description: format!("{} {}", gain.quantity.normalize(), gain.asset),
+                date_acquired: gain.acquired_at.format(DATE_FORMAT).to_string(),
+                date_sold: gain.disposed_at.format(DATE_FORMAT).to_string(),
+                proceeds,
+                cost_basis,
+                adjustment_code: match adjustment.is_zero() {
+                    true => String::new(),
+                    false => SELLING_EXPENSE_CODE.to_string(),
+                },
+                adjustment,
+                gain: proceeds - cost_basis + adjustment,
+            }
+        }
+    }
+
+    #[derive(Debug, PartialEq, Eq, Clone)]
+    pub struct Form8949 {
+        pub short_term: Vec<Form8949Row>,
+        pub long_term: Vec<Form8949Row>,
+    }
+
+    impl Form8949 {
+        /// Groups realized gains into Part I and Part II rows ordered by the date they were sold.
+        pub fn new(gains: &[RealizedGain]) -> Self {
+            let mut gains: Vec<&RealizedGain> = gains.iter().collect();
+            gains.sort_by_key(|gain| gain.disposed_at);
+
+            let (short_term, long_term) = gains
+                .into_iter()
+                .map(Form8949Row::from)
+                .partition(|row| row.part == Form8949Part::PartI);
+
+            Self {
+                short_term,
+                long_term,
+            }
+        }
+
+        /// The Schedule D totals for short term and long term transactions, in that order.
+        pub fn schedule_d_totals(&self) -> [Form8949Row; 2] {
+            [
+                total_row("Short-term totals", &self.short_term),
+                total_row("Long-term totals", &self.long_term),
+            ]
+        }
+
+        /// Writes Part I, Part II and the Schedule D totals as a single csv.
+        /// ```
+        /// # use chrono::{DateTime, Utc};
+        /// # use rust_decimal::Decimal;
+        /// # use cost_basis::{form_8949::Form8949, HoldingPeriod, RealizedGain};
+        /// let gain = RealizedGain {
+        ///     asset: "BTC".to_string(),
+        ///     lot_id: "buy".to_string(),
+        ///     disposal_id: "sell".to_string(),
+        ///     quantity: Decimal::new(15, 1),
+        ///     acquired_at: "2020-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap(),
+        ///     disposed_at: "2021-06-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap(),
+        ///     proceeds: Decimal::new(80, 0),
+        ///     cost_basis: Decimal::new(50, 0),
+        ///     fees: Decimal::new(1, 0),
+        ///     gain: Decimal::new(29, 0),
+        ///     holding_period: HoldingPeriod::LongTerm,
+        /// };
+        ///
+        /// let mut csv = Vec::new();
+        /// Form8949::new(&[gain]).write_csv(&mut csv).unwrap();
+        /// let csv = String::from_utf8(csv).unwrap();
+        ///
+        /// assert!(csv.contains("Part II,1.5 BTC,01/01/2020,06/01/2021,80.00,50.00,E,-1.00,29.00"));
+        /// assert!(csv.contains("Schedule D,Long-term totals,,,80.00,50.00,,-1.00,29.00"));
+        /// ```
+        pub fn write_csv<W: Write>(&self, writer: W) -> Result<(), csv::Error> {
+            let mut csv_writer = WriterBuilder::new().from_writer(writer);
+
+            for row in self
+                .short_term
+                .iter()
+                .chain(self.long_term.iter())
+                .chain(self.schedule_d_totals().iter())
+            {
+                csv_writer.serialize(row)?;
+            }
+
+            csv_writer.flush()?;
+            Ok(())
+        }
+    }
+
+    fn to_cents(value: Decimal) -> Decimal {
+        let mut cents = value.round_dp(2);
+        cents.rescale(2);
+        cents
+    }
+
+    fn total_row(description: &str, rows: &[Form8949Row]) -> Form8949Row {
+        Form8949Row {
+            part: Form8949Part::ScheduleD,
+            description: description.to_string(),
+            date_acquired: String::new(),
+            date_sold: String::new(),
+            proceeds: rows.iter().map(|row| row.proceeds).sum(),
+            cost_basis: rows.iter().map(|row| row.cost_basis).sum(),
+            adjustment_code: String::new(),
+            adjustment: rows.iter().map(|row| row.adjustment).sum(),
+            gain: rows.iter().map(|row| row.gain).sum(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod calculate_should {
+    use std::collections::HashMap;
+
+    use chrono::{DateTime, Utc};
+    use rust_decimal::Decimal;
+
+    use crate::{Acquisition, CostBasisCalculator, CostBasisEvent, CostBasisStrategy, Disposal};
+
+    fn acquisition(id: &str, quantity: i64, cost: i64, time: &str) -> CostBasisEvent {
+        CostBasisEvent::Acquisition(Acquisition {
+            id: id.to_string(),
+            asset: "BTC".to_string(),
+            quantity: Decimal::new(quantity, 0),
+            cost: Decimal::new(cost, 0),
+            fees: Decimal::ZERO,
+            time: time.parse::<DateTime<Utc>>().unwrap(),
+        })
+    }
+
+    fn disposal(id: &str, quantity: i64, proceeds: i64, time: &str) -> CostBasisEvent {
+        CostBasisEvent::Disposal(Disposal {
+            id: id.to_string(),
+            asset: "BTC".to_string(),
+            quantity: Decimal::new(quantity, 0),
+            proceeds: Decimal::new(proceeds, 0),
+            fees: Decimal::ZERO,
+            time: time.parse::<DateTime<Utc>>().unwrap(),
+        })
+    }
+
+    fn sample_events() -> Vec<CostBasisEvent> {
+        vec![
+            acquisition("first", 1, 10, "2021-01-01T00:00:00Z"),
+            acquisition("expensive", 1, 50, "2021-02-01T00:00:00Z"),
+            acquisition("last", 1, 30, "2021-03-01T00:00:00Z"),
+            disposal("sell", 1, 40, "2021-04-01T00:00:00Z"),
+        ]
+    }
+
+    #[test]
+    fn match_oldest_lot_with_fifo() {
+        let report = CostBasisCalculator::new(CostBasisStrategy::Fifo).calculate(sample_events());
+
+        let gain = report.realized_gains.first().unwrap();
+        assert_eq!(gain.lot_id, "first");
+        assert_eq!(gain.gain, Decimal::new(30, 0));
+        assert_eq!(report.open_lots.len(), 2);
+    }
+
+    #[test]
+    fn match_newest_lot_with_lifo() {
+        let report = CostBasisCalculator::new(CostBasisStrategy::Lifo).calculate(sample_events());
+
+        let gain = report.realized_gains.first().unwrap();
+        assert_eq!(gain.lot_id, "last");
+        assert_eq!(gain.gain, Decimal::new(10, 0));
+    }
+
+    #[test]
+    fn match_highest_cost_lot_with_hifo() {
+        let report = CostBasisCalculator::new(CostBasisStrategy::Hifo).calculate(sample_events());
+
+        let gain = report.realized_gains.first().unwrap();
+        assert_eq!(gain.lot_id, "expensive");
+        assert_eq!(gain.gain, Decimal::new(-10, 0));
+    }
+
+    #[test]
+    fn match_selected_lot_with_specific_id() {
+        let selections = HashMap::from([("sell".to_string(), vec!["last".to_string()])]);
+        let report = CostBasisCalculator::new(CostBasisStrategy::SpecificId(selections))
+            .calculate(sample_events());
+
+        let gain = report.realized_gains.first().unwrap();
+        assert_eq!(gain.lot_id, "last");
+    }
+
+    #[test]
+    fn split_disposal_across_lots() {
+        let events = vec![
+            acquisition("first", 1, 10, "2021-01-01T00:00:00Z"),
+            acquisition("second", 2, 40, "2021-02-01T00:00:00Z"),
+            disposal("sell", 2, 60, "2021-04-01T00:00:00Z"),
+        ];
+
+        let report = CostBasisCalculator::new(CostBasisStrategy::Fifo).calculate(events);
+
+        assert_eq!(report.realized_gains.len(), 2);
+        let first = report.realized_gains.first().unwrap();
+        let second = report.realized_gains.get(1).unwrap();
+        assert_eq!(first.lot_id, "first");
+        assert_eq!(first.proceeds, Decimal::new(30, 0));
+        assert_eq!(first.cost_basis, Decimal::new(10, 0));
+        assert_eq!(second.lot_id, "second");
+        assert_eq!(second.proceeds, Decimal::new(30, 0));
+        assert_eq!(second.cost_basis, Decimal::new(20, 0));
+        assert_eq!(
+            report.open_lots.first().unwrap().remaining,
+            Decimal::new(1, 0)
+        );
+    }
+
+    #[test]
+    fn report_disposals_without_lots() {
+        let events = vec![
+            acquisition("first", 1, 10, "2021-01-01T00:00:00Z"),
+            disposal("sell", 3, 60, "2021-04-01T00:00:00Z"),
+        ];
+
+        let report = CostBasisCalculator::new(CostBasisStrategy::Fifo).calculate(events);
+
+        assert_eq!(report.realized_gains.len(), 1);
+        let unmatched = report.unmatched_disposals.first().unwrap();
+        assert_eq!(unmatched.quantity, Decimal::new(2, 0));
+        assert_eq!(unmatched.proceeds, Decimal::new(40, 0));
+        assert!(report.open_lots.is_empty());
+    }
+
+    #[test]
+    fn ignore_lots_acquired_after_disposal() {
+        let events = vec![
+            disposal("sell", 1, 60, "2021-04-01T00:00:00Z"),
+            acquisition("later", 1, 10, "2021-05-01T00:00:00Z"),
+        ];
+
+        let report = CostBasisCalculator::new(CostBasisStrategy::Fifo).calculate(events);
+
+        assert!(report.realized_gains.is_empty());
+        assert_eq!(report.unmatched_disposals.len(), 1);
+        assert_eq!(report.open_lots.len(), 1);
+    }
+}
+
+#[cfg(test)]
+mod holding_period_should {
+    use chrono::{DateTime, Utc};
+    use rust_decimal::Decimal;
+
+    use crate::{
+        tax_year_summaries, Acquisition, CostBasisCalculator, CostBasisEvent, CostBasisStrategy,
+        Disposal, HoldingPeriod,
+    };
+
+    fn acquisition(id: &str, asset: &str, time: &str) -> CostBasisEvent {
+        CostBasisEvent::Acquisition(Acquisition {
+            id: id.to_string(),
+            asset: asset.to_string(),
+            quantity: Decimal::new(1, 0),
+            cost: Decimal::new(10, 0),
+            fees: Decimal::ZERO,
+            time: time.parse::<DateTime<Utc>>().unwrap(),
+        })
+    }
+
+    fn disposal(id: &str, asset: &str, quantity: i64, time: &str) -> CostBasisEvent {
+        CostBasisEvent::Disposal(Disposal {
+            id: id.to_string(),
+            asset: asset.to_string(),
+            quantity: Decimal::new(quantity, 0),
+            proceeds: Decimal::new(30 * quantity, 0),
+            fees: Decimal::ZERO,
+            time: time.parse::<DateTime<Utc>>().unwrap(),
+        })
+    }
+
+    #[test]
+    fn split_disposal_spanning_lots_of_different_ages() {
+        let events = vec![
+            acquisition("old", "BTC", "2020-01-01T00:00:00Z"),
+            acquisition("new", "BTC", "2021-03-01T00:00:00Z"),
+            disposal("sell", "BTC", 2, "2021-06-01T00:00:00Z"),
+        ];
+
+        let report = CostBasisCalculator::new(CostBasisStrategy::Fifo).calculate(events);
+
+        let periods: Vec<HoldingPeriod> = report
+            .realized_gains
+            .iter()
+            .map(|gain| gain.holding_period)
+            .collect();
+        assert_eq!(
+            periods,
+            vec![HoldingPeriod::LongTerm, HoldingPeriod::ShortTerm]
+        );
+    }
+
+    #[test]
+    fn treat_exactly_threshold_as_short_term() {
+        let events = vec![
+            acquisition("buy", "BTC", "2021-01-01T00:00:00Z"),
+            disposal("sell", "BTC", 1, "2022-01-01T00:00:00Z"),
+        ];
+
+        let report = CostBasisCalculator::new(CostBasisStrategy::Fifo).calculate(events);
+
+        assert_eq!(
+            report.realized_gains.first().unwrap().holding_period,
+            HoldingPeriod::ShortTerm
+        );
+    }
+
+    #[test]
+    fn count_a_year_in_calendar_dates_across_a_leap_day() {
+        let events = vec![
+            acquisition("first", "BTC", "2020-01-15T18:00:00Z"),
+            acquisition("second", "BTC", "2020-01-15T18:00:00Z"),
+            disposal("anniversary", "BTC", 1, "2021-01-15T23:00:00Z"),
+            disposal("day-after", "BTC", 1, "2021-01-16T00:00:00Z"),
+        ];
+
+        let report = CostBasisCalculator::new(CostBasisStrategy::Fifo).calculate(events);
+
+        let periods: Vec<HoldingPeriod> = report
+            .realized_gains
+            .iter()
+            .map(|gain| gain.holding_period)
+            .collect();
+        assert_eq!(
+            periods,
+            vec![HoldingPeriod::ShortTerm, HoldingPeriod::LongTerm]
+        );
+    }
+
+    #[test]
+    fn use_configured_threshold() {
+        let events = vec![
+            acquisition("buy", "BTC", "2021-01-01T00:00:00Z"),
+            disposal("sell", "BTC", 1, "2021-03-01T00:00:00Z"),
+        ];
+
+        let report = CostBasisCalculator::new(CostBasisStrategy::Fifo)
+            .with_long_term_threshold(30)
+            .calculate(events);
+
+        assert_eq!(
+            report.realized_gains.first().unwrap().holding_period,
+            HoldingPeriod::LongTerm
+        );
+    }
+
+    #[test]
+    fn summarize_by_tax_year_and_asset() {
+        let events = vec![
+            acquisition("btc-old", "BTC", "2019-01-01T00:00:00Z"),
+            acquisition("btc-new", "BTC", "2020-11-01T00:00:00Z"),
+            acquisition("eth", "ETH", "2020-11-01T00:00:00Z"),
+            disposal("btc-2020", "BTC", 1, "2020-12-01T00:00:00Z"),
+            disposal("btc-2021", "BTC", 1, "2021-01-01T00:00:00Z"),
+            disposal("eth-2021", "ETH", 1, "2021-02-01T00:00:00Z"),
+        ];
+
+        let report = CostBasisCalculator::new(CostBasisStrategy::Fifo).calculate(events);
+        let summaries = tax_year_summaries(&report.realized_gains);
+
+        assert_eq!(summaries.len(), 2);
+        let first_year = summaries.first().unwrap();
+        assert_eq!(first_year.tax_year, 2020);
+        assert_eq!(first_year.long_term.gain, Decimal::new(20, 0));
+        assert_eq!(first_year.short_term.gain, Decimal::ZERO);
+
+        let second_year = summaries.get(1).unwrap();
+        assert_eq!(second_year.tax_year, 2021);
+        assert_eq!(second_year.short_term.gain, Decimal::new(40, 0));
+        assert_eq!(second_year.assets.len(), 2);
+        assert_eq!(second_year.assets.first().unwrap().asset, "BTC");
+        assert_eq!(second_year.assets.get(1).unwrap().asset, "ETH");
+    }
+}
+
+#[cfg(test)]
+mod form_8949_should {
+    use chrono::{DateTime, Utc};
+    use rust_decimal::Decimal;
+
+    use crate::{
+        form_8949::{Form8949, Form8949Part},
+        HoldingPeriod, RealizedGain,
+    };
+
+    fn gain(disposed_at: &str, holding_period: HoldingPeriod, gain: i64) -> RealizedGain {
+        RealizedGain {
+            asset: "ETH".to_string(),
+            lot_id: "buy".to_string(),
+            disposal_id: "sell".to_string(),
+            quantity: Decimal::new(1, 0),
+            acquired_at: "2020-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap(),
+            disposed_at: disposed_at.parse::<DateTime<Utc>>().unwrap(),
+            proceeds: Decimal::new(100 + gain, 0),
+            cost_basis: Decimal::new(100, 0),
+            fees: Decimal::ZERO,
+            gain: Decimal::new(gain, 0),
+            holding_period,
+        }
+    }
+
+    #[test]
+    fn group_rows_by_holding_period() {
+        let gains = vec![
+            gain("2021-03-01T00:00:00Z", HoldingPeriod::LongTerm, 10),
+            gain("2020-02-01T00:00:00Z", HoldingPeriod::ShortTerm, -5),
+            gain("2021-02-01T00:00:00Z", HoldingPeriod::LongTerm, 20),
+        ];
+
+        let form = Form8949::new(&gains);
+
+        assert_eq!(form.short_term.len(), 1);
+        assert_eq!(form.long_term.len(), 2);
+        assert!(form
+            .short_term
+            .iter()
+            .all(|row| row.part == Form8949Part::PartI));
+        assert_eq!(form.long_term.first().unwrap().date_sold, "02/01/2021");
+        assert_eq!(form.long_term.get(1).unwrap().date_sold, "03/01/2021");
+    }
+
+    #[test]
+    fn total_schedule_d() {
+        let gains = vec![
+            gain("2021-03-01T00:00:00Z", HoldingPeriod::LongTerm, 10),
+            gain("2020-02-01T00:00:00Z", HoldingPeriod::ShortTerm, -5),
+            gain("2021-02-01T00:00:00Z", HoldingPeriod::LongTerm, 20),
+        ];
+
+        let [short_term, long_term] = Form8949::new(&gains).schedule_d_totals();
+
+        assert_eq!(short_term.part, Form8949Part::ScheduleD);
+        assert_eq!(short_term.gain, Decimal::new(-5, 0));
+        assert_eq!(long_term.proceeds, Decimal::new(230, 0));
+        assert_eq!(long_term.cost_basis, Decimal::new(200, 0));
+        assert_eq!(long_term.gain, Decimal::new(30, 0));
+    }
+}
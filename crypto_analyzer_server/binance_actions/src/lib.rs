@@ -0,0 +1,84 @@
+use crypto_database::binance_db::{self, BinanceTransaction, NewBinanceTransaction, Pagination};
+use diesel::PgConnection;
+use server_response::ServerResponse;
+use uuid::Uuid;
+
+pub fn get_binance_transaction(
+    id: i32,
+    connection: &mut PgConnection,
+) -> ServerResponse<BinanceTransaction> {
+    let binance_transaction = binance_db::get_binance_transaction(id, connection);
+
+    let messages = binance_transaction.as_ref().map_or(None, |transaction| {
+        Some(vec![format!(
+            "Found binance transaction with id: {}",
+            &transaction.id
+        )])
+    });
+    let errors = match binance_transaction.as_ref() {
+        Ok(_) => None,
+        Err(e) => Some(vec![format!("{}", e)]),
+    };
+
+    ServerResponse::new(
+        Some(Uuid::new_v4()),
+        binance_transaction.is_ok(),
+        binance_transaction.ok(),
+        messages,
+        errors,
+    )
+}
+
+pub fn get_binance_transactions(
+    pagination: Pagination,
+    connection: &mut PgConnection,
+) -> ServerResponse<Vec<BinanceTransaction>> {
+    let binance_transactions = binance_db::get_binance_transactions(&pagination, connection);
+
+    let messages = binance_transactions.as_ref().map_or(None, |transactions| {
+        Some(vec![format!(
+            "Retrieved {} records from page {}",
+            transactions.len(),
+            &pagination.page
+        )])
+    });
+    let errors = match binance_transactions.as_ref() {
+        Ok(_) => None,
+        Err(e) => Some(vec![format!("{}", e)]),
+    };
+
+    ServerResponse::new(
+        Some(Uuid::new_v4()),
+        binance_transactions.is_ok(),
+        binance_transactions.ok(),
+        messages,
+        errors,
+    )
+}
+
+pub fn insert_binance_transaction(
+    new_binance_transaction: NewBinanceTransaction,
+    connection: &mut PgConnection,
+) -> ServerResponse<BinanceTransaction> {
+    let binance_transaction =
+        binance_db::insert_binance_transaction(new_binance_transaction, connection);
+
+    let messages = binance_transaction.as_ref().map_or(None, |bt| {
+        Some(vec![format!(
+            "Inserted new binance transaction with id: {}",
+            &bt.id
+        )])
+    });
+    let errors = match binance_transaction.as_ref() {
+        Ok(_) => None,
+        Err(e) => Some(vec![format!("{}", e)]),
+    };
+
+    ServerResponse::new(
+        Some(Uuid::new_v4()),
+        binance_transaction.is_ok(),
+        binance_transaction.ok(),
+        messages,
+        errors,
+    )
+}
@@ -2,13 +2,15 @@ use crypto_database::{
     self,
     coinbase_db::{self, CoinbaseTransaction, NewCoinbaseTransaction, Pagination},
 };
+use diesel::PgConnection;
 use server_response::ServerResponse;
 use uuid::Uuid;
 
-pub fn get_coinbase_transaction(id: i32) -> ServerResponse<CoinbaseTransaction> {
-    let mut connection = crypto_database::establish_connection(None)
-        .expect("Failed to establish a connection to the database");
-    let result = coinbase_db::get_coinbase_transaction(id, &mut connection);
+pub fn get_coinbase_transaction(
+    id: i32,
+    connection: &mut PgConnection,
+) -> ServerResponse<CoinbaseTransaction> {
+    let result = coinbase_db::get_coinbase_transaction(id, connection);
 
     let messages = result.as_ref().map_or(None, |transaction| {
         Some(vec![format!(
@@ -32,12 +34,9 @@ pub fn get_coinbase_transaction(id: i32) -> ServerResponse<CoinbaseTransaction>
 
 pub fn get_coinbase_transactions(
     pagination: Pagination,
+    connection: &mut PgConnection,
 ) -> ServerResponse<Vec<CoinbaseTransaction>> {
-    // TODO: Probably shouldn't create a connection on every request. ... This may be handled behind the scenes in diesel. review
-    let mut connection = crypto_database::establish_connection(None)
-        .expect("Failed to establish a connection to the database");
-    let coinbase_transactions =
-        coinbase_db::get_coinbase_transactions(&pagination, &mut connection);
+    let coinbase_transactions = coinbase_db::get_coinbase_transactions(&pagination, connection);
 
     let messages = coinbase_transactions.as_ref().map_or(None, |cts| {
         Some(vec![format!(
@@ -62,12 +61,10 @@ pub fn get_coinbase_transactions(
 
 pub fn insert_coinbase_transaction(
     new_coinbase_transaction: NewCoinbaseTransaction,
+    connection: &mut PgConnection,
 ) -> ServerResponse<CoinbaseTransaction> {
-    // TODO: Probably shouldn't create a connection on every request. ... This may be handled behind the scenes in diesel. review
-    let mut connection =
-        crypto_database::establish_connection(None).expect("Failed to establish db connection");
     let coinbase_transaction =
-        coinbase_db::insert_coinbase_transaction(new_coinbase_transaction, &mut connection);
+        coinbase_db::insert_coinbase_transaction(new_coinbase_transaction, connection);
 
     let messages = coinbase_transaction.as_ref().map_or(None, |ct| {
         Some(vec![format!(
@@ -0,0 +1,107 @@
+use crypto_database::column_mapping_db::{
+    self, NewStoredColumnMapping, Pagination, StoredColumnMapping,
+};
+use diesel::PgConnection;
+pub use models::mapping::ColumnMapping;
+use server_response::ServerResponse;
+use uuid::Uuid;
+
+pub fn get_column_mapping(
+    id: i32,
+    connection: &mut PgConnection,
+) -> ServerResponse<StoredColumnMapping> {
+    let column_mapping = column_mapping_db::get_column_mapping(id, connection);
+
+    let messages = column_mapping.as_ref().map_or(None, |mapping| {
+        Some(vec![format!(
+            "Found column mapping \"{}\" with id: {}",
+            &mapping.name, &mapping.id
+        )])
+    });
+    let errors = match column_mapping.as_ref() {
+        Ok(_) => None,
+        Err(e) => Some(vec![format!("{}", e)]),
+    };
+
+    ServerResponse::new(
+        Some(Uuid::new_v4()),
+        column_mapping.is_ok(),
+        column_mapping.ok(),
+        messages,
+        errors,
+    )
+}
+
+pub fn get_column_mappings(
+    pagination: Pagination,
+    connection: &mut PgConnection,
+) -> ServerResponse<Vec<StoredColumnMapping>> {
+    let column_mappings = column_mapping_db::get_column_mappings(&pagination, connection);
+
+    let messages = column_mappings.as_ref().map_or(None, |mappings| {
+        Some(vec![format!(
+            "Retrieved {} records from page {}",
+            mappings.len(),
+            &pagination.page
+        )])
+    });
+    let errors = match column_mappings.as_ref() {
+        Ok(_) => None,
+        Err(e) => Some(vec![format!("{}", e)]),
+    };
+
+    ServerResponse::new(
+        Some(Uuid::new_v4()),
+        column_mappings.is_ok(),
+        column_mappings.ok(),
+        messages,
+        errors,
+    )
+}
+
+/// Stores a mapping written in JSON or TOML, replacing a mapping with the same name. Definitions that can not be
+/// read are not stored.
+pub fn insert_column_mapping(
+    definition: String,
+    connection: &mut PgConnection,
+) -> ServerResponse<StoredColumnMapping> {
+    let mapping = match ColumnMapping::from_definition(&definition) {
+        Ok(mapping) => mapping,
+        Err(e) => return ServerResponse::new(None, false, None, None, Some(vec![e])),
+    };
+
+    let column_mapping = column_mapping_db::insert_column_mapping(
+        NewStoredColumnMapping::from(&mapping),
+        connection,
+    );
+
+    let messages = column_mapping.as_ref().map_or(None, |mapping| {
+        Some(vec![format!(
+            "Saved column mapping \"{}\" with id: {}",
+            &mapping.name, &mapping.id
+        )])
+    });
+    let errors = match column_mapping.as_ref() {
+        Ok(_) => None,
+        Err(e) => Some(vec![format!("{}", e)]),
+    };
+
+    ServerResponse::new(
+        Some(Uuid::new_v4()),
+        column_mapping.is_ok(),
+        column_mapping.ok(),
+        messages,
+        errors,
+    )
+}
+
+/// Loads the stored mapping an upload asked for by name.
+pub fn find_column_mapping(
+    name: &str,
+    connection: &mut PgConnection,
+) -> Result<ColumnMapping, String> {
+    let stored = column_mapping_db::get_column_mapping_by_name(name, connection)
+        .map_err(|e| format!("Column mapping \"{name}\": {e}"))?;
+
+    ColumnMapping::try_from(&stored)
+}
@@ -0,0 +1,542 @@
+use std::{
+    cell::RefCell,
+    fmt,
+    io::{self, Cursor, Read},
+    rc::Rc,
+};
+
+use coinbase_parser::{CoinbaseTransactionRecord, SHARED_HEADERS as COINBASE_HEADERS};
+use crypto_database::{
+    coinbase_db::{self, NewCoinbaseTransaction},
+    import_batch_db::{
+        self, ImportBatch, ImportBatchTransactions, ImportBatchUpdate, NewImportBatch, Pagination,
+        RolledBackImportBatch,
+    },
+    kraken_db::{self, NewKrakenTransaction},
+    kraken_trades_db::{self, NewKrakenTradeFill},
+    DbPool, PoolError, INSERT_CHUNK_SIZE,
+};
+use csv_parser::{Csv, CsvRecords, ParseMode, RowError, MAX_PREAMBLE_LINES};
+use diesel::{result::Error, Connection, PgConnection};
+use kraken_parser::{
+    KrakenLedgerRecord, KrakenTradeRecord, CSV_HEADERS as KRAKEN_HEADERS, TRADE_CSV_HEADERS,
+};
+use parse_csv::{detect_format, CsvFormat, DETECTORS, SAMPLE_ROWS};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use server_response::ServerResponse;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Rows that failed to parse are counted past this many, rather than each reported.
+pub const MAX_REPORTED_ERRORS: usize = 100;
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportOptions {
+    /// Fail the whole import on the first row that does not parse.
+    pub strict: Option<bool>,
+    /// Name the batch is recorded with when the csv is the raw body, a multipart form names its own file.
+    pub filename: Option<String>,
+}
+
+impl ImportOptions {
+    pub fn mode(&self) -> ParseMode {
+        match self.strict {
+            Some(true) => ParseMode::Strict,
+            _ => ParseMode::Lenient,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportSummary {
+    pub inserted: usize,
+    /// Rows whose key was stored before, or belongs to an earlier row of the file.
+    pub duplicates: usize,
+    pub failed: usize,
+    /// The batch the import was recorded as, nothing is recorded when nothing was stored.
+    pub import_batch_id: Option<i32>,
+}
+
+/// A record an import stores, along with how it is stored without duplicating a stored row.
+trait Importable: DeserializeOwned {
+    /// Format recorded for the batch when the csv was not detected.
+    const FORMAT: CsvFormat;
+
+    /// Inserts the rows not stored before as part of the batch, returning how many were inserted and how many were
+    /// already present.
+    fn upsert(
+        records: &[Self],
+        import_batch_id: i32,
+        connection: &mut PgConnection,
+    ) -> Result<(usize, usize), Error>;
+}
+
+impl Importable for CoinbaseTransactionRecord {
+    const FORMAT: CsvFormat = CsvFormat::CoinbaseTransactions;
+
+    fn upsert(
+        records: &[Self],
+        import_batch_id: i32,
+        connection: &mut PgConnection,
+    ) -> Result<(usize, usize), Error> {
+        let new = records
+            .iter()
+            .map(|record| NewCoinbaseTransaction::from(record).in_import_batch(import_batch_id))
+            .collect();
+
+        coinbase_db::upsert_coinbase_transactions(new, connection)
+            .map(|upserted| (upserted.inserted.len(), upserted.existing.len()))
+    }
+}
+
+impl Importable for KrakenLedgerRecord {
+    const FORMAT: CsvFormat = CsvFormat::KrakenLedgers;
+
+    fn upsert(
+        records: &[Self],
+        import_batch_id: i32,
+        connection: &mut PgConnection,
+    ) -> Result<(usize, usize), Error> {
+        let new = records
+            .iter()
+            .map(|record| NewKrakenTransaction::from(record).in_import_batch(import_batch_id))
+            .collect();
+
+        kraken_db::upsert_kraken_transactions(new, connection)
+            .map(|upserted| (upserted.inserted.len(), upserted.existing.len()))
+    }
+}
+
+impl Importable for KrakenTradeRecord {
+    const FORMAT: CsvFormat = CsvFormat::KrakenTrades;
+
+    fn upsert(
+        records: &[Self],
+        import_batch_id: i32,
+        connection: &mut PgConnection,
+    ) -> Result<(usize, usize), Error> {
+        let new = records
+            .iter()
+            .map(|record| NewKrakenTradeFill::from(record).in_import_batch(import_batch_id))
+            .collect();
+
+        kraken_trades_db::upsert_kraken_trades(new, connection)
+            .map(|upserted| (upserted.inserted.len(), upserted.existing.len()))
+    }
+}
+
+/// The file an import reads, recorded with the batch its rows are stored in.
+struct Upload {
+    filename: Option<String>,
+    /// Hash of what has been read of the file so far.
+    hasher: Rc<RefCell<Sha256>>,
+}
+
+impl Upload {
+    /// Starts hashing the csv as it is read.
+    fn read<R: Read>(csv: R, filename: Option<String>) -> (Self, HashingReader<R>) {
+        let hasher = Rc::new(RefCell::new(Sha256::new()));
+        let reader = HashingReader {
+            inner: csv,
+            hasher: Rc::clone(&hasher),
+        };
+
+        (Upload { filename, hasher }, reader)
+    }
+
+    fn file_hash(&self) -> String {
+        format!("{:x}", self.hasher.borrow().clone().finalize())
+    }
+}
+
+/// Hashes everything read through it into the [`Upload`] it was made with.
+struct HashingReader<R> {
+    inner: R,
+    hasher: Rc<RefCell<Sha256>>,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.borrow_mut().update(&buf[..read]);
+
+        Ok(read)
+    }
+}
+
+/// Why an import was rolled back.
+#[derive(Debug)]
+enum ImportError {
+    Row(RowError),
+    Database(Error),
+}
+
+impl From<Error> for ImportError {
+    fn from(error: Error) -> Self {
+        ImportError::Database(error)
+    }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Row(row_error) => write!(f, "{row_error}"),
+            ImportError::Database(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+/// Why an import could not be answered with a summary, both are failures of the server rather than of the csv.
+#[derive(Debug)]
+pub enum ImportFailure {
+    /// The pool could not lend a connection.
+    Unavailable(PoolError),
+    /// The database rejected the rows, nothing was stored.
+    Database(Error),
+}
+
+impl From<PoolError> for ImportFailure {
+    fn from(error: PoolError) -> Self {
+        ImportFailure::Unavailable(error)
+    }
+}
+
+impl fmt::Display for ImportFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportFailure::Unavailable(error) => write!(f, "The database is unavailable: {error}"),
+            ImportFailure::Database(error) => write!(f, "Nothing was stored: {error}"),
+        }
+    }
+}
+
+/// Detects the format of a csv from its first rows, then stores its transactions the way
+/// [`import_coinbase_transactions`], [`import_kraken_transactions`] and [`import_kraken_trades`] do.
+pub fn import_csv(
+    csv: impl Read,
+    mode: ParseMode,
+    filename: Option<String>,
+    pool: &DbPool,
+) -> Result<ServerResponse<ImportSummary>, ImportFailure> {
+    let (upload, csv) = Upload::read(csv, filename);
+    let (head, rest) = match csv_parser::read_head(csv, MAX_PREAMBLE_LINES + SAMPLE_ROWS + 2) {
+        Ok(read) => read,
+        Err(e) => {
+            return Ok(ServerResponse::new(
+                None,
+                false,
+                None,
+                None,
+                Some(vec![e.to_string()]),
+            ))
+        }
+    };
+    let detection = detect_format(&String::from_utf8_lossy(&head));
+    let explanation = detection.explanation();
+    let Some((format, headers)) = detection.detected().and_then(|detected| {
+        DETECTORS
+            .iter()
+            .find(|detector| detector.format == detected.format)
+            .map(|detector| (detected.format, detector.headers))
+    }) else {
+        return Ok(ServerResponse::new(
+            None,
+            false,
+            None,
+            None,
+            Some(vec![explanation]),
+        ));
+    };
+
+    let csv = Cursor::new(head).chain(rest);
+    let mut server_response = match format {
+        CsvFormat::CoinbaseTransactions | CsvFormat::CoinbaseTransactionReport => {
+            import::<CoinbaseTransactionRecord>(csv, headers, mode, format, upload, pool)?
+        }
+        CsvFormat::KrakenLedgers => {
+            import::<KrakenLedgerRecord>(csv, headers, mode, format, upload, pool)?
+        }
+        CsvFormat::KrakenTrades => {
+            import::<KrakenTradeRecord>(csv, headers, mode, format, upload, pool)?
+        }
+        _ => ServerResponse::new(
+            None,
+            false,
+            None,
+            None,
+            Some(vec![format!(
+                "Importing {format} is not supported, it can still be read with parse-csv"
+            )]),
+        ),
+    };
+    server_response.messages.insert(0, explanation);
+
+    Ok(server_response)
+}
+
+/// Parses a Coinbase export as it is read and stores its transactions a chunk at a time, so files of any size are
+/// imported with bounded memory. Transaction reports have their preamble skipped.
+pub fn import_coinbase_transactions(
+    csv: impl Read,
+    mode: ParseMode,
+    filename: Option<String>,
+    pool: &DbPool,
+) -> Result<ServerResponse<ImportSummary>, ImportFailure> {
+    let (upload, csv) = Upload::read(csv, filename);
+
+    import::<CoinbaseTransactionRecord>(
+        csv,
+        COINBASE_HEADERS,
+        mode,
+        CoinbaseTransactionRecord::FORMAT,
+        upload,
+        pool,
+    )
+}
+
+/// Parses a Kraken ledgers export as it is read and stores its transactions a chunk at a time, so files of any size
+/// are imported with bounded memory.
+pub fn import_kraken_transactions(
+    csv: impl Read,
+    mode: ParseMode,
+    filename: Option<String>,
+    pool: &DbPool,
+) -> Result<ServerResponse<ImportSummary>, ImportFailure> {
+    let (upload, csv) = Upload::read(csv, filename);
+
+    import::<KrakenLedgerRecord>(
+        csv,
+        KRAKEN_HEADERS,
+        mode,
+        KrakenLedgerRecord::FORMAT,
+        upload,
+        pool,
+    )
+}
+
+/// Parses a Kraken trades export as it is read and stores its fills a chunk at a time, so they can price the trades of
+/// Kraken ledgers later on.
+pub fn import_kraken_trades(
+    csv: impl Read,
+    mode: ParseMode,
+    filename: Option<String>,
+    pool: &DbPool,
+) -> Result<ServerResponse<ImportSummary>, ImportFailure> {
+    let (upload, csv) = Upload::read(csv, filename);
+
+    import::<KrakenTradeRecord>(
+        csv,
+        TRADE_CSV_HEADERS,
+        mode,
+        KrakenTradeRecord::FORMAT,
+        upload,
+        pool,
+    )
+}
+
+fn import<C: Importable>(
+    csv: impl Read,
+    headers: &[&str],
+    mode: ParseMode,
+    format: CsvFormat,
+    upload: Upload,
+    pool: &DbPool,
+) -> Result<ServerResponse<ImportSummary>, ImportFailure> {
+    match Csv::stream_records_below_header::<C, _>(csv, headers.to_vec()) {
+        Ok(records) => import_in_chunks(records, mode, format, upload, pool),
+        Err(e) => Ok(ServerResponse::new(
+            None,
+            false,
+            None,
+            None,
+            Some(vec![e.to_string()]),
+        )),
+    }
+}
+
+/// Inserts [`INSERT_CHUNK_SIZE`] records at a time inside one transaction, skipping rows whose key is stored. The
+/// rows are stored as one import batch, recorded once the whole file has been read, or dropped when no row was
+/// inserted. Nothing is stored when the database rejects a chunk, which fails the import, or when a row fails to
+/// parse in [`ParseMode::Strict`], which is reported in the response.
+fn import_in_chunks<R: Read, C: Importable>(
+    mut records: CsvRecords<R, C>,
+    mode: ParseMode,
+    format: CsvFormat,
+    upload: Upload,
+    pool: &DbPool,
+) -> Result<ServerResponse<ImportSummary>, ImportFailure> {
+    let mut connection = pool.get()?;
+    let mut summary = ImportSummary::default();
+    let mut errors = Vec::new();
+
+    let imported = connection.transaction(|connection| {
+        let import_batch = import_batch_db::insert_import_batch(
+            NewImportBatch {
+                source_format: format.to_string(),
+                filename: upload.filename.clone(),
+                ..NewImportBatch::default()
+            },
+            connection,
+        )?;
+
+        while let Some(chunk) = records
+            .next_chunk(INSERT_CHUNK_SIZE, mode)
+            .map_err(ImportError::Row)?
+        {
+            summary.failed += chunk.errors.len();
+            errors.extend(
+                chunk
+                    .errors
+                    .iter()
+                    .take(MAX_REPORTED_ERRORS.saturating_sub(errors.len()))
+                    .map(ToString::to_string),
+            );
+
+            if !chunk.records.is_empty() {
+                let (inserted, existing) = C::upsert(&chunk.records, import_batch.id, connection)?;
+                summary.inserted += inserted;
+                summary.duplicates += existing;
+            }
+        }
+
+        // A file whose rows were all stored before, or all failed, leaves no batch behind.
+        if summary.inserted == 0 {
+            import_batch_db::rollback_import_batch(import_batch.id, connection)?;
+            return Ok(());
+        }
+        import_batch_db::update_import_batch(
+            import_batch.id,
+            ImportBatchUpdate {
+                file_hash: upload.file_hash(),
+                rows_inserted: summary.inserted as i32,
+                rows_duplicate: summary.duplicates as i32,
+                rows_failed: summary.failed as i32,
+                parse_warnings: errors.clone(),
+            },
+            connection,
+        )?;
+        summary.import_batch_id = Some(import_batch.id);
+
+        Ok::<(), ImportError>(())
+    });
+
+    match imported {
+        Ok(()) => {}
+        Err(ImportError::Database(error)) => return Err(ImportFailure::Database(error)),
+        Err(row_error) => {
+            summary.failed += 1;
+            summary.inserted = 0;
+            summary.duplicates = 0;
+            summary.import_batch_id = None;
+            errors.push(format!("Nothing was stored: {row_error}"));
+        }
+    }
+
+    let messages = vec![format!(
+        "Inserted {} transactions, skipped {} duplicates, {} rows failed",
+        summary.inserted, summary.duplicates, summary.failed
+    )];
+
+    Ok(ServerResponse::new(
+        None,
+        errors.is_empty(),
+        Some(summary),
+        Some(messages),
+        Some(errors),
+    ))
+}
+
+/// Batches from the most recent upload back.
+pub fn get_import_batches(
+    pagination: Pagination,
+    connection: &mut PgConnection,
+) -> ServerResponse<Vec<ImportBatch>> {
+    let import_batches = import_batch_db::get_import_batches(&pagination, connection);
+
+    let messages = import_batches.as_ref().map_or(None, |batches| {
+        Some(vec![format!(
+            "Retrieved {} records from page {}",
+            batches.len(),
+            &pagination.page
+        )])
+    });
+    let errors = match import_batches.as_ref() {
+        Ok(_) => None,
+        Err(e) => Some(vec![format!("{}", e)]),
+    };
+
+    ServerResponse::new(
+        Some(Uuid::new_v4()),
+        import_batches.is_ok(),
+        import_batches.ok(),
+        messages,
+        errors,
+    )
+}
+
+/// The batch along with a page of the transactions it stored.
+pub fn get_import_batch_transactions(
+    id: i32,
+    pagination: Pagination,
+    connection: &mut PgConnection,
+) -> ServerResponse<ImportBatchTransactions> {
+    let batch_transactions =
+        import_batch_db::get_import_batch_transactions(id, &pagination, connection);
+
+    let messages = batch_transactions.as_ref().map_or(None, |batch| {
+        Some(vec![format!(
+            "Found import batch with id: {}, retrieved {} Coinbase and {} Kraken transactions from page {}",
+            &batch.import_batch.id,
+            batch.coinbase_transactions.len(),
+            batch.kraken_transactions.len(),
+            &pagination.page
+        )])
+    });
+    let errors = match batch_transactions.as_ref() {
+        Ok(_) => None,
+        Err(e) => Some(vec![format!("{}", e)]),
+    };
+
+    ServerResponse::new(
+        Some(Uuid::new_v4()),
+        batch_transactions.is_ok(),
+        batch_transactions.ok(),
+        messages,
+        errors,
+    )
+}
+
+/// Removes the batch along with every transaction it stored, all of it or none of it. `None` when no batch has the
+/// id.
+pub fn rollback_import_batch(
+    id: i32,
+    connection: &mut PgConnection,
+) -> Option<ServerResponse<RolledBackImportBatch>> {
+    let rolled_back = import_batch_db::rollback_import_batch(id, connection);
+    if let Err(Error::NotFound) = rolled_back {
+        return None;
+    }
+
+    let messages = rolled_back.as_ref().map_or(None, |rolled_back| {
+        Some(vec![format!(
+            "Rolled back import batch with id: {}, removing {} Coinbase and {} Kraken transactions",
+            &rolled_back.import_batch.id,
+            rolled_back.coinbase_transactions,
+            rolled_back.kraken_transactions
+        )])
+    });
+    let errors = match rolled_back.as_ref() {
+        Ok(_) => None,
+        Err(e) => Some(vec![format!("{}", e)]),
+    };
+
+    Some(ServerResponse::new(
+        Some(Uuid::new_v4()),
+        rolled_back.is_ok(),
+        rolled_back.ok(),
+        messages,
+        errors,
+    ))
+}
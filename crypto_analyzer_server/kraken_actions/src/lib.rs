@@ -1,13 +1,17 @@
-use crypto_database::kraken_db::{
-    self, models_db::DBConfig, KrakenTransaction, NewKrakenTransaction, Pagination,
+use crypto_database::{
+    kraken_db::{self, KrakenTransaction, NewKrakenTransaction, Pagination},
+    kraken_trades_db,
 };
+use diesel::PgConnection;
+pub use kraken_parser::KrakenTradeRecord;
 use server_response::ServerResponse;
 use uuid::Uuid;
 
-pub fn get_kraken_transaction(id: i32) -> ServerResponse<KrakenTransaction> {
-    let mut connection = crypto_database::establish_connection(Some(DBConfig::init_from_env()))
-        .expect("Failed to establish db connection");
-    let kraken_transacton = kraken_db::get_kraken_transaction(id, &mut connection);
+pub fn get_kraken_transaction(
+    id: i32,
+    connection: &mut PgConnection,
+) -> ServerResponse<KrakenTransaction> {
+    let kraken_transacton = kraken_db::get_kraken_transaction(id, connection);
 
     let messages = kraken_transacton.as_ref().map_or(None, |transaction| {
         Some(vec![format!(
@@ -29,10 +33,11 @@ pub fn get_kraken_transaction(id: i32) -> ServerResponse<KrakenTransaction> {
     )
 }
 
-pub fn get_kraken_transactions(pagination: Pagination) -> ServerResponse<Vec<KrakenTransaction>> {
-    let mut connection = crypto_database::establish_connection(Some(DBConfig::init_from_env()))
-        .expect("Failed to establish db connection");
-    let kraken_transactions = kraken_db::get_kraken_transactions(&pagination, &mut connection);
+pub fn get_kraken_transactions(
+    pagination: Pagination,
+    connection: &mut PgConnection,
+) -> ServerResponse<Vec<KrakenTransaction>> {
+    let kraken_transactions = kraken_db::get_kraken_transactions(&pagination, connection);
 
     let messages = kraken_transactions.as_ref().map_or(None, |transactions| {
         Some(vec![format!(
@@ -57,11 +62,10 @@ pub fn get_kraken_transactions(pagination: Pagination) -> ServerResponse<Vec<Kra
 
 pub fn insert_kraken_transaction(
     new_kraken_transaction: NewKrakenTransaction,
+    connection: &mut PgConnection,
 ) -> ServerResponse<KrakenTransaction> {
-    let mut connection = crypto_database::establish_connection(Some(DBConfig::init_from_env()))
-        .expect("Failed to establish db connection");
     let kraken_transaction =
-        kraken_db::insert_kraken_transaction(new_kraken_transaction, &mut connection);
+        kraken_db::insert_kraken_transaction(new_kraken_transaction, connection);
 
     let messages = kraken_transaction.as_ref().map_or(None, |kt| {
         Some(vec![format!(
@@ -82,3 +86,14 @@ pub fn insert_kraken_transaction(
         errors,
     )
 }
+
+/// Every stored Kraken fill, to price the trades of a Kraken ledgers export with.
+pub fn find_stored_kraken_trades(
+    connection: &mut PgConnection,
+) -> Result<Vec<KrakenTradeRecord>, String> {
+    kraken_trades_db::get_all_kraken_trades(connection)
+        .map_err(|e| format!("Stored Kraken trades: {e}"))?
+        .iter()
+        .map(KrakenTradeRecord::try_from)
+        .collect()
+}
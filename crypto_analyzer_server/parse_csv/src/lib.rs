@@ -1,27 +1,470 @@
-use coinbase_parser::{CoinbaseTransactionRecord, CSV_HEADERS};
-use csv_parser::{Csv, CsvIdentifier, CsvParser};
-use kraken_parser::{KrakenLedgerRecord, CSV_HEADERS as KRAKEN_HEADERS};
-use serde::Serialize;
+use std::fmt;
+
+use binance_parser::{
+    BinanceTransactionRecord, CSV_HEADERS as BINANCE_HEADERS, DATE_FORMAT as BINANCE_DATE_FORMAT,
+    SHORT_DATE_FORMAT as BINANCE_SHORT_DATE_FORMAT,
+};
+use chrono::{NaiveDate, NaiveDateTime};
+use coinbase_parser::{
+    CoinbaseTransactionRecord, CSV_HEADERS, TRANSACTION_REPORT_DATE_FORMAT,
+    TRANSACTION_REPORT_HEADERS,
+};
+use coinbase_pro_parser::{CoinbaseProFill, CSV_HEADERS as COINBASE_PRO_HEADERS};
+use csv_parser::{Csv, CsvIdentifier, CsvParser, HeaderRow};
+pub use csv_parser::{ParseMode, RowError};
+use gemini_parser::{GeminiTransactionRecord, CSV_HEADERS as GEMINI_HEADERS};
+use kraken_parser::{
+    KrakenLedgerRecord, KrakenTradeRecord, CSV_HEADERS as KRAKEN_HEADERS,
+    DATE_FORMAT as KRAKEN_DATE_FORMAT, TRADE_CSV_HEADERS as KRAKEN_TRADE_HEADERS,
+    TRADE_DATE_FORMAT as KRAKEN_TRADE_DATE_FORMAT,
+};
+use mapped_parser::{parse_mapped_csv, ColumnMapping, MappedTransaction};
+use serde::{Deserialize, Serialize};
+
+const NOT_RECOGNIZED: &str = "Failed to match csv to known types";
+
+/// Timestamps written as RFC 3339 in UTC, with or without fractional seconds.
+const ISO_DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.fZ";
+
+/// How many rows below the header are parsed to score a format.
+pub const SAMPLE_ROWS: usize = 5;
+
+// Weights of the parts of a score, adding up to 100.
+const HEADER_WEIGHT: usize = 60;
+const ROW_WEIGHT: usize = 30;
+const DATE_WEIGHT: usize = 10;
 
 #[derive(Serialize)]
 #[serde(untagged)]
 pub enum CsvType {
     CoinbaseTransactions(Vec<CoinbaseTransactionRecord>),
     KrakenLedgers(Vec<KrakenLedgerRecord>),
-    NotRecognized(&'static str),
+    KrakenTrades(Vec<KrakenTradeRecord>),
+    BinanceTransactions(Vec<BinanceTransactionRecord>),
+    GeminiTransactions(Vec<GeminiTransactionRecord>),
+    CoinbaseProFills(Vec<CoinbaseProFill>),
+    MappedTransactions(Vec<MappedTransaction>),
+    /// Why the csv was not recognized.
+    NotRecognized(String),
+}
+
+/// Every export layout that can be recognized.
+#[derive(Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum CsvFormat {
+    CoinbaseTransactions,
+    CoinbaseTransactionReport,
+    KrakenLedgers,
+    KrakenTrades,
+    BinanceTransactions,
+    GeminiTransactions,
+    CoinbaseProFills,
+}
+
+impl fmt::Display for CsvFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsvFormat::CoinbaseTransactions => write!(f, "Coinbase transactions"),
+            CsvFormat::CoinbaseTransactionReport => write!(f, "Coinbase transaction report"),
+            CsvFormat::KrakenLedgers => write!(f, "Kraken ledgers"),
+            CsvFormat::KrakenTrades => write!(f, "Kraken trades"),
+            CsvFormat::BinanceTransactions => write!(f, "Binance transaction history"),
+            CsvFormat::GeminiTransactions => write!(f, "Gemini transaction history"),
+            CsvFormat::CoinbaseProFills => write!(f, "Coinbase Pro fills"),
+        }
+    }
+}
+
+/// How a format is recognized: its headers, how its rows parse and how it writes dates.
+pub struct FormatDetector {
+    pub format: CsvFormat,
+    pub headers: &'static [&'static str],
+    /// Column the date probe reads.
+    pub date_column: &'static str,
+    /// Formats the date column is written in, a date without a time is accepted too.
+    pub date_formats: &'static [&'static str],
+    /// Parses a sample of the file, returning how many rows parsed.
+    parse_sample: fn(&str) -> usize,
+}
+
+/// Every supported format, earlier formats win a tie.
+pub const DETECTORS: &[FormatDetector] = &[
+    FormatDetector {
+        format: CsvFormat::CoinbaseTransactions,
+        headers: CSV_HEADERS,
+        date_column: "Timestamp",
+        date_formats: &[ISO_DATE_FORMAT],
+        parse_sample: parse_sample::<CoinbaseTransactionRecord>,
+    },
+    FormatDetector {
+        format: CsvFormat::CoinbaseTransactionReport,
+        headers: TRANSACTION_REPORT_HEADERS,
+        date_column: "Timestamp",
+        date_formats: &[TRANSACTION_REPORT_DATE_FORMAT, ISO_DATE_FORMAT],
+        parse_sample: parse_sample::<CoinbaseTransactionRecord>,
+    },
+    FormatDetector {
+        format: CsvFormat::KrakenLedgers,
+        headers: KRAKEN_HEADERS,
+        date_column: "time",
+        date_formats: &[KRAKEN_DATE_FORMAT],
+        parse_sample: parse_sample::<KrakenLedgerRecord>,
+    },
+    FormatDetector {
+        format: CsvFormat::KrakenTrades,
+        headers: KRAKEN_TRADE_HEADERS,
+        date_column: "time",
+        date_formats: &[KRAKEN_TRADE_DATE_FORMAT],
+        parse_sample: parse_sample::<KrakenTradeRecord>,
+    },
+    FormatDetector {
+        format: CsvFormat::BinanceTransactions,
+        headers: BINANCE_HEADERS,
+        date_column: "UTC_Time",
+        date_formats: &[BINANCE_DATE_FORMAT, BINANCE_SHORT_DATE_FORMAT],
+        parse_sample: parse_sample::<BinanceTransactionRecord>,
+    },
+    FormatDetector {
+        format: CsvFormat::GeminiTransactions,
+        headers: GEMINI_HEADERS,
+        date_column: "Date",
+        date_formats: &["%Y-%m-%d"],
+        parse_sample: parse_sample::<GeminiTransactionRecord>,
+    },
+    FormatDetector {
+        format: CsvFormat::CoinbaseProFills,
+        headers: COINBASE_PRO_HEADERS,
+        date_column: "created at",
+        date_formats: &[ISO_DATE_FORMAT],
+        parse_sample: parse_sample::<CoinbaseProFill>,
+    },
+];
+
+impl FormatDetector {
+    /// Scores how well the csv fits the format. Formats are only scored against the header row that comes closest to
+    /// theirs, which may be below a preamble.
+    pub fn score(&self, csv: &str) -> Option<FormatCandidate> {
+        let header_match = Csv::closest_header_row(csv, self.headers.to_vec())?;
+        let sample = sample(&header_match.header_row);
+        let sampled_rows = sample.lines().count().saturating_sub(1);
+        let parsed_rows = (self.parse_sample)(&sample);
+        let dated_rows = Csv::parse_rows_with_diagnostics(&sample, ParseMode::Lenient, |row| {
+            Ok(row
+                .get(self.date_column)
+                .filter(|date| self.is_date(date))
+                .is_some())
+        })
+        .map_or(0, |parsed| {
+            parsed.records.into_iter().filter(|dated| *dated).count()
+        });
+
+        // A file with a header and no rows has nothing to disprove the format.
+        let share = |count: usize, weight: usize| match sampled_rows {
+            0 => weight,
+            sampled_rows => count * weight / sampled_rows,
+        };
+        let score = header_match.matched_headers * HEADER_WEIGHT / self.headers.len()
+            + share(parsed_rows, ROW_WEIGHT)
+            + share(dated_rows, DATE_WEIGHT);
+
+        Some(FormatCandidate {
+            format: self.format,
+            score: u8::try_from(score).unwrap_or(u8::MAX),
+            header_line: header_match.header_row.line,
+            matched_headers: header_match.matched_headers,
+            missing_headers: header_match.missing_headers,
+            sampled_rows,
+            parsed_rows,
+            dated_rows,
+        })
+    }
+
+    fn is_date(&self, date: &str) -> bool {
+        self.date_formats.iter().any(|format| {
+            NaiveDateTime::parse_from_str(date.trim(), format).is_ok()
+                || NaiveDate::parse_from_str(date.trim(), format).is_ok()
+        })
+    }
+}
+
+/// How well a csv fits one format.
+#[derive(Serialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FormatCandidate {
+    pub format: CsvFormat,
+    /// Out of 100. Matching headers count for 60, sampled rows that parse for 30 and dates in the expected format
+    /// for 10.
+    pub score: u8,
+    pub header_line: u64,
+    pub matched_headers: usize,
+    pub missing_headers: Vec<String>,
+    pub sampled_rows: usize,
+    pub parsed_rows: usize,
+    pub dated_rows: usize,
+}
+
+impl FormatCandidate {
+    /// Only a file with every header of a format can be parsed as it.
+    pub fn is_match(&self) -> bool {
+        self.missing_headers.is_empty()
+    }
+}
+
+impl fmt::Display for FormatCandidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} with a score of {}: {} of {} headers on line {}",
+            self.format,
+            self.score,
+            self.matched_headers,
+            self.matched_headers + self.missing_headers.len(),
+            self.header_line
+        )?;
+        if !self.missing_headers.is_empty() {
+            write!(f, ", missing {}", self.missing_headers.join(", "))?;
+        }
+        write!(
+            f,
+            "; {} of {} sampled rows parsed; {} had dates in the expected format",
+            self.parsed_rows, self.sampled_rows, self.dated_rows
+        )
+    }
+}
+
+/// Every format scored against a csv, best first.
+#[derive(Serialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Detection {
+    pub candidates: Vec<FormatCandidate>,
+}
+
+impl Detection {
+    /// The best scoring format the csv has every header of.
+    pub fn detected(&self) -> Option<&FormatCandidate> {
+        self.candidates
+            .iter()
+            .find(|candidate| candidate.is_match())
+    }
+
+    /// Why the csv was recognized as its format, or the format it came closest to when it was not.
+    pub fn explanation(&self) -> String {
+        match (self.detected(), self.candidates.first()) {
+            (Some(detected), _) => format!("Recognized as {detected}"),
+            (None, Some(closest)) if closest.matched_headers > 0 => {
+                format!("{NOT_RECOGNIZED}. The closest is {closest}")
+            }
+            _ => format!("{NOT_RECOGNIZED}. None of the headers of a known format were found"),
+        }
+    }
+}
+
+/// Scores the csv against every known format.
+///
+/// ```
+/// use parse_csv::{detect_format, CsvFormat};
+///
+/// let csv = "txid,refid,time,type,subtype,aclass,asset,amount,fee,balance\n\
+///     L7RLII-OFGWB-JTUO7J,RKB7ODD-ILZGC5-LCRRBL,2021-09-29 15:18:30,deposit,,currency,ADA,5.0,0.0,5.0\n";
+///
+/// let detection = detect_format(csv);
+/// let detected = detection.detected().unwrap();
+/// assert_eq!(detected.format, CsvFormat::KrakenLedgers);
+/// assert_eq!(detected.score, 100);
+///
+/// let detection = detect_format("txid,refid,time,type,asset,amount\n");
+/// assert!(detection.detected().is_none());
+/// assert!(detection.explanation().contains("missing subtype, aclass, fee, balance"));
+/// ```
+pub fn detect_format(csv: &str) -> Detection {
+    let mut candidates: Vec<FormatCandidate> = DETECTORS
+        .iter()
+        .filter_map(|detector| detector.score(csv))
+        .collect();
+    // Stable, so formats listed first win a tie.
+    candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.score));
+
+    Detection { candidates }
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ParseCsvOptions {
+    /// Fail the whole file on the first row that does not parse.
+    pub strict: Option<bool>,
+    /// Name of a stored column mapping to read the csv with, instead of detecting its type.
+    pub mapping: Option<String>,
+}
+
+impl ParseCsvOptions {
+    pub fn mode(&self) -> ParseMode {
+        match self.strict {
+            Some(true) => ParseMode::Strict,
+            _ => ParseMode::Lenient,
+        }
+    }
 }
 
 pub fn parse_csv(csv: String) -> CsvType {
-    let coinbase_headers: Vec<&str> = CSV_HEADERS.to_vec();
-    let kraken_headers: Vec<&str> = KRAKEN_HEADERS.to_vec();
-
-    if Csv::is_valid_csv(&csv, coinbase_headers) {
-        let coinbase_transactions: Vec<CoinbaseTransactionRecord> = Csv::parse_csv(&csv);
-        CsvType::CoinbaseTransactions(coinbase_transactions)
-    } else if Csv::is_valid_csv(&csv, kraken_headers) {
-        let kraken_ledgers = Csv::parse_csv(&csv);
-        CsvType::KrakenLedgers(kraken_ledgers)
-    } else {
-        CsvType::NotRecognized("Failed to match csv to known types. This may happen when headers are changed from coinbase or kraken")
+    match parse_csv_with_diagnostics(csv, ParseMode::Lenient) {
+        Ok((csv_type, _)) => csv_type,
+        Err(row_error) => CsvType::NotRecognized(row_error.to_string()),
+    }
+}
+
+/// Parses the csv as the format it scores best for, along with the rows that failed to parse.
+pub fn parse_csv_with_diagnostics(
+    csv: String,
+    mode: ParseMode,
+) -> Result<(CsvType, Vec<RowError>), RowError> {
+    let detection = detect_format(&csv);
+
+    parse_detected_csv(csv, &detection, mode)
+}
+
+/// Parses the csv as the format that was detected. Errors report lines of the whole file, preamble included.
+pub fn parse_detected_csv(
+    csv: String,
+    detection: &Detection,
+    mode: ParseMode,
+) -> Result<(CsvType, Vec<RowError>), RowError> {
+    let Some((detected, header_row)) = detection.detected().and_then(|detected| {
+        let detector = DETECTORS
+            .iter()
+            .find(|detector| detector.format == detected.format)?;
+
+        Some((
+            detected,
+            Csv::find_header_row(&csv, detector.headers.to_vec())?,
+        ))
+    }) else {
+        return Ok((CsvType::NotRecognized(detection.explanation()), Vec::new()));
+    };
+
+    match detected.format {
+        CsvFormat::CoinbaseTransactions | CsvFormat::CoinbaseTransactionReport => {
+            let parsed = header_row.parse_csv_with_diagnostics(mode)?;
+            Ok((CsvType::CoinbaseTransactions(parsed.records), parsed.errors))
+        }
+        CsvFormat::KrakenLedgers => {
+            let parsed = header_row.parse_csv_with_diagnostics(mode)?;
+            Ok((CsvType::KrakenLedgers(parsed.records), parsed.errors))
+        }
+        CsvFormat::KrakenTrades => {
+            let parsed = header_row.parse_csv_with_diagnostics(mode)?;
+            Ok((CsvType::KrakenTrades(parsed.records), parsed.errors))
+        }
+        CsvFormat::BinanceTransactions => {
+            let parsed = header_row.parse_csv_with_diagnostics(mode)?;
+            Ok((CsvType::BinanceTransactions(parsed.records), parsed.errors))
+        }
+        CsvFormat::GeminiTransactions => {
+            let parsed = header_row.parse_csv_with_diagnostics(mode)?;
+            Ok((CsvType::GeminiTransactions(parsed.records), parsed.errors))
+        }
+        CsvFormat::CoinbaseProFills => {
+            let parsed = header_row.parse_csv_with_diagnostics(mode)?;
+            Ok((CsvType::CoinbaseProFills(parsed.records), parsed.errors))
+        }
+    }
+}
+
+/// Parses the csv with the column mapping when one is given, otherwise as the known type its headers match.
+pub fn parse_csv_with_mapping(
+    csv: String,
+    mapping: Option<&ColumnMapping>,
+    mode: ParseMode,
+) -> Result<(CsvType, Vec<RowError>), RowError> {
+    match mapping {
+        Some(mapping) => {
+            let parsed = parse_mapped_csv(&csv, mapping, mode)?;
+            Ok((CsvType::MappedTransactions(parsed.records), parsed.errors))
+        }
+        None => parse_csv_with_diagnostics(csv, mode),
+    }
+}
+
+/// The header row and the first [`SAMPLE_ROWS`] rows below it.
+fn sample(header_row: &HeaderRow) -> String {
+    header_row
+        .csv
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .take(SAMPLE_ROWS + 1)
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+fn parse_sample<C: for<'a> Deserialize<'a>>(sample: &str) -> usize {
+    Csv::parse_csv_with_diagnostics::<C>(sample, ParseMode::Lenient)
+        .map_or(0, |parsed| parsed.records.len())
+}
+
+#[cfg(test)]
+mod detect_format_should {
+    use crate::{detect_format, CsvFormat};
+
+    #[test]
+    fn score_every_format_best_first() {
+        let csv = "portfolio,trade id,product,side,created at,size,size unit,price,fee,total,price/fee/total unit\n\
+            default,1,ETH-USD,BUY,2021-03-01T15:20:30.123Z,0.5,ETH,1500.00,3.75,-753.75,USD\n\
+            default,2,ETH-USD,HOLD,2021-03-02T15:20:30.123Z,0.5,ETH,1500.00,3.75,-753.75,USD\n";
+
+        let detection = detect_format(csv);
+        let detected = detection.detected().unwrap();
+
+        assert_eq!(detection.candidates.len(), 7);
+        assert_eq!(detected.format, CsvFormat::CoinbaseProFills);
+        assert_eq!((detected.parsed_rows, detected.sampled_rows), (1, 2));
+        assert_eq!(detected.dated_rows, 2);
+        assert_eq!(detected.score, 60 + 15 + 10);
+        assert!(detection
+            .candidates
+            .windows(2)
+            .all(|pair| pair[0].score >= pair[1].score));
+    }
+
+    #[test]
+    fn find_the_header_row_below_a_preamble() {
+        let csv = "Transactions\n\
+            User,Satoshi Nakamoto,9f7a5c1e\n\
+            ID,Timestamp,Transaction Type,Asset,Quantity Transacted,Price Currency,Price at Transaction,Subtotal,Total (inclusive of fees and/or spread),Fees and/or Spread,Notes\n\
+            65a1,2024-01-22 21:39:01 UTC,Sell,BTC,-0.0016458,USD,\"$1,617.57\",$97.01,$100.00,$2.99,Sold\n";
+
+        let detection = detect_format(csv);
+        let detected = detection.detected().unwrap();
+
+        assert_eq!(detected.format, CsvFormat::CoinbaseTransactionReport);
+        assert_eq!(detected.header_line, 3);
+        assert_eq!(detected.score, 100);
+    }
+
+    #[test]
+    fn explain_the_closest_format_when_nothing_matches() {
+        let csv = "User_ID,UTC_Time,Operation,Coin,Change\n\
+            12345678,2021-03-01 15:20:30,Deposit,BTC,0.5\n";
+
+        let detection = detect_format(csv);
+
+        assert!(detection.detected().is_none());
+        assert_eq!(
+            detection.candidates[0].format,
+            CsvFormat::BinanceTransactions
+        );
+        assert_eq!(
+            detection.explanation(),
+            "Failed to match csv to known types. The closest is Binance transaction history with a score of 52: 5 of 7 headers on line 1, missing Account, Remark; 0 of 1 sampled rows parsed; 1 had dates in the expected format"
+        );
+    }
+
+    #[test]
+    fn say_when_no_known_headers_were_found() {
+        let detection = detect_format("Something Random,Another Random Column\n1,2\n");
+
+        assert!(detection.detected().is_none());
+        assert_eq!(
+            detection.explanation(),
+            "Failed to match csv to known types. None of the headers of a known format were found"
+        );
     }
 }
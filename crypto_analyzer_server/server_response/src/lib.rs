@@ -29,8 +29,8 @@ where
             transaction_id: transaction_id.unwrap_or(Uuid::new_v4()),
             success,
             response,
-            messages: messages.unwrap_or(Default::default()),
-            errors: errors.unwrap_or(Default::default()),
+            messages: messages.unwrap_or_default(),
+            errors: errors.unwrap_or_default(),
         }
     }
 }
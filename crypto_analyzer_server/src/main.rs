@@ -1,21 +1,72 @@
 use axum::{
-    extract::{Path, Query},
-    http::StatusCode,
-    routing::{get, post},
+    async_trait,
+    body::{Body, Bytes, HttpBody},
+    extract::{FromRequest, FromRequestParts, Multipart, Path, Query, State},
+    http::{header, request::Parts, Request, StatusCode},
+    routing::{delete, get, post},
     Json, Router,
 };
+use column_mapping_actions::ColumnMapping;
 use crypto_database::{
+    binance_db::{BinanceTransaction, NewBinanceTransaction},
     coinbase_db::{CoinbaseTransaction, NewCoinbaseTransaction, Pagination},
-    kraken_db::{KrakenTransaction, NewKrakenTransaction},
+    column_mapping_db::StoredColumnMapping,
+    import_batch_db::{ImportBatch, ImportBatchTransactions, RolledBackImportBatch},
+    kraken_db::{models_db::DBConfig, KrakenTransaction, NewKrakenTransaction},
+    migrations::{self, MigrationError},
+    DbPool, PoolError, PooledPgConnection,
 };
-use parse_csv::{parse_csv, CsvType};
+use csv_parser::ByteStreamReader;
+use diesel::PgConnection;
+use import_actions::{ImportFailure, ImportOptions, ImportSummary};
+use kraken_actions::KrakenTradeRecord;
+use parse_csv::{
+    detect_format, parse_csv_with_mapping, parse_detected_csv, CsvType, ParseCsvOptions,
+};
+use serde::Serialize;
 use server_response::ServerResponse;
-use std::{env, net::SocketAddr, str::FromStr};
+use std::{env, io, net::SocketAddr, process, str::FromStr, time::Duration};
+use tax_actions::{CostBasisOptions, IncomeReport, StakingIncomeOptions};
+use tokio::sync::mpsc::Sender;
 
 const API_VERSION: &str = "v1";
 
+/// Chunks of a request body read ahead of an import, the upload waits while this many are queued.
+const BODY_CHUNKS_IN_FLIGHT: usize = 16;
+
+/// How long the server waits before migrating again when the database could not be migrated at startup.
+const MIGRATION_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
 #[tokio::main]
 async fn main() {
+    // `crypto_analyzer_server migrate` migrates the database without serving requests, failing when it can not.
+    let migrate_only = match env::args().nth(1).as_deref() {
+        None => false,
+        Some("migrate") => true,
+        Some(command) => {
+            eprintln!("Unknown command \"{command}\", run without one to serve requests or with \"migrate\"");
+            process::exit(2);
+        }
+    };
+
+    // Serving only stops for a database a newer build migrated, anything else is retried while requests are served.
+    let pool = crypto_database::create_pool(Some(DBConfig::init_from_env()));
+    match migrate(&pool) {
+        Ok(()) if migrate_only => return,
+        Ok(()) => {}
+        Err(e) if migrate_only || matches!(e, MigrationError::DatabaseAhead(_)) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!(
+                "Migrating again in {}s: {e}",
+                MIGRATION_RETRY_INTERVAL.as_secs()
+            );
+            tokio::spawn(retry_migrations(pool.clone()));
+        }
+    }
+
     let app = Router::new()
         .route(
             "/",
@@ -25,6 +76,30 @@ async fn main() {
             format!("/api/{}/parse-csv", API_VERSION).as_str(),
             post(parse_csver),
         )
+        .route(
+            format!("/api/{}/import", API_VERSION).as_str(),
+            post(import_csv),
+        )
+        .route(
+            format!("/api/{}/import-batch/:id", API_VERSION).as_str(),
+            get(get_import_batch_transactions),
+        )
+        .route(
+            format!("/api/{}/import-batch/:id", API_VERSION).as_str(),
+            delete(rollback_import_batch),
+        )
+        .route(
+            format!("/api/{}/import-batch", API_VERSION).as_str(),
+            get(get_import_batches),
+        )
+        .route(
+            format!("/api/{}/form-8949", API_VERSION).as_str(),
+            post(form_8949),
+        )
+        .route(
+            format!("/api/{}/staking-income", API_VERSION).as_str(),
+            post(staking_income),
+        )
         .route(
             format!("/api/{}/coinbase-transaction/:id", API_VERSION).as_str(),
             get(get_coinbase_transaction),
@@ -37,6 +112,10 @@ async fn main() {
             format!("/api/{}/coinbase-transaction", API_VERSION).as_str(),
             post(insert_coinbase_transaction),
         )
+        .route(
+            format!("/api/{}/coinbase-transaction/import", API_VERSION).as_str(),
+            post(import_coinbase_transactions),
+        )
         .route(
             format!("/api/{}/kraken-transaction/:id", API_VERSION).as_str(),
             get(get_kraken_transaction),
@@ -48,7 +127,40 @@ async fn main() {
         .route(
             format!("/api/{}/kraken-transaction", API_VERSION).as_str(),
             post(insert_kraken_transaction),
-        );
+        )
+        .route(
+            format!("/api/{}/kraken-transaction/import", API_VERSION).as_str(),
+            post(import_kraken_transactions),
+        )
+        .route(
+            format!("/api/{}/kraken-trade/import", API_VERSION).as_str(),
+            post(import_kraken_trades),
+        )
+        .route(
+            format!("/api/{}/binance-transaction/:id", API_VERSION).as_str(),
+            get(get_binance_transaction),
+        )
+        .route(
+            format!("/api/{}/binance-transaction", API_VERSION).as_str(),
+            get(get_binance_transactions),
+        )
+        .route(
+            format!("/api/{}/binance-transaction", API_VERSION).as_str(),
+            post(insert_binance_transaction),
+        )
+        .route(
+            format!("/api/{}/column-mapping/:id", API_VERSION).as_str(),
+            get(get_column_mapping),
+        )
+        .route(
+            format!("/api/{}/column-mapping", API_VERSION).as_str(),
+            get(get_column_mappings),
+        )
+        .route(
+            format!("/api/{}/column-mapping", API_VERSION).as_str(),
+            post(insert_column_mapping),
+        )
+        .with_state(pool);
 
     axum::Server::bind(&get_socket_address())
         .serve(app.into_make_service())
@@ -56,6 +168,48 @@ async fn main() {
         .unwrap();
 }
 
+/// Applies the migrations the database has not had yet and reports the schema version. Fails when the database can
+/// not be reached or rejects a migration, or was migrated by a newer build.
+fn migrate(pool: &DbPool) -> Result<(), MigrationError> {
+    let mut connection = pool
+        .get()
+        .map_err(|e| MigrationError::Failed(Box::new(e)))?;
+    let (applied, schema) = migrations::run_pending_migrations(&mut connection)?;
+
+    for version in &applied {
+        println!("Applied migration {version}");
+    }
+    println!(
+        "Database schema is at version {}",
+        schema.current.as_deref().unwrap_or("none")
+    );
+
+    Ok(())
+}
+
+/// Migrates every [`MIGRATION_RETRY_INTERVAL`] until the database is migrated. Stops without migrating once the
+/// database turns out to be migrated by a newer build, requests that need the newer schema fail until it is fixed.
+async fn retry_migrations(pool: DbPool) {
+    loop {
+        tokio::time::sleep(MIGRATION_RETRY_INTERVAL).await;
+        let pool = pool.clone();
+        match tokio::task::spawn_blocking(move || migrate(&pool))
+            .await
+            .expect("Migration task panicked")
+        {
+            Ok(()) => return,
+            Err(e @ MigrationError::DatabaseAhead(_)) => {
+                eprintln!("Stopped migrating: {e}");
+                return;
+            }
+            Err(e) => eprintln!(
+                "Migrating again in {}s: {e}",
+                MIGRATION_RETRY_INTERVAL.as_secs()
+            ),
+        }
+    }
+}
+
 fn get_socket_address() -> SocketAddr {
     let ip = env::var("ip_address").unwrap_or("0.0.0.0".to_string());
     let port = env::var("port").unwrap_or("3000".to_string());
@@ -70,36 +224,309 @@ fn get_socket_address() -> SocketAddr {
     }
 }
 
-async fn parse_csver(payload: String) -> (StatusCode, Json<CsvType>) {
-    match parse_csv(payload) {
-        CsvType::CoinbaseTransactions(value) => {
-            (StatusCode::OK, Json(CsvType::CoinbaseTransactions(value)))
+/// A connection borrowed from the pool for the length of a request, without blocking the runtime while waiting on the
+/// pool. Requests are refused with a 503 while the pool can not lend one.
+struct DbConnection(PooledPgConnection);
+
+#[async_trait]
+impl FromRequestParts<DbPool> for DbConnection {
+    type Rejection = (StatusCode, Json<ServerResponse<()>>);
+
+    async fn from_request_parts(
+        _parts: &mut Parts,
+        pool: &DbPool,
+    ) -> Result<Self, Self::Rejection> {
+        let pool = pool.clone();
+
+        // An exhausted pool is waited on for up to its connection timeout.
+        tokio::task::spawn_blocking(move || pool.get())
+            .await
+            .expect("Connection task panicked")
+            .map(DbConnection)
+            .map_err(database_unavailable)
+    }
+}
+
+impl DbConnection {
+    /// Runs diesel queries on the blocking thread pool, so a slow query does not hold up other requests.
+    async fn run<T: Send + 'static>(
+        mut self,
+        query: impl FnOnce(&mut PgConnection) -> T + Send + 'static,
+    ) -> T {
+        tokio::task::spawn_blocking(move || query(&mut self.0))
+            .await
+            .expect("Database task panicked")
+    }
+}
+
+/// Every pooled connection is in use, or the database can not be reached.
+fn database_unavailable<T: Serialize>(error: PoolError) -> (StatusCode, Json<ServerResponse<T>>) {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(ServerResponse::new(
+            None,
+            false,
+            None,
+            None,
+            Some(vec![format!("The database is unavailable: {error}")]),
+        )),
+    )
+}
+
+async fn parse_csver(
+    State(pool): State<DbPool>,
+    options: Query<ParseCsvOptions>,
+    payload: String,
+) -> (StatusCode, Json<ServerResponse<CsvType>>) {
+    // Loading a stored mapping queries the database, and large files take a while to parse.
+    tokio::task::spawn_blocking(move || parse_payload(options.0, payload, &pool))
+        .await
+        .expect("Parse task panicked")
+}
+
+fn parse_payload(
+    options: ParseCsvOptions,
+    payload: String,
+    pool: &DbPool,
+) -> (StatusCode, Json<ServerResponse<CsvType>>) {
+    let mapping = match requested_mapping(options.mapping.as_deref(), pool) {
+        Ok(mapping) => mapping,
+        Err((status_code, e)) => {
+            return (
+                status_code,
+                Json(ServerResponse::new(None, false, None, None, Some(vec![e]))),
+            )
         }
-        CsvType::KrakenLedgers(value) => (StatusCode::OK, Json(CsvType::KrakenLedgers(value))),
-        CsvType::NotRecognized(e) => (StatusCode::BAD_REQUEST, Json(CsvType::NotRecognized(e))),
+    };
+
+    // Says which format the file was recognized as, mapped files skip detection.
+    let (parsed, messages) = match mapping {
+        Some(mapping) => (
+            parse_csv_with_mapping(payload, Some(&mapping), options.mode()),
+            None,
+        ),
+        None => {
+            let detection = detect_format(&payload);
+            let explanation = detection.explanation();
+
+            (
+                parse_detected_csv(payload, &detection, options.mode()),
+                Some(vec![explanation]),
+            )
+        }
+    };
+
+    match parsed {
+        Ok((CsvType::NotRecognized(e), _)) => (
+            StatusCode::BAD_REQUEST,
+            Json(ServerResponse::new(
+                None,
+                false,
+                None,
+                None,
+                Some(vec![e.to_string()]),
+            )),
+        ),
+        Ok((csv_type, row_errors)) => (
+            StatusCode::OK,
+            Json(ServerResponse::new(
+                None,
+                row_errors.is_empty(),
+                Some(csv_type),
+                messages,
+                Some(row_errors.iter().map(ToString::to_string).collect()),
+            )),
+        ),
+        Err(row_error) => (
+            StatusCode::BAD_REQUEST,
+            Json(ServerResponse::new(
+                None,
+                false,
+                None,
+                None,
+                Some(vec![row_error.to_string()]),
+            )),
+        ),
+    }
+}
+
+/// Stores the transactions of a csv of any format that can be stored, taken as the raw body or a multipart form.
+async fn import_csv(
+    State(pool): State<DbPool>,
+    options: Query<ImportOptions>,
+    request: Request<Body>,
+) -> (StatusCode, Json<ServerResponse<ImportSummary>>) {
+    let mode = options.mode();
+
+    import_body(request, options.0.filename, move |csv, filename| {
+        import_actions::import_csv(csv, mode, filename, &pool)
+    })
+    .await
+}
+
+async fn get_import_batches(
+    connection: DbConnection,
+    pagination: Query<Pagination>,
+) -> (StatusCode, Json<ServerResponse<Vec<ImportBatch>>>) {
+    let import_batches = connection
+        .run(move |connection| import_actions::get_import_batches(pagination.0, connection))
+        .await;
+
+    (StatusCode::OK, Json(import_batches))
+}
+
+async fn get_import_batch_transactions(
+    connection: DbConnection,
+    id: Path<i32>,
+    pagination: Query<Pagination>,
+) -> (StatusCode, Json<ServerResponse<ImportBatchTransactions>>) {
+    let batch_transactions = connection
+        .run(move |connection| {
+            import_actions::get_import_batch_transactions(id.0, pagination.0, connection)
+        })
+        .await;
+
+    (StatusCode::OK, Json(batch_transactions))
+}
+
+/// Removes an import batch along with the transactions it stored.
+async fn rollback_import_batch(
+    connection: DbConnection,
+    id: Path<i32>,
+) -> (StatusCode, Json<ServerResponse<RolledBackImportBatch>>) {
+    let Path(id) = id;
+    let rolled_back = connection
+        .run(move |connection| import_actions::rollback_import_batch(id, connection))
+        .await;
+
+    match rolled_back {
+        Some(rolled_back) if rolled_back.success => (StatusCode::OK, Json(rolled_back)),
+        Some(rolled_back) => (StatusCode::INTERNAL_SERVER_ERROR, Json(rolled_back)),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(ServerResponse::new(
+                None,
+                false,
+                None,
+                None,
+                Some(vec![format!("No import batch with id: {id}")]),
+            )),
+        ),
     }
 }
 
+/// Takes the export as the raw body, or as the file of a multipart form. The form may also hold a Kraken trades export
+/// in a `trades` field to price the trades of a Kraken ledgers export from their fills, `storedKrakenTrades` prices them
+/// from the fills stored by earlier imports as well.
+async fn form_8949(
+    State(pool): State<DbPool>,
+    options: Query<CostBasisOptions>,
+    request: Request<Body>,
+) -> (StatusCode, Json<ServerResponse<String>>) {
+    let (payload, kraken_trades) = match read_form_8949_upload(request).await {
+        Ok(upload) => upload,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ServerResponse::new(None, false, None, None, Some(vec![e]))),
+            )
+        }
+    };
+
+    // Pricing staking rewards makes blocking http requests.
+    let (status_code, server_response) = tokio::task::spawn_blocking(move || {
+        let requested = requested_mapping(options.mapping.as_deref(), &pool).and_then(|mapping| {
+            requested_kraken_trades(options.stored_kraken_trades.unwrap_or_default(), &pool)
+                .map(|stored_kraken_trades| (mapping, stored_kraken_trades))
+        });
+        match requested {
+            Ok((mapping, stored_kraken_trades)) => {
+                let server_response = tax_actions::form_8949(
+                    payload,
+                    kraken_trades,
+                    stored_kraken_trades,
+                    options.0,
+                    mapping,
+                );
+                let status_code = match &server_response.success {
+                    true => StatusCode::OK,
+                    false => StatusCode::BAD_REQUEST,
+                };
+
+                (status_code, server_response)
+            }
+            Err((status_code, e)) => (
+                status_code,
+                ServerResponse::new(None, false, None, None, Some(vec![e])),
+            ),
+        }
+    })
+    .await
+    .expect("Form 8949 task panicked");
+
+    (status_code, Json(server_response))
+}
+
+async fn staking_income(
+    State(pool): State<DbPool>,
+    options: Query<StakingIncomeOptions>,
+    payload: String,
+) -> (StatusCode, Json<ServerResponse<IncomeReport>>) {
+    let (status_code, server_response) =
+        tokio::task::spawn_blocking(move || {
+            match requested_mapping(options.mapping.as_deref(), &pool) {
+                Ok(mapping) => {
+                    let server_response = tax_actions::staking_income(payload, mapping);
+                    let status_code = match &server_response.success {
+                        true => StatusCode::OK,
+                        false => StatusCode::BAD_REQUEST,
+                    };
+
+                    (status_code, server_response)
+                }
+                Err((status_code, e)) => (
+                    status_code,
+                    ServerResponse::new(None, false, None, None, Some(vec![e])),
+                ),
+            }
+        })
+        .await
+        .expect("Staking income task panicked");
+
+    (status_code, Json(server_response))
+}
+
 async fn get_coinbase_transaction(
+    connection: DbConnection,
     id: Path<i32>,
 ) -> (StatusCode, Json<ServerResponse<CoinbaseTransaction>>) {
-    let server_response = coinbase_actions::get_coinbase_transaction(id.0);
+    let server_response = connection
+        .run(move |connection| coinbase_actions::get_coinbase_transaction(id.0, connection))
+        .await;
 
     (StatusCode::OK, Json(server_response))
 }
 
 async fn get_coinbase_transactions(
+    connection: DbConnection,
     pagination: Query<Pagination>,
 ) -> (StatusCode, Json<ServerResponse<Vec<CoinbaseTransaction>>>) {
-    let server_response = coinbase_actions::get_coinbase_transactions(pagination.0);
+    let server_response = connection
+        .run(move |connection| {
+            coinbase_actions::get_coinbase_transactions(pagination.0, connection)
+        })
+        .await;
 
     (StatusCode::OK, Json(server_response))
 }
 
 async fn insert_coinbase_transaction(
+    connection: DbConnection,
     payload: Json<NewCoinbaseTransaction>,
 ) -> (StatusCode, Json<ServerResponse<CoinbaseTransaction>>) {
-    let coinbase_transaction = coinbase_actions::insert_coinbase_transaction(payload.0);
+    let coinbase_transaction = connection
+        .run(move |connection| coinbase_actions::insert_coinbase_transaction(payload.0, connection))
+        .await;
 
     let status_code = match &coinbase_transaction.success {
         true => StatusCode::CREATED,
@@ -109,26 +536,49 @@ async fn insert_coinbase_transaction(
     (status_code, Json(coinbase_transaction))
 }
 
+/// Takes the export as the raw body or a multipart form, which is streamed into the database rather than read whole.
+async fn import_coinbase_transactions(
+    State(pool): State<DbPool>,
+    options: Query<ImportOptions>,
+    request: Request<Body>,
+) -> (StatusCode, Json<ServerResponse<ImportSummary>>) {
+    let mode = options.mode();
+
+    import_body(request, options.0.filename, move |csv, filename| {
+        import_actions::import_coinbase_transactions(csv, mode, filename, &pool)
+    })
+    .await
+}
+
 async fn get_kraken_transaction(
+    connection: DbConnection,
     id: Path<i32>,
 ) -> (StatusCode, Json<ServerResponse<KrakenTransaction>>) {
-    let kraken_transaction = kraken_actions::get_kraken_transaction(id.0);
+    let kraken_transaction = connection
+        .run(move |connection| kraken_actions::get_kraken_transaction(id.0, connection))
+        .await;
 
     (StatusCode::OK, Json(kraken_transaction))
 }
 
 async fn get_kraken_transactions(
+    connection: DbConnection,
     pagination: Query<Pagination>,
 ) -> (StatusCode, Json<ServerResponse<Vec<KrakenTransaction>>>) {
-    let kraken_trasnactions = kraken_actions::get_kraken_transactions(pagination.0);
+    let kraken_trasnactions = connection
+        .run(move |connection| kraken_actions::get_kraken_transactions(pagination.0, connection))
+        .await;
 
     (StatusCode::OK, Json(kraken_trasnactions))
 }
 
 async fn insert_kraken_transaction(
+    connection: DbConnection,
     payload: Json<NewKrakenTransaction>,
 ) -> (StatusCode, Json<ServerResponse<KrakenTransaction>>) {
-    let kraken_transaction = kraken_actions::insert_kraken_transaction(payload.0);
+    let kraken_transaction = connection
+        .run(move |connection| kraken_actions::insert_kraken_transaction(payload.0, connection))
+        .await;
 
     let status_code = match &kraken_transaction.success {
         true => StatusCode::CREATED,
@@ -138,19 +588,331 @@ async fn insert_kraken_transaction(
     (status_code, Json(kraken_transaction))
 }
 
+/// Takes the export as the raw body or a multipart form, which is streamed into the database rather than read whole.
+async fn import_kraken_trades(
+    State(pool): State<DbPool>,
+    options: Query<ImportOptions>,
+    request: Request<Body>,
+) -> (StatusCode, Json<ServerResponse<ImportSummary>>) {
+    let mode = options.mode();
+
+    import_body(request, options.0.filename, move |csv, filename| {
+        import_actions::import_kraken_trades(csv, mode, filename, &pool)
+    })
+    .await
+}
+
+/// Takes the export as the raw body or a multipart form, which is streamed into the database rather than read whole.
+async fn import_kraken_transactions(
+    State(pool): State<DbPool>,
+    options: Query<ImportOptions>,
+    request: Request<Body>,
+) -> (StatusCode, Json<ServerResponse<ImportSummary>>) {
+    let mode = options.mode();
+
+    import_body(request, options.0.filename, move |csv, filename| {
+        import_actions::import_kraken_transactions(csv, mode, filename, &pool)
+    })
+    .await
+}
+
+async fn get_binance_transaction(
+    connection: DbConnection,
+    id: Path<i32>,
+) -> (StatusCode, Json<ServerResponse<BinanceTransaction>>) {
+    let binance_transaction = connection
+        .run(move |connection| binance_actions::get_binance_transaction(id.0, connection))
+        .await;
+
+    (StatusCode::OK, Json(binance_transaction))
+}
+
+async fn get_binance_transactions(
+    connection: DbConnection,
+    pagination: Query<Pagination>,
+) -> (StatusCode, Json<ServerResponse<Vec<BinanceTransaction>>>) {
+    let binance_transactions = connection
+        .run(move |connection| binance_actions::get_binance_transactions(pagination.0, connection))
+        .await;
+
+    (StatusCode::OK, Json(binance_transactions))
+}
+
+async fn insert_binance_transaction(
+    connection: DbConnection,
+    payload: Json<NewBinanceTransaction>,
+) -> (StatusCode, Json<ServerResponse<BinanceTransaction>>) {
+    let binance_transaction = connection
+        .run(move |connection| binance_actions::insert_binance_transaction(payload.0, connection))
+        .await;
+
+    let status_code = match &binance_transaction.success {
+        true => StatusCode::CREATED,
+        false => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+
+    (status_code, Json(binance_transaction))
+}
+
+async fn get_column_mapping(
+    connection: DbConnection,
+    id: Path<i32>,
+) -> (StatusCode, Json<ServerResponse<StoredColumnMapping>>) {
+    let column_mapping = connection
+        .run(move |connection| column_mapping_actions::get_column_mapping(id.0, connection))
+        .await;
+
+    (StatusCode::OK, Json(column_mapping))
+}
+
+async fn get_column_mappings(
+    connection: DbConnection,
+    pagination: Query<Pagination>,
+) -> (StatusCode, Json<ServerResponse<Vec<StoredColumnMapping>>>) {
+    let column_mappings = connection
+        .run(move |connection| {
+            column_mapping_actions::get_column_mappings(pagination.0, connection)
+        })
+        .await;
+
+    (StatusCode::OK, Json(column_mappings))
+}
+
+/// Takes the mapping definition as JSON or TOML.
+async fn insert_column_mapping(
+    connection: DbConnection,
+    payload: String,
+) -> (StatusCode, Json<ServerResponse<StoredColumnMapping>>) {
+    let column_mapping = connection
+        .run(move |connection| column_mapping_actions::insert_column_mapping(payload, connection))
+        .await;
+
+    let status_code = match &column_mapping.success {
+        true => StatusCode::CREATED,
+        false => StatusCode::BAD_REQUEST,
+    };
+
+    (status_code, Json(column_mapping))
+}
+
+/// Runs an import on a blocking thread, feeding it the csv as it arrives. The csv is the raw body, or the file of a
+/// multipart form, which is imported under the name the form gives it.
+async fn import_body(
+    request: Request<Body>,
+    filename: Option<String>,
+    import: impl FnOnce(
+            ByteStreamReader<Bytes>,
+            Option<String>,
+        ) -> Result<ServerResponse<ImportSummary>, ImportFailure>
+        + Send
+        + 'static,
+) -> (StatusCode, Json<ServerResponse<ImportSummary>>) {
+    let (sender, csv) = csv_parser::byte_stream(BODY_CHUNKS_IN_FLIGHT);
+    let start = move |filename| tokio::task::spawn_blocking(move || import(csv, filename));
+
+    let import = match is_multipart(&request) {
+        true => {
+            send_multipart_file(request, &sender, |file_name| start(file_name.or(filename))).await
+        }
+        false => {
+            let import = start(filename);
+            send_body(request.into_body(), &sender).await;
+            import
+        }
+    };
+    drop(sender);
+
+    // Rows that failed to parse are reported alongside the ones that were imported.
+    let server_response = match import.await.expect("Import task panicked") {
+        Ok(server_response) => server_response,
+        Err(ImportFailure::Unavailable(e)) => return database_unavailable(e),
+        Err(e @ ImportFailure::Database(_)) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ServerResponse::new(
+                    None,
+                    false,
+                    None,
+                    None,
+                    Some(vec![e.to_string()]),
+                )),
+            )
+        }
+    };
+    let imported = server_response
+        .response
+        .map_or(0, |summary| summary.inserted + summary.duplicates);
+    let status_code = match (server_response.success, imported) {
+        (false, 0) => StatusCode::BAD_REQUEST,
+        _ => StatusCode::CREATED,
+    };
+
+    (status_code, Json(server_response))
+}
+
+async fn send_body(mut body: Body, sender: &Sender<io::Result<Bytes>>) {
+    while let Some(chunk) = body.data().await {
+        // The import stops reading once it gives up on the file.
+        if sender.send(chunk.map_err(io::Error::other)).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Sends the first field of the form that holds a file, fields before it are skipped. The import is started with the
+/// name of the file once it is found, or without one when the form can not be read.
+async fn send_multipart_file<T>(
+    request: Request<Body>,
+    sender: &Sender<io::Result<Bytes>>,
+    start: impl FnOnce(Option<String>) -> T,
+) -> T {
+    let mut multipart = match Multipart::from_request(request, &()).await {
+        Ok(multipart) => multipart,
+        Err(rejection) => {
+            let import = start(None);
+            let _ = sender
+                .send(Err(io::Error::other(rejection.body_text())))
+                .await;
+            return import;
+        }
+    };
+
+    loop {
+        match multipart.next_field().await {
+            Ok(Some(mut field)) if field.file_name().is_some() || field.name() == Some("file") => {
+                let import = start(field.file_name().map(ToString::to_string));
+                while let Some(chunk) = field.chunk().await.transpose() {
+                    if sender.send(chunk.map_err(io::Error::other)).await.is_err() {
+                        break;
+                    }
+                }
+                return import;
+            }
+            Ok(Some(_)) => continue,
+            Ok(None) => {
+                let import = start(None);
+                let no_file = io::Error::other("The form does not hold a file");
+                let _ = sender.send(Err(no_file)).await;
+                return import;
+            }
+            Err(e) => {
+                let import = start(None);
+                let _ = sender.send(Err(io::Error::other(e))).await;
+                return import;
+            }
+        }
+    }
+}
+
+/// Reads the export sent for Form 8949 along with the Kraken trades export sent in the `trades` field of a form.
+async fn read_form_8949_upload(request: Request<Body>) -> Result<(String, Option<String>), String> {
+    if !is_multipart(&request) {
+        return String::from_request(request, &())
+            .await
+            .map(|csv| (csv, None))
+            .map_err(|rejection| rejection.body_text());
+    }
+
+    let mut multipart = Multipart::from_request(request, &())
+        .await
+        .map_err(|rejection| rejection.body_text())?;
+    let (mut csv, mut kraken_trades) = (None, None);
+    while let Some(field) = multipart.next_field().await.map_err(|e| e.to_string())? {
+        match field.name() {
+            Some("trades") => kraken_trades = Some(field.text().await.map_err(|e| e.to_string())?),
+            _ if csv.is_none() && (field.file_name().is_some() || field.name() == Some("file")) => {
+                csv = Some(field.text().await.map_err(|e| e.to_string())?)
+            }
+            _ => continue,
+        }
+    }
+
+    csv.map(|csv| (csv, kraken_trades))
+        .ok_or_else(|| "The form does not hold a file".to_string())
+}
+
+fn is_multipart(request: &Request<Body>) -> bool {
+    request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("multipart/form-data"))
+}
+
+/// Loads the stored column mapping a request named, if it named one. The database is only needed for a named mapping.
+fn requested_mapping(
+    name: Option<&str>,
+    pool: &DbPool,
+) -> Result<Option<ColumnMapping>, (StatusCode, String)> {
+    let Some(name) = name else {
+        return Ok(None);
+    };
+    let mut connection = pool.get().map_err(|e| {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("The database is unavailable: {e}"),
+        )
+    })?;
+
+    column_mapping_actions::find_column_mapping(name, &mut connection)
+        .map(Some)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+/// Loads the stored Kraken fills when a request asked to price its Kraken trades with them.
+fn requested_kraken_trades(
+    requested: bool,
+    pool: &DbPool,
+) -> Result<Vec<KrakenTradeRecord>, (StatusCode, String)> {
+    if !requested {
+        return Ok(Vec::new());
+    }
+    let mut connection = pool.get().map_err(|e| {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("The database is unavailable: {e}"),
+        )
+    })?;
+
+    kraken_actions::find_stored_kraken_trades(&mut connection)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
+}
+
+/// A pool the handlers under test never borrow from, connections are only made once one is borrowed.
+#[cfg(test)]
+fn unused_pool() -> State<DbPool> {
+    State(crypto_database::create_pool(None))
+}
+
+/// A pool whose database never answers, for handlers that borrow a connection before reading the request.
+#[cfg(test)]
+fn unreachable_pool() -> State<DbPool> {
+    use crypto_database::kraken_db::models_db::DBConfigOptions;
+
+    State(crypto_database::create_pool(Some(DBConfig::new(Some(
+        DBConfigOptions {
+            host: Some("127.0.0.1".to_string()),
+            port: Some("1".to_string()),
+            connection_timeout: Some(Duration::from_millis(200)),
+            ..DBConfigOptions::default()
+        },
+    )))))
+}
+
 #[cfg(test)]
 mod parse_csver_should {
     extern crate rust_decimal;
-    use std::{str::FromStr, vec};
+    use std::str::FromStr;
 
-    use coinbase_parser::CoinbaseTransactionRecord;
-    use kraken_parser::{KrakenLedgerRecord, DATE_FORMAT as KRAKEN_DATE_FORMAT};
+    use binance_parser::BinanceOperation;
+    use coinbase_parser::{CoinbaseTransactionRecord, CoinbaseTransactionType};
+    use kraken_parser::{KrakenLedgerRecord, KrakenLedgerType, DATE_FORMAT as KRAKEN_DATE_FORMAT};
 
-    use axum::{http::StatusCode, Json};
-    use parse_csv::CsvType;
+    use axum::{extract::Query, http::StatusCode, Json};
+    use parse_csv::{CsvType, ParseCsvOptions};
     use rust_decimal::Decimal;
 
-    use super::parse_csver;
+    use super::{parse_csver, unreachable_pool, unused_pool};
     use chrono::prelude::*;
 
     #[actix_rt::test]
@@ -159,10 +921,11 @@ mod parse_csver_should {
             + "2021-01-22T21:38:01Z,Buy,BTC,0.0016458,USD,1617.57,97.01,100.00,2.99,Bought 0.0016458 BTC for $2.66 USD\n"
             + "2022-01-22T21:39:01Z,Sell,BTC,0.0016458,USD,1617.57,97.01,100.00,2.99,Sold 0.0016458 BTC for $2.66 USD";
 
-        let expected_vec = vec![
+        let expected_vec = [
             CoinbaseTransactionRecord {
+                id: None,
                 time_of_transaction: "2021-01-22T21:38:01Z".parse::<DateTime<Utc>>().unwrap(),
-                transaction_type: "Buy".to_string(),
+                transaction_type: CoinbaseTransactionType::Buy,
                 asset: "BTC".to_string(),
                 quantity_transacted: Decimal::from_str("0.0016458").unwrap(),
                 spot_price_currency: "USD".to_string(),
@@ -173,8 +936,9 @@ mod parse_csver_should {
                 notes: "Bought 0.0016458 BTC for $2.66 USD".to_string(),
             },
             CoinbaseTransactionRecord {
+                id: None,
                 time_of_transaction: "2022-01-22T21:39:01Z".parse::<DateTime<Utc>>().unwrap(),
-                transaction_type: "Sell".to_string(),
+                transaction_type: CoinbaseTransactionType::Sell,
                 asset: "BTC".to_string(),
                 quantity_transacted: Decimal::from_str("0.0016458").unwrap(),
                 spot_price_currency: "USD".to_string(),
@@ -186,14 +950,16 @@ mod parse_csver_should {
             },
         ];
 
-        let (status_code, Json(parsed)) = parse_csver(csv).await;
+        let (status_code, Json(parsed)) =
+            parse_csver(unused_pool(), Query(ParseCsvOptions::default()), csv).await;
 
         assert_eq!(status_code, StatusCode::OK);
-        match parsed {
+        assert!(parsed.success);
+        match parsed.response.unwrap() {
             CsvType::CoinbaseTransactions(transaction_list) => {
                 assert_eq!(
-                    transaction_list.get(0).unwrap(),
-                    expected_vec.get(0).unwrap()
+                    transaction_list.first().unwrap(),
+                    expected_vec.first().unwrap()
                 );
                 assert_eq!(
                     transaction_list.get(1).unwrap(),
@@ -204,20 +970,54 @@ mod parse_csver_should {
         }
     }
 
+    #[actix_rt::test]
+    async fn parse_coinbase_transaction_report() {
+        let csv = ",,,,,,,,,,\n".to_string()
+            + "Transactions\n"
+            + "User,Satoshi Nakamoto,9f7a5c1e-24b1-4d7f-9b7e-1a2b3c4d5e6f\n"
+            + "ID,Timestamp,Transaction Type,Asset,Quantity Transacted,Price Currency,Price at Transaction,Subtotal,Total (inclusive of fees and/or spread),Fees and/or Spread,Notes\n"
+            + "65a1,2024-01-22 21:39:01 UTC,Sell,BTC,-0.0016458,USD,\"$1,617.57\",$97.01,$100.00,$2.99,Sold 0.0016458 BTC for $97.01 USD";
+
+        let (status_code, Json(parsed)) =
+            parse_csver(unused_pool(), Query(ParseCsvOptions::default()), csv).await;
+
+        assert_eq!(status_code, StatusCode::OK);
+        assert!(parsed.success);
+        match parsed.response.unwrap() {
+            CsvType::CoinbaseTransactions(transaction_list) => assert_eq!(
+                transaction_list,
+                [CoinbaseTransactionRecord {
+                    id: Some("65a1".to_string()),
+                    time_of_transaction: "2024-01-22T21:39:01Z".parse::<DateTime<Utc>>().unwrap(),
+                    transaction_type: CoinbaseTransactionType::Sell,
+                    asset: "BTC".to_string(),
+                    quantity_transacted: Decimal::from_str("0.0016458").unwrap(),
+                    spot_price_currency: "USD".to_string(),
+                    spot_price_at_transaction: Some(Decimal::from_str("1617.57").unwrap()),
+                    subtotal: Some(Decimal::from_str("97.01").unwrap()),
+                    total: Some(Decimal::from_str("100").unwrap()),
+                    fees: Some(Decimal::from_str("2.99").unwrap()),
+                    notes: "Sold 0.0016458 BTC for $97.01 USD".to_string(),
+                }]
+            ),
+            _ => panic!("Failed to parse as Coinbase transaction"),
+        }
+    }
+
     #[actix_rt::test]
     async fn parse_kraken_ledger() {
         let csv = "txid,refid,time,type,subtype,aclass,asset,amount,fee,balance\n".to_string() 
                 + "QWERTY-FOGWB-JOTO7J,QWERTY-ILZGGG-LCBLBL,2021-07-29 1:19:30,Buy,,currency,ADA,5.00000000,0.00000000,5.00000000\n"
                 + "YTREWQ-FOGWB-JOTO7J,YTREWQ-ILZGGG-LCBLBL,2022-07-29 1:19:30,Sell,,currency,ADA,5.00000000,0.00000000,0.00000000";
 
-        let expected_vec = vec![
+        let expected_vec = [
             KrakenLedgerRecord {
                 txid: Some("QWERTY-FOGWB-JOTO7J".to_string()),
                 refid: "QWERTY-ILZGGG-LCBLBL".to_string(),
                 time: Utc
                     .datetime_from_str("2021-07-29 1:19:30", KRAKEN_DATE_FORMAT)
                     .unwrap(),
-                record_type: "Buy".to_string(),
+                record_type: KrakenLedgerType::from("Buy"),
                 subtype: None,
                 a_class: "currency".to_string(),
                 asset: "ADA".to_string(),
@@ -231,7 +1031,7 @@ mod parse_csver_should {
                 time: Utc
                     .datetime_from_str("2022-07-29 1:19:30", KRAKEN_DATE_FORMAT)
                     .unwrap(),
-                record_type: "Sell".to_string(),
+                record_type: KrakenLedgerType::from("Sell"),
                 subtype: None,
                 a_class: "currency".to_string(),
                 asset: "ADA".to_string(),
@@ -241,30 +1041,763 @@ mod parse_csver_should {
             },
         ];
 
-        let (status_code, Json(parsed)) = parse_csver(csv).await;
+        let (status_code, Json(parsed)) =
+            parse_csver(unused_pool(), Query(ParseCsvOptions::default()), csv).await;
 
         assert_eq!(status_code, StatusCode::OK);
+        assert!(parsed.success);
+        assert!(parsed.messages[0].starts_with("Recognized as Kraken ledgers with a score of 100"));
 
-        match parsed {
+        match parsed.response.unwrap() {
             CsvType::KrakenLedgers(kraken_vec) => {
-                assert_eq!(kraken_vec.get(0).unwrap(), expected_vec.get(0).unwrap());
+                assert_eq!(kraken_vec.first().unwrap(), expected_vec.first().unwrap());
                 assert_eq!(kraken_vec.get(1).unwrap(), expected_vec.get(1).unwrap());
             }
             _ => panic!("Response was not parsed as a Kraken record"),
         }
     }
 
+    #[actix_rt::test]
+    async fn parse_kraken_trades() {
+        let csv = "txid,ordertxid,pair,time,type,ordertype,price,cost,fee,vol,margin,misc,ledgers\n"
+            .to_string()
+            + "TQWERT-FOGWB-JOTO7J,OQWERT-ILZGG-LCBLBL,ADAUSD,2021-07-29 01:19:30.1234,buy,limit,0.25000000,1.25000,0.00325,5.00000000,0.00000,,\"LQWERT-FOGWB-JOTO7J,LYTREW-FOGWB-JOTO7J\"";
+
+        let (status_code, Json(parsed)) =
+            parse_csver(unused_pool(), Query(ParseCsvOptions::default()), csv).await;
+
+        assert_eq!(status_code, StatusCode::OK);
+        assert!(parsed.success, "{:?}", parsed.errors);
+        match parsed.response.unwrap() {
+            CsvType::KrakenTrades(fills) => {
+                let fill = fills.first().unwrap();
+                assert_eq!(fill.pair, "ADAUSD");
+                assert_eq!(fill.price, Decimal::from_str("0.25").unwrap());
+                assert_eq!(
+                    fill.ledger_txids(),
+                    ["LQWERT-FOGWB-JOTO7J", "LYTREW-FOGWB-JOTO7J"]
+                );
+            }
+            _ => panic!("Response was not parsed as Kraken trades"),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn parse_binance_transactions() {
+        let csv = "\"User_ID\",\"UTC_Time\",\"Account\",\"Operation\",\"Coin\",\"Change\",\"Remark\"\n".to_string()
+            + "\"12345678\",\"2022-07-29 01:19:30\",\"Spot\",\"Deposit\",\"ADA\",\"5.00000000\",\"\"\n"
+            + "\"12345678\",\"2022-07-30 01:19:30\",\"Spot\",\"Staking Rewards\",\"ADA\",\"0.01250000\",\"\"";
+
+        let (status_code, Json(parsed)) =
+            parse_csver(unused_pool(), Query(ParseCsvOptions::default()), csv).await;
+
+        assert_eq!(status_code, StatusCode::OK);
+        assert!(parsed.success);
+        match parsed.response.unwrap() {
+            CsvType::BinanceTransactions(records) => {
+                assert_eq!(records.len(), 2);
+                assert_eq!(
+                    records.get(1).unwrap().operation,
+                    BinanceOperation::StakingRewards
+                );
+                assert_eq!(
+                    records.get(1).unwrap().change,
+                    Decimal::from_str("0.0125").unwrap()
+                );
+            }
+            _ => panic!("Response was not parsed as Binance transactions"),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn parse_gemini_transactions() {
+        let csv = "Date,Time (UTC),Type,Symbol,Specification,Liquidity Indicator,Trading Fee Rate (bps),USD Amount USD,Trading Fee (USD) USD,USD Balance USD,ETH Amount ETH,ETH Balance ETH\n".to_string()
+            + "2022-07-29,01:19:30.000,Credit,ETH,Earn Interest,,,,,,0.001 ETH,0.001 ETH";
+
+        let (status_code, Json(parsed)) =
+            parse_csver(unused_pool(), Query(ParseCsvOptions::default()), csv).await;
+
+        assert_eq!(status_code, StatusCode::OK);
+        assert!(parsed.success);
+        match parsed.response.unwrap() {
+            CsvType::GeminiTransactions(records) => {
+                assert_eq!(records.len(), 1);
+                assert!(records.first().unwrap().is_staking_reward());
+            }
+            _ => panic!("Response was not parsed as Gemini transactions"),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn parse_coinbase_pro_fills() {
+        let csv = "portfolio,trade id,product,side,created at,size,size unit,price,fee,total,price/fee/total unit\n".to_string()
+            + "default,1,ADA-USD,BUY,2021-03-01T15:20:30.123Z,100,ADA,1.25,0.625,-125.625,USD";
+
+        let (status_code, Json(parsed)) =
+            parse_csver(unused_pool(), Query(ParseCsvOptions::default()), csv).await;
+
+        assert_eq!(status_code, StatusCode::OK);
+        assert!(parsed.success);
+        match parsed.response.unwrap() {
+            CsvType::CoinbaseProFills(fills) => {
+                assert_eq!(fills.len(), 1);
+                assert_eq!(
+                    fills.first().unwrap().total,
+                    Decimal::from_str("-125.625").unwrap()
+                );
+            }
+            _ => panic!("Response was not parsed as Coinbase Pro fills"),
+        }
+    }
+
     #[actix_rt::test]
     async fn parse_not_not_recognized() {
         let csv = "Something Random,Another Random Column\n".to_string()
             + "some random data, some random column";
 
-        let (status_code, Json(parsed)) = parse_csver(csv).await;
+        let (status_code, Json(parsed)) =
+            parse_csver(unused_pool(), Query(ParseCsvOptions::default()), csv).await;
 
         assert_eq!(status_code, StatusCode::BAD_REQUEST);
-        match parsed {
-            CsvType::NotRecognized(message) => assert_eq!(message, "Failed to match csv to known types. This may happen when headers are changed from coinbase or kraken"),
-            _ => panic!("Response was recognized but shouldn't have"),
+        assert!(parsed.response.is_none());
+        assert_eq!(
+            parsed.errors,
+            ["Failed to match csv to known types. None of the headers of a known format were found"]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn report_rows_that_failed_to_parse() {
+        let csv = "txid,refid,time,type,subtype,aclass,asset,amount,fee,balance\n".to_string()
+                + "QWERTY-FOGWB-JOTO7J,QWERTY-ILZGGG-LCBLBL,2021-07-29 1:19:30,deposit,,currency,ADA,5.00000000,0.00000000,5.00000000\n"
+                + "YTREWQ-FOGWB-JOTO7J,YTREWQ-ILZGGG-LCBLBL,2022-07-29 1:19:30,deposit,,currency,ADA,$5.00,0.00000000,10.00000000";
+
+        let (status_code, Json(parsed)) = parse_csver(
+            unused_pool(),
+            Query(ParseCsvOptions::default()),
+            csv.clone(),
+        )
+        .await;
+
+        assert_eq!(status_code, StatusCode::OK);
+        assert!(!parsed.success);
+        assert_eq!(parsed.errors.len(), 1);
+        assert!(parsed
+            .errors
+            .first()
+            .unwrap()
+            .starts_with("Line 3, column \"amount\" (\"$5.00\")"));
+        match parsed.response.unwrap() {
+            CsvType::KrakenLedgers(kraken_vec) => assert_eq!(kraken_vec.len(), 1),
+            _ => panic!("Response was not parsed as a Kraken record"),
         }
+
+        let (status_code, Json(parsed)) = parse_csver(
+            unused_pool(),
+            Query(ParseCsvOptions {
+                strict: Some(true),
+                ..ParseCsvOptions::default()
+            }),
+            csv,
+        )
+        .await;
+
+        assert_eq!(status_code, StatusCode::BAD_REQUEST);
+        assert!(parsed.response.is_none());
+        assert_eq!(parsed.errors.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn answer_unavailable_when_the_mapping_can_not_be_looked_up() {
+        let csv = "Date,Coin,Amount\n2021-01-22T21:38:01Z,BTC,1".to_string();
+        let options = ParseCsvOptions {
+            mapping: Some("mapping-that-was-never-stored".to_string()),
+            ..ParseCsvOptions::default()
+        };
+
+        let (status_code, Json(parsed)) =
+            parse_csver(unreachable_pool(), Query(options), csv).await;
+
+        assert_eq!(status_code, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(!parsed.success);
+        assert_eq!(parsed.errors.len(), 1);
+    }
+}
+
+#[cfg(test)]
+mod form_8949_should {
+    use axum::{
+        body::Body,
+        extract::Query,
+        http::{header, Request, StatusCode},
+        Json,
+    };
+    use chrono::{DateTime, Utc};
+    use column_mapping_actions::ColumnMapping;
+    use kraken_parser::{Decimal, KrakenTradeRecord, TradeSide};
+    use tax_actions::CostBasisOptions;
+
+    use super::{form_8949, unreachable_pool, unused_pool};
+
+    #[actix_rt::test]
+    async fn export_realized_gains_as_csv() {
+        let csv = "Timestamp,Transaction Type,Asset,Quantity Transacted,Spot Price Currency,Spot Price at Transaction,Subtotal,Total (inclusive of fees and/or spread),Fees and/or Spread,Notes\n".to_string()
+            + "2021-01-22T21:38:01Z,Buy,BTC,2,USD,50.00,100.00,100.00,0,Bought 2 BTC for $100.00 USD\n"
+            + "2021-03-22T21:39:01Z,Sell,BTC,1,USD,80.00,80.00,80.00,0,Sold 1 BTC for $80.00 USD";
+
+        let (status_code, Json(response)) = form_8949(
+            unused_pool(),
+            Query(CostBasisOptions::default()),
+            Request::new(Body::from(csv)),
+        )
+        .await;
+
+        assert_eq!(status_code, StatusCode::OK);
+        assert!(response.errors.is_empty());
+        let body = response.response.unwrap();
+        assert!(body.contains("Part I,1 BTC,01/22/2021,03/22/2021,80.00,50.00,,0.00,30.00"));
+        assert!(body.contains("Schedule D,Short-term totals,,,80.00,50.00,,0.00,30.00"));
+    }
+
+    #[actix_rt::test]
+    async fn report_sales_without_a_lot() {
+        let csv = "Timestamp,Transaction Type,Asset,Quantity Transacted,Spot Price Currency,Spot Price at Transaction,Subtotal,Total (inclusive of fees and/or spread),Fees and/or Spread,Notes\n".to_string()
+            + "2021-01-22T21:38:01Z,Buy,BTC,1,USD,50.00,50.00,50.00,0,Bought 1 BTC for $50.00 USD\n"
+            + "2021-03-22T21:39:01Z,Sell,BTC,3,USD,80.00,240.00,240.00,0,Sold 3 BTC for $240.00 USD";
+
+        let (status_code, Json(response)) = form_8949(
+            unused_pool(),
+            Query(CostBasisOptions::default()),
+            Request::new(Body::from(csv)),
+        )
+        .await;
+
+        assert_eq!(status_code, StatusCode::OK);
+        assert!(response
+            .response
+            .unwrap()
+            .contains("Part I,1 BTC,01/22/2021,03/22/2021,80.00,50.00,,0.00,30.00"));
+        assert_eq!(
+            response.errors,
+            ["2 BTC disposed of on 2021-03-22 had no lot to match against and is left out of Form 8949"]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn report_unrecognized_transaction_types() {
+        let csv = "Timestamp,Transaction Type,Asset,Quantity Transacted,Spot Price Currency,Spot Price at Transaction,Subtotal,Total (inclusive of fees and/or spread),Fees and/or Spread,Notes\n".to_string()
+            + "2021-01-22T21:38:01Z,Buy,BTC,1,USD,50.00,50.00,50.00,0,Bought 1 BTC for $50.00 USD\n"
+            + "2021-02-22T21:39:01Z,Pro Withdrawal,BTC,1,USD,50.00,50.00,50.00,0,Moved 1 BTC";
+
+        let (status_code, Json(response)) = form_8949(
+            unused_pool(),
+            Query(CostBasisOptions::default()),
+            Request::new(Body::from(csv)),
+        )
+        .await;
+
+        assert_eq!(status_code, StatusCode::OK);
+        assert_eq!(
+            response.messages,
+            ["Skipped 1 transactions of unrecognized type \"Pro Withdrawal\" (BTC)"]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn give_rewards_their_value_on_receipt_as_basis() {
+        let csv = "Timestamp,Transaction Type,Asset,Quantity Transacted,Spot Price Currency,Spot Price at Transaction,Subtotal,Total (inclusive of fees and/or spread),Fees and/or Spread,Notes\n".to_string()
+            + "2021-01-22T21:38:01Z,Rewards Income,ALGO,2,USD,0.50,,,,Received 2 ALGO\n"
+            + "2021-03-22T21:39:01Z,Sell,ALGO,2,USD,1.00,2.00,2.00,0,Sold 2 ALGO for $2.00 USD\n"
+            + "2021-03-23T21:39:01Z,Buy,BTC,1,EUR,40.00,40.00,40.00,0,Bought 1 BTC for €40.00 EUR";
+
+        let (status_code, Json(response)) = form_8949(
+            unused_pool(),
+            Query(CostBasisOptions::default()),
+            Request::new(Body::from(csv)),
+        )
+        .await;
+
+        assert_eq!(status_code, StatusCode::OK);
+        assert!(response
+            .response
+            .unwrap()
+            .contains("Part I,2 ALGO,01/22/2021,03/22/2021,2.00,1.00,,0.00,1.00"));
+        assert_eq!(
+            response.errors,
+            ["Buy of 1 BTC on 2021-03-23 was valued in EUR rather than USD and is left out of Form 8949"]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn report_rows_that_could_not_be_read() {
+        let csv = "Timestamp,Transaction Type,Asset,Quantity Transacted,Spot Price Currency,Spot Price at Transaction,Subtotal,Total (inclusive of fees and/or spread),Fees and/or Spread,Notes\n".to_string()
+            + "2021-01-22T21:38:01Z,Buy,BTC,2,USD,50.00,100.00,100.00,0,Bought 2 BTC for $100.00 USD\n"
+            + "2021-02-22T21:38:01Z,Buy,BTC,lots,USD,50.00,100.00,100.00,0,Bought BTC\n"
+            + "2021-03-22T21:39:01Z,Sell,BTC,1,USD,80.00,80.00,80.00,0,Sold 1 BTC for $80.00 USD";
+
+        let (status_code, Json(response)) = form_8949(
+            unused_pool(),
+            Query(CostBasisOptions::default()),
+            Request::new(Body::from(csv)),
+        )
+        .await;
+
+        assert_eq!(status_code, StatusCode::OK);
+        assert!(response
+            .response
+            .unwrap()
+            .contains("Part I,1 BTC,01/22/2021,03/22/2021,80.00,50.00,,0.00,30.00"));
+        assert_eq!(response.errors.len(), 1);
+        assert!(response.errors[0].starts_with("Line 3, column \"Quantity Transacted\""));
+    }
+
+    #[actix_rt::test]
+    async fn report_converts_that_could_not_be_read() {
+        let csv = "Timestamp,Transaction Type,Asset,Quantity Transacted,Spot Price Currency,Spot Price at Transaction,Subtotal,Total (inclusive of fees and/or spread),Fees and/or Spread,Notes\n".to_string()
+            + "2021-01-22T21:38:01Z,Buy,BTC,1,USD,50.00,50.00,50.00,0,Bought 1 BTC for $50.00 USD\n"
+            + "2021-03-22T21:39:01Z,Convert,BTC,1,USD,80.00,80.00,80.00,0,Swapped 1 BTC";
+
+        let (status_code, Json(response)) = form_8949(
+            unused_pool(),
+            Query(CostBasisOptions::default()),
+            Request::new(Body::from(csv)),
+        )
+        .await;
+
+        assert_eq!(status_code, StatusCode::OK);
+        assert_eq!(
+            response.errors,
+            ["Convert of 1 BTC on 2021-03-22 could not be read, only the sale is in Form 8949: Convert notes are not recognized: Swapped 1 BTC"]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn report_kraken_trades_not_made_against_usd() {
+        let csv = "txid,refid,time,type,subtype,aclass,asset,amount,fee,balance\n".to_string()
+            + "L1,T1,2021-07-29 01:19:30,trade,,currency,DOT,-10.00000000,0.00000000,0.00000000\n"
+            + "L2,T1,2021-07-29 01:19:30,trade,,currency,XETH,0.10000000,0.00000000,0.10000000";
+
+        let (status_code, Json(response)) = form_8949(
+            unused_pool(),
+            Query(CostBasisOptions::default()),
+            Request::new(Body::from(csv)),
+        )
+        .await;
+
+        assert_eq!(status_code, StatusCode::OK);
+        assert_eq!(
+            response.errors,
+            ["Kraken trade T1 of 10 DOT for 0.1 ETH on 2021-07-29 was not made against USD and is left out of Form 8949"]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn leave_rewards_without_a_price_out_of_lots() {
+        let csv = "txid,refid,time,type,subtype,aclass,asset,amount,fee,balance\n".to_string()
+            + "L1,R1,2021-07-29 01:19:30,staking,,currency,NOTACOIN.S,2.00000000,0.00000000,2.00000000\n"
+            + "L2,T1,2021-08-29 01:19:30,trade,,currency,NOTACOIN.S,-2.00000000,0.00000000,0.00000000\n"
+            + "L3,T1,2021-08-29 01:19:30,trade,,currency,ZUSD,10.00000000,0.00000000,10.00000000";
+
+        let (status_code, Json(response)) = form_8949(
+            unused_pool(),
+            Query(CostBasisOptions::default()),
+            Request::new(Body::from(csv)),
+        )
+        .await;
+
+        assert_eq!(status_code, StatusCode::OK);
+        assert!(!response.response.unwrap().contains("Part I,2 NOTACOIN"));
+        assert_eq!(
+            response.errors,
+            [
+                "Reward of 2 NOTACOIN received on 2021-07-29 has no USD value and is left out of Form 8949",
+                "NOTACOIN has no CoinGecko id to look up its price",
+                "2 NOTACOIN disposed of on 2021-08-29 had no lot to match against and is left out of Form 8949",
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn price_kraken_trades_from_the_fills_sent_with_the_ledgers() {
+        let form = "--boundary\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"ledgers.csv\"\r\n\r\n\
+            txid,refid,time,type,subtype,aclass,asset,amount,fee,balance\n\
+            L1,T1,2021-07-29 01:19:30,trade,,currency,ZUSD,-10.00000000,0.00000000,0.00000000\n\
+            L2,T1,2021-07-29 01:19:30,trade,,currency,DOT,3.00000000,0.00000000,3.00000000\n\
+            L3,T2,2021-08-29 01:19:30,trade,,currency,DOT,-3.00000000,0.00000000,0.00000000\n\
+            L4,T2,2021-08-29 01:19:30,trade,,currency,ZUSD,12.00000000,0.00000000,12.00000000\r\n\
+            --boundary\r\n\
+            Content-Disposition: form-data; name=\"trades\"; filename=\"trades.csv\"\r\n\r\n\
+            txid,ordertxid,pair,time,type,ordertype,price,cost,fee,vol,margin,misc,ledgers\n\
+            F1,O1,DOTUSD,2021-07-29 01:19:30.1234,buy,limit,3.00000,9.00000,0.00000,3.00000000,0.00000,,\"L1,L2\"\r\n\
+            --boundary--\r\n";
+        let request = Request::builder()
+            .header(
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=boundary",
+            )
+            .body(Body::from(form))
+            .unwrap();
+
+        let (status_code, Json(response)) =
+            form_8949(unused_pool(), Query(CostBasisOptions::default()), request).await;
+
+        assert_eq!(status_code, StatusCode::OK, "{:?}", response.errors);
+        assert!(response
+            .response
+            .unwrap()
+            .contains("Part I,3 DOT,07/29/2021,08/29/2021,12.00,9.00,,0.00,3.00"));
+    }
+
+    #[actix_rt::test]
+    async fn reject_kraken_trades_sent_without_ledgers() {
+        let form = "--boundary\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"coinbase.csv\"\r\n\r\n\
+            Timestamp,Transaction Type,Asset,Quantity Transacted,Spot Price Currency,Spot Price at Transaction,Subtotal,Total (inclusive of fees and/or spread),Fees and/or Spread,Notes\n\
+            2021-01-22T21:38:01Z,Buy,BTC,2,USD,50.00,100.00,100.00,0,Bought 2 BTC for $100.00 USD\r\n\
+            --boundary\r\n\
+            Content-Disposition: form-data; name=\"trades\"; filename=\"trades.csv\"\r\n\r\n\
+            txid,ordertxid,pair,time,type,ordertype,price,cost,fee,vol,margin,misc,ledgers\n\
+            F1,O1,DOTUSD,2021-07-29 01:19:30.1234,buy,limit,3.00000,9.00000,0.00000,3.00000000,0.00000,,\"L1,L2\"\r\n\
+            --boundary--\r\n";
+        let request = Request::builder()
+            .header(
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=boundary",
+            )
+            .body(Body::from(form))
+            .unwrap();
+
+        let (status_code, Json(response)) =
+            form_8949(unused_pool(), Query(CostBasisOptions::default()), request).await;
+
+        assert_eq!(status_code, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.errors,
+            ["Kraken trades exports can only price the trades of a Kraken ledgers export"]
+        );
+    }
+
+    #[test]
+    fn export_realized_gains_of_a_mapped_csv() {
+        let mapping = ColumnMapping::from_definition(
+            r#"{"name": "wallet", "timestamp": "Date", "dateFormat": "%Y-%m-%d", "asset": "Coin",
+                "quantity": "Amount", "side": {"column": "Type", "inflow": ["in"], "outflow": ["out"]},
+                "fiatValue": "Value"}"#,
+        )
+        .unwrap();
+        let csv = "Date,Type,Coin,Amount,Value\n".to_string()
+            + "2021-01-22,in,BTC,2,$100.00\n"
+            + "2021-03-22,out,BTC,1,$80.00";
+
+        let body = tax_actions::form_8949(
+            csv,
+            None,
+            Vec::new(),
+            CostBasisOptions::default(),
+            Some(mapping),
+        )
+        .response
+        .unwrap();
+
+        assert!(body.contains("Part I,1 BTC,01/22/2021,03/22/2021,80.00,50.00,,0.00,30.00"));
+    }
+
+    #[test]
+    fn price_kraken_trades_from_the_stored_fills() {
+        let csv = "txid,refid,time,type,subtype,aclass,asset,amount,fee,balance\n".to_string()
+            + "L1,T1,2021-07-29 01:19:30,trade,,currency,ZUSD,-10.00000000,0.00000000,0.00000000\n"
+            + "L2,T1,2021-07-29 01:19:30,trade,,currency,DOT,3.00000000,0.00000000,3.00000000\n"
+            + "L3,T2,2021-08-29 01:19:30,trade,,currency,DOT,-3.00000000,0.00000000,0.00000000\n"
+            + "L4,T2,2021-08-29 01:19:30,trade,,currency,ZUSD,12.00000000,0.00000000,12.00000000";
+        let stored_fill = KrakenTradeRecord {
+            txid: "F1".to_string(),
+            ordertxid: "O1".to_string(),
+            pair: "DOTUSD".to_string(),
+            time: "2021-07-29T01:19:30Z".parse::<DateTime<Utc>>().unwrap(),
+            trade_type: TradeSide::Buy,
+            ordertype: "limit".to_string(),
+            price: Decimal::new(3, 0),
+            cost: Decimal::new(9, 0),
+            fee: Decimal::ZERO,
+            vol: Decimal::new(3, 0),
+            margin: Decimal::ZERO,
+            misc: String::new(),
+            ledgers: "L1,L2".to_string(),
+        };
+
+        let body = tax_actions::form_8949(
+            csv,
+            None,
+            vec![stored_fill],
+            CostBasisOptions::default(),
+            None,
+        )
+        .response
+        .unwrap();
+
+        assert!(body.contains("Part I,3 DOT,07/29/2021,08/29/2021,12.00,9.00,,0.00,3.00"));
+    }
+
+    #[actix_rt::test]
+    async fn answer_unavailable_when_stored_fills_can_not_be_read() {
+        let csv = "txid,refid,time,type,subtype,aclass,asset,amount,fee,balance\n".to_string()
+            + "L1,T1,2021-07-29 01:19:30,trade,,currency,ZUSD,-10.00000000,0.00000000,0.00000000\n"
+            + "L2,T1,2021-07-29 01:19:30,trade,,currency,DOT,3.00000000,0.00000000,3.00000000";
+
+        let (status_code, Json(response)) = form_8949(
+            unreachable_pool(),
+            Query(CostBasisOptions {
+                stored_kraken_trades: Some(true),
+                ..CostBasisOptions::default()
+            }),
+            Request::new(Body::from(csv)),
+        )
+        .await;
+
+        assert_eq!(status_code, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(response.errors[0].starts_with("The database is unavailable"));
+    }
+
+    #[actix_rt::test]
+    async fn reject_unrecognized_csv() {
+        let csv = "Something Random,Another Random Column\n".to_string()
+            + "some random data, some random column";
+
+        let (status_code, Json(response)) = form_8949(
+            unused_pool(),
+            Query(CostBasisOptions::default()),
+            Request::new(Body::from(csv)),
+        )
+        .await;
+
+        assert_eq!(status_code, StatusCode::BAD_REQUEST);
+        assert!(!response.success);
+    }
+}
+
+#[cfg(test)]
+mod staking_income_should {
+    extern crate rust_decimal;
+
+    use axum::{extract::Query, http::StatusCode, Json};
+    use rust_decimal::Decimal;
+    use tax_actions::StakingIncomeOptions;
+
+    use super::{staking_income, unused_pool};
+
+    #[actix_rt::test]
+    async fn value_rewards_at_receipt() {
+        let csv = "Timestamp,Transaction Type,Asset,Quantity Transacted,Spot Price Currency,Spot Price at Transaction,Subtotal,Total (inclusive of fees and/or spread),Fees and/or Spread,Notes\n".to_string()
+            + "2021-01-22T21:38:01Z,Rewards Income,ALGO,2,USD,0.50,1.00,1.00,,Received 2 ALGO\n"
+            + "2021-02-22T21:39:01Z,Learning Reward,GRT,4,USD,0.25,1.00,1.00,,Received 4 GRT\n"
+            + "2021-02-23T21:39:01Z,Buy,BTC,1,USD,50.00,50.00,50.00,0,Bought 1 BTC";
+
+        let (status_code, Json(response)) =
+            staking_income(unused_pool(), Query(StakingIncomeOptions::default()), csv).await;
+
+        assert_eq!(status_code, StatusCode::OK);
+        let report = response.response.unwrap();
+        assert_eq!(report.records.len(), 2);
+        assert_eq!(report.monthly_totals.len(), 2);
+        assert_eq!(
+            report.yearly_totals.first().unwrap().usd_value,
+            Decimal::new(2, 0)
+        );
+        assert!(report.unpriced.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn report_rows_that_could_not_be_read() {
+        let csv = "Timestamp,Transaction Type,Asset,Quantity Transacted,Spot Price Currency,Spot Price at Transaction,Subtotal,Total (inclusive of fees and/or spread),Fees and/or Spread,Notes\n".to_string()
+            + "2021-01-22T21:38:01Z,Rewards Income,ALGO,2,USD,0.50,1.00,1.00,,Received 2 ALGO\n"
+            + "2021-02-22T21:39:01Z,Rewards Income,ALGO,two,USD,0.50,1.00,1.00,,Received ALGO";
+
+        let (status_code, Json(response)) =
+            staking_income(unused_pool(), Query(StakingIncomeOptions::default()), csv).await;
+
+        assert_eq!(status_code, StatusCode::OK);
+        assert_eq!(response.response.unwrap().records.len(), 1);
+        assert_eq!(response.errors.len(), 1);
+        assert!(response.errors[0].starts_with("Line 3, column \"Quantity Transacted\""));
+    }
+
+    #[actix_rt::test]
+    async fn report_unrecognized_transaction_types() {
+        let csv = "Timestamp,Transaction Type,Asset,Quantity Transacted,Spot Price Currency,Spot Price at Transaction,Subtotal,Total (inclusive of fees and/or spread),Fees and/or Spread,Notes\n".to_string()
+            + "2023-01-22T21:38:01Z,Staking Income,ETH,0.01,USD,1500.00,15.00,15.00,,Received 0.01 ETH\n"
+            + "2023-02-22T21:39:01Z,Pro Withdrawal,BTC,1,USD,50.00,50.00,50.00,0,Moved 1 BTC";
+
+        let (status_code, Json(response)) =
+            staking_income(unused_pool(), Query(StakingIncomeOptions::default()), csv).await;
+
+        assert_eq!(status_code, StatusCode::OK);
+        assert_eq!(response.response.unwrap().records.len(), 1);
+        assert!(response.messages.contains(
+            &"Skipped 1 transactions of unrecognized type \"Pro Withdrawal\" (BTC)".to_string()
+        ));
+    }
+}
+
+#[cfg(test)]
+mod import_transactions_should {
+    use axum::{
+        body::Body,
+        extract::Query,
+        http::{header, Request, StatusCode},
+        Json,
+    };
+    use import_actions::ImportOptions;
+
+    use super::{
+        import_coinbase_transactions, import_csv, import_kraken_transactions, unreachable_pool,
+        unused_pool,
+    };
+
+    #[actix_rt::test]
+    async fn reject_a_file_without_the_expected_headers() {
+        let csv = "asset,amount\nBTC,1.5\n";
+
+        let (status_code, Json(response)) = import_kraken_transactions(
+            unused_pool(),
+            Query(ImportOptions::default()),
+            Request::new(Body::from(csv)),
+        )
+        .await;
+
+        assert_eq!(status_code, StatusCode::BAD_REQUEST);
+        assert!(!response.success);
+        assert_eq!(
+            response.errors,
+            ["Line 1: None of the first 11 rows hold the expected headers"]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn answer_unavailable_when_the_database_is_down() {
+        let csv = "Timestamp,Transaction Type,Asset,Quantity Transacted,Spot Price Currency,Spot Price at Transaction,Subtotal,Total (inclusive of fees and/or spread),Fees and/or Spread,Notes\n".to_string()
+            + "2021-01-22T21:38:01Z,Buy,BTC,1,USD,50.00,50.00,50.00,0,Bought 1 BTC for $50.00 USD\n";
+
+        let (status_code, Json(response)) = import_coinbase_transactions(
+            unreachable_pool(),
+            Query(ImportOptions::default()),
+            Request::new(Body::from(csv)),
+        )
+        .await;
+
+        assert_eq!(status_code, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(response.response.is_none());
+        assert!(response.errors[0].starts_with("The database is unavailable"));
+    }
+
+    #[actix_rt::test]
+    async fn reject_a_body_that_is_not_text() {
+        let (status_code, Json(response)) = import_coinbase_transactions(
+            unused_pool(),
+            Query(ImportOptions::default()),
+            Request::new(Body::from(vec![0xff, 0xfe, b'\n'])),
+        )
+        .await;
+
+        assert_eq!(status_code, StatusCode::BAD_REQUEST);
+        assert!(response.response.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn explain_why_a_csv_was_not_recognized() {
+        let (status_code, Json(response)) = import_csv(
+            unused_pool(),
+            Query(ImportOptions::default()),
+            Request::new(Body::from("day,note\n2021-09-29,hello\n")),
+        )
+        .await;
+
+        assert_eq!(status_code, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.errors,
+            ["Failed to match csv to known types. None of the headers of a known format were found"]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn refuse_formats_that_are_not_stored() {
+        let csv = "User_ID,UTC_Time,Account,Operation,Coin,Change,Remark\n\
+            12345678,2021-03-01 15:20:30,Spot,Deposit,BTC,0.5,\n";
+
+        let (status_code, Json(response)) = import_csv(
+            unused_pool(),
+            Query(ImportOptions::default()),
+            Request::new(Body::from(csv)),
+        )
+        .await;
+
+        assert_eq!(status_code, StatusCode::BAD_REQUEST);
+        assert!(response.messages[0].starts_with("Recognized as Binance transaction history"));
+        assert_eq!(
+            response.errors,
+            ["Importing Binance transaction history is not supported, it can still be read with parse-csv"]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn reject_a_form_without_a_file() {
+        let form = "--boundary\r\n\
+            Content-Disposition: form-data; name=\"note\"\r\n\r\n\
+            not a file\r\n\
+            --boundary--\r\n";
+        let request = Request::builder()
+            .header(
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=boundary",
+            )
+            .body(Body::from(form))
+            .unwrap();
+
+        let (status_code, Json(response)) =
+            import_csv(unused_pool(), Query(ImportOptions::default()), request).await;
+
+        assert_eq!(status_code, StatusCode::BAD_REQUEST);
+        assert_eq!(response.errors, ["The form does not hold a file"]);
+    }
+}
+
+#[cfg(test)]
+mod db_connection_should {
+    use std::{sync::Mutex, time::Duration};
+
+    use axum::{
+        extract::FromRequestParts,
+        http::{Request, StatusCode},
+    };
+    use crypto_database::kraken_db::models_db::{DBConfig, DBConfigOptions};
+
+    use super::DbConnection;
+
+    #[actix_rt::test]
+    async fn let_other_requests_run_while_waiting_on_the_pool() {
+        let pool = crypto_database::create_pool(Some(DBConfig::new(Some(DBConfigOptions {
+            port: Some("1".to_string()),
+            connection_timeout: Some(Duration::from_millis(500)),
+            ..Default::default()
+        }))));
+        let (mut parts, _) = Request::new(()).into_parts();
+        let finished = Mutex::new(Vec::new());
+
+        tokio::join!(
+            async {
+                let connection = DbConnection::from_request_parts(&mut parts, &pool).await;
+                let Err((status_code, _)) = connection else {
+                    panic!("Borrowed a connection to a database that is not there");
+                };
+                assert_eq!(status_code, StatusCode::SERVICE_UNAVAILABLE);
+                finished.lock().unwrap().push("connection");
+            },
+            async {
+                tokio::task::yield_now().await;
+                finished.lock().unwrap().push("other request");
+            }
+        );
+
+        assert_eq!(*finished.lock().unwrap(), ["other request", "connection"]);
     }
 }
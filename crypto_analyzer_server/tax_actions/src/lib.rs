@@ -0,0 +1,362 @@
+use std::collections::HashSet;
+
+use binance_parser::BinanceParser;
+use coin_gecko::coin_gecko::CoinGeckoPrices;
+use coinbase_parser::{
+    CoinbaseParser, ForeignCurrencyTransaction, UnknownTransactionType, UnknownTransactionTypes,
+    UnreadConversion,
+};
+use cost_basis::{
+    form_8949::Form8949, income_report, Acquisition, CostBasisCalculator, CostBasisEvent,
+    CostBasisEvents, Disposal, IncomeRecord, StakingIncome,
+};
+use gemini_parser::GeminiParser;
+use kraken_parser::{KrakenParser, KrakenTrade, KrakenTradeRecord};
+use mapped_parser::{ColumnMapping, MappedParser};
+use parse_csv::{parse_csv_with_mapping, CsvType, ParseMode};
+use serde::Deserialize;
+use server_response::ServerResponse;
+
+pub use cost_basis::{CostBasisStrategy, IncomeReport};
+
+/// Dates in messages, Form 8949 itself uses the IRS format.
+const MESSAGE_DATE_FORMAT: &str = "%Y-%m-%d";
+
+const KRAKEN_TRADES_ONLY: &str =
+    "Kraken trades exports only carry fill prices, upload the ledgers export to calculate taxes";
+
+const KRAKEN_TRADES_WITHOUT_LEDGERS: &str =
+    "Kraken trades exports can only price the trades of a Kraken ledgers export";
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CostBasisOptions {
+    pub strategy: Option<CostBasisStrategy>,
+    /// Days a lot must be held before its gains are long term, more than one calendar year when not given.
+    pub long_term_days: Option<i64>,
+    /// Name of a stored column mapping to read the csv with.
+    pub mapping: Option<String>,
+    /// Also price Kraken ledger trades from the Kraken trades stored by earlier imports.
+    pub stored_kraken_trades: Option<bool>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct StakingIncomeOptions {
+    /// Name of a stored column mapping to read the csv with.
+    pub mapping: Option<String>,
+}
+
+/// Writes the realized gains of a csv as Form 8949 rows and Schedule D totals, listing what was left out in the errors.
+/// Kraken ledger trades are priced from the fills of a Kraken trades export when one is given, and from the stored
+/// fills, which the fills given take the place of.
+pub fn form_8949(
+    csv: String,
+    kraken_trades: Option<String>,
+    stored_kraken_trades: Vec<KrakenTradeRecord>,
+    options: CostBasisOptions,
+    mapping: Option<ColumnMapping>,
+) -> ServerResponse<String> {
+    let (csv_type, mut errors) = match parse(csv, mapping.as_ref()) {
+        Ok(parsed) => parsed,
+        Err(error) => return ServerResponse::new(None, false, None, None, Some(vec![error])),
+    };
+    let fills = match kraken_trades.map(parse_kraken_trades).transpose() {
+        Ok(Some((fills, row_errors))) => {
+            errors.extend(
+                row_errors
+                    .iter()
+                    .map(|e| format!("Kraken trades file: {e}")),
+            );
+            Some(fills)
+        }
+        Ok(None) => None,
+        Err(error) => return ServerResponse::new(None, false, None, None, Some(vec![error])),
+    };
+    if fills.is_some() && !matches!(csv_type, CsvType::KrakenLedgers(_)) {
+        return ServerResponse::new(
+            None,
+            false,
+            None,
+            None,
+            Some(vec![KRAKEN_TRADES_WITHOUT_LEDGERS.to_string()]),
+        );
+    }
+    let mut unknown = Vec::new();
+    let events: Vec<CostBasisEvent> = match csv_type {
+        CsvType::CoinbaseTransactions(records) => {
+            let coinbase_parser = CoinbaseParser::new(records);
+            unknown = coinbase_parser.unknown_transaction_types();
+            errors.extend(
+                coinbase_parser
+                    .unread_conversions()
+                    .iter()
+                    .map(unread_conversion_message),
+            );
+            errors.extend(
+                coinbase_parser
+                    .foreign_currency_transactions()
+                    .iter()
+                    .map(foreign_currency_message),
+            );
+            let prices = CoinGeckoPrices::new();
+            let mut events = coinbase_parser.cost_basis_events();
+            events.extend(reward_acquisitions(
+                coinbase_parser.staking_income(&prices),
+                &mut errors,
+            ));
+            errors.extend(prices.errors());
+
+            events
+        }
+        CsvType::KrakenLedgers(records) => {
+            // Kraken staking rewards are not part of its trades so they are priced separately to become lots.
+            let kraken_parser = KrakenParser::new(records).with_trades(with_stored_fills(
+                fills.unwrap_or_default(),
+                stored_kraken_trades,
+            ));
+            unknown = kraken_parser.unknown_transaction_types();
+            let prices = CoinGeckoPrices::new();
+            let mut events = kraken_parser.cost_basis_events();
+            events.extend(reward_acquisitions(
+                kraken_parser.staking_income(&prices),
+                &mut errors,
+            ));
+            errors.extend(prices.errors());
+            errors.extend(
+                kraken_parser
+                    .trades_not_in_usd()
+                    .iter()
+                    .map(trade_not_in_usd_message),
+            );
+
+            events
+        }
+        CsvType::KrakenTrades(_) => {
+            return ServerResponse::new(
+                None,
+                false,
+                None,
+                None,
+                Some(vec![KRAKEN_TRADES_ONLY.to_string()]),
+            )
+        }
+        CsvType::BinanceTransactions(_) => return no_cost_basis("Binance transaction history"),
+        CsvType::GeminiTransactions(_) => return no_cost_basis("Gemini transaction history"),
+        CsvType::CoinbaseProFills(_) => return no_cost_basis("Coinbase Pro fills"),
+        CsvType::MappedTransactions(records) => MappedParser::new(records).cost_basis_events(),
+        CsvType::NotRecognized(message) => {
+            return ServerResponse::new(None, false, None, None, Some(vec![message.to_string()]))
+        }
+    };
+
+    let calculator = CostBasisCalculator::new(options.strategy.unwrap_or_default());
+    let report = match options.long_term_days {
+        Some(days) => calculator.with_long_term_threshold(days),
+        None => calculator,
+    }
+    .calculate(events);
+    errors.extend(
+        report
+            .unmatched_disposals
+            .iter()
+            .map(unmatched_disposal_message),
+    );
+
+    let mut csv = Vec::new();
+    match Form8949::new(&report.realized_gains)
+        .write_csv(&mut csv)
+        .map_err(|e| format!("{}", e))
+        .and_then(|_| String::from_utf8(csv).map_err(|e| format!("{}", e)))
+    {
+        Ok(csv) => ServerResponse::new(
+            None,
+            true,
+            Some(csv),
+            Some(unknown.iter().map(unknown_type_message).collect()),
+            Some(errors),
+        ),
+        Err(e) => ServerResponse::new(None, false, None, None, Some(vec![e])),
+    }
+}
+
+pub fn staking_income(csv: String, mapping: Option<ColumnMapping>) -> ServerResponse<IncomeReport> {
+    let prices = CoinGeckoPrices::new();
+    let (csv_type, row_errors) = match parse(csv, mapping.as_ref()) {
+        Ok(parsed) => parsed,
+        Err(error) => return ServerResponse::new(None, false, None, None, Some(vec![error])),
+    };
+    let (records, unknown) = match csv_type {
+        CsvType::CoinbaseTransactions(records) => {
+            let coinbase_parser = CoinbaseParser::new(records);
+            (
+                coinbase_parser.staking_income(&prices),
+                coinbase_parser.unknown_transaction_types(),
+            )
+        }
+        CsvType::KrakenLedgers(records) => {
+            let kraken_parser = KrakenParser::new(records);
+            (
+                kraken_parser.staking_income(&prices),
+                kraken_parser.unknown_transaction_types(),
+            )
+        }
+        CsvType::BinanceTransactions(records) => {
+            let binance_parser = BinanceParser::new(records);
+            (
+                binance_parser.staking_income(&prices),
+                binance_parser.unknown_transaction_types(),
+            )
+        }
+        CsvType::GeminiTransactions(records) => {
+            let gemini_parser = GeminiParser::new(records);
+            (
+                gemini_parser.staking_income(&prices),
+                gemini_parser.unknown_transaction_types(),
+            )
+        }
+        // Coinbase Pro only traded, rewards were paid to the Coinbase account.
+        CsvType::CoinbaseProFills(_) => (Vec::new(), Vec::new()),
+        // Mappings do not say what a row was for, so rewards can not be told apart.
+        CsvType::MappedTransactions(_) => (Vec::new(), Vec::new()),
+        CsvType::KrakenTrades(_) => {
+            return ServerResponse::new(
+                None,
+                false,
+                None,
+                None,
+                Some(vec![KRAKEN_TRADES_ONLY.to_string()]),
+            )
+        }
+        CsvType::NotRecognized(message) => {
+            return ServerResponse::new(None, false, None, None, Some(vec![message.to_string()]))
+        }
+    };
+
+    let report = income_report(records);
+    let mut messages = vec![format!("Found {} rewards", report.records.len())];
+    messages.extend(unknown.iter().map(unknown_type_message));
+    let mut errors = row_errors;
+    if !report.unpriced.is_empty() {
+        errors.push(format!(
+            "{} rewards could not be priced and are left out of the totals",
+            report.unpriced.len()
+        ));
+    }
+    errors.extend(prices.errors());
+
+    ServerResponse::new(None, true, Some(report), Some(messages), Some(errors))
+}
+
+/// Reads a csv leniently, rows that could not be read are skipped and returned as errors for the response.
+fn parse(csv: String, mapping: Option<&ColumnMapping>) -> Result<(CsvType, Vec<String>), String> {
+    parse_csv_with_mapping(csv, mapping, ParseMode::Lenient)
+        .map(|(csv_type, row_errors)| {
+            (
+                csv_type,
+                row_errors.iter().map(|error| error.to_string()).collect(),
+            )
+        })
+        .map_err(|error| error.to_string())
+}
+
+fn parse_kraken_trades(csv: String) -> Result<(Vec<KrakenTradeRecord>, Vec<String>), String> {
+    match parse(csv, None)? {
+        (CsvType::KrakenTrades(fills), row_errors) => Ok((fills, row_errors)),
+        _ => Err("The trades file is not a Kraken trades export".to_string()),
+    }
+}
+
+fn no_cost_basis(export: &str) -> ServerResponse<String> {
+    ServerResponse::new(
+        None,
+        false,
+        None,
+        None,
+        Some(vec![format!(
+            "{export} can not be used for Form 8949 yet, it is only used for holdings and staking income"
+        )]),
+    )
+}
+
+fn with_stored_fills(
+    mut fills: Vec<KrakenTradeRecord>,
+    stored_fills: Vec<KrakenTradeRecord>,
+) -> Vec<KrakenTradeRecord> {
+    let given: HashSet<String> = fills.iter().map(|fill| fill.txid.to_string()).collect();
+    fills.extend(
+        stored_fills
+            .into_iter()
+            .filter(|fill| !given.contains(&fill.txid)),
+    );
+
+    fills
+}
+
+fn trade_not_in_usd_message(trade: &KrakenTrade) -> String {
+    format!(
+        "Kraken trade {} of {} {} for {} {} on {} was not made against USD and is left out of Form 8949",
+        trade.refid,
+        trade.base_quantity.normalize(),
+        trade.base,
+        trade.quote_quantity.normalize(),
+        trade.quote,
+        trade.time.format(MESSAGE_DATE_FORMAT)
+    )
+}
+
+/// Rewards become lots at their value on receipt, rewards that could not be priced are listed in the errors instead.
+fn reward_acquisitions(
+    records: Vec<IncomeRecord>,
+    errors: &mut Vec<String>,
+) -> Vec<CostBasisEvent> {
+    records
+        .iter()
+        .filter_map(|record| match Acquisition::try_from(record) {
+            Ok(acquisition) => Some(CostBasisEvent::Acquisition(acquisition)),
+            Err(e) => {
+                errors.push(format!("{e} and is left out of Form 8949"));
+                None
+            }
+        })
+        .collect()
+}
+
+fn foreign_currency_message(transaction: &ForeignCurrencyTransaction) -> String {
+    format!(
+        "{} of {} {} on {} was valued in {} rather than USD and is left out of Form 8949",
+        transaction.transaction_type,
+        transaction.quantity.normalize(),
+        transaction.asset,
+        transaction.time.format(MESSAGE_DATE_FORMAT),
+        transaction.currency
+    )
+}
+
+fn unread_conversion_message(unread: &UnreadConversion) -> String {
+    format!(
+        "Convert of {} {} on {} could not be read, only the sale is in Form 8949: {}",
+        unread.quantity.normalize(),
+        unread.asset,
+        unread.time.format(MESSAGE_DATE_FORMAT),
+        unread.error
+    )
+}
+
+fn unmatched_disposal_message(disposal: &Disposal) -> String {
+    format!(
+        "{} {} disposed of on {} had no lot to match against and is left out of Form 8949",
+        disposal.quantity.normalize(),
+        disposal.asset,
+        disposal.time.format(MESSAGE_DATE_FORMAT)
+    )
+}
+
+fn unknown_type_message(unknown: &UnknownTransactionType) -> String {
+    format!(
+        "Skipped {} transactions of unrecognized type \"{}\" ({})",
+        unknown.occurrences,
+        unknown.transaction_type,
+        unknown.assets.join(", ")
+    )
+}
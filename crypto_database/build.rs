@@ -0,0 +1,4 @@
+// Migrations are embedded into the crate, so it is rebuilt whenever one changes.
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
@@ -0,0 +1,18 @@
+-- Your SQL goes here
+
+CREATE TABLE kraken_trades (
+    id SERIAL PRIMARY KEY,
+    txid TEXT NOT NULL,
+    ordertxid TEXT NOT NULL,
+    pair TEXT NOT NULL,
+    trade_time TIMESTAMPTZ NOT NULL,
+    trade_type TEXT NOT NULL,
+    ordertype TEXT NOT NULL,
+    price NUMERIC NOT NULL,
+    cost NUMERIC NOT NULL,
+    fee NUMERIC NOT NULL,
+    vol NUMERIC NOT NULL,
+    margin NUMERIC NOT NULL,
+    misc TEXT NOT NULL,
+    ledgers TEXT NOT NULL
+)
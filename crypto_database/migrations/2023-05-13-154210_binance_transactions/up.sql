@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+CREATE TABLE binance_transactions (
+    id SERIAL PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    transaction_time TIMESTAMPTZ NOT NULL,
+    account TEXT NOT NULL,
+    operation TEXT NOT NULL,
+    coin TEXT NOT NULL,
+    change NUMERIC NOT NULL,
+    remark TEXT NOT NULL
+)
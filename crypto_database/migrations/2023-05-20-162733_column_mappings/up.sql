@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+CREATE TABLE column_mappings (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    definition TEXT NOT NULL
+)
@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE kraken_transactions DROP CONSTRAINT kraken_transactions_txid_key;
+INSERT INTO kraken_transactions SELECT * FROM quarantined_kraken_transactions;
+DROP TABLE quarantined_kraken_transactions;
+ALTER TABLE coinbase_transactions DROP CONSTRAINT coinbase_transactions_transaction_key_key;
+INSERT INTO coinbase_transactions SELECT * FROM quarantined_coinbase_transactions;
+DROP TABLE quarantined_coinbase_transactions;
+ALTER TABLE coinbase_transactions DROP COLUMN coinbase_id;
+ALTER TABLE coinbase_transactions DROP COLUMN transaction_key
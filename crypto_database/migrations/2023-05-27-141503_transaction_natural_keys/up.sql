@@ -0,0 +1,48 @@
+-- Your SQL goes here
+ALTER TABLE coinbase_transactions ADD COLUMN transaction_key TEXT;
+ALTER TABLE coinbase_transactions ADD COLUMN coinbase_id TEXT;
+
+-- Stored rows are keyed the same way NewCoinbaseTransaction::key keys new ones, by their Coinbase id when they have
+-- one and by their content otherwise. Trailing zeros of the quantity are trimmed with a pattern, trim_scale only
+-- exists from PostgreSQL 13.
+UPDATE coinbase_transactions
+SET transaction_key = COALESCE(
+    NULLIF(trim(coinbase_id), ''),
+    encode(
+        sha256(convert_to(concat_ws(
+            '|',
+            to_char(time_of_transaction AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"'),
+            transaction_type,
+            asset,
+            regexp_replace(regexp_replace(quantity_transacted::TEXT, '(\.\d*?)0+$', '\1'), '\.$', '')
+        ), 'UTF8')),
+        'hex'
+    )
+);
+
+-- Earlier imports stored duplicates, they are moved aside rather than dropped and the first of each key stays. Rows
+-- with different Coinbase ids have different keys, so they are never taken for duplicates.
+CREATE TABLE quarantined_coinbase_transactions AS
+SELECT duplicate.*
+FROM coinbase_transactions duplicate
+WHERE EXISTS (
+    SELECT 1 FROM coinbase_transactions original
+    WHERE original.transaction_key = duplicate.transaction_key AND original.id < duplicate.id
+);
+
+DELETE FROM coinbase_transactions WHERE id IN (SELECT id FROM quarantined_coinbase_transactions);
+
+ALTER TABLE coinbase_transactions ALTER COLUMN transaction_key SET NOT NULL;
+ALTER TABLE coinbase_transactions ADD CONSTRAINT coinbase_transactions_transaction_key_key UNIQUE (transaction_key);
+
+CREATE TABLE quarantined_kraken_transactions AS
+SELECT duplicate.*
+FROM kraken_transactions duplicate
+WHERE EXISTS (
+    SELECT 1 FROM kraken_transactions original
+    WHERE original.txid = duplicate.txid AND original.id < duplicate.id
+);
+
+DELETE FROM kraken_transactions WHERE id IN (SELECT id FROM quarantined_kraken_transactions);
+
+ALTER TABLE kraken_transactions ADD CONSTRAINT kraken_transactions_txid_key UNIQUE (txid);
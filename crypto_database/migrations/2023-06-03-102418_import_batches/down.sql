@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE kraken_transactions DROP COLUMN import_batch_id;
+ALTER TABLE coinbase_transactions DROP COLUMN import_batch_id;
+DROP TABLE import_batches
@@ -0,0 +1,19 @@
+-- Your SQL goes here
+CREATE TABLE import_batches (
+    id SERIAL PRIMARY KEY,
+    source_format TEXT NOT NULL,
+    filename TEXT,
+    file_hash TEXT NOT NULL,
+    rows_inserted INTEGER NOT NULL DEFAULT 0,
+    rows_duplicate INTEGER NOT NULL DEFAULT 0,
+    rows_failed INTEGER NOT NULL DEFAULT 0,
+    uploaded_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    parse_warnings TEXT[] NOT NULL DEFAULT '{}'
+);
+
+-- Transactions stored before batches existed, or inserted one at a time, belong to no batch.
+ALTER TABLE coinbase_transactions ADD COLUMN import_batch_id INTEGER REFERENCES import_batches (id);
+CREATE INDEX coinbase_transactions_import_batch_id_idx ON coinbase_transactions (import_batch_id);
+
+ALTER TABLE kraken_transactions ADD COLUMN import_batch_id INTEGER REFERENCES import_batches (id);
+CREATE INDEX kraken_transactions_import_batch_id_idx ON kraken_transactions (import_batch_id);
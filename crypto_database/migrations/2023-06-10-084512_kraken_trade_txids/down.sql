@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE kraken_trades DROP CONSTRAINT kraken_trades_txid_key;
+INSERT INTO kraken_trades SELECT * FROM quarantined_kraken_trades;
+DROP TABLE quarantined_kraken_trades
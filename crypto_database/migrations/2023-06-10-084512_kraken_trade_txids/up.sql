@@ -0,0 +1,14 @@
+-- Your SQL goes here
+
+-- Trades stored more than once are moved aside rather than dropped, the first of each txid stays.
+CREATE TABLE quarantined_kraken_trades AS
+SELECT duplicate.*
+FROM kraken_trades duplicate
+WHERE EXISTS (
+    SELECT 1 FROM kraken_trades original
+    WHERE original.txid = duplicate.txid AND original.id < duplicate.id
+);
+
+DELETE FROM kraken_trades WHERE id IN (SELECT id FROM quarantined_kraken_trades);
+
+ALTER TABLE kraken_trades ADD CONSTRAINT kraken_trades_txid_key UNIQUE (txid);
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE kraken_trades DROP COLUMN import_batch_id
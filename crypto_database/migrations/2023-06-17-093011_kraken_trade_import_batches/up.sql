@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+-- Trades stored before they were imported in batches belong to no batch.
+ALTER TABLE kraken_trades ADD COLUMN import_batch_id INTEGER REFERENCES import_batches (id);
+CREATE INDEX kraken_trades_import_batch_id_idx ON kraken_trades (import_batch_id);
@@ -1,6 +1,26 @@
-use diesel::*;
+use diesel::{
+    r2d2::{ConnectionManager, Pool, PooledConnection},
+    *,
+};
 use models_db::DBConfig;
 
+pub use diesel::r2d2::PoolError;
+
+/// Connections shared by every request of the server.
+pub type DbPool = Pool<ConnectionManager<PgConnection>>;
+pub type PooledPgConnection = PooledConnection<ConnectionManager<PgConnection>>;
+
+/// The rows of a bulk upsert, split by whether they were stored before.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Upserted<T, N> {
+    pub inserted: Vec<T>,
+    /// Rows that were already stored, as they were given.
+    pub existing: Vec<N>,
+}
+
+/// Rows written by one bulk insert, well under the 65535 bind parameters Postgres allows a statement.
+pub const INSERT_CHUNK_SIZE: usize = 1_000;
+
 pub fn establish_connection(config: Option<DBConfig>) -> Result<PgConnection, ConnectionError> {
     let config = config.unwrap_or_default();
     println!(
@@ -13,7 +33,29 @@ pub fn establish_connection(config: Option<DBConfig>) -> Result<PgConnection, Co
     PgConnection::establish(&config.connection_string())
 }
 
+/// Pools connections to the configured database. Connections are made as they are needed, so the pool is created
+/// while the database is down and lends connections once it is back. The server starts on such a pool and migrates
+/// the database once it can reach it.
+pub fn create_pool(config: Option<DBConfig>) -> DbPool {
+    let config = config.unwrap_or_default();
+    println!(
+        "Pooling up to {} connections to {}:{}/{}",
+        config.get_max_connections(),
+        config.get_host(),
+        config.get_port(),
+        config.get_database_name()
+    );
+
+    Pool::builder()
+        .max_size(config.get_max_connections())
+        .min_idle(Some(0))
+        .connection_timeout(config.get_connection_timeout())
+        .build_unchecked(ConnectionManager::new(config.connection_string()))
+}
+
 pub mod coinbase_db {
+    use std::collections::HashSet;
+
     use diesel::{prelude::*, result::Error};
     pub use models_db::{
         self,
@@ -21,12 +63,15 @@ pub mod coinbase_db {
         CoinbaseTransaction, NewCoinbaseTransaction, Pagination,
     };
 
+    use crate::Upserted;
+
     pub fn insert_coinbase_transaction(
         new_coinbase_transaction: NewCoinbaseTransaction,
         connection: &mut PgConnection,
     ) -> Result<CoinbaseTransaction, Error> {
         diesel::insert_into(coinbase_transactions)
-            .values(&new_coinbase_transaction)
+            .values(&new_coinbase_transaction.keyed())
+            .returning(CoinbaseTransaction::as_returning())
             .get_result::<CoinbaseTransaction>(connection)
     }
 
@@ -34,16 +79,62 @@ pub mod coinbase_db {
         new_coinbase_transactions: Vec<NewCoinbaseTransaction>,
         connection: &mut PgConnection,
     ) -> Result<Vec<CoinbaseTransaction>, Error> {
+        let new_coinbase_transactions: Vec<NewCoinbaseTransaction> = new_coinbase_transactions
+            .into_iter()
+            .map(NewCoinbaseTransaction::keyed)
+            .collect();
+
         diesel::insert_into(coinbase_transactions)
             .values(&new_coinbase_transactions)
+            .returning(CoinbaseTransaction::as_returning())
             .get_results::<CoinbaseTransaction>(connection)
     }
 
+    /// Inserts the transactions whose key is not stored yet, the rest are returned as already present. A key
+    /// repeated within the batch is inserted once.
+    pub fn upsert_coinbase_transactions(
+        new_coinbase_transactions: Vec<NewCoinbaseTransaction>,
+        connection: &mut PgConnection,
+    ) -> Result<Upserted<CoinbaseTransaction, NewCoinbaseTransaction>, Error> {
+        let new_coinbase_transactions: Vec<NewCoinbaseTransaction> = new_coinbase_transactions
+            .into_iter()
+            .map(NewCoinbaseTransaction::keyed)
+            .collect();
+
+        let inserted: Vec<(CoinbaseTransaction, String)> =
+            diesel::insert_into(coinbase_transactions)
+                .values(&new_coinbase_transactions)
+                .on_conflict(schema::coinbase_transactions::transaction_key)
+                .do_nothing()
+                .returning((
+                    CoinbaseTransaction::as_returning(),
+                    schema::coinbase_transactions::transaction_key,
+                ))
+                .get_results(connection)?;
+
+        let mut inserted_keys: HashSet<&str> =
+            inserted.iter().map(|(_, key)| key.as_str()).collect();
+        let existing = new_coinbase_transactions
+            .iter()
+            .filter(|transaction| !inserted_keys.remove(transaction.transaction_key.as_str()))
+            .cloned()
+            .collect();
+
+        Ok(Upserted {
+            inserted: inserted
+                .into_iter()
+                .map(|(transaction, _)| transaction)
+                .collect(),
+            existing,
+        })
+    }
+
     pub fn get_coinbase_transactions(
         pagination: &Pagination,
         connection: &mut PgConnection,
     ) -> Result<Vec<CoinbaseTransaction>, Error> {
         coinbase_transactions
+            .select(CoinbaseTransaction::as_select())
             .offset(pagination.items_per_page * pagination.page)
             .limit(pagination.items_per_page)
             .get_results::<CoinbaseTransaction>(connection)
@@ -55,11 +146,14 @@ pub mod coinbase_db {
     ) -> Result<CoinbaseTransaction, Error> {
         coinbase_transactions
             .find(id)
+            .select(CoinbaseTransaction::as_select())
             .get_result::<CoinbaseTransaction>(connection)
     }
 }
 
 pub mod kraken_db {
+    use std::collections::HashSet;
+
     use diesel::{prelude::*, result::Error};
     pub use models_db::{
         self,
@@ -67,6 +161,8 @@ pub mod kraken_db {
         KrakenTransaction, NewKrakenTransaction, Pagination,
     };
 
+    use crate::Upserted;
+
     pub fn insert_kraken_transaction(
         new_kraken_transaction: NewKrakenTransaction,
         connection: &mut PgConnection,
@@ -85,6 +181,36 @@ pub mod kraken_db {
             .get_results::<KrakenTransaction>(connection)
     }
 
+    /// Inserts the transactions whose `txid` is not stored yet, the rest are returned as already present. A `txid`
+    /// repeated within the batch is inserted once, transactions without one are always inserted.
+    pub fn upsert_kraken_transactions(
+        new_kraken_transactions: Vec<NewKrakenTransaction>,
+        connection: &mut PgConnection,
+    ) -> Result<Upserted<KrakenTransaction, NewKrakenTransaction>, Error> {
+        let inserted = diesel::insert_into(kraken_transactions)
+            .values(&new_kraken_transactions)
+            .on_conflict(schema::kraken_transactions::txid)
+            .do_nothing()
+            .get_results::<KrakenTransaction>(connection)?;
+
+        let mut inserted_txids: HashSet<&str> = inserted
+            .iter()
+            .filter_map(|transaction| transaction.txid.as_deref())
+            .collect();
+        let existing = new_kraken_transactions
+            .iter()
+            .filter(|transaction| {
+                transaction
+                    .txid
+                    .as_deref()
+                    .is_some_and(|txid| !inserted_txids.remove(txid))
+            })
+            .cloned()
+            .collect();
+
+        Ok(Upserted { inserted, existing })
+    }
+
     pub fn get_kraken_transactions(
         pagination: &Pagination,
         connection: &mut PgConnection,
@@ -104,3 +230,408 @@ pub mod kraken_db {
             .get_result::<KrakenTransaction>(connection)
     }
 }
+
+pub mod kraken_trades_db {
+    use std::collections::HashSet;
+
+    use diesel::{prelude::*, result::Error};
+    pub use models_db::{
+        self,
+        schema::{self, kraken_trades::dsl::kraken_trades},
+        KrakenTradeFill, NewKrakenTradeFill, Pagination,
+    };
+
+    use crate::Upserted;
+
+    pub fn insert_kraken_trade(
+        new_kraken_trade: NewKrakenTradeFill,
+        connection: &mut PgConnection,
+    ) -> Result<KrakenTradeFill, Error> {
+        diesel::insert_into(kraken_trades)
+            .values(&new_kraken_trade)
+            .get_result::<KrakenTradeFill>(connection)
+    }
+
+    pub fn bulk_insert_kraken_trades(
+        new_kraken_trades: Vec<NewKrakenTradeFill>,
+        connection: &mut PgConnection,
+    ) -> Result<Vec<KrakenTradeFill>, Error> {
+        diesel::insert_into(kraken_trades)
+            .values(&new_kraken_trades)
+            .get_results::<KrakenTradeFill>(connection)
+    }
+
+    /// Inserts the fills whose `txid` is not stored yet, the rest are returned as already present. A `txid` repeated
+    /// within the batch is inserted once.
+    pub fn upsert_kraken_trades(
+        new_kraken_trades: Vec<NewKrakenTradeFill>,
+        connection: &mut PgConnection,
+    ) -> Result<Upserted<KrakenTradeFill, NewKrakenTradeFill>, Error> {
+        let inserted = diesel::insert_into(kraken_trades)
+            .values(&new_kraken_trades)
+            .on_conflict(schema::kraken_trades::txid)
+            .do_nothing()
+            .get_results::<KrakenTradeFill>(connection)?;
+
+        let mut inserted_txids: HashSet<&str> =
+            inserted.iter().map(|fill| fill.txid.as_str()).collect();
+        let existing = new_kraken_trades
+            .iter()
+            .filter(|fill| !inserted_txids.remove(fill.txid.as_str()))
+            .cloned()
+            .collect();
+
+        Ok(Upserted { inserted, existing })
+    }
+
+    pub fn get_kraken_trades(
+        pagination: &Pagination,
+        connection: &mut PgConnection,
+    ) -> Result<Vec<KrakenTradeFill>, Error> {
+        kraken_trades
+            .offset(pagination.items_per_page * pagination.page)
+            .limit(pagination.items_per_page)
+            .get_results::<KrakenTradeFill>(connection)
+    }
+
+    /// Every stored fill, oldest first.
+    pub fn get_all_kraken_trades(
+        connection: &mut PgConnection,
+    ) -> Result<Vec<KrakenTradeFill>, Error> {
+        kraken_trades
+            .order(schema::kraken_trades::trade_time)
+            .get_results::<KrakenTradeFill>(connection)
+    }
+
+    pub fn get_kraken_trade(
+        id: i32,
+        connection: &mut PgConnection,
+    ) -> Result<KrakenTradeFill, Error> {
+        kraken_trades
+            .find(id)
+            .get_result::<KrakenTradeFill>(connection)
+    }
+}
+
+pub mod binance_db {
+    use diesel::{prelude::*, result::Error};
+    pub use models_db::{
+        self,
+        schema::{self, binance_transactions::dsl::binance_transactions},
+        BinanceTransaction, NewBinanceTransaction, Pagination,
+    };
+
+    pub fn insert_binance_transaction(
+        new_binance_transaction: NewBinanceTransaction,
+        connection: &mut PgConnection,
+    ) -> Result<BinanceTransaction, Error> {
+        diesel::insert_into(binance_transactions)
+            .values(&new_binance_transaction)
+            .get_result::<BinanceTransaction>(connection)
+    }
+
+    pub fn bulk_insert_binance_transaction(
+        new_binance_transactions: Vec<NewBinanceTransaction>,
+        connection: &mut PgConnection,
+    ) -> Result<Vec<BinanceTransaction>, Error> {
+        diesel::insert_into(binance_transactions)
+            .values(&new_binance_transactions)
+            .get_results::<BinanceTransaction>(connection)
+    }
+
+    pub fn get_binance_transactions(
+        pagination: &Pagination,
+        connection: &mut PgConnection,
+    ) -> Result<Vec<BinanceTransaction>, Error> {
+        binance_transactions
+            .offset(pagination.items_per_page * pagination.page)
+            .limit(pagination.items_per_page)
+            .get_results::<BinanceTransaction>(connection)
+    }
+
+    pub fn get_binance_transaction(
+        id: i32,
+        connection: &mut PgConnection,
+    ) -> Result<BinanceTransaction, Error> {
+        binance_transactions
+            .find(id)
+            .get_result::<BinanceTransaction>(connection)
+    }
+}
+
+pub mod column_mapping_db {
+    use diesel::{prelude::*, result::Error};
+    pub use models_db::{
+        self,
+        schema::{
+            self,
+            column_mappings::dsl::{column_mappings, definition, name},
+        },
+        NewStoredColumnMapping, Pagination, StoredColumnMapping,
+    };
+
+    /// Stores the mapping, replacing the definition of a mapping with the same name.
+    pub fn insert_column_mapping(
+        new_column_mapping: NewStoredColumnMapping,
+        connection: &mut PgConnection,
+    ) -> Result<StoredColumnMapping, Error> {
+        diesel::insert_into(column_mappings)
+            .values(&new_column_mapping)
+            .on_conflict(name)
+            .do_update()
+            .set(definition.eq(&new_column_mapping.definition))
+            .get_result::<StoredColumnMapping>(connection)
+    }
+
+    pub fn get_column_mappings(
+        pagination: &Pagination,
+        connection: &mut PgConnection,
+    ) -> Result<Vec<StoredColumnMapping>, Error> {
+        column_mappings
+            .order(name)
+            .offset(pagination.items_per_page * pagination.page)
+            .limit(pagination.items_per_page)
+            .get_results::<StoredColumnMapping>(connection)
+    }
+
+    pub fn get_column_mapping(
+        id: i32,
+        connection: &mut PgConnection,
+    ) -> Result<StoredColumnMapping, Error> {
+        column_mappings
+            .find(id)
+            .get_result::<StoredColumnMapping>(connection)
+    }
+
+    pub fn get_column_mapping_by_name(
+        mapping_name: &str,
+        connection: &mut PgConnection,
+    ) -> Result<StoredColumnMapping, Error> {
+        column_mappings
+            .filter(name.eq(mapping_name))
+            .get_result::<StoredColumnMapping>(connection)
+    }
+}
+
+pub mod import_batch_db {
+    use diesel::{prelude::*, result::Error};
+    pub use models_db::{
+        self,
+        schema::{
+            self,
+            import_batches::dsl::{id, import_batches},
+        },
+        CoinbaseTransaction, ImportBatch, ImportBatchTransactions, ImportBatchUpdate,
+        KrakenTradeFill, KrakenTransaction, NewImportBatch, Pagination, RolledBackImportBatch,
+    };
+
+    pub fn insert_import_batch(
+        new_import_batch: NewImportBatch,
+        connection: &mut PgConnection,
+    ) -> Result<ImportBatch, Error> {
+        diesel::insert_into(import_batches)
+            .values(&new_import_batch)
+            .get_result::<ImportBatch>(connection)
+    }
+
+    /// Records what was learned about a batch once its file has been read.
+    pub fn update_import_batch(
+        import_batch_id: i32,
+        import_batch: ImportBatchUpdate,
+        connection: &mut PgConnection,
+    ) -> Result<ImportBatch, Error> {
+        diesel::update(import_batches.find(import_batch_id))
+            .set(&import_batch)
+            .get_result::<ImportBatch>(connection)
+    }
+
+    /// Batches from the most recent upload back.
+    pub fn get_import_batches(
+        pagination: &Pagination,
+        connection: &mut PgConnection,
+    ) -> Result<Vec<ImportBatch>, Error> {
+        import_batches
+            .order(id.desc())
+            .offset(pagination.items_per_page * pagination.page)
+            .limit(pagination.items_per_page)
+            .get_results::<ImportBatch>(connection)
+    }
+
+    pub fn get_import_batch(
+        import_batch_id: i32,
+        connection: &mut PgConnection,
+    ) -> Result<ImportBatch, Error> {
+        import_batches
+            .find(import_batch_id)
+            .get_result::<ImportBatch>(connection)
+    }
+
+    /// The batch along with the same page of each kind of transaction it stored.
+    pub fn get_import_batch_transactions(
+        import_batch_id: i32,
+        pagination: &Pagination,
+        connection: &mut PgConnection,
+    ) -> Result<ImportBatchTransactions, Error> {
+        use schema::{coinbase_transactions, kraken_trades, kraken_transactions};
+
+        let import_batch = get_import_batch(import_batch_id, connection)?;
+        let coinbase_transactions = coinbase_transactions::table
+            .filter(coinbase_transactions::import_batch_id.eq(import_batch_id))
+            .order(coinbase_transactions::id)
+            .select(CoinbaseTransaction::as_select())
+            .offset(pagination.items_per_page * pagination.page)
+            .limit(pagination.items_per_page)
+            .get_results::<CoinbaseTransaction>(connection)?;
+        let kraken_transactions = kraken_transactions::table
+            .filter(kraken_transactions::import_batch_id.eq(import_batch_id))
+            .order(kraken_transactions::id)
+            .offset(pagination.items_per_page * pagination.page)
+            .limit(pagination.items_per_page)
+            .get_results::<KrakenTransaction>(connection)?;
+        let kraken_trades = kraken_trades::table
+            .filter(kraken_trades::import_batch_id.eq(import_batch_id))
+            .order(kraken_trades::id)
+            .offset(pagination.items_per_page * pagination.page)
+            .limit(pagination.items_per_page)
+            .get_results::<KrakenTradeFill>(connection)?;
+
+        Ok(ImportBatchTransactions {
+            import_batch,
+            coinbase_transactions,
+            kraken_transactions,
+            kraken_trades,
+        })
+    }
+
+    /// Removes the batch and every transaction it stored, or nothing at all if any of it can not be removed.
+    pub fn rollback_import_batch(
+        import_batch_id: i32,
+        connection: &mut PgConnection,
+    ) -> Result<RolledBackImportBatch, Error> {
+        use schema::{coinbase_transactions, kraken_trades, kraken_transactions};
+
+        connection.transaction(|connection| {
+            let coinbase_transactions = diesel::delete(
+                coinbase_transactions::table
+                    .filter(coinbase_transactions::import_batch_id.eq(import_batch_id)),
+            )
+            .execute(connection)?;
+            let kraken_transactions = diesel::delete(
+                kraken_transactions::table
+                    .filter(kraken_transactions::import_batch_id.eq(import_batch_id)),
+            )
+            .execute(connection)?;
+            let kraken_trades = diesel::delete(
+                kraken_trades::table.filter(kraken_trades::import_batch_id.eq(import_batch_id)),
+            )
+            .execute(connection)?;
+            let import_batch = diesel::delete(import_batches.find(import_batch_id))
+                .get_result::<ImportBatch>(connection)?;
+
+            Ok(RolledBackImportBatch {
+                import_batch,
+                coinbase_transactions,
+                kraken_transactions,
+                kraken_trades,
+            })
+        })
+    }
+}
+
+pub mod migrations {
+    use std::{error::Error, fmt};
+
+    use diesel::{migration::MigrationSource, pg::Pg, PgConnection};
+    use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+
+    /// The migrations under `crypto_database/migrations`, built into the binary so it can migrate any database it
+    /// is pointed at.
+    pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
+
+    /// Where the schema of a database stands against the migrations built into this binary. Versions are the
+    /// timestamps migrations are named with.
+    #[derive(Debug, PartialEq, Eq, Clone)]
+    pub struct SchemaVersion {
+        /// Latest migration applied to the database, `None` before any was.
+        pub current: Option<String>,
+        /// Latest migration built into this binary.
+        pub latest: Option<String>,
+        /// Migrations built into this binary the database has not had yet.
+        pub pending: Vec<String>,
+    }
+
+    #[derive(Debug)]
+    pub enum MigrationError {
+        /// The database had migrations applied this binary does not have, holding their versions.
+        DatabaseAhead(Vec<String>),
+        Failed(Box<dyn Error + Send + Sync>),
+    }
+
+    impl From<Box<dyn Error + Send + Sync>> for MigrationError {
+        fn from(error: Box<dyn Error + Send + Sync>) -> Self {
+            MigrationError::Failed(error)
+        }
+    }
+
+    impl fmt::Display for MigrationError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                MigrationError::DatabaseAhead(versions) => write!(
+                    f,
+                    "The database was migrated by a newer build, this build does not have migrations {}",
+                    versions.join(", ")
+                ),
+                MigrationError::Failed(error) => write!(f, "Failed to migrate the database: {error}"),
+            }
+        }
+    }
+
+    impl Error for MigrationError {}
+
+    /// Compares the migrations applied to the database with the ones built into this binary. A database that has
+    /// never been migrated gets the table diesel tracks migrations in.
+    pub fn schema_version(connection: &mut PgConnection) -> Result<SchemaVersion, MigrationError> {
+        let built_in: Vec<String> = MigrationSource::<Pg>::migrations(&MIGRATIONS)?
+            .iter()
+            .map(|migration| migration.name().version().to_string())
+            .collect();
+        let applied: Vec<String> = connection
+            .applied_migrations()?
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        let unknown: Vec<String> = applied
+            .iter()
+            .filter(|version| !built_in.contains(version))
+            .cloned()
+            .collect();
+        if !unknown.is_empty() {
+            return Err(MigrationError::DatabaseAhead(unknown));
+        }
+
+        Ok(SchemaVersion {
+            current: applied.iter().max().cloned(),
+            latest: built_in.iter().max().cloned(),
+            pending: built_in
+                .into_iter()
+                .filter(|version| !applied.contains(version))
+                .collect(),
+        })
+    }
+
+    /// Applies the migrations the database has not had yet, returning the versions applied along with where the
+    /// schema stands after. A database a newer build has migrated is left as is.
+    pub fn run_pending_migrations(
+        connection: &mut PgConnection,
+    ) -> Result<(Vec<String>, SchemaVersion), MigrationError> {
+        schema_version(connection)?;
+        let applied = connection
+            .run_pending_migrations(MIGRATIONS)?
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        Ok((applied, schema_version(connection)?))
+    }
+}
@@ -0,0 +1,107 @@
+mod common;
+
+mod binance_db_should {
+    use chrono::DateTime;
+    use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+
+    use crate::common::create_test_context;
+    use crypto_database::binance_db;
+    use models::binance::{BinanceOperation, BinanceTransactionRecord};
+    use models_db::{BinanceTransaction, NewBinanceTransaction};
+    use rand::{self, Rng};
+    use rust_decimal::Decimal;
+
+    pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
+    const BINANCE_DB_NAME: &str = "binance_test_database";
+
+    #[test]
+    fn read_back_the_transaction_record() {
+        let test_context = create_test_context(Some(BINANCE_DB_NAME.to_owned()));
+        let mut db_connection = test_context.create_connection();
+        db_connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let new_binance_transaction = NewBinanceTransaction {
+            operation: "Simple Earn Flexible Interest".to_string(),
+            ..create_random_binance()
+        };
+        let inserted = binance_db::insert_binance_transaction(
+            new_binance_transaction.clone(),
+            &mut db_connection,
+        )
+        .unwrap();
+        let stored = binance_db::get_binance_transaction(inserted.id, &mut db_connection).unwrap();
+        let record = BinanceTransactionRecord::from(&stored);
+
+        assert_eq!(
+            stored,
+            create_binance_transaction_from_new(new_binance_transaction.clone(), inserted.id)
+        );
+        assert_eq!(
+            record.operation,
+            BinanceOperation::SimpleEarnFlexibleInterest
+        );
+        assert_eq!(record.change, new_binance_transaction.change);
+        assert_eq!(record.user_id, new_binance_transaction.user_id);
+    }
+
+    #[test]
+    fn keep_operations_that_are_not_known() {
+        let test_context = create_test_context(Some(BINANCE_DB_NAME.to_owned()));
+        let mut db_connection = test_context.create_connection();
+        db_connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let inserted = binance_db::insert_binance_transaction(
+            NewBinanceTransaction {
+                operation: "Crypto Box".to_string(),
+                ..create_random_binance()
+            },
+            &mut db_connection,
+        )
+        .unwrap();
+        let record = BinanceTransactionRecord::from(&inserted);
+
+        assert!(record.operation.is_unknown());
+        assert_eq!(record.operation.as_str(), "Crypto Box");
+    }
+
+    fn create_random_binance() -> NewBinanceTransaction {
+        let coins = ["ADA", "BTC", "SOL", "ETH"];
+        let operations = ["Deposit", "Staking Rewards", "Transaction Buy"];
+        let mut rng = rand::thread_rng();
+
+        let coin = coins
+            .get(rng.gen_range(0..coins.len()))
+            .unwrap()
+            .to_string();
+        let operation = operations
+            .get(rng.gen_range(0..operations.len()))
+            .unwrap()
+            .to_string();
+
+        NewBinanceTransaction {
+            user_id: rng.gen_range(10000000..99999999).to_string(),
+            transaction_time: DateTime::default(),
+            account: "Spot".to_string(),
+            operation,
+            coin,
+            change: Decimal::new(rng.gen_range(-100000..100000), rng.gen_range(0..8)),
+            remark: "".to_string(),
+        }
+    }
+
+    fn create_binance_transaction_from_new(
+        new_binance_transaction: NewBinanceTransaction,
+        id: i32,
+    ) -> BinanceTransaction {
+        BinanceTransaction {
+            id,
+            user_id: new_binance_transaction.user_id,
+            transaction_time: new_binance_transaction.transaction_time,
+            account: new_binance_transaction.account,
+            operation: new_binance_transaction.operation,
+            coin: new_binance_transaction.coin,
+            change: new_binance_transaction.change,
+            remark: new_binance_transaction.remark,
+        }
+    }
+}
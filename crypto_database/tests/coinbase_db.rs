@@ -3,7 +3,7 @@ mod common;
 mod coinbase_db_should {
     use rand::{self, Rng};
 
-    use chrono::{DateTime, Utc};
+    use chrono::{DateTime, TimeZone, Utc};
     use crypto_database::{
         self,
         coinbase_db::{
@@ -11,8 +11,9 @@ mod coinbase_db_should {
             schema::coinbase_transactions, CoinbaseTransaction, NewCoinbaseTransaction, Pagination,
         },
     };
-    use diesel::prelude::*;
+    use diesel::{dsl::sql, migration::MigrationSource, prelude::*, sql_types::BigInt};
     use rust_decimal::Decimal;
+    use uuid::Uuid;
 
     use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 
@@ -20,6 +21,8 @@ mod coinbase_db_should {
 
     pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
     pub const COINBASE_DB_NAME: &str = "coinbase_test_database";
+    /// Version of the migration that keyed the stored transactions.
+    const NATURAL_KEYS_VERSION: &str = "20230527141503";
 
     #[test]
     fn insert_coinbase_data() {
@@ -74,7 +77,8 @@ mod coinbase_db_should {
         // Add the transactions above directly to the database, not using crypto_database.
         let inserted_transactions = diesel::insert_into(coinbase_transactions::table)
             .values(&transactions_to_add)
-            .get_results::<CoinbaseTransaction>(&mut test_connection)
+            .returning(CoinbaseTransaction::as_returning())
+            .get_results(&mut test_connection)
             .unwrap();
 
         // Get the transactions from the database using crypto_database
@@ -116,7 +120,8 @@ mod coinbase_db_should {
         // Add the transactions above directly to the database, not using crypto_database.
         let inserted_transactions = diesel::insert_into(coinbase_transactions::table)
             .values(&transactions_to_add)
-            .get_results::<CoinbaseTransaction>(&mut test_connection)
+            .returning(CoinbaseTransaction::as_returning())
+            .get_results(&mut test_connection)
             .unwrap();
         assert_eq!(transactions_to_add.len(), inserted_transactions.len());
 
@@ -177,26 +182,159 @@ mod coinbase_db_should {
         }
     }
 
+    #[test]
+    fn skip_transactions_already_stored() {
+        let ctx = create_test_context(Some(COINBASE_DB_NAME.to_owned()));
+
+        let mut test_connection = ctx.create_connection();
+        test_connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let stored = create_random_new_coinbase_transaction();
+        insert_coinbase_transaction(stored.clone(), &mut test_connection).unwrap();
+
+        let new = create_random_new_coinbase_transaction();
+        let upserted = coinbase_db::upsert_coinbase_transactions(
+            vec![stored.clone(), new.clone(), new.clone()],
+            &mut test_connection,
+        )
+        .unwrap();
+
+        assert_eq!(upserted.inserted.len(), 1);
+        assert_eq!(
+            upserted.inserted[0],
+            create_coinbase_transaction_from_new(new.clone(), upserted.inserted[0].id)
+        );
+        assert!(upserted.existing == [stored, new]);
+
+        let transactions =
+            get_coinbase_transactions(&Pagination::default(), &mut test_connection).unwrap();
+        assert_eq!(transactions.len(), 2);
+    }
+
+    #[test]
+    fn key_transactions_by_their_coinbase_id() {
+        let ctx = create_test_context(Some(COINBASE_DB_NAME.to_owned()));
+
+        let mut test_connection = ctx.create_connection();
+        test_connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let fill = create_random_new_coinbase_transaction();
+        // Another fill of the same order, in the same second and of the same size.
+        let same_content = NewCoinbaseTransaction {
+            coinbase_id: Some(Uuid::new_v4().to_string()),
+            ..fill.clone()
+        };
+        let imported_again = NewCoinbaseTransaction {
+            notes: "Imported again".to_string(),
+            ..fill.clone()
+        };
+
+        let first = coinbase_db::upsert_coinbase_transactions(
+            vec![fill, same_content],
+            &mut test_connection,
+        )
+        .unwrap();
+        let second =
+            coinbase_db::upsert_coinbase_transactions(vec![imported_again], &mut test_connection)
+                .unwrap();
+
+        assert_eq!(first.inserted.len(), 2);
+        assert!(second.inserted.is_empty());
+        assert_eq!(second.existing.len(), 1);
+    }
+
+    #[test]
+    fn key_transactions_without_an_id_by_their_content() {
+        let ctx = create_test_context(Some(COINBASE_DB_NAME.to_owned()));
+
+        let mut test_connection = ctx.create_connection();
+        test_connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let legacy = NewCoinbaseTransaction {
+            coinbase_id: None,
+            transaction_key: String::new(),
+            ..create_random_new_coinbase_transaction()
+        };
+        let exported_again = NewCoinbaseTransaction {
+            notes: "Exported again".to_string(),
+            ..legacy.clone()
+        };
+
+        let first =
+            coinbase_db::upsert_coinbase_transactions(vec![legacy], &mut test_connection).unwrap();
+        let second =
+            coinbase_db::upsert_coinbase_transactions(vec![exported_again], &mut test_connection)
+                .unwrap();
+
+        assert_eq!(first.inserted.len(), 1);
+        assert!(second.inserted.is_empty());
+        assert_eq!(second.existing.len(), 1);
+    }
+
+    #[test]
+    fn quarantine_transactions_stored_twice_before_keys_existed() {
+        let ctx = create_test_context(Some(COINBASE_DB_NAME.to_owned()));
+        let mut test_connection = ctx.create_connection();
+        // Creates the table diesel tracks migrations in.
+        test_connection.applied_migrations().unwrap();
+        let mut migrations = MIGRATIONS.migrations().unwrap();
+        migrations.sort_by_key(|migration| migration.name().version().to_string());
+        for migration in migrations.iter().filter(|migration| {
+            migration.name().version().to_string().as_str() < NATURAL_KEYS_VERSION
+        }) {
+            test_connection.run_migration(migration).unwrap();
+        }
+
+        let stored_twice = "INSERT INTO coinbase_transactions \
+            (time_of_transaction, transaction_type, asset, quantity_transacted, spot_price_currency, notes) \
+            VALUES ('2021-01-22T21:38:01Z', 'Buy', 'BTC', 1.6458000, 'USD', 'Bought BTC')";
+        diesel::sql_query(stored_twice)
+            .execute(&mut test_connection)
+            .unwrap();
+        diesel::sql_query(stored_twice)
+            .execute(&mut test_connection)
+            .unwrap();
+        test_connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let keys: Vec<String> = coinbase_transactions::table
+            .select(coinbase_transactions::transaction_key)
+            .load(&mut test_connection)
+            .unwrap();
+        let quarantined: i64 = diesel::select(sql::<BigInt>(
+            "(SELECT count(*) FROM quarantined_coinbase_transactions)",
+        ))
+        .get_result(&mut test_connection)
+        .unwrap();
+        let expected_key = NewCoinbaseTransaction {
+            time_of_transaction: "2021-01-22T21:38:01Z".parse().unwrap(),
+            transaction_type: "Buy".to_string(),
+            asset: "BTC".to_string(),
+            quantity_transacted: Decimal::new(16458, 4),
+            ..create_random_new_coinbase_transaction()
+        }
+        .content_key();
+        assert_eq!(keys, [expected_key]);
+        assert_eq!(quarantined, 1);
+    }
+
     fn create_random_new_coinbase_transaction() -> NewCoinbaseTransaction {
-        let assets = vec!["ADA", "BTC", "SOL", "ETH"];
+        let assets = ["ADA", "BTC", "SOL", "ETH"];
         let mut rng = rand::thread_rng();
 
-        let time_of_transaction: DateTime<Utc> = DateTime::default();
+        let time_of_transaction: DateTime<Utc> = Utc
+            .timestamp_opt(rng.gen_range(0..2_000_000_000), 0)
+            .unwrap();
         let asset = assets
             .get(rng.gen_range(0..assets.len()))
             .unwrap()
             .to_string();
         let quantity_transacted = Decimal::new(rng.gen_range(0..100000), rng.gen_range(0..6));
-        let spot_price = Some(Decimal::new(rng.gen_range(0..40000), rng.gen_range(0..=2)));
+        let price = Decimal::new(rng.gen_range(0..40000), rng.gen_range(0..=2));
+        let spot_price = Some(price);
         let fees = Some(Decimal::new(rng.gen_range(0..10), 0));
         let subtotal = spot_price.map(|price| price * quantity_transacted);
         let total = subtotal.zip(fees).map(|(subtotal, fees)| subtotal + fees);
-        let notes = format!(
-            "Bought {} {} at {} USD",
-            quantity_transacted,
-            asset,
-            spot_price.unwrap()
-        );
+        let notes = format!("Bought {} {} at {} USD", quantity_transacted, asset, price);
 
         NewCoinbaseTransaction {
             time_of_transaction,
@@ -209,7 +347,11 @@ mod coinbase_db_should {
             total,
             fees,
             notes,
+            coinbase_id: Some(Uuid::new_v4().to_string()),
+            transaction_key: String::new(),
+            import_batch_id: None,
         }
+        .keyed()
     }
 
     fn create_coinbase_transaction_from_new(
@@ -228,6 +370,8 @@ mod coinbase_db_should {
             total: new_coinbase_transaction.total,
             fees: new_coinbase_transaction.fees,
             notes: new_coinbase_transaction.notes,
+            coinbase_id: new_coinbase_transaction.coinbase_id,
+            import_batch_id: new_coinbase_transaction.import_batch_id,
         }
     }
 }
@@ -0,0 +1,127 @@
+mod common;
+
+mod column_mapping_db_should {
+    use diesel::result::Error;
+    use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+    use uuid::Uuid;
+
+    use crate::common::create_test_context;
+    use crypto_database::column_mapping_db;
+    use models::mapping::ColumnMapping;
+    use models_db::{NewStoredColumnMapping, Pagination};
+
+    pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
+    const COLUMN_MAPPING_DB_NAME: &str = "column_mapping_test_database";
+
+    #[test]
+    fn find_a_mapping_by_name() {
+        let test_context = create_test_context(Some(COLUMN_MAPPING_DB_NAME.to_owned()));
+        let mut db_connection = test_context.create_connection();
+        db_connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let wanted = create_column_mapping("ledger", "Units");
+        column_mapping_db::insert_column_mapping(
+            create_column_mapping("fills", "Amount"),
+            &mut db_connection,
+        )
+        .unwrap();
+        column_mapping_db::insert_column_mapping(wanted.clone(), &mut db_connection).unwrap();
+
+        let found =
+            column_mapping_db::get_column_mapping_by_name("ledger", &mut db_connection).unwrap();
+
+        assert_eq!(found.name, "ledger");
+        let mapping = ColumnMapping::try_from(&found).unwrap();
+        assert_eq!(mapping.quantity, "Units");
+        assert_eq!(NewStoredColumnMapping::from(&mapping), wanted);
+    }
+
+    #[test]
+    fn report_a_name_that_is_not_stored() {
+        let test_context = create_test_context(Some(COLUMN_MAPPING_DB_NAME.to_owned()));
+        let mut db_connection = test_context.create_connection();
+        db_connection.run_pending_migrations(MIGRATIONS).unwrap();
+        column_mapping_db::insert_column_mapping(
+            create_column_mapping("ledger", "Amount"),
+            &mut db_connection,
+        )
+        .unwrap();
+
+        let result = column_mapping_db::get_column_mapping_by_name("fills", &mut db_connection);
+
+        assert_eq!(result, Err(Error::NotFound));
+    }
+
+    #[test]
+    fn replace_a_mapping_with_the_same_name() {
+        let test_context = create_test_context(Some(COLUMN_MAPPING_DB_NAME.to_owned()));
+        let mut db_connection = test_context.create_connection();
+        db_connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let first = create_random_column_mapping();
+        let inserted =
+            column_mapping_db::insert_column_mapping(first.clone(), &mut db_connection).unwrap();
+
+        let mut mapping = ColumnMapping::try_from(&inserted).unwrap();
+        mapping.quantity = "Units".to_string();
+        let replaced = column_mapping_db::insert_column_mapping(
+            NewStoredColumnMapping::from(&mapping),
+            &mut db_connection,
+        )
+        .unwrap();
+
+        assert_eq!(replaced.id, inserted.id);
+        let found =
+            column_mapping_db::get_column_mapping_by_name(&first.name, &mut db_connection).unwrap();
+        assert_eq!(ColumnMapping::try_from(&found).unwrap().quantity, "Units");
+    }
+
+    #[test]
+    fn list_mappings_ordered_by_name() {
+        let test_context = create_test_context(Some(COLUMN_MAPPING_DB_NAME.to_owned()));
+        let mut db_connection = test_context.create_connection();
+        db_connection.run_pending_migrations(MIGRATIONS).unwrap();
+        for mapping_name in ["delta", "alpha", "echo", "charlie", "bravo"] {
+            column_mapping_db::insert_column_mapping(
+                create_column_mapping(mapping_name, "Amount"),
+                &mut db_connection,
+            )
+            .unwrap();
+        }
+
+        let mut names = |page| -> Vec<String> {
+            let pagination = Pagination {
+                page,
+                items_per_page: 2,
+            };
+            column_mapping_db::get_column_mappings(&pagination, &mut db_connection)
+                .unwrap()
+                .into_iter()
+                .map(|mapping| mapping.name)
+                .collect()
+        };
+
+        assert_eq!(names(0), ["alpha", "bravo"]);
+        assert_eq!(names(1), ["charlie", "delta"]);
+        assert_eq!(names(2), ["echo"]);
+    }
+
+    fn create_column_mapping(mapping_name: &str, quantity: &str) -> NewStoredColumnMapping {
+        let mapping = ColumnMapping::from_definition(&format!(
+            r#"{{"name": "{mapping_name}", "timestamp": "Date", "asset": "Coin", "quantity": "{quantity}"}}"#
+        ))
+        .unwrap();
+
+        NewStoredColumnMapping::from(&mapping)
+    }
+
+    fn create_random_column_mapping() -> NewStoredColumnMapping {
+        let mapping = ColumnMapping::from_definition(&format!(
+            r#"{{"name": "{}", "timestamp": "Date", "asset": "Coin", "quantity": "Amount"}}"#,
+            Uuid::new_v4()
+        ))
+        .unwrap();
+
+        NewStoredColumnMapping::from(&mapping)
+    }
+}
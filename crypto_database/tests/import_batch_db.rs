@@ -0,0 +1,258 @@
+mod common;
+
+mod import_batch_db_should {
+    use chrono::{DateTime, TimeZone, Utc};
+    use diesel::result::Error;
+    use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+    use rand::Rng;
+    use uuid::Uuid;
+
+    use crate::common::create_test_context;
+    use crypto_database::{coinbase_db, import_batch_db, kraken_db, kraken_trades_db};
+    use models_db::{
+        ImportBatchUpdate, NewCoinbaseTransaction, NewImportBatch, NewKrakenTradeFill,
+        NewKrakenTransaction, Pagination,
+    };
+    use rust_decimal::Decimal;
+
+    pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
+    const IMPORT_BATCH_DB_NAME: &str = "import_batch_test_database";
+
+    #[test]
+    fn list_batches_from_the_most_recent() {
+        let test_context = create_test_context(Some(IMPORT_BATCH_DB_NAME.to_owned()));
+        let mut db_connection = test_context.create_connection();
+        db_connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let first = import_batch_db::insert_import_batch(
+            create_import_batch("ledgers.csv"),
+            &mut db_connection,
+        )
+        .unwrap();
+        let second = import_batch_db::update_import_batch(
+            import_batch_db::insert_import_batch(
+                create_import_batch("report.csv"),
+                &mut db_connection,
+            )
+            .unwrap()
+            .id,
+            ImportBatchUpdate {
+                file_hash: "abc123".to_string(),
+                rows_inserted: 2,
+                rows_failed: 1,
+                parse_warnings: vec!["Line 3: invalid date".to_string()],
+                ..ImportBatchUpdate::default()
+            },
+            &mut db_connection,
+        )
+        .unwrap();
+
+        assert_eq!(second.source_format, "Kraken ledgers");
+        assert_eq!(second.filename.as_deref(), Some("report.csv"));
+        assert_eq!(second.file_hash, "abc123");
+        assert_eq!(second.rows_inserted, 2);
+        assert_eq!(second.parse_warnings, ["Line 3: invalid date"]);
+
+        let batches =
+            import_batch_db::get_import_batches(&Pagination::default(), &mut db_connection)
+                .unwrap();
+        assert_eq!(batches, [second, first]);
+    }
+
+    #[test]
+    fn find_the_transactions_a_batch_stored() {
+        let test_context = create_test_context(Some(IMPORT_BATCH_DB_NAME.to_owned()));
+        let mut db_connection = test_context.create_connection();
+        db_connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let batch = import_batch_db::insert_import_batch(
+            create_import_batch("mixed.csv"),
+            &mut db_connection,
+        )
+        .unwrap();
+        let coinbase = coinbase_db::insert_coinbase_transaction(
+            create_coinbase_transaction().in_import_batch(batch.id),
+            &mut db_connection,
+        )
+        .unwrap();
+        let kraken = kraken_db::insert_kraken_transaction(
+            create_kraken_transaction().in_import_batch(batch.id),
+            &mut db_connection,
+        )
+        .unwrap();
+        let kraken_trade = kraken_trades_db::insert_kraken_trade(
+            create_kraken_trade().in_import_batch(batch.id),
+            &mut db_connection,
+        )
+        .unwrap();
+        coinbase_db::insert_coinbase_transaction(create_coinbase_transaction(), &mut db_connection)
+            .unwrap();
+
+        let found = import_batch_db::get_import_batch_transactions(
+            batch.id,
+            &Pagination::default(),
+            &mut db_connection,
+        )
+        .unwrap();
+
+        assert_eq!(found.import_batch, batch);
+        assert_eq!(found.coinbase_transactions, [coinbase]);
+        assert_eq!(found.kraken_transactions, [kraken]);
+        assert_eq!(found.kraken_trades, [kraken_trade]);
+    }
+
+    #[test]
+    fn roll_back_only_the_transactions_of_the_batch() {
+        let test_context = create_test_context(Some(IMPORT_BATCH_DB_NAME.to_owned()));
+        let mut db_connection = test_context.create_connection();
+        db_connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let batch = import_batch_db::insert_import_batch(
+            create_import_batch("bad.csv"),
+            &mut db_connection,
+        )
+        .unwrap();
+        coinbase_db::bulk_insert_coinbase_transaction(
+            vec![
+                create_coinbase_transaction().in_import_batch(batch.id),
+                create_coinbase_transaction().in_import_batch(batch.id),
+            ],
+            &mut db_connection,
+        )
+        .unwrap();
+        kraken_db::insert_kraken_transaction(
+            create_kraken_transaction().in_import_batch(batch.id),
+            &mut db_connection,
+        )
+        .unwrap();
+        kraken_trades_db::insert_kraken_trade(
+            create_kraken_trade().in_import_batch(batch.id),
+            &mut db_connection,
+        )
+        .unwrap();
+        let kept = coinbase_db::insert_coinbase_transaction(
+            create_coinbase_transaction(),
+            &mut db_connection,
+        )
+        .unwrap();
+
+        let rolled_back =
+            import_batch_db::rollback_import_batch(batch.id, &mut db_connection).unwrap();
+
+        assert_eq!(rolled_back.import_batch, batch);
+        assert_eq!(rolled_back.coinbase_transactions, 2);
+        assert_eq!(rolled_back.kraken_transactions, 1);
+        assert_eq!(rolled_back.kraken_trades, 1);
+        assert_eq!(
+            coinbase_db::get_coinbase_transactions(&Pagination::default(), &mut db_connection)
+                .unwrap(),
+            [kept]
+        );
+        assert!(
+            kraken_db::get_kraken_transactions(&Pagination::default(), &mut db_connection)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            kraken_trades_db::get_kraken_trades(&Pagination::default(), &mut db_connection)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(matches!(
+            import_batch_db::get_import_batch(batch.id, &mut db_connection),
+            Err(Error::NotFound)
+        ));
+    }
+
+    #[test]
+    fn keep_everything_when_the_batch_does_not_exist() {
+        let test_context = create_test_context(Some(IMPORT_BATCH_DB_NAME.to_owned()));
+        let mut db_connection = test_context.create_connection();
+        db_connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let batch = import_batch_db::insert_import_batch(
+            create_import_batch("kept.csv"),
+            &mut db_connection,
+        )
+        .unwrap();
+        kraken_db::insert_kraken_transaction(
+            create_kraken_transaction().in_import_batch(batch.id),
+            &mut db_connection,
+        )
+        .unwrap();
+
+        let rolled_back = import_batch_db::rollback_import_batch(batch.id + 1, &mut db_connection);
+
+        assert!(matches!(rolled_back, Err(Error::NotFound)));
+        assert_eq!(
+            kraken_db::get_kraken_transactions(&Pagination::default(), &mut db_connection)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    fn create_import_batch(filename: &str) -> NewImportBatch {
+        NewImportBatch {
+            source_format: "Kraken ledgers".to_string(),
+            filename: Some(filename.to_string()),
+            ..NewImportBatch::default()
+        }
+    }
+
+    /// Transactions are keyed by their content, each is made at a time of its own.
+    fn create_coinbase_transaction() -> NewCoinbaseTransaction {
+        NewCoinbaseTransaction {
+            time_of_transaction: Utc
+                .timestamp_opt(rand::thread_rng().gen_range(0..2_000_000_000), 0)
+                .unwrap(),
+            transaction_type: "Buy".to_string(),
+            asset: "BTC".to_string(),
+            quantity_transacted: Decimal::new(5, 1),
+            spot_price_currency: "USD".to_string(),
+            spot_price_at_transaction: None,
+            subtotal: None,
+            total: None,
+            fees: None,
+            notes: String::new(),
+            coinbase_id: None,
+            transaction_key: String::new(),
+            import_batch_id: None,
+        }
+    }
+
+    fn create_kraken_transaction() -> NewKrakenTransaction {
+        NewKrakenTransaction {
+            txid: Some(Uuid::new_v4().to_string()),
+            refid: Uuid::new_v4().to_string(),
+            transaction_time: DateTime::default(),
+            record_type: "trade".to_string(),
+            subtype: None,
+            a_class: "currency".to_string(),
+            asset: "ETH".to_string(),
+            amount: Decimal::new(2, 0),
+            fee: Decimal::ZERO,
+            balance: None,
+            import_batch_id: None,
+        }
+    }
+
+    fn create_kraken_trade() -> NewKrakenTradeFill {
+        NewKrakenTradeFill {
+            txid: Uuid::new_v4().to_string(),
+            ordertxid: Uuid::new_v4().to_string(),
+            pair: "XETHZUSD".to_string(),
+            trade_time: DateTime::default(),
+            trade_type: "buy".to_string(),
+            ordertype: "limit".to_string(),
+            price: Decimal::new(3000, 0),
+            cost: Decimal::new(6000, 0),
+            fee: Decimal::ZERO,
+            vol: Decimal::new(2, 0),
+            margin: Decimal::ZERO,
+            misc: String::new(),
+            ledgers: format!("{},{}", Uuid::new_v4(), Uuid::new_v4()),
+            import_batch_id: None,
+        }
+    }
+}
@@ -47,21 +47,19 @@ mod kraken_db_should {
         let results = kraken_db::get_kraken_transactions(&pagination, &mut db_connection).unwrap();
         assert_eq!(results.len(), 0);
 
-        let kraken_transactions: Vec<NewKrakenTransaction> = (0..10)
-            .into_iter()
-            .map(|_| create_random_kraken())
-            .collect();
+        let kraken_transactions: Vec<NewKrakenTransaction> =
+            (0..10).map(|_| create_random_kraken()).collect();
         let results = kraken_db::bulk_insert_kraken_transaction(
             kraken_transactions.clone(),
             &mut db_connection,
         )
         .unwrap();
-        assert!(results.len() > 0, "Bulk insert did not return a vec.");
+        assert!(!results.is_empty(), "Bulk insert did not return a vec.");
         assert_eq!(kraken_transactions.len(), results.len());
 
         for i in 0..kraken_transactions.len() {
-            let new_transaction = kraken_transactions.iter().nth(i).unwrap().clone();
-            let result = results.iter().nth(i).unwrap().clone();
+            let new_transaction = kraken_transactions.get(i).unwrap().clone();
+            let result = results.get(i).unwrap().clone();
             let expected = create_kraken_transaction_from_new(new_transaction, result.id);
             assert_eq!(result, expected);
         }
@@ -93,10 +91,8 @@ mod kraken_db_should {
         let mut db_connection = test_context.create_connection();
         db_connection.run_pending_migrations(MIGRATIONS).unwrap();
 
-        let kraken_transactions: Vec<NewKrakenTransaction> = (0..15)
-            .into_iter()
-            .map(|_| create_random_kraken())
-            .collect();
+        let kraken_transactions: Vec<NewKrakenTransaction> =
+            (0..15).map(|_| create_random_kraken()).collect();
         let inserted_transactions = kraken_db::bulk_insert_kraken_transaction(
             kraken_transactions.clone(),
             &mut db_connection,
@@ -110,13 +106,13 @@ mod kraken_db_should {
         };
         let results = kraken_db::get_kraken_transactions(&pagination, &mut db_connection).unwrap();
         assert_eq!(results.len() as i64, pagination.items_per_page);
-        assert!(results.len() > 0);
+        assert!(!results.is_empty());
 
         for i in 0..kraken_transactions.len() {
-            let new_kraken_transaction = kraken_transactions.iter().nth(i).unwrap().clone();
-            let inserted = inserted_transactions.iter().nth(i).unwrap().clone();
+            let new_kraken_transaction = kraken_transactions.get(i).unwrap().clone();
+            let inserted = inserted_transactions.get(i).unwrap().clone();
             let expected = create_kraken_transaction_from_new(new_kraken_transaction, inserted.id);
-            let result = results.iter().nth(i).unwrap().clone();
+            let result = results.get(i).unwrap().clone();
             assert_eq!(inserted, expected);
             assert_eq!(result, expected);
         }
@@ -132,10 +128,8 @@ mod kraken_db_should {
             items_per_page: 5,
         };
 
-        let kraken_transactions: Vec<NewKrakenTransaction> = (0..10)
-            .into_iter()
-            .map(|_| create_random_kraken())
-            .collect();
+        let kraken_transactions: Vec<NewKrakenTransaction> =
+            (0..10).map(|_| create_random_kraken()).collect();
         let inserted_transactions = kraken_db::bulk_insert_kraken_transaction(
             kraken_transactions.clone(),
             &mut db_connection,
@@ -147,10 +141,10 @@ mod kraken_db_should {
         assert_eq!(page.len() as i64, pagination.items_per_page);
 
         for i in 0..pagination.items_per_page as usize {
-            let new_kraken_transaction = kraken_transactions.iter().nth(i).unwrap().clone();
-            let inserted = inserted_transactions.iter().nth(i).unwrap().clone();
+            let new_kraken_transaction = kraken_transactions.get(i).unwrap().clone();
+            let inserted = inserted_transactions.get(i).unwrap().clone();
             let expected = create_kraken_transaction_from_new(new_kraken_transaction, inserted.id);
-            let result = page.iter().nth(i).unwrap().clone();
+            let result = page.get(i).unwrap().clone();
             assert_eq!(result, expected);
         }
 
@@ -160,16 +154,60 @@ mod kraken_db_should {
         assert_eq!(page.len() as i64, pagination.items_per_page);
 
         for i in 5..(pagination.items_per_page + 5) as usize {
-            let new_kraken_transaction = kraken_transactions.iter().nth(i).unwrap().clone();
-            let inserted = inserted_transactions.iter().nth(i).unwrap().clone();
+            let new_kraken_transaction = kraken_transactions.get(i).unwrap().clone();
+            let inserted = inserted_transactions.get(i).unwrap().clone();
             let expected = create_kraken_transaction_from_new(new_kraken_transaction, inserted.id);
-            let result = page.iter().nth(i - 5).unwrap().clone();
+            let result = page.get(i - 5).unwrap().clone();
             assert_eq!(result, expected);
         }
     }
 
+    #[test]
+    fn skip_transactions_already_stored() {
+        let test_context = create_test_context(Some(KRAKEN_DB_NAME.to_owned()));
+        let mut db_connection = test_context.create_connection();
+        db_connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let stored = create_random_kraken();
+        kraken_db::insert_kraken_transaction(stored.clone(), &mut db_connection).unwrap();
+
+        let new = create_random_kraken();
+        let upserted = kraken_db::upsert_kraken_transactions(
+            vec![stored.clone(), new.clone(), new.clone()],
+            &mut db_connection,
+        )
+        .unwrap();
+
+        assert_eq!(upserted.inserted.len(), 1);
+        assert_eq!(
+            upserted.inserted[0],
+            create_kraken_transaction_from_new(new.clone(), upserted.inserted[0].id)
+        );
+        assert!(upserted.existing == [stored, new]);
+    }
+
+    #[test]
+    fn store_every_transaction_without_a_txid() {
+        let test_context = create_test_context(Some(KRAKEN_DB_NAME.to_owned()));
+        let mut db_connection = test_context.create_connection();
+        db_connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let without_txid = NewKrakenTransaction {
+            txid: None,
+            ..create_random_kraken()
+        };
+        let upserted = kraken_db::upsert_kraken_transactions(
+            vec![without_txid.clone(), without_txid],
+            &mut db_connection,
+        )
+        .unwrap();
+
+        assert_eq!(upserted.inserted.len(), 2);
+        assert!(upserted.existing.is_empty());
+    }
+
     fn create_random_kraken() -> NewKrakenTransaction {
-        let assets = vec!["ADA", "BTC", "SOL", "ETH"];
+        let assets = ["ADA", "BTC", "SOL", "ETH"];
         let mut rng = rand::thread_rng();
 
         let asset = assets
@@ -190,6 +228,7 @@ mod kraken_db_should {
             amount,
             fee,
             balance: Some(amount),
+            import_batch_id: None,
         }
     }
 
@@ -209,6 +248,7 @@ mod kraken_db_should {
             amount: new_kraken_transaction.amount,
             fee: new_kraken_transaction.fee,
             balance: new_kraken_transaction.balance,
+            import_batch_id: new_kraken_transaction.import_batch_id,
         }
     }
 }
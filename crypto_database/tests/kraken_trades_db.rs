@@ -0,0 +1,214 @@
+mod common;
+
+mod kraken_trades_db_should {
+    use chrono::DateTime;
+    use diesel::{
+        dsl::{select, sql},
+        migration::MigrationSource,
+        sql_types::BigInt,
+        RunQueryDsl,
+    };
+    use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+    use uuid::Uuid;
+
+    use crate::common::create_test_context;
+    use crypto_database::kraken_trades_db;
+    use models::kraken::KrakenTradeRecord;
+    use models_db::{KrakenTradeFill, NewKrakenTradeFill, Pagination};
+    use rand::{self, Rng};
+    use rust_decimal::Decimal;
+
+    pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
+    const KRAKEN_TRADES_DB_NAME: &str = "kraken_trades_test_database";
+    /// Migration that made trade txids unique.
+    const TXID_KEY_VERSION: &str = "20230610084512";
+
+    #[test]
+    fn read_back_the_trade_record() {
+        let test_context = create_test_context(Some(KRAKEN_TRADES_DB_NAME.to_owned()));
+        let mut db_connection = test_context.create_connection();
+        db_connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let new_kraken_trade = create_random_kraken_trade();
+        let inserted =
+            kraken_trades_db::insert_kraken_trade(new_kraken_trade.clone(), &mut db_connection)
+                .unwrap();
+        let record = KrakenTradeRecord::try_from(&inserted).unwrap();
+
+        assert!(NewKrakenTradeFill::from(&record) == new_kraken_trade);
+        assert_eq!(record.ledger_txids().len(), 2);
+    }
+
+    #[test]
+    fn refuse_a_stored_side_kraken_does_not_use() {
+        let test_context = create_test_context(Some(KRAKEN_TRADES_DB_NAME.to_owned()));
+        let mut db_connection = test_context.create_connection();
+        db_connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let inserted = kraken_trades_db::insert_kraken_trade(
+            NewKrakenTradeFill {
+                trade_type: "settle".to_string(),
+                ..create_random_kraken_trade()
+            },
+            &mut db_connection,
+        )
+        .unwrap();
+
+        assert_eq!(
+            KrakenTradeRecord::try_from(&inserted),
+            Err("\"settle\" is not a trade side, expected buy or sell".to_string())
+        );
+    }
+
+    #[test]
+    fn skip_trades_whose_txid_is_stored() {
+        let test_context = create_test_context(Some(KRAKEN_TRADES_DB_NAME.to_owned()));
+        let mut db_connection = test_context.create_connection();
+        db_connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let stored = create_random_kraken_trade();
+        kraken_trades_db::insert_kraken_trade(stored.clone(), &mut db_connection).unwrap();
+
+        let new = create_random_kraken_trade();
+        let upserted = kraken_trades_db::upsert_kraken_trades(
+            vec![stored.clone(), new.clone(), new.clone()],
+            &mut db_connection,
+        )
+        .unwrap();
+
+        assert_eq!(upserted.inserted.len(), 1);
+        assert_eq!(
+            upserted.inserted[0],
+            create_kraken_trade_from_new(new.clone(), upserted.inserted[0].id)
+        );
+        assert!(upserted.existing == [stored, new]);
+    }
+
+    #[test]
+    fn read_every_trade_oldest_first() {
+        let test_context = create_test_context(Some(KRAKEN_TRADES_DB_NAME.to_owned()));
+        let mut db_connection = test_context.create_connection();
+        db_connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let newer = kraken_trades_db::insert_kraken_trade(
+            NewKrakenTradeFill {
+                trade_time: "2021-08-29T01:19:30Z".parse().unwrap(),
+                ..create_random_kraken_trade()
+            },
+            &mut db_connection,
+        )
+        .unwrap();
+        let older = kraken_trades_db::insert_kraken_trade(
+            NewKrakenTradeFill {
+                trade_time: "2021-07-29T01:19:30Z".parse().unwrap(),
+                ..create_random_kraken_trade()
+            },
+            &mut db_connection,
+        )
+        .unwrap();
+
+        assert_eq!(
+            kraken_trades_db::get_all_kraken_trades(&mut db_connection).unwrap(),
+            [older, newer]
+        );
+    }
+
+    #[test]
+    fn quarantine_trades_stored_twice_before_txids_were_unique() {
+        let test_context = create_test_context(Some(KRAKEN_TRADES_DB_NAME.to_owned()));
+        let mut db_connection = test_context.create_connection();
+        // Creates the table diesel tracks migrations in.
+        db_connection.applied_migrations().unwrap();
+        let mut migrations = MIGRATIONS.migrations().unwrap();
+        migrations.sort_by_key(|migration| migration.name().version().to_string());
+        for migration in migrations
+            .iter()
+            .filter(|migration| migration.name().version().to_string().as_str() < TXID_KEY_VERSION)
+        {
+            db_connection.run_migration(migration).unwrap();
+        }
+
+        let stored_twice = "INSERT INTO kraken_trades \
+            (txid, ordertxid, pair, trade_time, trade_type, ordertype, price, cost, fee, vol, margin, misc, ledgers) \
+            VALUES ('F1', 'O1', 'DOTUSD', '2021-07-29T01:19:30Z', 'buy', 'limit', 3, 9, 0, 3, 0, '', 'L1,L2')";
+        diesel::sql_query(stored_twice)
+            .execute(&mut db_connection)
+            .unwrap();
+        diesel::sql_query(stored_twice)
+            .execute(&mut db_connection)
+            .unwrap();
+        db_connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let pagination = Pagination::default();
+        let quarantined: i64 = select(sql::<BigInt>(
+            "(SELECT count(*) FROM quarantined_kraken_trades)",
+        ))
+        .get_result(&mut db_connection)
+        .unwrap();
+        let trades = kraken_trades_db::get_kraken_trades(&pagination, &mut db_connection).unwrap();
+        assert_eq!(
+            trades
+                .iter()
+                .map(|trade| (trade.id, trade.txid.as_str()))
+                .collect::<Vec<_>>(),
+            [(1, "F1")]
+        );
+        assert_eq!(quarantined, 1);
+    }
+
+    fn create_random_kraken_trade() -> NewKrakenTradeFill {
+        let pairs = ["ADAUSD", "XXBTZUSD", "SOLUSD", "XETHZUSD"];
+        let trade_types = ["buy", "sell"];
+        let mut rng = rand::thread_rng();
+
+        let pair = pairs
+            .get(rng.gen_range(0..pairs.len()))
+            .unwrap()
+            .to_string();
+        let price = Decimal::new(rng.gen_range(1..100000), rng.gen_range(0..4));
+        let vol = Decimal::new(rng.gen_range(1..100000), rng.gen_range(0..8));
+
+        NewKrakenTradeFill {
+            txid: Uuid::new_v4().to_string(),
+            ordertxid: Uuid::new_v4().to_string(),
+            pair,
+            trade_time: DateTime::default(),
+            trade_type: trade_types
+                .get(rng.gen_range(0..trade_types.len()))
+                .unwrap()
+                .to_string(),
+            ordertype: "limit".to_string(),
+            price,
+            cost: price * vol,
+            fee: Decimal::new(rng.gen_range(0..10), 0),
+            vol,
+            margin: Decimal::ZERO,
+            misc: "".to_string(),
+            ledgers: format!("{},{}", Uuid::new_v4(), Uuid::new_v4()),
+            import_batch_id: None,
+        }
+    }
+
+    fn create_kraken_trade_from_new(
+        new_kraken_trade: NewKrakenTradeFill,
+        id: i32,
+    ) -> KrakenTradeFill {
+        KrakenTradeFill {
+            id,
+            txid: new_kraken_trade.txid,
+            ordertxid: new_kraken_trade.ordertxid,
+            pair: new_kraken_trade.pair,
+            trade_time: new_kraken_trade.trade_time,
+            trade_type: new_kraken_trade.trade_type,
+            ordertype: new_kraken_trade.ordertype,
+            price: new_kraken_trade.price,
+            cost: new_kraken_trade.cost,
+            fee: new_kraken_trade.fee,
+            vol: new_kraken_trade.vol,
+            margin: new_kraken_trade.margin,
+            misc: new_kraken_trade.misc,
+            ledgers: new_kraken_trade.ledgers,
+            import_batch_id: new_kraken_trade.import_batch_id,
+        }
+    }
+}
@@ -0,0 +1,55 @@
+mod common;
+
+mod migrations_db_should {
+    use diesel::{sql_query, RunQueryDsl};
+
+    use crate::common::create_test_context;
+    use crypto_database::migrations::{self, MigrationError};
+
+    const MIGRATIONS_DB_NAME: &str = "migrations_test_database";
+
+    #[test]
+    fn apply_every_migration_to_a_new_database() {
+        let test_context = create_test_context(Some(MIGRATIONS_DB_NAME.to_owned()));
+        let mut db_connection = test_context.create_connection();
+
+        let before = migrations::schema_version(&mut db_connection).unwrap();
+        assert_eq!(before.current, None);
+        assert!(!before.pending.is_empty());
+
+        let (applied, after) = migrations::run_pending_migrations(&mut db_connection).unwrap();
+
+        assert_eq!(applied, before.pending);
+        assert_eq!(after.current, before.latest);
+        assert!(after.pending.is_empty());
+    }
+
+    #[test]
+    fn apply_nothing_to_a_migrated_database() {
+        let test_context = create_test_context(Some(MIGRATIONS_DB_NAME.to_owned()));
+        let mut db_connection = test_context.create_connection();
+        migrations::run_pending_migrations(&mut db_connection).unwrap();
+
+        let (applied, schema) = migrations::run_pending_migrations(&mut db_connection).unwrap();
+
+        assert!(applied.is_empty());
+        assert_eq!(schema.current, schema.latest);
+    }
+
+    #[test]
+    fn refuse_a_database_migrated_by_a_newer_build() {
+        let test_context = create_test_context(Some(MIGRATIONS_DB_NAME.to_owned()));
+        let mut db_connection = test_context.create_connection();
+        migrations::run_pending_migrations(&mut db_connection).unwrap();
+        sql_query("INSERT INTO __diesel_schema_migrations (version) VALUES ('99991231235959')")
+            .execute(&mut db_connection)
+            .unwrap();
+
+        let migrated = migrations::run_pending_migrations(&mut db_connection);
+
+        let Err(MigrationError::DatabaseAhead(versions)) = migrated else {
+            panic!("Migrated a database that is ahead of the build");
+        };
+        assert_eq!(versions, ["99991231235959"]);
+    }
+}
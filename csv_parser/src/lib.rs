@@ -1,29 +1,590 @@
 extern crate csv;
 
-use csv::ReaderBuilder;
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, BufRead, BufReader, Chain, Cursor, Read},
+    marker::PhantomData,
+};
+
+use csv::{DeserializeErrorKind, Reader, ReaderBuilder, StringRecord};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::mpsc;
 
 pub trait CsvParser {
+    /// Parses every row that can be deserialized, rows that fail are dropped.
     fn parse_csv<C: for<'a> serde::Deserialize<'a>>(csv: &str) -> Vec<C>;
+
+    /// Parses the rows and reports every row that failed. In [`ParseMode::Strict`] the first failing row fails the
+    /// whole file.
+    fn parse_csv_with_diagnostics<C: for<'a> serde::Deserialize<'a>>(
+        csv: &str,
+        mode: ParseMode,
+    ) -> Result<ParsedCsv<C>, RowError>;
 }
 
 pub trait CsvIdentifier {
     fn is_valid_csv<'a>(csv: &str, expected_csv_headers: impl IntoIterator<Item = &'a str>)
         -> bool;
+
+    /// Finds the row holding all of the expected headers within the first [`MAX_PREAMBLE_LINES`] rows, for exports
+    /// that put a preamble above their header row.
+    fn find_header_row<'c, 'a>(
+        csv: &'c str,
+        expected_csv_headers: impl IntoIterator<Item = &'a str>,
+    ) -> Option<HeaderRow<'c>>;
+
+    /// Finds the row within the first [`MAX_PREAMBLE_LINES`] rows holding the most of the expected headers, along
+    /// with the headers it is missing. The first such row wins a tie.
+    fn closest_header_row<'c, 'a>(
+        csv: &'c str,
+        expected_csv_headers: impl IntoIterator<Item = &'a str>,
+    ) -> Option<HeaderMatch<'c>>;
+}
+
+/// How many rows above the header row are searched by [`CsvIdentifier::find_header_row`].
+pub const MAX_PREAMBLE_LINES: usize = 10;
+
+/// The header row of a file along with everything below it.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct HeaderRow<'c> {
+    /// Line of the file the header row is on, 1 when there is no preamble.
+    pub line: u64,
+    /// The file from the header row on.
+    pub csv: &'c str,
+}
+
+/// The row that came closest to holding a set of expected headers.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct HeaderMatch<'c> {
+    pub header_row: HeaderRow<'c>,
+    pub matched_headers: usize,
+    /// Expected headers the row does not have, in the order they were expected.
+    pub missing_headers: Vec<String>,
+}
+
+impl HeaderRow<'_> {
+    /// Parses the rows below the header. Errors report lines of the whole file, preamble included.
+    pub fn parse_csv_with_diagnostics<C: for<'a> serde::Deserialize<'a>>(
+        &self,
+        mode: ParseMode,
+    ) -> Result<ParsedCsv<C>, RowError> {
+        let preamble = self.line.saturating_sub(1);
+
+        Csv::parse_csv_with_diagnostics(self.csv, mode)
+            .map(|parsed| ParsedCsv {
+                records: parsed.records,
+                errors: parsed
+                    .errors
+                    .into_iter()
+                    .map(|error| error.below(preamble))
+                    .collect(),
+            })
+            .map_err(|error| error.below(preamble))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ParseMode {
+    /// Keep every row that parses and report the others.
+    #[default]
+    Lenient,
+    /// Stop at the first row that does not parse.
+    Strict,
+}
+
+/// A row that could not be parsed.
+#[derive(Debug, Serialize, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RowError {
+    /// Line of the file the row starts on, the header is line 1.
+    pub line: u64,
+    /// Header of the column that failed, when the failure can be tied to one.
+    pub column: Option<String>,
+    pub raw_value: Option<String>,
+    pub reason: String,
+}
+
+impl RowError {
+    /// Moves the error down by the given number of lines, lines that are not known are left alone.
+    fn below(self, lines: u64) -> Self {
+        match self.line {
+            0 => self,
+            line => Self {
+                line: line + lines,
+                ..self
+            },
+        }
+    }
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.column, &self.raw_value) {
+            (Some(column), Some(raw_value)) => write!(
+                f,
+                "Line {}, column \"{column}\" (\"{raw_value}\"): {}",
+                self.line, self.reason
+            ),
+            (Some(column), None) => {
+                write!(
+                    f,
+                    "Line {}, column \"{column}\": {}",
+                    self.line, self.reason
+                )
+            }
+            _ => write!(f, "Line {}: {}", self.line, self.reason),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParsedCsv<C> {
+    pub records: Vec<C>,
+    pub errors: Vec<RowError>,
 }
 
 pub struct Csv;
 
 impl CsvParser for Csv {
     fn parse_csv<C: for<'a> serde::Deserialize<'a>>(csv: &str) -> Vec<C> {
-        ReaderBuilder::new()
-            .flexible(true)
-            .from_reader(csv.as_bytes())
-            .into_deserialize()
-            .flatten()
-            .collect()
+        Csv::parse_csv_with_diagnostics(csv, ParseMode::Lenient)
+            .map(|parsed| parsed.records)
+            .unwrap_or_default()
+    }
+
+    /// ```
+    /// use csv_parser::{Csv, CsvParser, ParseMode};
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Row {
+    ///     asset: String,
+    ///     amount: f64,
+    /// }
+    ///
+    /// let csv = "asset,amount\nBTC,1.5\nETH,$1,234.00\n";
+    ///
+    /// let parsed = Csv::parse_csv_with_diagnostics::<Row>(csv, ParseMode::Lenient).unwrap();
+    /// assert_eq!(parsed.records.len(), 1);
+    /// assert_eq!(parsed.errors.first().unwrap().line, 3);
+    /// assert_eq!(parsed.errors.first().unwrap().column.as_deref(), Some("amount"));
+    /// assert_eq!(parsed.errors.first().unwrap().raw_value.as_deref(), Some("$1"));
+    ///
+    /// assert!(Csv::parse_csv_with_diagnostics::<Row>(csv, ParseMode::Strict).is_err());
+    /// ```
+    fn parse_csv_with_diagnostics<C: for<'a> serde::Deserialize<'a>>(
+        csv: &str,
+        mode: ParseMode,
+    ) -> Result<ParsedCsv<C>, RowError> {
+        parse_records(csv, mode, deserialize_record)
     }
 }
 
+impl Csv {
+    /// Parses the rows with a function given each row keyed by header, for layouts that are only known at runtime.
+    /// The line of an error the function returns is filled in.
+    ///
+    /// ```
+    /// use csv_parser::{Csv, ParseMode, RowError};
+    ///
+    /// let csv = "asset,amount\nBTC,1.5\nETH,lots\n";
+    /// let parsed = Csv::parse_rows_with_diagnostics(csv, ParseMode::Lenient, |row| {
+    ///     row["amount"].parse::<f64>().map_err(|error| RowError {
+    ///         line: 0,
+    ///         column: Some("amount".to_string()),
+    ///         raw_value: Some(row["amount"].to_string()),
+    ///         reason: error.to_string(),
+    ///     })
+    /// })
+    /// .unwrap();
+    ///
+    /// assert_eq!(parsed.records, [1.5]);
+    /// assert_eq!(parsed.errors.first().unwrap().line, 3);
+    /// ```
+    pub fn parse_rows_with_diagnostics<C>(
+        csv: &str,
+        mode: ParseMode,
+        parse_row: impl Fn(&HashMap<String, String>) -> Result<C, RowError>,
+    ) -> Result<ParsedCsv<C>, RowError> {
+        parse_records(csv, mode, |headers, record| {
+            let row = headers
+                .iter()
+                .zip(record.iter())
+                .map(|(header, value)| (header.to_string(), value.to_string()))
+                .collect();
+
+            parse_row(&row).map_err(|error| RowError {
+                line: record.position().map_or(0, |position| position.line()),
+                ..error
+            })
+        })
+    }
+}
+
+impl Csv {
+    /// Parses the rows of a file as they are read rather than all at once, so files of any size are parsed with
+    /// bounded memory.
+    ///
+    /// ```
+    /// use csv_parser::{Csv, ParseMode};
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Row {
+    ///     asset: String,
+    ///     amount: f64,
+    /// }
+    ///
+    /// let csv = "asset,amount\nBTC,1.5\nETH,lots\nADA,3\n";
+    /// let mut records = Csv::stream_records::<Row, _>(csv.as_bytes()).unwrap();
+    ///
+    /// let chunk = records.next_chunk(2, ParseMode::Lenient).unwrap().unwrap();
+    /// assert_eq!(chunk.records.len(), 1);
+    /// assert_eq!(chunk.errors.first().unwrap().line, 3);
+    ///
+    /// let chunk = records.next_chunk(2, ParseMode::Lenient).unwrap().unwrap();
+    /// assert_eq!(chunk.records.first().unwrap().asset, "ADA");
+    /// assert!(records.next_chunk(2, ParseMode::Lenient).unwrap().is_none());
+    /// ```
+    pub fn stream_records<C: DeserializeOwned, R: Read>(
+        reader: R,
+    ) -> Result<CsvRecords<R, C>, RowError> {
+        CsvRecords::new(reader, 0)
+    }
+
+    /// Like [`Csv::stream_records`] for exports that put a preamble above their header row. Only the first
+    /// [`MAX_PREAMBLE_LINES`] rows are held while looking for the header row, errors report lines of the whole file.
+    pub fn stream_records_below_header<'a, C: DeserializeOwned, R: Read>(
+        reader: R,
+        expected_csv_headers: impl IntoIterator<Item = &'a str>,
+    ) -> Result<CsvRecords<BelowHeader<R>, C>, RowError> {
+        let (mut head, reader) = read_head(reader, MAX_PREAMBLE_LINES + 1).map_err(stream_error)?;
+        let head_csv = std::str::from_utf8(&head).map_err(|error| RowError {
+            line: 1,
+            column: None,
+            raw_value: None,
+            reason: error.to_string(),
+        })?;
+        let header_row =
+            Csv::find_header_row(head_csv, expected_csv_headers).ok_or_else(|| RowError {
+                line: 1,
+                column: None,
+                raw_value: None,
+                reason: format!(
+                    "None of the first {} rows hold the expected headers",
+                    MAX_PREAMBLE_LINES + 1
+                ),
+            })?;
+        let header_start = head.len() - header_row.csv.len();
+        let preamble = header_row.line.saturating_sub(1);
+
+        CsvRecords::new(
+            Cursor::new(head.split_off(header_start)).chain(reader),
+            preamble,
+        )
+    }
+}
+
+/// Reads the first `lines` lines of a file, returning them along with a reader over the rest. Meant for looking at
+/// the start of a file before streaming it, `Cursor::new(head).chain(rest)` reads the whole file again.
+///
+/// ```
+/// use std::io::Read;
+///
+/// let (head, mut rest) = csv_parser::read_head("asset,amount\nBTC,1.5\nETH,2\n".as_bytes(), 2).unwrap();
+/// let mut tail = String::new();
+/// rest.read_to_string(&mut tail).unwrap();
+///
+/// assert_eq!(head, b"asset,amount\nBTC,1.5\n");
+/// assert_eq!(tail, "ETH,2\n");
+/// ```
+pub fn read_head<R: Read>(reader: R, lines: usize) -> io::Result<(Vec<u8>, BufReader<R>)> {
+    let mut reader = BufReader::new(reader);
+    let mut head = Vec::new();
+    for _ in 0..lines {
+        if reader.read_until(b'\n', &mut head)? == 0 {
+            break;
+        }
+    }
+
+    Ok((head, reader))
+}
+
+/// A reader picking up at the header row, after the rows searched for it were read.
+pub type BelowHeader<R> = Chain<Cursor<Vec<u8>>, BufReader<R>>;
+
+/// The rows of a csv, parsed one at a time as they are read. Built by [`Csv::stream_records`].
+pub struct CsvRecords<R, C> {
+    reader: Reader<R>,
+    headers: StringRecord,
+    record: StringRecord,
+    /// Lines above the header row, added to the lines of errors.
+    preamble: u64,
+    /// Set once the underlying reader fails, the rest of the file cannot be read.
+    failed: bool,
+    record_type: PhantomData<fn() -> C>,
+}
+
+impl<R: Read, C: DeserializeOwned> CsvRecords<R, C> {
+    fn new(reader: R, preamble: u64) -> Result<Self, RowError> {
+        let mut reader = ReaderBuilder::new().flexible(true).from_reader(reader);
+        let headers = reader
+            .headers()
+            .cloned()
+            .map_err(|error| RowError {
+                line: 1,
+                column: None,
+                raw_value: None,
+                reason: error.to_string(),
+            })
+            .map_err(|error| error.below(preamble))?;
+
+        Ok(Self {
+            reader,
+            headers,
+            record: StringRecord::new(),
+            preamble,
+            failed: false,
+            record_type: PhantomData,
+        })
+    }
+
+    /// Parses up to `size` rows, `None` once every row has been read. In [`ParseMode::Strict`] the first failing row
+    /// fails the chunk.
+    pub fn next_chunk(
+        &mut self,
+        size: usize,
+        mode: ParseMode,
+    ) -> Result<Option<ParsedCsv<C>>, RowError> {
+        let mut chunk = ParsedCsv {
+            records: Vec::new(),
+            errors: Vec::new(),
+        };
+
+        for row in self.by_ref().take(size) {
+            match (row, mode) {
+                (Ok(record), _) => chunk.records.push(record),
+                (Err(error), ParseMode::Strict) => return Err(error),
+                (Err(error), ParseMode::Lenient) => chunk.errors.push(error),
+            }
+        }
+
+        match chunk.records.is_empty() && chunk.errors.is_empty() {
+            true => Ok(None),
+            false => Ok(Some(chunk)),
+        }
+    }
+}
+
+impl<R: Read, C: DeserializeOwned> Iterator for CsvRecords<R, C> {
+    type Item = Result<C, RowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let row = match self.reader.read_record(&mut self.record) {
+            Ok(false) => return None,
+            Ok(true) => deserialize_record(&self.headers, &self.record),
+            Err(error) => {
+                self.failed = error.is_io_error();
+                Err(read_error(error))
+            }
+        };
+
+        Some(row.map_err(|error| error.below(self.preamble)))
+    }
+}
+
+/// Creates a reader over bytes sent from async code, such as a request body, with room for `buffer` chunks in
+/// flight. The sender waits while the buffer is full, so a slow parse holds back the upload instead of filling
+/// memory.
+///
+/// The reader blocks while it waits for chunks, read it on a blocking thread such as `tokio::task::spawn_blocking`.
+/// The file ends once the sender is dropped.
+///
+/// ```
+/// use csv_parser::{byte_stream, Csv};
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Row {
+///     asset: String,
+///     amount: f64,
+/// }
+///
+/// let (sender, reader) = byte_stream::<&[u8]>(2);
+/// let parse = std::thread::spawn(move || {
+///     Csv::stream_records::<Row, _>(reader)
+///         .unwrap()
+///         .map(|row| row.unwrap().amount)
+///         .sum::<f64>()
+/// });
+///
+/// for chunk in ["asset,amo", "unt\nBTC,1.5\nETH,", "2\n"] {
+///     sender.blocking_send(Ok(chunk.as_bytes())).unwrap();
+/// }
+/// drop(sender);
+///
+/// assert_eq!(parse.join().unwrap(), 3.5);
+/// ```
+pub fn byte_stream<B: AsRef<[u8]>>(
+    buffer: usize,
+) -> (mpsc::Sender<io::Result<B>>, ByteStreamReader<B>) {
+    let (sender, receiver) = mpsc::channel(buffer);
+
+    (
+        sender,
+        ByteStreamReader {
+            receiver,
+            chunk: None,
+        },
+    )
+}
+
+/// The reading half of a [`byte_stream`].
+pub struct ByteStreamReader<B> {
+    receiver: mpsc::Receiver<io::Result<B>>,
+    /// The chunk being read and how much of it has been read.
+    chunk: Option<(B, usize)>,
+}
+
+impl<B: AsRef<[u8]>> Read for ByteStreamReader<B> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some((chunk, read)) = self.chunk.as_mut() {
+                let remaining = &chunk.as_ref()[*read..];
+                if !remaining.is_empty() {
+                    let length = remaining.len().min(buf.len());
+                    buf[..length].copy_from_slice(&remaining[..length]);
+                    *read += length;
+
+                    return Ok(length);
+                }
+            }
+
+            match self.receiver.blocking_recv() {
+                Some(chunk) => self.chunk = Some((chunk?, 0)),
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+fn stream_error(error: io::Error) -> RowError {
+    RowError {
+        line: 0,
+        column: None,
+        raw_value: None,
+        reason: error.to_string(),
+    }
+}
+
+fn parse_records<C>(
+    csv: &str,
+    mode: ParseMode,
+    parse_record: impl Fn(&StringRecord, &StringRecord) -> Result<C, RowError>,
+) -> Result<ParsedCsv<C>, RowError> {
+    let mut reader = ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(csv.as_bytes());
+    let headers = reader.headers().cloned().map_err(|error| RowError {
+        line: 1,
+        column: None,
+        raw_value: None,
+        reason: error.to_string(),
+    })?;
+
+    let mut parsed = ParsedCsv {
+        records: Vec::new(),
+        errors: Vec::new(),
+    };
+
+    for result in reader.records() {
+        let row = result
+            .map_err(read_error)
+            .and_then(|record| parse_record(&headers, &record));
+
+        match (row, mode) {
+            (Ok(record), _) => parsed.records.push(record),
+            (Err(error), ParseMode::Strict) => return Err(error),
+            (Err(error), ParseMode::Lenient) => parsed.errors.push(error),
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn read_error(error: csv::Error) -> RowError {
+    RowError {
+        line: error.position().map_or(0, |position| position.line()),
+        column: None,
+        raw_value: None,
+        reason: error.to_string(),
+    }
+}
+
+fn deserialize_record<C: for<'a> serde::Deserialize<'a>>(
+    headers: &StringRecord,
+    record: &StringRecord,
+) -> Result<C, RowError> {
+    record.deserialize(Some(headers)).map_err(|error| {
+        let line = record.position().map_or(0, |position| position.line());
+
+        match error.kind() {
+            csv::ErrorKind::Deserialize { err, .. } => {
+                let column = err
+                    .field()
+                    .map(|field| field as usize)
+                    .or_else(|| failing_column::<C>(headers, record, err.kind()));
+
+                RowError {
+                    line,
+                    column: column
+                        .and_then(|column| headers.get(column))
+                        .map(str::to_string),
+                    raw_value: column
+                        .and_then(|column| record.get(column))
+                        .map(str::to_string),
+                    reason: err.kind().to_string(),
+                }
+            }
+            _ => RowError {
+                line,
+                column: None,
+                raw_value: None,
+                reason: error.to_string(),
+            },
+        }
+    })
+}
+
+/// Finds the column of an error `csv` could not tie to one, such as those raised by `deserialize_with` functions.
+/// Fields are deserialized in order, so the column is the last one of the shortest leading run of the row that
+/// fails the same way.
+fn failing_column<C: for<'a> serde::Deserialize<'a>>(
+    headers: &StringRecord,
+    record: &StringRecord,
+    kind: &DeserializeErrorKind,
+) -> Option<usize> {
+    (1..=record.len())
+        .find(|&length| {
+            let leading_headers: StringRecord = headers.iter().take(length).collect();
+            let leading_fields: StringRecord = record.iter().take(length).collect();
+
+            match leading_fields.deserialize::<C>(Some(&leading_headers)) {
+                Err(error) => matches!(
+                    error.kind(),
+                    csv::ErrorKind::Deserialize { err, .. } if err.kind() == kind
+                ),
+                Ok(_) => false,
+            }
+        })
+        .map(|length| length - 1)
+}
+
 impl CsvIdentifier for Csv {
     fn is_valid_csv<'a>(
         csv: &str,
@@ -39,6 +600,85 @@ impl CsvIdentifier for Csv {
             .into_iter()
             .all(|expected_header| header_row_headers.contains(&expected_header))
     }
+
+    /// ```
+    /// use csv_parser::{Csv, CsvIdentifier};
+    ///
+    /// let csv = "Transactions\nUser,Satoshi,1234\nasset,amount\nBTC,1.5\n";
+    ///
+    /// let header_row = Csv::find_header_row(csv, ["asset", "amount"]).unwrap();
+    /// assert_eq!(header_row.line, 3);
+    /// assert_eq!(header_row.csv, "asset,amount\nBTC,1.5\n");
+    /// assert!(Csv::find_header_row(csv, ["asset", "price"]).is_none());
+    /// ```
+    fn find_header_row<'c, 'a>(
+        csv: &'c str,
+        expected_csv_headers: impl IntoIterator<Item = &'a str>,
+    ) -> Option<HeaderRow<'c>> {
+        let expected_csv_headers: Vec<&str> = expected_csv_headers.into_iter().collect();
+        let mut reader = ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(csv.as_bytes());
+
+        reader
+            .records()
+            .take(MAX_PREAMBLE_LINES + 1)
+            .map_while(Result::ok)
+            .find(|record| {
+                expected_csv_headers
+                    .iter()
+                    .all(|expected_header| record.iter().any(|header| header.eq(*expected_header)))
+            })
+            .and_then(|record| record.position().cloned())
+            .and_then(|position| {
+                Some(HeaderRow {
+                    line: position.line(),
+                    csv: csv.get(usize::try_from(position.byte()).ok()?..)?,
+                })
+            })
+    }
+
+    fn closest_header_row<'c, 'a>(
+        csv: &'c str,
+        expected_csv_headers: impl IntoIterator<Item = &'a str>,
+    ) -> Option<HeaderMatch<'c>> {
+        let expected_csv_headers: Vec<&str> = expected_csv_headers.into_iter().collect();
+        let mut reader = ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(csv.as_bytes());
+
+        reader
+            .records()
+            .take(MAX_PREAMBLE_LINES + 1)
+            .map_while(Result::ok)
+            .filter_map(|record| {
+                let position = record.position()?;
+                let missing_headers: Vec<String> = expected_csv_headers
+                    .iter()
+                    .filter(|expected_header| {
+                        !record.iter().any(|header| header.eq(**expected_header))
+                    })
+                    .map(|expected_header| expected_header.to_string())
+                    .collect();
+
+                Some(HeaderMatch {
+                    header_row: HeaderRow {
+                        line: position.line(),
+                        csv: csv.get(usize::try_from(position.byte()).ok()?..)?,
+                    },
+                    matched_headers: expected_csv_headers.len() - missing_headers.len(),
+                    missing_headers,
+                })
+            })
+            .reduce(|closest, header_match| {
+                match header_match.matched_headers > closest.matched_headers {
+                    true => header_match,
+                    false => closest,
+                }
+            })
+    }
 }
 
 #[cfg(test)]
@@ -52,7 +692,7 @@ mod parse_csv_should {
     use crate::{Csv, CsvIdentifier, CsvParser};
     use models::{
         coinbase::CSV_HEADERS as COINBASE_HEADERS,
-        kraken::{KrakenLedgerRecord, DATE_FORMAT as KRAKEN_DATE_FORMAT},
+        kraken::{KrakenLedgerRecord, KrakenLedgerType, DATE_FORMAT as KRAKEN_DATE_FORMAT},
     };
 
     #[test]
@@ -81,6 +721,21 @@ mod parse_csv_should {
         assert!(!valid_csv);
     }
 
+    #[test]
+    fn find_the_closest_header_row_below_a_preamble() {
+        let csv = "Transactions\nUser,Satoshi\nTimestamp,Asset,Quantity Transacted\nBTC,0.01\n";
+
+        let header_match = Csv::closest_header_row(csv, COINBASE_HEADERS.to_vec()).unwrap();
+
+        assert_eq!(header_match.header_row.line, 3);
+        assert_eq!(header_match.matched_headers, 3);
+        assert_eq!(
+            header_match.missing_headers.len(),
+            COINBASE_HEADERS.len() - 3
+        );
+        assert!(Csv::closest_header_row("", COINBASE_HEADERS.to_vec()).is_none());
+    }
+
     #[test]
     fn should_parse_csv() {
         let given_csv: String =
@@ -93,7 +748,7 @@ mod parse_csv_should {
             time: Utc
                 .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
                 .unwrap(),
-            record_type: "deposit".to_string(),
+            record_type: KrakenLedgerType::Deposit,
             subtype: None,
             a_class: "currency".to_string(),
             asset: "ADA".to_string(),
@@ -107,6 +762,163 @@ mod parse_csv_should {
     }
 }
 
+#[cfg(test)]
+mod parse_csv_with_diagnostics_should {
+    extern crate models;
+
+    extern crate rust_decimal;
+
+    use self::models::{
+        coinbase::CoinbaseTransactionRecord,
+        kraken::{KrakenLedgerRecord, CSV_HEADERS as KRAKEN_HEADERS},
+    };
+    use self::rust_decimal::Decimal;
+    use crate::{Csv, CsvIdentifier, CsvParser, ParseMode, ParsedCsv, RowError};
+
+    const KRAKEN_CSV: &str = "txid,refid,time,type,subtype,aclass,asset,amount,fee,balance\n\
+        L1,R1,2021-09-29 15:18:30,deposit,,currency,ADA,5.00000000,0.00000000,5.00000000\n\
+        L2,R2,29/09/2021 15:18:30,deposit,,currency,ADA,5.00000000,0.00000000,10.00000000\n\
+        L3,R3,2021-09-29 15:18:30,deposit,,currency,ADA,five,0.00000000,15.00000000\n";
+
+    #[test]
+    fn report_the_line_column_and_value_of_bad_rows() {
+        let parsed: ParsedCsv<KrakenLedgerRecord> =
+            Csv::parse_csv_with_diagnostics(KRAKEN_CSV, ParseMode::Lenient).unwrap();
+
+        let locations: Vec<(u64, Option<&str>, Option<&str>)> = parsed
+            .errors
+            .iter()
+            .map(|error| {
+                (
+                    error.line,
+                    error.column.as_deref(),
+                    error.raw_value.as_deref(),
+                )
+            })
+            .collect();
+
+        assert_eq!(parsed.records.len(), 1);
+        assert_eq!(
+            locations,
+            [
+                (3, Some("time"), Some("29/09/2021 15:18:30")),
+                (4, Some("amount"), Some("five")),
+            ]
+        );
+    }
+
+    #[test]
+    fn fail_on_the_first_bad_row_when_strict() {
+        let error: RowError =
+            Csv::parse_csv_with_diagnostics::<KrakenLedgerRecord>(KRAKEN_CSV, ParseMode::Strict)
+                .unwrap_err();
+
+        assert_eq!(error.line, 3);
+        assert_eq!(error.column.as_deref(), Some("time"));
+    }
+
+    #[test]
+    fn read_currency_formatted_amounts() {
+        let csv = "Timestamp,Transaction Type,Asset,Quantity Transacted,Spot Price Currency,Spot Price at Transaction,Subtotal,Total (inclusive of fees and/or spread),Fees and/or Spread,Notes\n".to_string()
+            + "2021-01-22T21:38:01Z,Buy,BTC,0.0016458,USD,\"$1,617.57\",$97.01,$100.00,$2.99,Bought 0.0016458 BTC for $100.00 USD\n"
+            + "2021-01-22T21:38:01Z,Buy,BTC,0.0016458,USD,1.617.57,97.01,100.00,2.99,Bought 0.0016458 BTC for $100.00 USD";
+
+        let parsed: ParsedCsv<CoinbaseTransactionRecord> =
+            Csv::parse_csv_with_diagnostics(&csv, ParseMode::Lenient).unwrap();
+        let error = parsed.errors.first().unwrap();
+
+        assert_eq!(
+            parsed.records.first().unwrap().spot_price_at_transaction,
+            Some(Decimal::new(161757, 2))
+        );
+        assert_eq!(error.line, 3);
+        assert_eq!(error.column.as_deref(), Some("Spot Price at Transaction"));
+        assert_eq!(error.raw_value.as_deref(), Some("1.617.57"));
+    }
+
+    #[test]
+    fn report_lines_of_the_whole_file_below_a_preamble() {
+        let csv = "Transactions\nUser,Satoshi,1234\n".to_string() + KRAKEN_CSV;
+        let header_row = Csv::find_header_row(&csv, KRAKEN_HEADERS.to_vec()).unwrap();
+
+        let parsed: ParsedCsv<KrakenLedgerRecord> = header_row
+            .parse_csv_with_diagnostics(ParseMode::Lenient)
+            .unwrap();
+        let lines: Vec<u64> = parsed.errors.iter().map(|error| error.line).collect();
+
+        assert_eq!(header_row.line, 3);
+        assert_eq!(lines, [5, 6]);
+    }
+}
+
+#[cfg(test)]
+mod stream_records_should {
+    extern crate models;
+
+    use std::io::{self, Read};
+
+    use self::models::kraken::{KrakenLedgerRecord, CSV_HEADERS as KRAKEN_HEADERS};
+    use crate::{byte_stream, Csv, CsvRecords, ParseMode};
+
+    const KRAKEN_CSV: &str = "txid,refid,time,type,subtype,aclass,asset,amount,fee,balance\n\
+        L1,R1,2021-09-29 15:18:30,deposit,,currency,ADA,5.00000000,0.00000000,5.00000000\n\
+        L2,R2,29/09/2021 15:18:30,deposit,,currency,ADA,5.00000000,0.00000000,10.00000000\n\
+        L3,R3,2021-09-29 15:18:30,deposit,,currency,ADA,5.00000000,0.00000000,15.00000000\n";
+
+    #[test]
+    fn report_lines_of_the_whole_file_below_a_preamble() {
+        let csv = "Transactions\nUser,Satoshi,1234\n".to_string() + KRAKEN_CSV;
+
+        let records: CsvRecords<_, KrakenLedgerRecord> =
+            Csv::stream_records_below_header(csv.as_bytes(), KRAKEN_HEADERS.to_vec()).unwrap();
+        let rows: Vec<Result<String, u64>> = records
+            .map(|row| row.map(|record| record.refid).map_err(|error| error.line))
+            .collect();
+
+        assert_eq!(rows, [Ok("R1".to_string()), Err(5), Ok("R3".to_string())]);
+    }
+
+    #[test]
+    fn fail_the_chunk_holding_a_bad_row_when_strict() {
+        let mut records: CsvRecords<_, KrakenLedgerRecord> =
+            Csv::stream_records(KRAKEN_CSV.as_bytes()).unwrap();
+
+        let first = records.next_chunk(1, ParseMode::Strict).unwrap().unwrap();
+        let error = records.next_chunk(1, ParseMode::Strict).unwrap_err();
+
+        assert_eq!(first.records.len(), 1);
+        assert_eq!(error.line, 3);
+        assert_eq!(error.column.as_deref(), Some("time"));
+    }
+
+    #[test]
+    fn reject_a_file_without_the_expected_headers() {
+        let error = Csv::stream_records_below_header::<KrakenLedgerRecord, _>(
+            "asset,amount\nBTC,1\n".as_bytes(),
+            KRAKEN_HEADERS.to_vec(),
+        )
+        .err()
+        .unwrap();
+
+        assert_eq!(error.line, 1);
+    }
+
+    #[test]
+    fn pass_on_errors_of_the_byte_stream() {
+        let (sender, mut reader) = byte_stream::<Vec<u8>>(2);
+        sender.blocking_send(Ok(b"asset".to_vec())).unwrap();
+        sender
+            .blocking_send(Err(io::Error::other("connection reset")))
+            .unwrap();
+        drop(sender);
+
+        let mut read = String::new();
+        let error = reader.read_to_string(&mut read).unwrap_err();
+
+        assert_eq!(error.to_string(), "connection reset");
+    }
+}
+
 #[cfg(test)]
 mod is_valid_csv {
     extern crate models;
@@ -144,3 +956,37 @@ mod is_valid_csv {
         assert!(!valid_csv);
     }
 }
+
+#[cfg(test)]
+mod find_header_row_should {
+    extern crate models;
+    use self::models::coinbase::{CSV_HEADERS, TRANSACTION_REPORT_HEADERS};
+    use crate::{Csv, CsvIdentifier, MAX_PREAMBLE_LINES};
+
+    const TRANSACTION_REPORT: &str = ",,,,,,,,,,\n\
+        Transactions\n\
+        User,Satoshi Nakamoto,9f7a5c1e-24b1-4d7f-9b7e-1a2b3c4d5e6f\n\
+        ID,Timestamp,Transaction Type,Asset,Quantity Transacted,Price Currency,Price at Transaction,Subtotal,Total (inclusive of fees and/or spread),Fees and/or Spread,Notes\n\
+        65a1,2024-01-22 21:38:01 UTC,Buy,BTC,0.0016458,USD,$1617.57,$97.01,$100.00,$2.99,Bought 0.0016458 BTC for $100.00 USD\n";
+
+    #[test]
+    fn skip_the_preamble_of_a_transaction_report() {
+        let header_row =
+            Csv::find_header_row(TRANSACTION_REPORT, TRANSACTION_REPORT_HEADERS.to_vec()).unwrap();
+
+        assert_eq!(header_row.line, 4);
+        assert!(header_row.csv.starts_with("ID,Timestamp"));
+    }
+
+    #[test]
+    fn tell_the_header_generations_apart() {
+        assert!(Csv::find_header_row(TRANSACTION_REPORT, CSV_HEADERS.to_vec()).is_none());
+    }
+
+    #[test]
+    fn give_up_after_the_longest_preamble() {
+        let csv = "preamble\n".repeat(MAX_PREAMBLE_LINES + 1) + "asset,amount\nBTC,1\n";
+
+        assert!(Csv::find_header_row(&csv, ["asset", "amount"]).is_none());
+    }
+}
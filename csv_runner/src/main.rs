@@ -1,35 +1,38 @@
-use std::time::SystemTime;
+use std::{fmt::Display, fs::File, process, time::SystemTime};
 
-use coinbase_parser::CoinbaseTransactionRecord;
-use crypto_database::coinbase_db::NewCoinbaseTransaction;
-use csv_parser::{Csv, CsvParser};
+use coinbase_parser::{CoinbaseTransactionRecord, SHARED_HEADERS};
+use crypto_database::{coinbase_db::NewCoinbaseTransaction, INSERT_CHUNK_SIZE};
+use csv_parser::{Csv, CsvRecords, ParseMode};
 
 fn main() {
     let path = std::env::var("CSV_PATH").unwrap_or("./data/very-large-dataset.csv".to_string());
-    let data = std::fs::read_to_string(path).unwrap();
+    let file = File::open(&path).unwrap_or_else(|e| exit_with(format!("{path}: {e}")));
     let start = SystemTime::now();
-    let _data: Vec<NewCoinbaseTransaction> = Csv::parse_csv(&data)
-        .into_iter()
-        .map(|ctr: CoinbaseTransactionRecord| NewCoinbaseTransaction {
-            time_of_transaction: ctr.time_of_transaction,
-            transaction_type: ctr.transaction_type,
-            asset: ctr.asset,
-            quantity_transacted: ctr.quantity_transacted,
-            spot_price_currency: ctr.spot_price_currency,
-            spot_price_at_transaction: ctr.spot_price_at_transaction,
-            subtotal: ctr.subtotal,
-            total: ctr.total,
-            fees: ctr.fees,
-            notes: ctr.notes,
-        })
-        .collect();
+    // Legacy exports and transaction reports both hold these headers, only reports have an ID column.
+    let mut records: CsvRecords<_, CoinbaseTransactionRecord> =
+        Csv::stream_records_below_header(file, SHARED_HEADERS.to_vec()).unwrap_or_else(exit_with);
 
-    // let mut connection = crypto_database::establish_connection();
+    // let mut connection = crypto_database::establish_connection(None).unwrap();
 
-    // let result = crypto_database::bulk_insert_coinbase_transaction(data.collect(), &mut connection)
-    //     .expect("Didn't write records");
+    let mut parsed = 0;
+    while let Some(chunk) = records
+        .next_chunk(INSERT_CHUNK_SIZE, ParseMode::Lenient)
+        .unwrap_or_else(exit_with)
+    {
+        let _data: Vec<NewCoinbaseTransaction> = chunk.records.iter().map(Into::into).collect();
+        parsed += chunk.records.len();
 
+        // crypto_database::coinbase_db::bulk_insert_coinbase_transaction(_data, &mut connection)
+        //     .expect("Didn't write records");
+    }
+
+    println!("Parsed {parsed} records");
     if let Ok(elapsed) = start.elapsed() {
         println!("Elapsed millis is: {}", elapsed.as_millis());
     }
 }
+
+fn exit_with<T>(error: impl Display) -> T {
+    eprintln!("{error}");
+    process::exit(1)
+}
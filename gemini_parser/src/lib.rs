@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+
+use models::{asset::canonical_ticker, income::IncomeRecord, InputTransaction};
+use rust_decimal::Decimal;
+
+pub use models::{
+    gemini::{
+        GeminiTransactionRecord, GeminiTransactionType, CSV_HEADERS, DATE_FORMAT, TRANSACTION_TYPES,
+    },
+    ActiveAssetValues, HistoricalPrice, InputTransactions, RecordsByAsset, StakingIncome,
+    StakingRewards, UnknownTransactionType, UnknownTransactionTypes,
+};
+
+pub struct GeminiParser<T> {
+    data: Vec<T>,
+}
+
+impl<T> GeminiParser<T> {
+    pub fn new(data: Vec<T>) -> Self {
+        Self { data }
+    }
+}
+
+impl GeminiParser<GeminiTransactionRecord> {
+    /// The credits that paid out Earn interest or staking rewards.
+    pub fn staking_reward_records(&self) -> Vec<&GeminiTransactionRecord> {
+        self.data
+            .iter()
+            .filter(|record| record.is_staking_reward())
+            .collect()
+    }
+}
+
+impl StakingRewards for GeminiParser<GeminiTransactionRecord> {
+    fn staking_rewards(&self) -> HashMap<String, Decimal> {
+        self.staking_reward_records()
+            .into_iter()
+            .flat_map(|record| record.amounts.iter())
+            .fold(HashMap::new(), |mut reward_map, (asset, amount)| {
+                *reward_map
+                    .entry(canonical_ticker(asset))
+                    .or_insert(Decimal::ZERO) += amount;
+
+                reward_map
+            })
+    }
+}
+
+impl ActiveAssetValues for GeminiParser<GeminiTransactionRecord> {
+    /// Adds up the change to every asset with fees taken out. Rows of a type that is not understood are left out.
+    fn active_assets(&self) -> HashMap<String, Decimal> {
+        self.data
+            .iter()
+            .filter(|record| !record.transaction_type.is_unknown())
+            .flat_map(|record| {
+                record
+                    .amounts
+                    .iter()
+                    .map(|(asset, amount)| (asset, *amount))
+                    .chain(record.fees.iter().map(|(asset, fee)| (asset, -fee)))
+            })
+            .fold(HashMap::new(), |mut map, (asset, change)| {
+                *map.entry(canonical_ticker(asset)).or_insert(Decimal::ZERO) += change;
+
+                map
+            })
+    }
+}
+
+impl RecordsByAsset<GeminiTransactionRecord> for GeminiParser<GeminiTransactionRecord> {
+    /// Rows collected by the asset they are about, the base of a trade or the asset credited or debited.
+    fn by_asset(&self) -> HashMap<String, Vec<&GeminiTransactionRecord>> {
+        self.data
+            .iter()
+            .fold(HashMap::new(), |mut currency_map, record| {
+                currency_map
+                    .entry(canonical_ticker(record.asset()))
+                    .or_insert_with(Vec::new)
+                    .push(record);
+
+                currency_map
+            })
+    }
+}
+
+impl InputTransactions<GeminiTransactionRecord> for GeminiParser<GeminiTransactionRecord> {
+    fn input_transactions(&self) -> Vec<&GeminiTransactionRecord> {
+        self.data
+            .iter()
+            .filter(|record| record.is_input_transaction())
+            .collect()
+    }
+}
+
+impl UnknownTransactionTypes for GeminiParser<GeminiTransactionRecord> {
+    fn unknown_transaction_types(&self) -> Vec<UnknownTransactionType> {
+        UnknownTransactionType::tally(
+            self.data
+                .iter()
+                .filter(|record| record.transaction_type.is_unknown())
+                .map(|record| (record.transaction_type.as_str(), record.asset())),
+        )
+    }
+}
+
+impl StakingIncome for GeminiParser<GeminiTransactionRecord> {
+    /// Gemini does not export prices, so every reward is valued with the given prices.
+    fn staking_income(&self, prices: &dyn HistoricalPrice) -> Vec<IncomeRecord> {
+        self.data
+            .iter()
+            .enumerate()
+            .filter(|(_, record)| record.is_staking_reward())
+            .flat_map(|(index, record)| {
+                record.amounts.iter().map(move |(asset, amount)| {
+                    let asset = canonical_ticker(asset);
+                    let usd_price = prices.usd_price_at(&asset, &record.time);
+
+                    IncomeRecord {
+                        id: format!("gemini-{index}-{asset}"),
+                        asset,
+                        quantity: *amount,
+                        received_at: record.time,
+                        usd_price,
+                        usd_value: usd_price.map(|price| price * amount),
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod gemini_parser_should {
+    use chrono::{DateTime, Utc};
+    use rust_decimal::Decimal;
+
+    use crate::{
+        ActiveAssetValues, GeminiParser, GeminiTransactionRecord, HistoricalPrice,
+        InputTransactions, RecordsByAsset, StakingIncome, StakingRewards, UnknownTransactionTypes,
+    };
+
+    const HISTORY: &str = "Date,Time (UTC),Type,Symbol,Specification,Liquidity Indicator,Trading Fee Rate (bps),USD Amount USD,Trading Fee (USD) USD,USD Balance USD,BTC Amount BTC,Trading Fee (BTC) BTC,BTC Balance BTC,ETH Amount ETH,ETH Balance ETH\n\
+        2021-03-01,15:20:30.123,Credit,USD,Deposit,,,\"$2,000.00\",,\"$2,000.00\",,,,,\n\
+        2021-03-01,15:21:00.000,Buy,BTCUSD,Exchange Limit,Taker,35,\"($1,000.00)\",($3.50),$996.50,0.02 BTC,,0.02 BTC,,\n\
+        2021-03-02,00:00:00.000,Credit,ETH,Earn Interest,,,,,,,,,0.001 ETH,0.001 ETH\n\
+        2021-03-03,00:00:00.000,Auction Buy,ETHUSD,Auction,,,($10.00),,$986.50,,,,0.004 ETH,0.005 ETH\n\
+        2021-03-04,00:00:00.000,Custody Transfer,ETH,Cold Storage,,,,,,,,,(0.005 ETH),0 ETH\n";
+
+    fn records() -> Vec<GeminiTransactionRecord> {
+        csv::Reader::from_reader(HISTORY.as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    struct FixedPrice;
+
+    impl HistoricalPrice for FixedPrice {
+        fn usd_price_at(&self, _asset: &str, _time: &DateTime<Utc>) -> Option<Decimal> {
+            Some(Decimal::new(2000, 0))
+        }
+    }
+
+    #[test]
+    fn add_up_holdings_with_fees_taken_out() {
+        let active_assets = GeminiParser::new(records()).active_assets();
+
+        assert_eq!(active_assets["USD"], Decimal::new(9865, 1));
+        assert_eq!(active_assets["BTC"], Decimal::new(2, 2));
+        assert_eq!(active_assets["ETH"], Decimal::new(5, 3));
+    }
+
+    #[test]
+    fn group_rows_by_the_asset_they_are_about() {
+        let gemini_parser = GeminiParser::new(records());
+        let by_asset = gemini_parser.by_asset();
+
+        assert_eq!(by_asset["USD"].len(), 1);
+        assert_eq!(by_asset["BTC"].len(), 1);
+        assert_eq!(by_asset["ETH"].len(), 3);
+        assert_eq!(gemini_parser.input_transactions().len(), 4);
+    }
+
+    #[test]
+    fn find_earn_interest_as_staking_income() {
+        let gemini_parser = GeminiParser::new(records());
+        let income = gemini_parser.staking_income(&FixedPrice);
+
+        assert_eq!(gemini_parser.staking_rewards()["ETH"], Decimal::new(1, 3));
+        assert_eq!(income.len(), 1);
+        assert_eq!(income[0].usd_value, Some(Decimal::new(2, 0)));
+    }
+
+    #[test]
+    fn give_rewards_received_at_the_same_time_their_own_ids() {
+        let mut records = records();
+        records.push(records[2].clone());
+
+        let income = GeminiParser::new(records).staking_income(&FixedPrice);
+
+        assert_eq!(income.len(), 2);
+        assert_eq!(income[0].id, "gemini-2-ETH");
+        assert_eq!(income[1].id, "gemini-5-ETH");
+    }
+
+    #[test]
+    fn report_unknown_transaction_types() {
+        let unknown = GeminiParser::new(records()).unknown_transaction_types();
+
+        assert_eq!(unknown.len(), 1);
+        assert_eq!(unknown[0].transaction_type, "Custody Transfer");
+        assert_eq!(unknown[0].assets, ["ETH"]);
+    }
+}
@@ -1,19 +1,294 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+use models::{
+    asset::canonical_ticker,
+    cost_basis::{Acquisition, CostBasisEvent, Disposal},
+    income::IncomeRecord,
+    ActiveAssetValues, InputTransaction, InputTransactions, RecordsByAsset,
+};
 pub use models::{
-    kraken::{KrakenLedgerRecord, CSV_HEADERS, DATE_FORMAT},
-    StakingRewards,
+    kraken::{
+        BalanceMismatch, BalanceReconciliation, KrakenLedgerRecord, KrakenLedgerSubtype,
+        KrakenLedgerType, KrakenTrade, KrakenTradeRecord, KrakenTradeReport, TradeSide,
+        UnbalancedReason, UnbalancedRefid, CSV_HEADERS, DATE_FORMAT, LEDGER_SUBTYPES, LEDGER_TYPES,
+        TRADE_CSV_HEADERS, TRADE_DATE_FORMAT,
+    },
+    CostBasisEvents, HistoricalPrice, StakingIncome, StakingRewards, UnknownTransactionType,
+    UnknownTransactionTypes,
 };
-use models::{ActiveAssetValues, InputTransaction, InputTransactions, RecordsByAsset};
 pub use rust_decimal::Decimal;
 
+/// Ledger types that make up one side of a trade, grouped together by `refid`. Margin rows are left out, they
+/// settle the profit or loss of a position rather than exchange one asset for another.
+pub const TRADE_TYPES: &[KrakenLedgerType] = &[
+    KrakenLedgerType::Trade,
+    KrakenLedgerType::Spend,
+    KrakenLedgerType::Receive,
+];
+
+/// Assets treated as USD when valuing the other side of a trade.
+pub const USD_ASSETS: &[&str] = &["USD", "ZUSD"];
+
+/// Tickers that are used as the quote of a trade, in order of preference. When neither side of a trade is listed
+/// the spent asset is the quote.
+pub const QUOTE_ASSETS: &[&str] = &[
+    "USD", "EUR", "GBP", "CAD", "AUD", "JPY", "CHF", "USDT", "USDC", "DAI", "BTC", "ETH",
+];
+
 pub struct KrakenParser<T> {
     data: Vec<T>,
+    fills: Vec<KrakenTradeRecord>,
 }
 
 impl<T> KrakenParser<T> {
     pub fn new(data: Vec<T>) -> Self {
-        Self { data }
+        Self {
+            data,
+            fills: Vec::new(),
+        }
+    }
+
+    /// Adds fills from the trades export. Trades are joined to their fills through the ledger txids each fill
+    /// lists, and take the executed price and cost from them instead of working it out from ledger amounts.
+    pub fn with_trades(mut self, fills: Vec<KrakenTradeRecord>) -> Self {
+        self.fills = fills;
+        self
+    }
+}
+
+impl KrakenParser<KrakenLedgerRecord> {
+    /// Combines the ledger rows of every trade into a [`KrakenTrade`]. Refids that do not come down to one asset
+    /// spent for another are reported as unbalanced. Trades booked by a fill given to [`KrakenParser::with_trades`]
+    /// use the price of the fill.
+    /// ```
+    /// # use chrono::{TimeZone, Utc};
+    /// # use models::kraken::{KrakenLedgerRecord, KrakenLedgerType, DATE_FORMAT as KRAKEN_DATE_FORMAT};
+    /// # use rust_decimal::prelude::{Decimal, Zero};
+    /// # use kraken_parser::{KrakenParser, TradeSide};
+    /// #
+    /// let spent = KrakenLedgerRecord {
+    ///     txid: Some("L7RLII-OFGWB-JTUO7J".to_string()),
+    ///     refid: "TKB7ODD-ILZGC5-LCRRBL".to_string(),
+    ///     time: Utc
+    ///         .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
+    ///         .unwrap(),
+    ///     record_type: KrakenLedgerType::Trade,
+    ///     subtype: None,
+    ///     a_class: "currency".to_string(),
+    ///     asset: "ZUSD".to_string(),
+    ///     amount: Decimal::new(-100, 0),
+    ///     fee: Decimal::new(1, 0),
+    ///     balance: Some(Decimal::zero()),
+    /// };
+    /// let received = KrakenLedgerRecord {
+    ///     txid: Some("L8RLII-OFGWB-JTUO7J".to_string()),
+    ///     asset: "DOT".to_string(),
+    ///     amount: Decimal::new(4, 0),
+    ///     fee: Decimal::zero(),
+    ///     balance: Some(Decimal::new(4, 0)),
+    ///     ..spent.clone()
+    /// };
+    ///
+    /// let report = KrakenParser::new(vec![spent, received]).trades();
+    /// let trade = report.trades.first().unwrap();
+    /// assert_eq!(trade.side, TradeSide::Buy);
+    /// assert_eq!(trade.base, "DOT");
+    /// assert_eq!(trade.quote, "USD");
+    /// assert_eq!(trade.price, Decimal::new(25, 0));
+    /// assert_eq!(trade.quote_fee, Decimal::new(1, 0));
+    /// assert!(report.unbalanced.is_empty());
+    /// ```
+    pub fn trades(&self) -> KrakenTradeReport {
+        let mut refids: Vec<&str> = Vec::new();
+        let legs_by_refid = self
+            .data
+            .iter()
+            .filter(|record| record.txid.is_some())
+            .filter(|record| TRADE_TYPES.contains(&record.record_type))
+            .fold(HashMap::new(), |mut trade_map, record| {
+                trade_map
+                    .entry(record.refid.as_str())
+                    .or_insert_with(|| {
+                        refids.push(record.refid.as_str());
+                        Vec::new()
+                    })
+                    .push(record);
+
+                trade_map
+            });
+
+        let fills_by_ledger_txid: HashMap<&str, &KrakenTradeRecord> = self
+            .fills
+            .iter()
+            .flat_map(|fill| {
+                fill.ledger_txids()
+                    .into_iter()
+                    .map(move |txid| (txid, fill))
+            })
+            .collect();
+
+        refids
+            .into_iter()
+            .filter_map(|refid| legs_by_refid.get(refid).map(|legs| (refid, legs)))
+            .fold(KrakenTradeReport::default(), |mut report, (refid, legs)| {
+                match reconstruct_trade(refid, legs) {
+                    Ok(trade) => {
+                        report
+                            .trades
+                            .push(price_from_fills(trade, legs, &fills_by_ledger_txid))
+                    }
+                    Err(reason) => report.unbalanced.push(UnbalancedRefid {
+                        refid: refid.to_string(),
+                        reason,
+                        records: legs.iter().map(|leg| (*leg).clone()).collect(),
+                    }),
+                }
+
+                report
+            })
+    }
+
+    /// Trades that were not made against USD, which have no USD value in the ledger and so make no cost basis
+    /// events.
+    pub fn trades_not_in_usd(&self) -> Vec<KrakenTrade> {
+        self.trades()
+            .trades
+            .into_iter()
+            .filter(|trade| !USD_ASSETS.contains(&trade.quote.as_str()))
+            .collect()
+    }
+
+    /// The rows that paid out a staking or Earn reward. Moving holdings in and out of staking also shows up in the
+    /// ledger, sometimes as `staking` rows, so a row under a refid that also has a transfer, deposit, withdrawal or
+    /// an outgoing amount is a move of the same holding and not income. Rows without a txid are duplicates.
+    /// ```
+    /// # use chrono::{TimeZone, Utc};
+    /// # use models::kraken::{KrakenLedgerRecord, DATE_FORMAT as KRAKEN_DATE_FORMAT};
+    /// # use rust_decimal::prelude::{Decimal, Zero};
+    /// # use kraken_parser::{KrakenLedgerSubtype, KrakenLedgerType, KrakenParser};
+    /// #
+    /// let unstaked = KrakenLedgerRecord {
+    ///     txid: Some("L7RLII-OFGWB-JTUO7J".to_string()),
+    ///     refid: "RKB7ODD-ILZGC5-LCRRBL".to_string(),
+    ///     time: Utc
+    ///         .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
+    ///         .unwrap(),
+    ///     record_type: KrakenLedgerType::Transfer,
+    ///     subtype: Some(KrakenLedgerSubtype::StakingToSpot),
+    ///     a_class: "currency".to_string(),
+    ///     asset: "DOT.S".to_string(),
+    ///     amount: Decimal::new(-10, 0),
+    ///     fee: Decimal::zero(),
+    ///     balance: Some(Decimal::zero()),
+    /// };
+    /// let received = KrakenLedgerRecord {
+    ///     txid: Some("L8RLII-OFGWB-JTUO7J".to_string()),
+    ///     record_type: KrakenLedgerType::Staking,
+    ///     subtype: None,
+    ///     asset: "DOT".to_string(),
+    ///     amount: Decimal::new(10, 0),
+    ///     balance: Some(Decimal::new(10, 0)),
+    ///     ..unstaked.clone()
+    /// };
+    /// let reward = KrakenLedgerRecord {
+    ///     txid: Some("L9RLII-OFGWB-JTUO7J".to_string()),
+    ///     refid: "STKB7ODD-ILZGC5-LCRRBL".to_string(),
+    ///     amount: Decimal::new(1, 1),
+    ///     ..received.clone()
+    /// };
+    ///
+    /// let kraken_parser = KrakenParser::new(vec![unstaked, received, reward.clone()]);
+    /// assert_eq!(kraken_parser.staking_reward_records(), vec![&reward]);
+    /// ```
+    pub fn staking_reward_records(&self) -> Vec<&KrakenLedgerRecord> {
+        let transfer_refids: HashSet<&str> = self
+            .data
+            .iter()
+            .filter(|record| is_holding_move(record))
+            .map(|record| record.refid.as_str())
+            .collect();
+
+        self.data
+            .iter()
+            .filter(|record| record.txid.is_some())
+            .filter(|record| is_reward(record))
+            .filter(|record| !transfer_refids.contains(record.refid.as_str()))
+            .collect()
+    }
+
+    /// Replays the ledger per asset code and reports every row where the running total, amount minus fee,
+    /// disagrees with the `balance` Kraken exported. Rows without a txid are duplicates and are not replayed.
+    /// ```
+    /// # use chrono::{TimeZone, Utc};
+    /// # use models::kraken::{KrakenLedgerRecord, KrakenLedgerType, DATE_FORMAT as KRAKEN_DATE_FORMAT};
+    /// # use rust_decimal::prelude::{Decimal, Zero};
+    /// # use kraken_parser::KrakenParser;
+    /// #
+    /// let deposit = KrakenLedgerRecord {
+    ///     txid: Some("L7RLII-OFGWB-JTUO7J".to_string()),
+    ///     refid: "RKB7ODD-ILZGC5-LCRRBL".to_string(),
+    ///     time: Utc
+    ///         .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
+    ///         .unwrap(),
+    ///     record_type: KrakenLedgerType::Deposit,
+    ///     subtype: None,
+    ///     a_class: "currency".to_string(),
+    ///     asset: "DOT".to_string(),
+    ///     amount: Decimal::new(10, 0),
+    ///     fee: Decimal::new(1, 0),
+    ///     balance: Some(Decimal::new(9, 0)),
+    /// };
+    /// let withdrawal = KrakenLedgerRecord {
+    ///     txid: Some("L8RLII-OFGWB-JTUO7J".to_string()),
+    ///     record_type: KrakenLedgerType::Withdrawal,
+    ///     amount: Decimal::new(-4, 0),
+    ///     fee: Decimal::zero(),
+    ///     balance: Some(Decimal::new(4, 0)),
+    ///     ..deposit.clone()
+    /// };
+    ///
+    /// let reconciliation = KrakenParser::new(vec![deposit, withdrawal]).reconcile_balances();
+    /// let mismatch = reconciliation.mismatches.first().unwrap();
+    /// assert_eq!(mismatch.txid, "L8RLII-OFGWB-JTUO7J");
+    /// assert_eq!(mismatch.computed_balance, Decimal::new(5, 0));
+    /// assert_eq!(mismatch.difference, Decimal::new(1, 0));
+    /// ```
+    pub fn reconcile_balances(&self) -> BalanceReconciliation {
+        let mut records: Vec<&KrakenLedgerRecord> = self
+            .data
+            .iter()
+            .filter(|record| record.txid.is_some())
+            .collect();
+        records.sort_by_key(|record| record.time);
+
+        records.into_iter().fold(
+            BalanceReconciliation::default(),
+            |mut reconciliation, record| {
+                let computed_balance = reconciliation
+                    .balances
+                    .entry(record.asset.to_string())
+                    .or_insert(Decimal::ZERO);
+                *computed_balance += record.amount - record.fee;
+                let computed_balance = *computed_balance;
+
+                if let Some(kraken_balance) = record
+                    .balance
+                    .filter(|balance| computed_balance.ne(balance))
+                {
+                    reconciliation.mismatches.push(BalanceMismatch {
+                        txid: record.txid.clone().unwrap_or_default(),
+                        refid: record.refid.to_string(),
+                        time: record.time,
+                        asset: record.asset.to_string(),
+                        kraken_balance,
+                        computed_balance,
+                        difference: computed_balance - kraken_balance,
+                    });
+                }
+
+                reconciliation
+            },
+        )
     }
 }
 
@@ -22,7 +297,7 @@ impl StakingRewards for KrakenParser<KrakenLedgerRecord> {
     /// ```
     /// use chrono::{TimeZone, Utc};
     ///    use models::{
-    ///        kraken::{KrakenLedgerRecord, DATE_FORMAT as KRAKEN_DATE_FORMAT},
+    ///        kraken::{KrakenLedgerRecord, KrakenLedgerType, DATE_FORMAT as KRAKEN_DATE_FORMAT},
     ///        StakingRewards,
     ///    };
     ///    use rust_decimal::prelude::{Decimal, Zero};
@@ -36,7 +311,7 @@ impl StakingRewards for KrakenParser<KrakenLedgerRecord> {
     ///            time: Utc
     ///                .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
     ///                .unwrap(),
-    ///            record_type: "staking".to_string(),
+    ///            record_type: KrakenLedgerType::Staking,
     ///            subtype: None,
     ///            a_class: "currency".to_string(),
     ///            asset: "DOT".to_string(),
@@ -44,14 +319,14 @@ impl StakingRewards for KrakenParser<KrakenLedgerRecord> {
     ///            fee: Decimal::zero(),
     ///            balance: Some(Decimal::new(5, 0)),
     ///        };
-
+    ///
     ///        let sample_ledger_2 = KrakenLedgerRecord {
     ///            txid: Some("899OJA-OFGWB-JTUO7J".to_string()),
     ///            refid: "RKB7ODD-ILZGC5-LCRRBL".to_string(),
     ///            time: Utc
     ///                .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
     ///                .unwrap(),
-    ///            record_type: "staking".to_string(),
+    ///            record_type: KrakenLedgerType::Staking,
     ///            subtype: None,
     ///            a_class: "currency".to_string(),
     ///            asset: "ADA".to_string(),
@@ -71,14 +346,14 @@ impl StakingRewards for KrakenParser<KrakenLedgerRecord> {
     ///    assert_eq!(*reward_map.get("DOT").unwrap(), Decimal::new(51002, 4));
     /// ```
     fn staking_rewards(&self) -> HashMap<String, Decimal> {
-        self.data
-            .iter()
-            .filter(|record| record.record_type.eq("staking"))
+        self.staking_reward_records()
+            .into_iter()
             .fold(HashMap::new(), |mut reward_map, record| {
-                if let Some(value) = reward_map.get(&record.asset) {
-                    reward_map.insert(record.asset.to_string(), value + record.amount);
+                let asset = canonical_ticker(&record.asset);
+                if let Some(value) = reward_map.get(&asset) {
+                    reward_map.insert(asset, value + record.amount);
                 } else {
-                    reward_map.insert(record.asset.to_string(), record.amount);
+                    reward_map.insert(asset, record.amount);
                 }
 
                 reward_map
@@ -88,10 +363,11 @@ impl StakingRewards for KrakenParser<KrakenLedgerRecord> {
 
 impl ActiveAssetValues for KrakenParser<KrakenLedgerRecord> {
     /// Retieve the active asset status for all assets. This calculates the current number of assets considering all input and output transactions
+    /// with their fees taken out.
     /// ```
     /// # use chrono::{TimeZone, Utc};
     /// # use models::{
-    /// #   kraken::{KrakenLedgerRecord, DATE_FORMAT as KRAKEN_DATE_FORMAT},
+    /// #   kraken::{KrakenLedgerRecord, KrakenLedgerType, DATE_FORMAT as KRAKEN_DATE_FORMAT},
     /// #   ActiveAssetValues,
     /// # };
     /// # use rust_decimal::prelude::{Decimal, Zero};
@@ -103,7 +379,7 @@ impl ActiveAssetValues for KrakenParser<KrakenLedgerRecord> {
     ///     time: Utc
     ///         .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
     ///         .unwrap(),
-    ///     record_type: "staking".to_string(),
+    ///     record_type: KrakenLedgerType::Staking,
     ///     subtype: None,
     ///     a_class: "currency".to_string(),
     ///     asset: "DOT".to_string(),
@@ -118,7 +394,7 @@ impl ActiveAssetValues for KrakenParser<KrakenLedgerRecord> {
     ///     time: Utc
     ///         .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
     ///         .unwrap(),
-    ///     record_type: "staking".to_string(),
+    ///     record_type: KrakenLedgerType::Staking,
     ///     subtype: None,
     ///     a_class: "currency".to_string(),
     ///     asset: "DOT".to_string(),
@@ -143,10 +419,11 @@ impl ActiveAssetValues for KrakenParser<KrakenLedgerRecord> {
             .iter()
             .filter(|record| record.txid.is_some())
             .fold(HashMap::new(), |mut map, record| {
-                if let Some(value) = map.get(&record.asset) {
-                    map.insert(record.asset.to_string(), value + record.amount);
+                let asset = canonical_ticker(&record.asset);
+                if let Some(value) = map.get(&asset) {
+                    map.insert(asset, value + record.amount - record.fee);
                 } else {
-                    map.insert(record.asset.to_string(), record.amount);
+                    map.insert(asset, record.amount - record.fee);
                 }
 
                 map
@@ -159,7 +436,7 @@ impl RecordsByAsset<KrakenLedgerRecord> for KrakenParser<KrakenLedgerRecord> {
     /// ```
     /// # use chrono::{TimeZone, Utc};
     /// # use models::{
-    /// #   kraken::{KrakenLedgerRecord, DATE_FORMAT as KRAKEN_DATE_FORMAT},
+    /// #   kraken::{KrakenLedgerRecord, KrakenLedgerType, DATE_FORMAT as KRAKEN_DATE_FORMAT},
     /// #   RecordsByAsset,
     /// # };
     /// # use rust_decimal::prelude::{Decimal, Zero};
@@ -171,7 +448,7 @@ impl RecordsByAsset<KrakenLedgerRecord> for KrakenParser<KrakenLedgerRecord> {
     ///    time: Utc
     ///        .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
     ///        .unwrap(),
-    ///    record_type: "staking".to_string(),
+    ///    record_type: KrakenLedgerType::Staking,
     ///    subtype: None,
     ///    a_class: "currency".to_string(),
     ///    asset: "DOT".to_string(),
@@ -186,7 +463,7 @@ impl RecordsByAsset<KrakenLedgerRecord> for KrakenParser<KrakenLedgerRecord> {
     ///    time: Utc
     ///        .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
     ///        .unwrap(),
-    ///    record_type: "staking".to_string(),
+    ///    record_type: KrakenLedgerType::Staking,
     ///    subtype: None,
     ///    a_class: "currency".to_string(),
     ///    asset: "DOT".to_string(),
@@ -199,15 +476,15 @@ impl RecordsByAsset<KrakenLedgerRecord> for KrakenParser<KrakenLedgerRecord> {
     ///
     /// let kraken_parser = KrakenParser::new(sample_vec.clone());
     /// let map = kraken_parser.by_asset();
-
+    ///
     /// assert_eq!(map.keys().len(), 1);
     /// assert_eq!(map.get("DOT").unwrap().len(), 2);
     /// assert_eq!(
     ///    **map.get("DOT").unwrap().iter().next().unwrap(),
-    ///    *sample_vec.get(0).unwrap()
+    ///    *sample_vec.first().unwrap()
     /// );
     /// assert_eq!(
-    ///    **map.get("DOT").unwrap().iter().nth(1).unwrap(),
+    ///    **map.get("DOT").unwrap().get(1).unwrap(),
     ///    *sample_vec.get(1).unwrap()
     /// );
     /// ```
@@ -215,10 +492,11 @@ impl RecordsByAsset<KrakenLedgerRecord> for KrakenParser<KrakenLedgerRecord> {
         self.data
             .iter()
             .fold(HashMap::new(), |mut currency_map, record| {
-                let mut vector = currency_map.remove(&record.asset).unwrap_or_default();
+                let asset = canonical_ticker(&record.asset);
+                let mut vector = currency_map.remove(&asset).unwrap_or_default();
 
                 vector.push(record);
-                currency_map.insert(record.asset.to_string(), vector);
+                currency_map.insert(asset, vector);
 
                 currency_map
             })
@@ -229,7 +507,7 @@ impl InputTransactions<KrakenLedgerRecord> for KrakenParser<KrakenLedgerRecord>
     /// ```
     /// # use chrono::{TimeZone, Utc};
     /// # use models::{
-    /// #   kraken::{KrakenLedgerRecord, DATE_FORMAT as KRAKEN_DATE_FORMAT},
+    /// #   kraken::{KrakenLedgerRecord, KrakenLedgerType, DATE_FORMAT as KRAKEN_DATE_FORMAT},
     /// #   InputTransactions,
     /// # };
     /// # use rust_decimal::prelude::{Decimal, Zero};
@@ -242,7 +520,7 @@ impl InputTransactions<KrakenLedgerRecord> for KrakenParser<KrakenLedgerRecord>
     ///    time: Utc
     ///        .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
     ///        .unwrap(),
-    ///    record_type: "buy".to_string(),
+    ///    record_type: KrakenLedgerType::Trade,
     ///    subtype: None,
     ///    a_class: "currency".to_string(),
     ///    asset: "DOT".to_string(),
@@ -257,7 +535,7 @@ impl InputTransactions<KrakenLedgerRecord> for KrakenParser<KrakenLedgerRecord>
     ///    time: Utc
     ///        .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
     ///        .unwrap(),
-    ///    record_type: "sell".to_string(),
+    ///    record_type: KrakenLedgerType::Trade,
     ///    subtype: None,
     ///    a_class: "currency".to_string(),
     ///    asset: "DOT".to_string(),
@@ -280,13 +558,303 @@ impl InputTransactions<KrakenLedgerRecord> for KrakenParser<KrakenLedgerRecord>
     }
 }
 
+impl CostBasisEvents for KrakenParser<KrakenLedgerRecord> {
+    /// Creates acquisitions and disposals from trades that were made against USD. Trades between two non USD assets
+    /// can not be valued from the ledger alone, they are left out and listed by [`KrakenParser::trades_not_in_usd`].
+    /// ```
+    /// # use chrono::{TimeZone, Utc};
+    /// # use models::{
+    /// #   cost_basis::CostBasisEvent,
+    /// #   kraken::{KrakenLedgerRecord, KrakenLedgerType, DATE_FORMAT as KRAKEN_DATE_FORMAT},
+    /// #   CostBasisEvents,
+    /// # };
+    /// # use rust_decimal::prelude::{Decimal, Zero};
+    /// # use kraken_parser::KrakenParser;
+    /// #
+    /// let time = Utc
+    ///     .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
+    ///     .unwrap();
+    /// let spent = KrakenLedgerRecord {
+    ///     txid: Some("L7RLII-OFGWB-JTUO7J".to_string()),
+    ///     refid: "TKB7ODD-ILZGC5-LCRRBL".to_string(),
+    ///     time,
+    ///     record_type: KrakenLedgerType::Trade,
+    ///     subtype: None,
+    ///     a_class: "currency".to_string(),
+    ///     asset: "ZUSD".to_string(),
+    ///     amount: Decimal::new(-100, 0),
+    ///     fee: Decimal::new(1, 0),
+    ///     balance: Some(Decimal::zero()),
+    /// };
+    /// let received = KrakenLedgerRecord {
+    ///     txid: Some("L8RLII-OFGWB-JTUO7J".to_string()),
+    ///     asset: "DOT".to_string(),
+    ///     amount: Decimal::new(10, 0),
+    ///     fee: Decimal::zero(),
+    ///     balance: Some(Decimal::new(10, 0)),
+    ///     ..spent.clone()
+    /// };
+    ///
+    /// let kraken_parser = KrakenParser::new(vec![spent, received]);
+    /// let events = kraken_parser.cost_basis_events();
+    /// assert_eq!(events.len(), 1);
+    /// match events.first() {
+    ///     Some(CostBasisEvent::Acquisition(acquisition)) => {
+    ///         assert_eq!(acquisition.asset, "DOT");
+    ///         assert_eq!(acquisition.cost, Decimal::new(101, 0));
+    ///     }
+    ///     _ => panic!("Trade was not seen as an acquisition"),
+    /// }
+    /// ```
+    fn cost_basis_events(&self) -> Vec<CostBasisEvent> {
+        self.trades()
+            .trades
+            .iter()
+            .filter(|trade| USD_ASSETS.contains(&trade.quote.as_str()))
+            .map(trade_cost_basis_event)
+            .collect()
+    }
+}
+
+impl UnknownTransactionTypes for KrakenParser<KrakenLedgerRecord> {
+    /// Reports ledger types, and subtypes as `type/subtype`, that are not understood. Rows of these types still
+    /// count towards balances, they are left out of trades and income.
+    fn unknown_transaction_types(&self) -> Vec<UnknownTransactionType> {
+        let names: Vec<(String, &str)> = self
+            .data
+            .iter()
+            .filter_map(|record| match &record.subtype {
+                Some(subtype) if subtype.is_unknown() => Some((
+                    format!("{}/{}", record.record_type, subtype),
+                    record.asset.as_str(),
+                )),
+                _ if record.record_type.is_unknown() => {
+                    Some((record.record_type.to_string(), record.asset.as_str()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        UnknownTransactionType::tally(
+            names
+                .iter()
+                .map(|(record_type, asset)| (record_type.as_str(), *asset)),
+        )
+    }
+}
+
+impl StakingIncome for KrakenParser<KrakenLedgerRecord> {
+    /// Kraken does not export prices, so every staking reward is valued with the given prices.
+    /// ```
+    /// # use chrono::{DateTime, TimeZone, Utc};
+    /// # use models::{
+    /// #   kraken::{KrakenLedgerRecord, KrakenLedgerType, DATE_FORMAT as KRAKEN_DATE_FORMAT},
+    /// #   HistoricalPrice, StakingIncome,
+    /// # };
+    /// # use rust_decimal::prelude::{Decimal, Zero};
+    /// # use kraken_parser::KrakenParser;
+    /// #
+    /// struct FixedPrice;
+    /// impl HistoricalPrice for FixedPrice {
+    ///     fn usd_price_at(&self, _: &str, _: &DateTime<Utc>) -> Option<Decimal> {
+    ///         Some(Decimal::new(5, 0))
+    ///     }
+    /// }
+    ///
+    /// let reward = KrakenLedgerRecord {
+    ///     txid: Some("L7RLII-OFGWB-JTUO7J".to_string()),
+    ///     refid: "RKB7ODD-ILZGC5-LCRRBL".to_string(),
+    ///     time: Utc
+    ///         .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
+    ///         .unwrap(),
+    ///     record_type: KrakenLedgerType::Staking,
+    ///     subtype: None,
+    ///     a_class: "currency".to_string(),
+    ///     asset: "DOT.S".to_string(),
+    ///     amount: Decimal::new(2, 0),
+    ///     fee: Decimal::zero(),
+    ///     balance: Some(Decimal::new(2, 0)),
+    /// };
+    ///
+    /// let income = KrakenParser::new(vec![reward]).staking_income(&FixedPrice);
+    /// assert_eq!(income.first().unwrap().usd_value, Some(Decimal::new(10, 0)));
+    /// ```
+    fn staking_income(&self, prices: &dyn HistoricalPrice) -> Vec<IncomeRecord> {
+        self.staking_reward_records()
+            .into_iter()
+            .map(|record| {
+                let quantity = record.amount - record.fee;
+                let asset = canonical_ticker(&record.asset);
+                let usd_price = prices.usd_price_at(&asset, &record.time);
+
+                IncomeRecord {
+                    id: format!("kraken-{}", record.txid.as_deref().unwrap_or(&record.refid)),
+                    asset,
+                    quantity,
+                    received_at: record.time,
+                    usd_price,
+                    usd_value: usd_price.map(|price| price * quantity),
+                }
+            })
+            .collect()
+    }
+}
+
+fn is_reward(record: &KrakenLedgerRecord) -> bool {
+    match (&record.record_type, &record.subtype) {
+        (KrakenLedgerType::Staking, subtype) => {
+            record.amount > Decimal::ZERO
+                && !subtype
+                    .as_ref()
+                    .is_some_and(KrakenLedgerSubtype::is_staking_transfer)
+        }
+        (KrakenLedgerType::Earn, Some(KrakenLedgerSubtype::Reward)) => true,
+        _ => false,
+    }
+}
+
+fn is_holding_move(record: &KrakenLedgerRecord) -> bool {
+    matches!(
+        record.record_type,
+        KrakenLedgerType::Transfer | KrakenLedgerType::Deposit | KrakenLedgerType::Withdrawal
+    ) || record
+        .subtype
+        .as_ref()
+        .is_some_and(KrakenLedgerSubtype::is_staking_transfer)
+        || (record.record_type == KrakenLedgerType::Staking && record.amount < Decimal::ZERO)
+}
+
+fn reconstruct_trade(
+    refid: &str,
+    legs: &[&KrakenLedgerRecord],
+) -> Result<KrakenTrade, UnbalancedReason> {
+    let mut assets: Vec<&str> = Vec::new();
+    let totals = legs.iter().fold(HashMap::new(), |mut asset_map, leg| {
+        let (amount, fee) = asset_map.entry(leg.asset.as_str()).or_insert_with(|| {
+            assets.push(leg.asset.as_str());
+            (Decimal::ZERO, Decimal::ZERO)
+        });
+        *amount += leg.amount;
+        *fee += leg.fee;
+
+        asset_map
+    });
+
+    let (spent, received) = match assets.as_slice() {
+        [_] => return Err(UnbalancedReason::SingleAsset),
+        [first, second] => match (
+            totals[first].0.is_sign_negative(),
+            totals[second].0.is_sign_negative(),
+        ) {
+            (true, false) => (*first, *second),
+            (false, true) => (*second, *first),
+            _ => return Err(UnbalancedReason::SameDirection),
+        },
+        _ => return Err(UnbalancedReason::TooManyAssets),
+    };
+
+    let quote_rank = |asset: &str| {
+        let ticker = canonical_ticker(asset);
+        QUOTE_ASSETS
+            .iter()
+            .position(|quote_asset| quote_asset.eq(&ticker))
+            .unwrap_or(QUOTE_ASSETS.len())
+    };
+    let (side, base, quote) = if quote_rank(received) < quote_rank(spent) {
+        (TradeSide::Sell, spent, received)
+    } else {
+        (TradeSide::Buy, received, spent)
+    };
+
+    let (base_amount, base_fee) = totals[base];
+    let (quote_amount, quote_fee) = totals[quote];
+    if base_amount.is_zero() {
+        return Err(UnbalancedReason::SameDirection);
+    }
+
+    Ok(KrakenTrade {
+        refid: refid.to_string(),
+        base_txid: legs
+            .iter()
+            .find(|leg| leg.asset.eq(base))
+            .and_then(|leg| leg.txid.clone()),
+        time: legs.iter().map(|leg| leg.time).min().unwrap_or_default(),
+        side,
+        base: canonical_ticker(base),
+        quote: canonical_ticker(quote),
+        base_quantity: base_amount.abs(),
+        quote_quantity: quote_amount.abs(),
+        price: quote_amount.abs() / base_amount.abs(),
+        base_fee,
+        quote_fee,
+    })
+}
+
+/// Replaces the quantities and price worked out from the ledger with those of the fills that booked the legs.
+/// Fills of a pair quoted the other way round, which show up with the opposite side, are left alone.
+fn price_from_fills(
+    trade: KrakenTrade,
+    legs: &[&KrakenLedgerRecord],
+    fills_by_ledger_txid: &HashMap<&str, &KrakenTradeRecord>,
+) -> KrakenTrade {
+    let mut fill_txids: HashSet<&str> = HashSet::new();
+    let fills: Vec<&KrakenTradeRecord> = legs
+        .iter()
+        .filter_map(|leg| leg.txid.as_deref())
+        .filter_map(|txid| fills_by_ledger_txid.get(txid).copied())
+        .filter(|fill| fill_txids.insert(fill.txid.as_str()))
+        .collect();
+
+    let vol: Decimal = fills.iter().map(|fill| fill.vol).sum();
+    if fills.is_empty() || vol.is_zero() || fills.iter().any(|fill| fill.trade_type != trade.side) {
+        return trade;
+    }
+
+    KrakenTrade {
+        base_quantity: vol,
+        quote_quantity: fills.iter().map(|fill| fill.cost).sum(),
+        price: fills
+            .iter()
+            .map(|fill| fill.price * fill.vol)
+            .sum::<Decimal>()
+            / vol,
+        ..trade
+    }
+}
+
+fn trade_cost_basis_event(trade: &KrakenTrade) -> CostBasisEvent {
+    let id = format!(
+        "kraken-{}",
+        trade.base_txid.as_deref().unwrap_or(&trade.refid)
+    );
+
+    match trade.side {
+        TradeSide::Buy => CostBasisEvent::Acquisition(Acquisition {
+            id,
+            asset: trade.base.to_string(),
+            quantity: trade.base_quantity - trade.base_fee,
+            cost: trade.quote_quantity + trade.quote_fee,
+            fees: trade.quote_fee,
+            time: trade.time,
+        }),
+        TradeSide::Sell => CostBasisEvent::Disposal(Disposal {
+            id,
+            asset: trade.base.to_string(),
+            quantity: trade.base_quantity + trade.base_fee,
+            proceeds: trade.quote_quantity,
+            fees: trade.quote_fee,
+            time: trade.time,
+        }),
+    }
+}
+
 #[cfg(test)]
 mod staking_rewards_for {
     #[cfg(test)]
     mod kraken_ledger_record {
         use chrono::{TimeZone, Utc};
         use models::{
-            kraken::{KrakenLedgerRecord, DATE_FORMAT as KRAKEN_DATE_FORMAT},
+            kraken::{KrakenLedgerRecord, KrakenLedgerType, DATE_FORMAT as KRAKEN_DATE_FORMAT},
             StakingRewards,
         };
         use rust_decimal::prelude::{Decimal, Zero};
@@ -301,7 +869,7 @@ mod staking_rewards_for {
                 time: Utc
                     .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
                     .unwrap(),
-                record_type: "staking".to_string(),
+                record_type: KrakenLedgerType::Staking,
                 subtype: None,
                 a_class: "currency".to_string(),
                 asset: "DOT".to_string(),
@@ -316,7 +884,7 @@ mod staking_rewards_for {
                 time: Utc
                     .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
                     .unwrap(),
-                record_type: "staking".to_string(),
+                record_type: KrakenLedgerType::Staking,
                 subtype: None,
                 a_class: "currency".to_string(),
                 asset: "ADA".to_string(),
@@ -353,7 +921,7 @@ mod staking_rewards_for {
                 time: Utc
                     .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
                     .unwrap(),
-                record_type: "staking".to_string(),
+                record_type: KrakenLedgerType::Staking,
                 subtype: None,
                 a_class: "currency".to_string(),
                 asset: "DOT".to_string(),
@@ -368,7 +936,7 @@ mod staking_rewards_for {
                 time: Utc
                     .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
                     .unwrap(),
-                record_type: "staking".to_string(),
+                record_type: KrakenLedgerType::Staking,
                 subtype: None,
                 a_class: "currency".to_string(),
                 asset: "DOT".to_string(),
@@ -402,7 +970,7 @@ mod active_asset_values_for {
     mod kraken_ledger_record {
         use chrono::{TimeZone, Utc};
         use models::{
-            kraken::{KrakenLedgerRecord, DATE_FORMAT as KRAKEN_DATE_FORMAT},
+            kraken::{KrakenLedgerRecord, KrakenLedgerType, DATE_FORMAT as KRAKEN_DATE_FORMAT},
             ActiveAssetValues,
         };
         use rust_decimal::prelude::{Decimal, Zero};
@@ -417,7 +985,7 @@ mod active_asset_values_for {
                 time: Utc
                     .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
                     .unwrap(),
-                record_type: "staking".to_string(),
+                record_type: KrakenLedgerType::Staking,
                 subtype: None,
                 a_class: "currency".to_string(),
                 asset: "DOT".to_string(),
@@ -432,7 +1000,7 @@ mod active_asset_values_for {
                 time: Utc
                     .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
                     .unwrap(),
-                record_type: "staking".to_string(),
+                record_type: KrakenLedgerType::Staking,
                 subtype: None,
                 a_class: "currency".to_string(),
                 asset: "DOT".to_string(),
@@ -454,6 +1022,61 @@ mod active_asset_values_for {
             assert_eq!(*active_assets.get("DOT").unwrap(), expected_sum);
         }
 
+        #[test]
+        fn should_combine_legacy_and_staked_codes_of_one_asset() {
+            let record = KrakenLedgerRecord {
+                txid: Some("L7RLII-OFGWB-JTUO7J".to_string()),
+                refid: "RKB7ODD-ILZGC5-LCRRBL".to_string(),
+                time: Utc
+                    .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
+                    .unwrap(),
+                record_type: KrakenLedgerType::Deposit,
+                subtype: None,
+                a_class: "currency".to_string(),
+                asset: "XXBT".to_string(),
+                amount: Decimal::new(1, 0),
+                fee: Decimal::zero(),
+                balance: Some(Decimal::new(1, 0)),
+            };
+            let staked = KrakenLedgerRecord {
+                asset: "XBT.M".to_string(),
+                ..record.clone()
+            };
+
+            let active_assets = KrakenParser::new(vec![record, staked]).active_assets();
+
+            assert_eq!(active_assets.len(), 1);
+            assert_eq!(*active_assets.get("BTC").unwrap(), Decimal::new(2, 0));
+        }
+
+        #[test]
+        fn should_take_fees_out_of_the_balance() {
+            let record = KrakenLedgerRecord {
+                txid: Some("L7RLII-OFGWB-JTUO7J".to_string()),
+                refid: "RKB7ODD-ILZGC5-LCRRBL".to_string(),
+                time: Utc
+                    .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
+                    .unwrap(),
+                record_type: KrakenLedgerType::Withdrawal,
+                subtype: None,
+                a_class: "currency".to_string(),
+                asset: "DOT".to_string(),
+                amount: Decimal::new(-5, 0),
+                fee: Decimal::new(1, 1),
+                balance: Some(Decimal::new(49, 1)),
+            };
+            let deposit = KrakenLedgerRecord {
+                record_type: KrakenLedgerType::Deposit,
+                amount: Decimal::new(10, 0),
+                fee: Decimal::zero(),
+                ..record.clone()
+            };
+
+            let active_assets = KrakenParser::new(vec![deposit, record]).active_assets();
+
+            assert_eq!(*active_assets.get("DOT").unwrap(), Decimal::new(49, 1));
+        }
+
         #[test]
         fn should_subtract_negative_values_book() {
             let sample_ledger_1 = KrakenLedgerRecord {
@@ -462,7 +1085,7 @@ mod active_asset_values_for {
                 time: Utc
                     .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
                     .unwrap(),
-                record_type: "staking".to_string(),
+                record_type: KrakenLedgerType::Staking,
                 subtype: None,
                 a_class: "currency".to_string(),
                 asset: "DOT".to_string(),
@@ -477,7 +1100,7 @@ mod active_asset_values_for {
                 time: Utc
                     .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
                     .unwrap(),
-                record_type: "staking".to_string(),
+                record_type: KrakenLedgerType::Staking,
                 subtype: None,
                 a_class: "currency".to_string(),
                 asset: "DOT".to_string(),
@@ -517,7 +1140,7 @@ mod records_by_asset_for {
     mod kraken_ledger_record {
         use chrono::{TimeZone, Utc};
         use models::{
-            kraken::{KrakenLedgerRecord, DATE_FORMAT as KRAKEN_DATE_FORMAT},
+            kraken::{KrakenLedgerRecord, KrakenLedgerType, DATE_FORMAT as KRAKEN_DATE_FORMAT},
             RecordsByAsset,
         };
         use rust_decimal::{prelude::Zero, Decimal};
@@ -532,7 +1155,7 @@ mod records_by_asset_for {
                 time: Utc
                     .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
                     .unwrap(),
-                record_type: "staking".to_string(),
+                record_type: KrakenLedgerType::Staking,
                 subtype: None,
                 a_class: "currency".to_string(),
                 asset: "DOT".to_string(),
@@ -547,7 +1170,7 @@ mod records_by_asset_for {
                 time: Utc
                     .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
                     .unwrap(),
-                record_type: "staking".to_string(),
+                record_type: KrakenLedgerType::Staking,
                 subtype: None,
                 a_class: "currency".to_string(),
                 asset: "DOT".to_string(),
@@ -565,10 +1188,10 @@ mod records_by_asset_for {
             assert_eq!(map.get("DOT").unwrap().len(), 2);
             assert_eq!(
                 **map.get("DOT").unwrap().iter().next().unwrap(),
-                *sample_vec.get(0).unwrap()
+                *sample_vec.first().unwrap()
             );
             assert_eq!(
-                **map.get("DOT").unwrap().iter().nth(1).unwrap(),
+                **map.get("DOT").unwrap().get(1).unwrap(),
                 *sample_vec.get(1).unwrap()
             );
         }
@@ -581,7 +1204,7 @@ mod records_by_asset_for {
                 time: Utc
                     .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
                     .unwrap(),
-                record_type: "staking".to_string(),
+                record_type: KrakenLedgerType::Staking,
                 subtype: None,
                 a_class: "currency".to_string(),
                 asset: "ADA".to_string(),
@@ -597,7 +1220,7 @@ mod records_by_asset_for {
             assert_eq!(asset_map.get("ADA").unwrap().len(), 1);
             assert_eq!(
                 **asset_map.get("ADA").unwrap().iter().next().unwrap(),
-                *sample_vec.get(0).unwrap()
+                *sample_vec.first().unwrap()
             );
         }
 
@@ -611,3 +1234,593 @@ mod records_by_asset_for {
         }
     }
 }
+
+#[cfg(test)]
+mod cost_basis_events_for {
+    #[cfg(test)]
+    mod kraken_ledger_record {
+        use chrono::{TimeZone, Utc};
+        use models::{
+            cost_basis::CostBasisEvent,
+            kraken::{KrakenLedgerRecord, KrakenLedgerType, DATE_FORMAT as KRAKEN_DATE_FORMAT},
+            CostBasisEvents,
+        };
+        use rust_decimal::{prelude::Zero, Decimal};
+
+        use crate::KrakenParser;
+
+        fn trade_leg(txid: &str, refid: &str, asset: &str, amount: Decimal) -> KrakenLedgerRecord {
+            KrakenLedgerRecord {
+                txid: Some(txid.to_string()),
+                refid: refid.to_string(),
+                time: Utc
+                    .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
+                    .unwrap(),
+                record_type: KrakenLedgerType::Trade,
+                subtype: None,
+                a_class: "currency".to_string(),
+                asset: asset.to_string(),
+                amount,
+                fee: Decimal::zero(),
+                balance: None,
+            }
+        }
+
+        #[test]
+        fn create_disposal_when_selling_for_usd() {
+            let sold = KrakenLedgerRecord {
+                fee: Decimal::new(1, 1),
+                ..trade_leg("L1", "T1", "DOT", Decimal::new(-10, 0))
+            };
+            let received = KrakenLedgerRecord {
+                fee: Decimal::new(2, 0),
+                ..trade_leg("L2", "T1", "ZUSD", Decimal::new(200, 0))
+            };
+
+            let kraken_parser = KrakenParser::new(vec![sold, received]);
+
+            match kraken_parser.cost_basis_events().as_slice() {
+                [CostBasisEvent::Disposal(disposal)] => {
+                    assert_eq!(disposal.id, "kraken-L1");
+                    assert_eq!(disposal.asset, "DOT");
+                    assert_eq!(disposal.quantity, Decimal::new(101, 1));
+                    assert_eq!(disposal.proceeds, Decimal::new(200, 0));
+                    assert_eq!(disposal.fees, Decimal::new(2, 0));
+                }
+                events => panic!("Unexpected events {events:?}"),
+            }
+        }
+
+        #[test]
+        fn list_trades_without_usd_instead_of_valuing_them() {
+            let kraken_parser = KrakenParser::new(vec![
+                trade_leg("L1", "T1", "DOT", Decimal::new(-10, 0)),
+                trade_leg("L2", "T1", "XETH", Decimal::new(1, 0)),
+                trade_leg("L3", "T2", "DOT", Decimal::new(-10, 0)),
+                trade_leg("L4", "T2", "ZUSD", Decimal::new(200, 0)),
+            ]);
+
+            assert_eq!(kraken_parser.cost_basis_events().len(), 1);
+            match kraken_parser.trades_not_in_usd().as_slice() {
+                [trade] => {
+                    assert_eq!(trade.refid, "T1");
+                    assert_eq!(trade.base, "DOT");
+                    assert_eq!(trade.quote, "ETH");
+                }
+                trades => panic!("Unexpected trades {trades:?}"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod trades_should {
+    use chrono::{TimeZone, Utc};
+    use models::kraken::{
+        KrakenLedgerRecord, KrakenLedgerType, KrakenTradeRecord, TradeSide, UnbalancedReason,
+        DATE_FORMAT as KRAKEN_DATE_FORMAT,
+    };
+    use rust_decimal::{prelude::Zero, Decimal};
+
+    use crate::KrakenParser;
+
+    fn trade_leg(txid: &str, refid: &str, asset: &str, amount: Decimal) -> KrakenLedgerRecord {
+        KrakenLedgerRecord {
+            txid: Some(txid.to_string()),
+            refid: refid.to_string(),
+            time: Utc
+                .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
+                .unwrap(),
+            record_type: KrakenLedgerType::Trade,
+            subtype: None,
+            a_class: "currency".to_string(),
+            asset: asset.to_string(),
+            amount,
+            fee: Decimal::zero(),
+            balance: None,
+        }
+    }
+
+    #[test]
+    fn pair_a_sell_with_its_quote() {
+        let sold = KrakenLedgerRecord {
+            fee: Decimal::new(1, 1),
+            ..trade_leg("L1", "T1", "DOT", Decimal::new(-10, 0))
+        };
+        let received = KrakenLedgerRecord {
+            fee: Decimal::new(2, 0),
+            ..trade_leg("L2", "T1", "ZUSD", Decimal::new(200, 0))
+        };
+
+        let report = KrakenParser::new(vec![received, sold]).trades();
+        let trade = report.trades.first().unwrap();
+
+        assert_eq!(trade.side, TradeSide::Sell);
+        assert_eq!(trade.base, "DOT");
+        assert_eq!(trade.quote, "USD");
+        assert_eq!(trade.base_txid.as_deref(), Some("L1"));
+        assert_eq!(trade.base_quantity, Decimal::new(10, 0));
+        assert_eq!(trade.quote_quantity, Decimal::new(200, 0));
+        assert_eq!(trade.price, Decimal::new(20, 0));
+        assert_eq!(trade.base_fee, Decimal::new(1, 1));
+        assert_eq!(trade.quote_fee, Decimal::new(2, 0));
+    }
+
+    fn fill(
+        trade_type: TradeSide,
+        price: Decimal,
+        vol: Decimal,
+        ledgers: &str,
+    ) -> KrakenTradeRecord {
+        KrakenTradeRecord {
+            txid: "T1".to_string(),
+            ordertxid: "O1".to_string(),
+            pair: "DOTUSD".to_string(),
+            time: Utc
+                .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
+                .unwrap(),
+            trade_type,
+            ordertype: "limit".to_string(),
+            price,
+            cost: (price * vol).round_dp(5),
+            fee: Decimal::zero(),
+            vol,
+            margin: Decimal::zero(),
+            misc: "".to_string(),
+            ledgers: ledgers.to_string(),
+        }
+    }
+
+    #[test]
+    fn take_the_price_from_the_fill() {
+        let report = KrakenParser::new(vec![
+            trade_leg("L1", "T1", "ZUSD", Decimal::new(-1000, 2)),
+            trade_leg("L2", "T1", "DOT", Decimal::new(3, 0)),
+        ])
+        .with_trades(vec![fill(
+            TradeSide::Buy,
+            Decimal::new(33333, 4),
+            Decimal::new(3, 0),
+            "L1,L2",
+        )])
+        .trades();
+        let trade = report.trades.first().unwrap();
+
+        assert_eq!(trade.price, Decimal::new(33333, 4));
+        assert_eq!(trade.quote_quantity, Decimal::new(99999, 4));
+        assert_eq!(trade.base_quantity, Decimal::new(3, 0));
+    }
+
+    #[test]
+    fn keep_the_ledger_price_when_the_fill_is_quoted_the_other_way() {
+        let report = KrakenParser::new(vec![
+            trade_leg("L1", "T1", "ZUSD", Decimal::new(-100, 0)),
+            trade_leg("L2", "T1", "DOT", Decimal::new(4, 0)),
+        ])
+        .with_trades(vec![fill(
+            TradeSide::Sell,
+            Decimal::new(4, 2),
+            Decimal::new(100, 0),
+            "L1,L2",
+        )])
+        .trades();
+        let trade = report.trades.first().unwrap();
+
+        assert_eq!(trade.price, Decimal::new(25, 0));
+        assert_eq!(trade.quote_quantity, Decimal::new(100, 0));
+    }
+
+    #[test]
+    fn leave_margin_rows_out_of_trades() {
+        let report = KrakenParser::new(vec![
+            KrakenLedgerRecord {
+                record_type: KrakenLedgerType::Margin,
+                ..trade_leg("L1", "T1", "ZUSD", Decimal::new(-25, 0))
+            },
+            KrakenLedgerRecord {
+                record_type: KrakenLedgerType::Rollover,
+                fee: Decimal::new(1, 1),
+                ..trade_leg("L2", "T2", "ZUSD", Decimal::zero())
+            },
+        ])
+        .trades();
+
+        assert!(report.trades.is_empty());
+        assert!(report.unbalanced.is_empty());
+    }
+
+    #[test]
+    fn combine_partial_fills_of_the_same_refid() {
+        let report = KrakenParser::new(vec![
+            trade_leg("L1", "T1", "XXBT", Decimal::new(-1, 0)),
+            trade_leg("L2", "T1", "DOT", Decimal::new(600, 0)),
+            trade_leg("L3", "T1", "XXBT", Decimal::new(-1, 0)),
+            trade_leg("L4", "T1", "DOT", Decimal::new(400, 0)),
+        ])
+        .trades();
+        let trade = report.trades.first().unwrap();
+
+        assert_eq!(trade.side, TradeSide::Buy);
+        assert_eq!(trade.base, "DOT");
+        assert_eq!(trade.quote, "BTC");
+        assert_eq!(trade.base_quantity, Decimal::new(1000, 0));
+        assert_eq!(trade.price, Decimal::new(2, 3));
+    }
+
+    #[test]
+    fn flag_refids_that_do_not_balance() {
+        let report = KrakenParser::new(vec![
+            trade_leg("L1", "T1", "DOT", Decimal::new(-10, 0)),
+            trade_leg("L2", "T2", "DOT", Decimal::new(10, 0)),
+            trade_leg("L3", "T2", "ZUSD", Decimal::new(10, 0)),
+            trade_leg("L4", "T3", "DOT", Decimal::new(10, 0)),
+            trade_leg("L5", "T3", "ZUSD", Decimal::new(-10, 0)),
+            trade_leg("L6", "T3", "XETH", Decimal::new(-1, 0)),
+        ])
+        .trades();
+
+        let reasons: Vec<(&str, UnbalancedReason)> = report
+            .unbalanced
+            .iter()
+            .map(|unbalanced| (unbalanced.refid.as_str(), unbalanced.reason))
+            .collect();
+
+        assert!(report.trades.is_empty());
+        assert_eq!(
+            reasons,
+            [
+                ("T1", UnbalancedReason::SingleAsset),
+                ("T2", UnbalancedReason::SameDirection),
+                ("T3", UnbalancedReason::TooManyAssets),
+            ]
+        );
+    }
+}
+
+#[cfg(test)]
+mod reconcile_balances_should {
+    use chrono::{TimeZone, Utc};
+    use models::kraken::{KrakenLedgerRecord, KrakenLedgerType, DATE_FORMAT as KRAKEN_DATE_FORMAT};
+    use rust_decimal::{prelude::Zero, Decimal};
+
+    use crate::KrakenParser;
+
+    fn ledger_row(
+        txid: Option<&str>,
+        time: &str,
+        asset: &str,
+        amount: Decimal,
+        balance: Option<Decimal>,
+    ) -> KrakenLedgerRecord {
+        KrakenLedgerRecord {
+            txid: txid.map(str::to_string),
+            refid: "RKB7ODD-ILZGC5-LCRRBL".to_string(),
+            time: Utc.datetime_from_str(time, KRAKEN_DATE_FORMAT).unwrap(),
+            record_type: KrakenLedgerType::Deposit,
+            subtype: None,
+            a_class: "currency".to_string(),
+            asset: asset.to_string(),
+            amount,
+            fee: Decimal::zero(),
+            balance,
+        }
+    }
+
+    #[test]
+    fn report_nothing_when_the_ledger_is_complete() {
+        let reconciliation = KrakenParser::new(vec![
+            ledger_row(
+                Some("L2"),
+                "2021-09-30 15:18:30",
+                "DOT",
+                Decimal::new(-4, 0),
+                Some(Decimal::new(6, 0)),
+            ),
+            ledger_row(
+                Some("L1"),
+                "2021-09-29 15:18:30",
+                "DOT",
+                Decimal::new(10, 0),
+                Some(Decimal::new(10, 0)),
+            ),
+            ledger_row(
+                None,
+                "2021-09-29 15:18:30",
+                "DOT",
+                Decimal::new(10, 0),
+                Some(Decimal::new(10, 0)),
+            ),
+        ])
+        .reconcile_balances();
+
+        assert!(reconciliation.mismatches.is_empty());
+        assert_eq!(
+            reconciliation.balances.get("DOT"),
+            Some(&Decimal::new(6, 0))
+        );
+    }
+
+    #[test]
+    fn keep_a_balance_per_asset_code() {
+        let reconciliation = KrakenParser::new(vec![
+            ledger_row(
+                Some("L1"),
+                "2021-09-29 15:18:30",
+                "DOT",
+                Decimal::new(10, 0),
+                Some(Decimal::new(10, 0)),
+            ),
+            ledger_row(
+                Some("L2"),
+                "2021-09-30 15:18:30",
+                "DOT.S",
+                Decimal::new(3, 0),
+                Some(Decimal::new(3, 0)),
+            ),
+        ])
+        .reconcile_balances();
+
+        assert!(reconciliation.mismatches.is_empty());
+        assert_eq!(reconciliation.balances.len(), 2);
+    }
+
+    #[test]
+    fn report_every_row_after_a_missing_row() {
+        let reconciliation = KrakenParser::new(vec![
+            ledger_row(
+                Some("L2"),
+                "2021-09-30 15:18:30",
+                "DOT",
+                Decimal::new(1, 0),
+                Some(Decimal::new(11, 0)),
+            ),
+            ledger_row(
+                Some("L3"),
+                "2021-10-01 15:18:30",
+                "DOT",
+                Decimal::new(1, 0),
+                None,
+            ),
+            ledger_row(
+                Some("L4"),
+                "2021-10-02 15:18:30",
+                "DOT",
+                Decimal::new(1, 0),
+                Some(Decimal::new(13, 0)),
+            ),
+        ])
+        .reconcile_balances();
+
+        let mismatches: Vec<(&str, Decimal)> = reconciliation
+            .mismatches
+            .iter()
+            .map(|mismatch| (mismatch.txid.as_str(), mismatch.difference))
+            .collect();
+
+        assert_eq!(
+            mismatches,
+            [("L2", Decimal::new(-10, 0)), ("L4", Decimal::new(-10, 0))]
+        );
+    }
+}
+
+#[cfg(test)]
+mod staking_reward_records_should {
+    use chrono::{TimeZone, Utc};
+    use models::{
+        kraken::{
+            KrakenLedgerRecord, KrakenLedgerSubtype, KrakenLedgerType,
+            DATE_FORMAT as KRAKEN_DATE_FORMAT,
+        },
+        StakingRewards,
+    };
+    use rust_decimal::{prelude::Zero, Decimal};
+
+    use crate::KrakenParser;
+
+    fn ledger_row(
+        txid: &str,
+        refid: &str,
+        record_type: KrakenLedgerType,
+        subtype: Option<KrakenLedgerSubtype>,
+        asset: &str,
+        amount: Decimal,
+    ) -> KrakenLedgerRecord {
+        KrakenLedgerRecord {
+            txid: Some(txid.to_string()),
+            refid: refid.to_string(),
+            time: Utc
+                .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
+                .unwrap(),
+            record_type,
+            subtype,
+            a_class: "currency".to_string(),
+            asset: asset.to_string(),
+            amount,
+            fee: Decimal::zero(),
+            balance: None,
+        }
+    }
+
+    #[test]
+    fn not_count_staking_transfers_as_rewards() {
+        let kraken_parser = KrakenParser::new(vec![
+            ledger_row(
+                "L1",
+                "R1",
+                KrakenLedgerType::Transfer,
+                Some(KrakenLedgerSubtype::SpotToStaking),
+                "DOT",
+                Decimal::new(-10, 0),
+            ),
+            ledger_row(
+                "L2",
+                "R2",
+                KrakenLedgerType::Transfer,
+                Some(KrakenLedgerSubtype::StakingFromSpot),
+                "DOT.S",
+                Decimal::new(10, 0),
+            ),
+            ledger_row(
+                "L3",
+                "R3",
+                KrakenLedgerType::Withdrawal,
+                None,
+                "DOT",
+                Decimal::new(-5, 0),
+            ),
+            ledger_row(
+                "L4",
+                "R3",
+                KrakenLedgerType::Staking,
+                None,
+                "DOT.S",
+                Decimal::new(5, 0),
+            ),
+            ledger_row(
+                "L5",
+                "R4",
+                KrakenLedgerType::Staking,
+                None,
+                "DOT.S",
+                Decimal::new(2, 1),
+            ),
+        ]);
+
+        let rewards = kraken_parser.staking_rewards();
+
+        assert_eq!(rewards.len(), 1);
+        assert_eq!(*rewards.get("DOT").unwrap(), Decimal::new(2, 1));
+    }
+
+    #[test]
+    fn count_earn_rewards_but_not_allocations() {
+        let kraken_parser = KrakenParser::new(vec![
+            ledger_row(
+                "L1",
+                "R1",
+                KrakenLedgerType::Earn,
+                Some(KrakenLedgerSubtype::Allocation),
+                "ETH",
+                Decimal::new(1, 0),
+            ),
+            ledger_row(
+                "L2",
+                "R2",
+                KrakenLedgerType::Earn,
+                Some(KrakenLedgerSubtype::Reward),
+                "ETH",
+                Decimal::new(1, 3),
+            ),
+        ]);
+
+        let rewards = kraken_parser.staking_reward_records();
+
+        assert_eq!(rewards.len(), 1);
+        assert_eq!(rewards.first().unwrap().txid.as_deref(), Some("L2"));
+    }
+
+    #[test]
+    fn skip_duplicate_rows_without_a_txid() {
+        let reward = ledger_row(
+            "L1",
+            "R1",
+            KrakenLedgerType::Staking,
+            None,
+            "ADA.S",
+            Decimal::new(3, 0),
+        );
+        let duplicate = KrakenLedgerRecord {
+            txid: None,
+            ..reward.clone()
+        };
+
+        let kraken_parser = KrakenParser::new(vec![duplicate, reward]);
+
+        assert_eq!(
+            *kraken_parser.staking_rewards().get("ADA").unwrap(),
+            Decimal::new(3, 0)
+        );
+    }
+}
+
+#[cfg(test)]
+mod unknown_transaction_types_for {
+    use chrono::{TimeZone, Utc};
+    use models::{
+        kraken::{
+            KrakenLedgerRecord, KrakenLedgerSubtype, KrakenLedgerType,
+            DATE_FORMAT as KRAKEN_DATE_FORMAT,
+        },
+        UnknownTransactionType, UnknownTransactionTypes,
+    };
+    use rust_decimal::{prelude::Zero, Decimal};
+
+    use crate::KrakenParser;
+
+    #[test]
+    fn report_unknown_types_and_subtypes() {
+        let record = KrakenLedgerRecord {
+            txid: Some("L1".to_string()),
+            refid: "R1".to_string(),
+            time: Utc
+                .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
+                .unwrap(),
+            record_type: KrakenLedgerType::from("nfttrade"),
+            subtype: None,
+            a_class: "currency".to_string(),
+            asset: "ETH".to_string(),
+            amount: Decimal::new(-1, 0),
+            fee: Decimal::zero(),
+            balance: None,
+        };
+        let transfer = KrakenLedgerRecord {
+            record_type: KrakenLedgerType::Transfer,
+            subtype: Some(KrakenLedgerSubtype::from("spottoopt")),
+            asset: "DOT".to_string(),
+            ..record.clone()
+        };
+        let known = KrakenLedgerRecord {
+            record_type: KrakenLedgerType::Deposit,
+            ..record.clone()
+        };
+
+        let unknown = KrakenParser::new(vec![record, transfer, known]).unknown_transaction_types();
+
+        assert_eq!(
+            unknown,
+            vec![
+                UnknownTransactionType {
+                    transaction_type: "nfttrade".to_string(),
+                    occurrences: 1,
+                    assets: vec!["ETH".to_string()],
+                },
+                UnknownTransactionType {
+                    transaction_type: "transfer/spottoopt".to_string(),
+                    occurrences: 1,
+                    assets: vec!["DOT".to_string()],
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+
+use csv_parser::{Csv, CsvIdentifier, ParseMode, ParsedCsv, RowError};
+use models::{
+    asset::canonical_ticker,
+    cost_basis::{Acquisition, CostBasisEvent, Disposal},
+    InputTransaction,
+};
+use rust_decimal::Decimal;
+
+pub use models::{
+    mapping::{ColumnError, ColumnMapping, MappedTransaction, SideMapping},
+    ActiveAssetValues, CostBasisEvents, InputTransactions, RecordsByAsset, StakingRewards,
+};
+
+pub struct MappedParser<T> {
+    data: Vec<T>,
+}
+
+impl<T> MappedParser<T> {
+    pub fn new(data: Vec<T>) -> Self {
+        Self { data }
+    }
+}
+
+/// Reads a csv with a user defined mapping. The csv has to have every column the mapping reads.
+pub fn parse_mapped_csv(
+    csv: &str,
+    mapping: &ColumnMapping,
+    mode: ParseMode,
+) -> Result<ParsedCsv<MappedTransaction>, RowError> {
+    if !Csv::is_valid_csv(csv, mapping.columns()) {
+        return Err(RowError {
+            line: 1,
+            column: None,
+            raw_value: None,
+            reason: format!(
+                "The csv does not have every column of mapping \"{}\": {}",
+                mapping.name,
+                mapping.columns().join(", ")
+            ),
+        });
+    }
+
+    Csv::parse_rows_with_diagnostics(csv, mode, |row| {
+        mapping.map_row(row).map_err(|error| RowError {
+            line: 0,
+            column: Some(error.column),
+            raw_value: error.raw_value,
+            reason: error.reason,
+        })
+    })
+}
+
+impl StakingRewards for MappedParser<MappedTransaction> {
+    /// Mappings do not say what a transaction was for, so no row is known to be a reward.
+    fn staking_rewards(&self) -> HashMap<String, Decimal> {
+        HashMap::new()
+    }
+}
+
+impl ActiveAssetValues for MappedParser<MappedTransaction> {
+    fn active_assets(&self) -> HashMap<String, Decimal> {
+        self.data
+            .iter()
+            .flat_map(|transaction| {
+                [
+                    (&transaction.asset, transaction.quantity),
+                    (&transaction.fee_asset, -transaction.fee),
+                ]
+            })
+            .fold(HashMap::new(), |mut map, (asset, change)| {
+                *map.entry(canonical_ticker(asset)).or_insert(Decimal::ZERO) += change;
+
+                map
+            })
+    }
+}
+
+impl RecordsByAsset<MappedTransaction> for MappedParser<MappedTransaction> {
+    fn by_asset(&self) -> HashMap<String, Vec<&MappedTransaction>> {
+        self.data
+            .iter()
+            .fold(HashMap::new(), |mut currency_map, transaction| {
+                currency_map
+                    .entry(canonical_ticker(&transaction.asset))
+                    .or_insert_with(Vec::new)
+                    .push(transaction);
+
+                currency_map
+            })
+    }
+}
+
+impl InputTransactions<MappedTransaction> for MappedParser<MappedTransaction> {
+    fn input_transactions(&self) -> Vec<&MappedTransaction> {
+        self.data
+            .iter()
+            .filter(|transaction| transaction.is_input_transaction())
+            .collect()
+    }
+}
+
+impl CostBasisEvents for MappedParser<MappedTransaction> {
+    /// Rows with a USD value are buys when the asset was received and sells when it was sent. Rows without one are
+    /// taken to be transfers and left out, as are rows of USD itself.
+    /// ```
+    /// # use chrono::{TimeZone, Utc};
+    /// # use models::cost_basis::CostBasisEvent;
+    /// # use rust_decimal::Decimal;
+    /// # use mapped_parser::{CostBasisEvents, MappedParser, MappedTransaction};
+    /// #
+    /// let bought = MappedTransaction {
+    ///     mapping: "wallet".to_string(),
+    ///     time: Utc.with_ymd_and_hms(2021, 3, 1, 15, 20, 30).unwrap(),
+    ///     asset: "BTC".to_string(),
+    ///     quantity: Decimal::new(1, 1),
+    ///     fee: Decimal::new(5, 0),
+    ///     fee_asset: "USD".to_string(),
+    ///     fiat_value: Some(Decimal::new(5000, 0)),
+    /// };
+    /// let withdrawn = MappedTransaction {
+    ///     quantity: Decimal::new(-1, 1),
+    ///     fiat_value: None,
+    ///     ..bought.clone()
+    /// };
+    ///
+    /// let events = MappedParser::new(vec![bought, withdrawn]).cost_basis_events();
+    /// assert_eq!(events.len(), 1);
+    /// match events.first() {
+    ///     Some(CostBasisEvent::Acquisition(acquisition)) => {
+    ///         assert_eq!(acquisition.cost, Decimal::new(5005, 0));
+    ///     }
+    ///     _ => panic!("Received row was not seen as an acquisition"),
+    /// }
+    /// ```
+    fn cost_basis_events(&self) -> Vec<CostBasisEvent> {
+        self.data
+            .iter()
+            .enumerate()
+            .filter(|(_, transaction)| canonical_ticker(&transaction.asset) != "USD")
+            .filter_map(|(index, transaction)| {
+                let value = transaction.fiat_value?;
+                let id = format!("{}-{index}", transaction.mapping);
+                let asset = canonical_ticker(&transaction.asset);
+                let fees = match canonical_ticker(&transaction.fee_asset).as_str() {
+                    "USD" => transaction.fee,
+                    _ => Decimal::ZERO,
+                };
+
+                Some(match transaction.is_input_transaction() {
+                    true => CostBasisEvent::Acquisition(Acquisition {
+                        id,
+                        asset,
+                        quantity: transaction.quantity,
+                        cost: value + fees,
+                        fees,
+                        time: transaction.time,
+                    }),
+                    false => CostBasisEvent::Disposal(Disposal {
+                        id,
+                        asset,
+                        quantity: transaction.quantity.abs(),
+                        proceeds: value,
+                        fees,
+                        time: transaction.time,
+                    }),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod mapped_parser_should {
+    use rust_decimal::Decimal;
+
+    use crate::{
+        parse_mapped_csv, ActiveAssetValues, ColumnMapping, CostBasisEvents, InputTransactions,
+        MappedParser, RecordsByAsset,
+    };
+    use csv_parser::ParseMode;
+
+    const MAPPING: &str = r#"
+        name = "wallet"
+        timestamp = "Date"
+        date_format = "%Y-%m-%d %H:%M"
+        asset = "Coin"
+        quantity = "Amount"
+        fee = "Fee"
+        fee_asset = "Fee Coin"
+        fiat_value = "USD Value"
+
+        [side]
+        column = "Type"
+        inflow = ["Buy", "Deposit"]
+        outflow = ["Sell", "Withdraw"]
+    "#;
+
+    const CSV: &str = "Date,Type,Coin,Amount,Fee,Fee Coin,USD Value\n\
+        2021-03-01 10:00,Buy,ETH,1.5,$4.50,USD,\"$2,250.00\"\n\
+        2021-03-02 10:00,Withdraw,ETH,0.5,0.001,ETH,\n\
+        2021-03-03 10:00,Stake,ETH,1,,,\n\
+        2021-03-04 10:00,Sell,ETH,0.25,$1.00,USD,$400.00\n";
+
+    fn mapping() -> ColumnMapping {
+        ColumnMapping::from_definition(MAPPING).unwrap()
+    }
+
+    #[test]
+    fn report_rows_the_mapping_can_not_read() {
+        let parsed = parse_mapped_csv(CSV, &mapping(), ParseMode::Lenient).unwrap();
+
+        assert_eq!(parsed.records.len(), 3);
+        assert_eq!(parsed.errors.len(), 1);
+        assert_eq!(parsed.errors[0].line, 4);
+        assert_eq!(parsed.errors[0].column.as_deref(), Some("Type"));
+        assert!(parse_mapped_csv(CSV, &mapping(), ParseMode::Strict).is_err());
+    }
+
+    #[test]
+    fn refuse_a_csv_without_the_mapped_columns() {
+        let error =
+            parse_mapped_csv("Date,Coin,Amount\n", &mapping(), ParseMode::Lenient).unwrap_err();
+
+        assert_eq!(error.line, 1);
+        assert!(error.reason.contains("wallet"));
+    }
+
+    #[test]
+    fn add_up_holdings_with_fees_taken_out() {
+        let parsed = parse_mapped_csv(CSV, &mapping(), ParseMode::Lenient).unwrap();
+        let mapped_parser = MappedParser::new(parsed.records);
+        let active_assets = mapped_parser.active_assets();
+
+        assert_eq!(active_assets["ETH"], Decimal::new(749, 3));
+        assert_eq!(active_assets["USD"], Decimal::new(-55, 1));
+        assert_eq!(mapped_parser.by_asset()["ETH"].len(), 3);
+        assert_eq!(mapped_parser.input_transactions().len(), 1);
+    }
+
+    #[test]
+    fn create_events_for_rows_with_a_usd_value() {
+        let parsed = parse_mapped_csv(CSV, &mapping(), ParseMode::Lenient).unwrap();
+        let events = MappedParser::new(parsed.records).cost_basis_events();
+
+        assert_eq!(events.len(), 2);
+    }
+}
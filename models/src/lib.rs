@@ -1,5 +1,7 @@
+use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
-use std::collections::HashMap;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 // TODO: This is not the right place for this trait.
 pub trait StakingRewards {
@@ -22,91 +24,1690 @@ pub trait RecordsByAsset<T> {
     fn by_asset(&self) -> HashMap<String, Vec<&T>>;
 }
 
+pub trait CostBasisEvents {
+    fn cost_basis_events(&self) -> Vec<cost_basis::CostBasisEvent>;
+}
+
+pub trait HistoricalPrice {
+    /// The USD price of a single unit of the asset at the given time, if it is known.
+    fn usd_price_at(&self, asset: &str, time: &DateTime<Utc>) -> Option<Decimal>;
+}
+
+pub trait StakingIncome {
+    fn staking_income(&self, prices: &dyn HistoricalPrice) -> Vec<income::IncomeRecord>;
+}
+
+pub trait UnknownTransactionTypes {
+    /// Transaction types that are not understood and so are left out of balances and tax events.
+    fn unknown_transaction_types(&self) -> Vec<UnknownTransactionType>;
+}
+
+/// A transaction type that was not understood, with how often it appeared and the assets it moved.
+#[derive(Debug, Serialize, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UnknownTransactionType {
+    pub transaction_type: String,
+    pub occurrences: usize,
+    pub assets: Vec<String>,
+}
+
+impl UnknownTransactionType {
+    /// Tallies pairs of transaction type and asset into one entry per type, ordered by type.
+    ///
+    /// ```
+    /// # use models::UnknownTransactionType;
+    /// let unknown = UnknownTransactionType::tally(vec![
+    ///     ("Pro Withdrawal", "BTC"),
+    ///     ("Pro Withdrawal", "ETH"),
+    ///     ("Pro Withdrawal", "BTC"),
+    /// ]);
+    ///
+    /// assert_eq!(
+    ///     unknown,
+    ///     vec![UnknownTransactionType {
+    ///         transaction_type: "Pro Withdrawal".to_string(),
+    ///         occurrences: 3,
+    ///         assets: vec!["BTC".to_string(), "ETH".to_string()],
+    ///     }]
+    /// );
+    /// ```
+    pub fn tally<'a>(types: impl IntoIterator<Item = (&'a str, &'a str)>) -> Vec<Self> {
+        types
+            .into_iter()
+            .fold(
+                BTreeMap::new(),
+                |mut tally: BTreeMap<&str, (usize, BTreeSet<&str>)>, (transaction_type, asset)| {
+                    let (occurrences, assets) = tally.entry(transaction_type).or_default();
+                    *occurrences += 1;
+                    assets.insert(asset);
+                    tally
+                },
+            )
+            .into_iter()
+            .map(|(transaction_type, (occurrences, assets))| Self {
+                transaction_type: transaction_type.to_string(),
+                occurrences,
+                assets: assets.into_iter().map(str::to_string).collect(),
+            })
+            .collect()
+    }
+}
+
+pub mod amount {
+    use std::str::FromStr;
+
+    use rust_decimal::Decimal;
+
+    /// Symbols exports may put in front of an amount.
+    const CURRENCY_SYMBOLS: &[char] = &['$', '€', '£', '¥'];
+
+    /// Reads an amount the way exports write it, e.g. "0.0016458", "-$1,617.57", "($1,617.57)", "0.5 BTC" or
+    /// "1.2e-7". A leading minus or parentheses make the amount negative, currency symbols, thousands separators and
+    /// a trailing unit are left out.
+    ///
+    /// ```
+    /// # use models::amount::unformat_amount;
+    /// # use rust_decimal::Decimal;
+    /// assert_eq!(unformat_amount("-$1,617.57"), Ok(Decimal::new(-161757, 2)));
+    /// assert_eq!(unformat_amount("($1,617.57)"), Ok(Decimal::new(-161757, 2)));
+    /// assert_eq!(unformat_amount("€0.50"), Ok(Decimal::new(5, 1)));
+    /// assert_eq!(unformat_amount("0.5 BTC"), Ok(Decimal::new(5, 1)));
+    /// assert!(unformat_amount("five").is_err());
+    /// ```
+    pub fn unformat_amount(amount: &str) -> Result<Decimal, String> {
+        let trimmed = amount.trim();
+        let (negative, unsigned) = match trimmed
+            .strip_prefix('(')
+            .and_then(|inner| inner.strip_suffix(')'))
+        {
+            Some(inner) => (true, inner),
+            None => match trimmed.strip_prefix('-') {
+                Some(inner) => (true, inner),
+                None => (false, trimmed),
+            },
+        };
+        let number: String = unsigned
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .filter(|character| *character != ',' && !CURRENCY_SYMBOLS.contains(character))
+            .collect();
+
+        Decimal::from_str(&number)
+            .or_else(|_| Decimal::from_scientific(&number))
+            .map(|value| if negative { -value } else { value })
+            .map_err(|_| format!("{amount:?} is not an amount"))
+    }
+}
+
+pub mod asset {
+    use std::fmt;
+
+    use serde::{Deserialize, Serialize};
+
+    /// Exchange specific codes that are not the ticker the asset is known by, paired with that ticker.
+    pub const LEGACY_CODES: &[(&str, &str)] = &[
+        ("XXBT", "BTC"),
+        ("XBT", "BTC"),
+        ("XETH", "ETH"),
+        ("XETC", "ETC"),
+        ("XLTC", "LTC"),
+        ("XXRP", "XRP"),
+        ("XXLM", "XLM"),
+        ("XXMR", "XMR"),
+        ("XZEC", "ZEC"),
+        ("XREP", "REP"),
+        ("XMLN", "MLN"),
+        ("XXDG", "DOGE"),
+        ("XDG", "DOGE"),
+        ("ZUSD", "USD"),
+        ("ZEUR", "EUR"),
+        ("ZGBP", "GBP"),
+        ("ZCAD", "CAD"),
+        ("ZAUD", "AUD"),
+        ("ZJPY", "JPY"),
+    ];
+
+    /// Where an asset is held when the exchange keeps it apart from the spot balance.
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Hash, Default)]
+    #[serde(rename_all = "camelCase")]
+    pub enum AssetVariant {
+        #[default]
+        Spot,
+        /// `.S` assets and ETH2, locked in staking.
+        Staked,
+        /// `.M` assets, opted in to rewards.
+        OptInRewards,
+        /// `.F` assets, earning through flexible earn.
+        FlexibleEarn,
+        /// `.B` assets, bonded in the newer staking program.
+        Bonded,
+    }
+
+    impl AssetVariant {
+        pub fn suffix(&self) -> Option<&'static str> {
+            match self {
+                AssetVariant::Spot => None,
+                AssetVariant::Staked => Some("S"),
+                AssetVariant::OptInRewards => Some("M"),
+                AssetVariant::FlexibleEarn => Some("F"),
+                AssetVariant::Bonded => Some("B"),
+            }
+        }
+
+        fn from_suffix(suffix: &str) -> Option<Self> {
+            match suffix {
+                "S" => Some(AssetVariant::Staked),
+                "M" => Some(AssetVariant::OptInRewards),
+                "F" => Some(AssetVariant::FlexibleEarn),
+                "B" => Some(AssetVariant::Bonded),
+                _ => None,
+            }
+        }
+    }
+
+    /// An asset code from an export split into the ticker the asset is known by and where it is held.
+    /// ```
+    /// use models::asset::{Asset, AssetVariant};
+    ///
+    /// assert_eq!(Asset::new("XXBT"), Asset { ticker: "BTC".to_string(), variant: AssetVariant::Spot });
+    /// assert_eq!(Asset::new("ETH2.S"), Asset { ticker: "ETH".to_string(), variant: AssetVariant::Staked });
+    /// assert_eq!(Asset::new("USDC.M").variant, AssetVariant::OptInRewards);
+    /// assert_eq!(Asset::new("dot").ticker, "DOT");
+    /// ```
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Hash)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Asset {
+        pub ticker: String,
+        pub variant: AssetVariant,
+    }
+
+    impl Asset {
+        pub fn new(code: &str) -> Self {
+            let code = code.trim().to_ascii_uppercase();
+            let (code, variant) = code
+                .rsplit_once('.')
+                .and_then(|(base, suffix)| {
+                    AssetVariant::from_suffix(suffix).map(|variant| (base.to_string(), variant))
+                })
+                .unwrap_or((code, AssetVariant::Spot));
+
+            match code.as_str() {
+                "ETH2" => Self {
+                    ticker: "ETH".to_string(),
+                    variant: AssetVariant::Staked,
+                },
+                code => Self {
+                    ticker: LEGACY_CODES
+                        .iter()
+                        .find(|(legacy_code, _)| legacy_code.eq(&code))
+                        .map_or(code, |(_, ticker)| ticker)
+                        .to_string(),
+                    variant,
+                },
+            }
+        }
+    }
+
+    impl From<&str> for Asset {
+        fn from(code: &str) -> Self {
+            Self::new(code)
+        }
+    }
+
+    impl fmt::Display for Asset {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self.variant.suffix() {
+                Some(suffix) => write!(f, "{}.{suffix}", self.ticker),
+                None => write!(f, "{}", self.ticker),
+            }
+        }
+    }
+
+    /// The ticker an asset code is known by, ignoring where it is held.
+    pub fn canonical_ticker(code: &str) -> String {
+        Asset::new(code).ticker
+    }
+
+    #[cfg(test)]
+    mod asset_should {
+        use super::{canonical_ticker, Asset, AssetVariant};
+
+        #[test]
+        fn map_legacy_kraken_codes() {
+            let tickers: Vec<String> = ["XXBT", "XBT", "XETH", "ZUSD", "ZEUR", "XXDG"]
+                .iter()
+                .map(|code| canonical_ticker(code))
+                .collect();
+
+            assert_eq!(tickers, ["BTC", "BTC", "ETH", "USD", "EUR", "DOGE"]);
+        }
+
+        #[test]
+        fn keep_the_variant_of_suffixed_codes() {
+            let variants: Vec<(String, AssetVariant)> =
+                ["DOT.S", "USDC.M", "SOL.F", "ADA.B", "XBT.M", "ETH2"]
+                    .iter()
+                    .map(|code| Asset::new(code))
+                    .map(|asset| (asset.ticker, asset.variant))
+                    .collect();
+
+            assert_eq!(
+                variants,
+                [
+                    ("DOT".to_string(), AssetVariant::Staked),
+                    ("USDC".to_string(), AssetVariant::OptInRewards),
+                    ("SOL".to_string(), AssetVariant::FlexibleEarn),
+                    ("ADA".to_string(), AssetVariant::Bonded),
+                    ("BTC".to_string(), AssetVariant::OptInRewards),
+                    ("ETH".to_string(), AssetVariant::Staked),
+                ]
+            );
+        }
+
+        #[test]
+        fn leave_unknown_codes_and_suffixes_alone() {
+            assert_eq!(
+                Asset::new("ABC.X"),
+                Asset {
+                    ticker: "ABC.X".to_string(),
+                    variant: AssetVariant::Spot
+                }
+            );
+            assert_eq!(Asset::new("DOT.S").to_string(), "DOT.S");
+        }
+    }
+}
+
+pub mod binance {
+    use std::{fmt, str::FromStr};
+
+    pub use chrono::{DateTime, Utc};
+    use chrono::{NaiveDateTime, TimeZone};
+    use rust_decimal::Decimal;
+    use serde::{de, Deserialize, Deserializer, Serialize};
+
+    use crate::InputTransaction;
+
+    pub const CSV_HEADERS: &[&str] = &[
+        "User_ID",
+        "UTC_Time",
+        "Account",
+        "Operation",
+        "Coin",
+        "Change",
+        "Remark",
+    ];
+
+    pub const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+    /// Older exports write the year with two digits.
+    pub const SHORT_DATE_FORMAT: &str = "%y-%m-%d %H:%M:%S";
+
+    /// Every operation that is understood, anything else is [`BinanceOperation::Unknown`].
+    pub const OPERATIONS: &[BinanceOperation] = &[
+        BinanceOperation::Deposit,
+        BinanceOperation::Withdraw,
+        BinanceOperation::Buy,
+        BinanceOperation::Sell,
+        BinanceOperation::Fee,
+        BinanceOperation::TransactionRelated,
+        BinanceOperation::TransactionBuy,
+        BinanceOperation::TransactionSpend,
+        BinanceOperation::TransactionFee,
+        BinanceOperation::TransactionSold,
+        BinanceOperation::TransactionRevenue,
+        BinanceOperation::SmallAssetsExchangeBnb,
+        BinanceOperation::BinanceConvert,
+        BinanceOperation::StakingRewards,
+        BinanceOperation::Eth2StakingRewards,
+        BinanceOperation::SimpleEarnFlexibleInterest,
+        BinanceOperation::SimpleEarnLockedRewards,
+        BinanceOperation::SavingsInterest,
+        BinanceOperation::PosSavingsInterest,
+        BinanceOperation::LaunchpoolInterest,
+        BinanceOperation::Distribution,
+        BinanceOperation::ReferralCommission,
+        BinanceOperation::SimpleEarnFlexibleSubscription,
+        BinanceOperation::SimpleEarnFlexibleRedemption,
+        BinanceOperation::SimpleEarnLockedSubscription,
+        BinanceOperation::SimpleEarnLockedRedemption,
+        BinanceOperation::StakingPurchase,
+        BinanceOperation::StakingRedemption,
+        BinanceOperation::Eth2Staking,
+        BinanceOperation::WalletTransfer,
+    ];
+
+    /// The Operation column of a Binance transaction history export. Operations Binance adds later are kept as
+    /// [`BinanceOperation::Unknown`] rather than failing the row.
+    ///
+    /// ```
+    /// # use models::binance::BinanceOperation;
+    /// assert_eq!(
+    ///     BinanceOperation::from("ETH 2.0 Staking Rewards"),
+    ///     BinanceOperation::Eth2StakingRewards
+    /// );
+    /// assert_eq!(
+    ///     BinanceOperation::from("NFT Mystery Box"),
+    ///     BinanceOperation::Unknown("NFT Mystery Box".to_string())
+    /// );
+    /// ```
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Hash)]
+    #[serde(from = "String", into = "String")]
+    pub enum BinanceOperation {
+        Deposit,
+        Withdraw,
+        Buy,
+        Sell,
+        Fee,
+        /// Either side of a spot trade in older exports.
+        TransactionRelated,
+        TransactionBuy,
+        TransactionSpend,
+        TransactionFee,
+        TransactionSold,
+        TransactionRevenue,
+        /// Dust converted to BNB.
+        SmallAssetsExchangeBnb,
+        BinanceConvert,
+        StakingRewards,
+        Eth2StakingRewards,
+        SimpleEarnFlexibleInterest,
+        SimpleEarnLockedRewards,
+        SavingsInterest,
+        PosSavingsInterest,
+        LaunchpoolInterest,
+        /// Airdrops and other distributions.
+        Distribution,
+        ReferralCommission,
+        SimpleEarnFlexibleSubscription,
+        SimpleEarnFlexibleRedemption,
+        SimpleEarnLockedSubscription,
+        SimpleEarnLockedRedemption,
+        StakingPurchase,
+        StakingRedemption,
+        /// ETH moved in to ETH 2.0 staking for BETH.
+        Eth2Staking,
+        /// Moves between the spot and funding wallets.
+        WalletTransfer,
+        Unknown(String),
+    }
+
+    impl BinanceOperation {
+        /// The name Binance uses for the operation in its exports.
+        pub fn as_str(&self) -> &str {
+            match self {
+                Self::Deposit => "Deposit",
+                Self::Withdraw => "Withdraw",
+                Self::Buy => "Buy",
+                Self::Sell => "Sell",
+                Self::Fee => "Fee",
+                Self::TransactionRelated => "Transaction Related",
+                Self::TransactionBuy => "Transaction Buy",
+                Self::TransactionSpend => "Transaction Spend",
+                Self::TransactionFee => "Transaction Fee",
+                Self::TransactionSold => "Transaction Sold",
+                Self::TransactionRevenue => "Transaction Revenue",
+                Self::SmallAssetsExchangeBnb => "Small Assets Exchange BNB",
+                Self::BinanceConvert => "Binance Convert",
+                Self::StakingRewards => "Staking Rewards",
+                Self::Eth2StakingRewards => "ETH 2.0 Staking Rewards",
+                Self::SimpleEarnFlexibleInterest => "Simple Earn Flexible Interest",
+                Self::SimpleEarnLockedRewards => "Simple Earn Locked Rewards",
+                Self::SavingsInterest => "Savings Interest",
+                Self::PosSavingsInterest => "POS savings interest",
+                Self::LaunchpoolInterest => "Launchpool Interest",
+                Self::Distribution => "Distribution",
+                Self::ReferralCommission => "Referral Commission",
+                Self::SimpleEarnFlexibleSubscription => "Simple Earn Flexible Subscription",
+                Self::SimpleEarnFlexibleRedemption => "Simple Earn Flexible Redemption",
+                Self::SimpleEarnLockedSubscription => "Simple Earn Locked Subscription",
+                Self::SimpleEarnLockedRedemption => "Simple Earn Locked Redemption",
+                Self::StakingPurchase => "Staking Purchase",
+                Self::StakingRedemption => "Staking Redemption",
+                Self::Eth2Staking => "ETH 2.0 Staking",
+                Self::WalletTransfer => "Transfer Between Main and Funding Wallet",
+                Self::Unknown(operation) => operation,
+            }
+        }
+
+        pub fn is_unknown(&self) -> bool {
+            matches!(self, Self::Unknown(_))
+        }
+    }
+
+    impl From<&str> for BinanceOperation {
+        fn from(operation: &str) -> Self {
+            OPERATIONS
+                .iter()
+                .find(|known| known.as_str().eq_ignore_ascii_case(operation.trim()))
+                .cloned()
+                .unwrap_or_else(|| Self::Unknown(operation.to_string()))
+        }
+    }
+
+    impl From<String> for BinanceOperation {
+        fn from(operation: String) -> Self {
+            Self::from(operation.as_str())
+        }
+    }
+
+    impl From<BinanceOperation> for String {
+        fn from(operation: BinanceOperation) -> Self {
+            operation.to_string()
+        }
+    }
+
+    impl fmt::Display for BinanceOperation {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.as_str())
+        }
+    }
+
+    /// A row of the Binance transaction history export. Every row is a single change to the balance of one coin in
+    /// one account, so a trade is spread over several rows that share a time.
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+    #[serde(rename_all(serialize = "camelCase"))]
+    pub struct BinanceTransactionRecord {
+        #[serde(rename(deserialize = "User_ID"))]
+        pub user_id: String,
+        #[serde(rename(deserialize = "UTC_Time"), deserialize_with = "parse_date_time")]
+        pub time: DateTime<Utc>,
+        /// The wallet the change was made to, such as `Spot`, `Funding` or `Earn`.
+        #[serde(rename(deserialize = "Account"))]
+        pub account: String,
+        #[serde(rename(deserialize = "Operation"))]
+        pub operation: BinanceOperation,
+        #[serde(rename(deserialize = "Coin"))]
+        pub coin: String,
+        /// Signed change to the balance of the coin, negative when the coin was spent.
+        #[serde(rename(deserialize = "Change"), deserialize_with = "parse_change")]
+        pub change: Decimal,
+        #[serde(rename(deserialize = "Remark"), default)]
+        pub remark: String,
+    }
+
+    impl InputTransaction for BinanceTransactionRecord {
+        fn is_input_transaction(&self) -> bool {
+            !self.change.is_sign_negative()
+        }
+    }
+
+    fn parse_date_time<'de, D: Deserializer<'de>>(d: D) -> Result<DateTime<Utc>, D::Error> {
+        // 2021-09-29 15:18:30 or 21-09-29 15:18:30
+        let time = String::deserialize(d)?;
+        let format = match time.trim().find('-') {
+            Some(2) => SHORT_DATE_FORMAT,
+            _ => DATE_FORMAT,
+        };
+
+        NaiveDateTime::parse_from_str(time.trim(), format)
+            .map(|time| Utc.from_utc_datetime(&time))
+            .map_err(de::Error::custom)
+    }
+
+    fn parse_change<'de, D: Deserializer<'de>>(d: D) -> Result<Decimal, D::Error> {
+        // Small changes are written in scientific notation, e.g. 1.2E-7
+        let change = String::deserialize(d)?;
+
+        Decimal::from_str(change.trim())
+            .or_else(|_| Decimal::from_scientific(change.trim()))
+            .map_err(de::Error::custom)
+    }
+
+    #[cfg(test)]
+    mod binance_operation_should {
+        use super::{BinanceOperation, OPERATIONS};
+
+        #[test]
+        fn read_every_known_operation_back_from_its_name() {
+            for operation in OPERATIONS {
+                assert_eq!(&BinanceOperation::from(operation.as_str()), operation);
+            }
+        }
+
+        #[test]
+        fn ignore_the_case_of_operations() {
+            assert_eq!(
+                BinanceOperation::from("POS Savings Interest"),
+                BinanceOperation::PosSavingsInterest
+            );
+        }
+
+        #[test]
+        fn keep_the_name_of_unknown_operations() {
+            let operation = BinanceOperation::from("Crypto Box");
+
+            assert!(operation.is_unknown());
+            assert_eq!(String::from(operation), "Crypto Box");
+        }
+    }
+}
+
 pub mod coinbase {
+    use std::fmt;
+
+    pub use chrono::{DateTime, Utc};
+    use chrono::{NaiveDateTime, TimeZone};
+    use rust_decimal::Decimal;
+    use serde::{de, Deserialize, Deserializer, Serialize};
+
+    use crate::{amount::unformat_amount, InputTransaction};
+
+    /// Every transaction type that is understood, anything else is [`CoinbaseTransactionType::Unknown`].
+    pub const INCLUDE_TRANSACTIONS: &[CoinbaseTransactionType] = &[
+        CoinbaseTransactionType::Buy,
+        CoinbaseTransactionType::Send,
+        CoinbaseTransactionType::Receive,
+        CoinbaseTransactionType::Convert,
+        CoinbaseTransactionType::RewardsIncome,
+        CoinbaseTransactionType::StakingIncome,
+        CoinbaseTransactionType::InflationReward,
+        CoinbaseTransactionType::CardSpend,
+        CoinbaseTransactionType::CardBuyBack,
+        CoinbaseTransactionType::LearningReward,
+        CoinbaseTransactionType::Sell,
+        CoinbaseTransactionType::AdvancedTradeBuy,
+        CoinbaseTransactionType::AdvancedTradeSell,
+        CoinbaseTransactionType::Deposit,
+        CoinbaseTransactionType::Withdrawal,
+    ];
+
+    pub const INPUT_TRANSACTIONS: &[CoinbaseTransactionType] = &[
+        CoinbaseTransactionType::Buy,
+        CoinbaseTransactionType::Receive,
+        CoinbaseTransactionType::RewardsIncome,
+        CoinbaseTransactionType::StakingIncome,
+        CoinbaseTransactionType::InflationReward,
+        CoinbaseTransactionType::CardBuyBack,
+        CoinbaseTransactionType::LearningReward,
+        CoinbaseTransactionType::AdvancedTradeBuy,
+        CoinbaseTransactionType::Deposit,
+    ];
+
+    pub const OUTPUT_TRANSACTIONS: &[CoinbaseTransactionType] = &[
+        CoinbaseTransactionType::Sell,
+        CoinbaseTransactionType::Send,
+        CoinbaseTransactionType::CardSpend,
+        CoinbaseTransactionType::AdvancedTradeSell,
+        CoinbaseTransactionType::Withdrawal,
+    ];
+
+    /// The Transaction Type column of a Coinbase export. Types Coinbase adds later are kept as
+    /// [`CoinbaseTransactionType::Unknown`] rather than failing the row.
+    ///
+    /// ```
+    /// # use models::coinbase::CoinbaseTransactionType;
+    /// assert_eq!(
+    ///     CoinbaseTransactionType::from("Advanced Trade Sell"),
+    ///     CoinbaseTransactionType::AdvancedTradeSell
+    /// );
+    /// assert_eq!(
+    ///     CoinbaseTransactionType::from("Pro Withdrawal"),
+    ///     CoinbaseTransactionType::Unknown("Pro Withdrawal".to_string())
+    /// );
+    /// assert_eq!(CoinbaseTransactionType::StakingIncome.to_string(), "Staking Income");
+    /// ```
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Hash)]
+    #[serde(from = "String", into = "String")]
+    pub enum CoinbaseTransactionType {
+        Buy,
+        Sell,
+        Send,
+        Receive,
+        Convert,
+        RewardsIncome,
+        StakingIncome,
+        InflationReward,
+        CardSpend,
+        CardBuyBack,
+        LearningReward,
+        AdvancedTradeBuy,
+        AdvancedTradeSell,
+        Deposit,
+        Withdrawal,
+        Unknown(String),
+    }
+
+    impl CoinbaseTransactionType {
+        /// The name Coinbase uses for the type in its exports.
+        pub fn as_str(&self) -> &str {
+            match self {
+                Self::Buy => "Buy",
+                Self::Sell => "Sell",
+                Self::Send => "Send",
+                Self::Receive => "Receive",
+                Self::Convert => "Convert",
+                Self::RewardsIncome => "Rewards Income",
+                Self::StakingIncome => "Staking Income",
+                Self::InflationReward => "Inflation Reward",
+                Self::CardSpend => "CardSpend",
+                Self::CardBuyBack => "CardBuyBack",
+                Self::LearningReward => "Learning Reward",
+                Self::AdvancedTradeBuy => "Advanced Trade Buy",
+                Self::AdvancedTradeSell => "Advanced Trade Sell",
+                Self::Deposit => "Deposit",
+                Self::Withdrawal => "Withdrawal",
+                Self::Unknown(transaction_type) => transaction_type,
+            }
+        }
+
+        pub fn is_unknown(&self) -> bool {
+            matches!(self, Self::Unknown(_))
+        }
+    }
+
+    impl From<&str> for CoinbaseTransactionType {
+        fn from(transaction_type: &str) -> Self {
+            INCLUDE_TRANSACTIONS
+                .iter()
+                .find(|known| known.as_str().eq(transaction_type.trim()))
+                .cloned()
+                .unwrap_or_else(|| Self::Unknown(transaction_type.to_string()))
+        }
+    }
+
+    impl From<String> for CoinbaseTransactionType {
+        fn from(transaction_type: String) -> Self {
+            Self::from(transaction_type.as_str())
+        }
+    }
+
+    impl From<CoinbaseTransactionType> for String {
+        fn from(transaction_type: CoinbaseTransactionType) -> Self {
+            transaction_type.to_string()
+        }
+    }
+
+    impl fmt::Display for CoinbaseTransactionType {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.as_str())
+        }
+    }
+
+    pub const CSV_HEADERS: &[&str] = &[
+        "Timestamp",
+        "Transaction Type",
+        "Asset",
+        "Quantity Transacted",
+        "Spot Price Currency",
+        "Spot Price at Transaction",
+        "Subtotal",
+        "Total (inclusive of fees and/or spread)",
+        "Fees and/or Spread",
+        "Notes",
+    ];
+
+    /// Headers of the transaction report Coinbase exports since 2023. The file starts with a few lines about the
+    /// account before this header row, and amounts are formatted in the account currency, e.g. "$1,617.57".
+    pub const TRANSACTION_REPORT_HEADERS: &[&str] = &[
+        "ID",
+        "Timestamp",
+        "Transaction Type",
+        "Asset",
+        "Quantity Transacted",
+        "Price Currency",
+        "Price at Transaction",
+        "Subtotal",
+        "Total (inclusive of fees and/or spread)",
+        "Fees and/or Spread",
+        "Notes",
+    ];
+
+    /// Headers both generations of Coinbase exports share, the transaction report renamed its price columns.
+    pub const SHARED_HEADERS: &[&str] = &[
+        "Timestamp",
+        "Transaction Type",
+        "Asset",
+        "Quantity Transacted",
+    ];
+
+    /// Timestamp format of the transaction report, older exports use RFC 3339.
+    pub const TRANSACTION_REPORT_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S UTC";
+
+    /// A row of either Coinbase export. Amounts are always positive, the transaction type gives the direction.
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+    pub struct CoinbaseTransactionRecord {
+        /// Id Coinbase gives the transaction, only the transaction report has one.
+        #[serde(rename(serialize = "id", deserialize = "ID"), default)]
+        pub id: Option<String>,
+        #[serde(
+            rename(serialize = "timeOfTransaction", deserialize = "Timestamp"),
+            deserialize_with = "parse_timestamp"
+        )]
+        pub time_of_transaction: DateTime<Utc>,
+        #[serde(rename(serialize = "transactionType", deserialize = "Transaction Type"))]
+        pub transaction_type: CoinbaseTransactionType,
+        #[serde(rename(serialize = "asset", deserialize = "Asset"))]
+        pub asset: String,
+        #[serde(
+            rename(serialize = "quantityTransacted", deserialize = "Quantity Transacted"),
+            deserialize_with = "parse_amount"
+        )]
+        pub quantity_transacted: Decimal,
+        #[serde(
+            rename(serialize = "quantityTransacted", deserialize = "Spot Price Currency"),
+            alias = "Price Currency"
+        )]
+        pub spot_price_currency: String,
+        #[serde(
+            rename(
+                serialize = "spotPriceAtTransaction",
+                deserialize = "Spot Price at Transaction"
+            ),
+            alias = "Price at Transaction",
+            deserialize_with = "parse_optional_amount"
+        )]
+        pub spot_price_at_transaction: Option<Decimal>,
+        #[serde(
+            rename(serialize = "subtotal", deserialize = "Subtotal"),
+            deserialize_with = "parse_optional_amount"
+        )]
+        pub subtotal: Option<Decimal>,
+        #[serde(
+            rename(
+                serialize = "total",
+                deserialize = "Total (inclusive of fees and/or spread)"
+            ),
+            deserialize_with = "parse_optional_amount"
+        )]
+        pub total: Option<Decimal>,
+        #[serde(
+            rename(serialize = "fees", deserialize = "Fees and/or Spread"),
+            deserialize_with = "parse_optional_amount"
+        )]
+        pub fees: Option<Decimal>,
+        #[serde(rename(serialize = "notes", deserialize = "Notes"))]
+        pub notes: String,
+    }
+
+    fn parse_timestamp<'de, D: Deserializer<'de>>(d: D) -> Result<DateTime<Utc>, D::Error> {
+        let timestamp = String::deserialize(d)?;
+
+        timestamp
+            .trim()
+            .parse::<DateTime<Utc>>()
+            .or_else(|_| {
+                NaiveDateTime::parse_from_str(timestamp.trim(), TRANSACTION_REPORT_DATE_FORMAT)
+                    .map(|time| Utc.from_utc_datetime(&time))
+            })
+            .map_err(de::Error::custom)
+    }
+
+    /// Only the size of an amount is kept, the transaction type says which way it went.
+    fn parse_amount<'de, D: Deserializer<'de>>(d: D) -> Result<Decimal, D::Error> {
+        let amount = String::deserialize(d)?;
+
+        unformat_amount(&amount)
+            .map(|amount| amount.abs())
+            .map_err(de::Error::custom)
+    }
+
+    fn parse_optional_amount<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Decimal>, D::Error> {
+        match Option::<String>::deserialize(d)? {
+            Some(amount) if !amount.trim().is_empty() => unformat_amount(&amount)
+                .map(|amount| Some(amount.abs()))
+                .map_err(de::Error::custom),
+            _ => Ok(None),
+        }
+    }
+
+    impl InputTransaction for CoinbaseTransactionRecord {
+        fn is_input_transaction(&self) -> bool {
+            INPUT_TRANSACTIONS.contains(&self.transaction_type)
+        }
+    }
+
+    /// Both sides of a Convert along with what Coinbase valued it at.
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Conversion {
+        pub from_asset: String,
+        pub from_quantity: Decimal,
+        pub to_asset: String,
+        pub to_quantity: Decimal,
+        pub fiat_currency: Option<String>,
+        /// Value of the sent side, fees included.
+        pub fiat_value: Option<Decimal>,
+        pub fees: Option<Decimal>,
+    }
+
+    #[derive(Debug, PartialEq, Eq, Clone)]
+    pub enum ConversionError {
+        /// The transaction type was not Convert.
+        NotAConversion(String),
+        /// The notes do not start with "Converted".
+        UnrecognizedNotes(String),
+        /// The notes do not say what the asset was converted to.
+        MissingTarget(String),
+        InvalidAmount(String),
+        MissingAsset(String),
+    }
+
+    impl fmt::Display for ConversionError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                ConversionError::NotAConversion(transaction_type) => {
+                    write!(f, "{transaction_type} is not a Convert transaction")
+                }
+                ConversionError::UnrecognizedNotes(notes) => {
+                    write!(f, "Convert notes are not recognized: {notes}")
+                }
+                ConversionError::MissingTarget(notes) => {
+                    write!(f, "Convert notes do not name what was received: {notes}")
+                }
+                ConversionError::InvalidAmount(amount) => {
+                    write!(f, "Convert amount is not a number: {amount}")
+                }
+                ConversionError::MissingAsset(side) => {
+                    write!(f, "Convert amount has no asset: {side}")
+                }
+            }
+        }
+    }
+
+    impl std::error::Error for ConversionError {}
+
+    impl Conversion {
+        /// Reads both sides of a Convert from its notes. The notes only hold the assets and quantities, the fiat
+        /// value is left empty.
+        /// ```
+        /// use models::coinbase::Conversion;
+        /// use rust_decimal::Decimal;
+        ///
+        /// let conversion = Conversion::from_notes("Converted 2 BTC to 1,337.0245 STORJ").unwrap();
+        /// assert_eq!(conversion.from_asset, "BTC");
+        /// assert_eq!(conversion.to_asset, "STORJ");
+        /// assert_eq!(conversion.to_quantity, Decimal::new(13370245, 4));
+        ///
+        /// let conversion = Conversion::from_notes("Converted $1,000.00 USDC to 10 Wrapped Bitcoin").unwrap();
+        /// assert_eq!(conversion.from_quantity, Decimal::new(1000, 0));
+        /// assert_eq!(conversion.to_asset, "Wrapped Bitcoin");
+        ///
+        /// assert!(Conversion::from_notes("Converted 2 BTC").is_err());
+        /// ```
+        pub fn from_notes(notes: &str) -> Result<Self, ConversionError> {
+            let trimmed = notes.trim();
+            let sides = trimmed
+                .get(..10)
+                .filter(|prefix| prefix.eq_ignore_ascii_case("Converted "))
+                .map(|_| &trimmed[10..])
+                .ok_or_else(|| ConversionError::UnrecognizedNotes(notes.to_string()))?;
+
+            // An asset name may itself contain " to ", so every occurrence is tried until both sides read.
+            let mut result = Err(ConversionError::MissingTarget(notes.to_string()));
+            for (index, _) in sides.match_indices(" to ") {
+                result = convert_side(&sides[..index]).and_then(|(from_quantity, from_asset)| {
+                    convert_side(&sides[index + 4..]).map(|(to_quantity, to_asset)| Self {
+                        from_asset,
+                        from_quantity,
+                        to_asset,
+                        to_quantity,
+                        fiat_currency: None,
+                        fiat_value: None,
+                        fees: None,
+                    })
+                });
+
+                if result.is_ok() {
+                    break;
+                }
+            }
+
+            result
+        }
+    }
+
+    impl TryFrom<&CoinbaseTransactionRecord> for Conversion {
+        type Error = ConversionError;
+
+        fn try_from(record: &CoinbaseTransactionRecord) -> Result<Self, Self::Error> {
+            if record.transaction_type != CoinbaseTransactionType::Convert {
+                return Err(ConversionError::NotAConversion(
+                    record.transaction_type.to_string(),
+                ));
+            }
+
+            Ok(Self {
+                fiat_currency: Some(record.spot_price_currency.to_string())
+                    .filter(|currency| !currency.is_empty()),
+                fiat_value: record.subtotal.or_else(|| {
+                    record
+                        .spot_price_at_transaction
+                        .map(|price| price * record.quantity_transacted)
+                }),
+                fees: record.fees,
+                ..Self::from_notes(&record.notes)?
+            })
+        }
+    }
+
+    /// Reads one side of a Convert, e.g. "1,337.0245 STORJ" or "$100.00 USDC".
+    fn convert_side(side: &str) -> Result<(Decimal, String), ConversionError> {
+        let (amount, asset) = side
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| ConversionError::MissingAsset(side.trim().to_string()))?;
+        let asset = asset.trim().trim_end_matches('.').trim();
+        if asset.is_empty() {
+            return Err(ConversionError::MissingAsset(side.trim().to_string()));
+        }
+
+        let quantity = unformat_amount(amount)
+            .map(|quantity| quantity.abs())
+            .map_err(|_| ConversionError::InvalidAmount(amount.to_string()))?;
+
+        Ok((quantity, asset.to_string()))
+    }
+
+    #[cfg(test)]
+    mod coinbase_transaction_type_should {
+        use super::{CoinbaseTransactionType, INCLUDE_TRANSACTIONS};
+
+        #[test]
+        fn read_every_known_type_back_from_its_name() {
+            for transaction_type in INCLUDE_TRANSACTIONS {
+                assert_eq!(
+                    &CoinbaseTransactionType::from(transaction_type.as_str()),
+                    transaction_type
+                );
+            }
+        }
+
+        #[test]
+        fn keep_the_name_of_unknown_types() {
+            let transaction_type = CoinbaseTransactionType::from("Pro Deposit");
+
+            assert!(transaction_type.is_unknown());
+            assert_eq!(transaction_type.to_string(), "Pro Deposit");
+            assert_eq!(String::from(transaction_type), "Pro Deposit");
+        }
+
+        #[test]
+        fn ignore_surrounding_whitespace() {
+            assert_eq!(
+                CoinbaseTransactionType::from(" Staking Income "),
+                CoinbaseTransactionType::StakingIncome
+            );
+        }
+    }
+
+    #[cfg(test)]
+    mod conversion_should {
+        use rust_decimal::Decimal;
+
+        use super::{Conversion, ConversionError};
+
+        #[test]
+        fn read_both_sides_of_the_notes() {
+            let conversion =
+                Conversion::from_notes("Converted 18.02442 BTC to 337.0245 DOT").unwrap();
+
+            assert_eq!(
+                (
+                    conversion.from_quantity,
+                    conversion.from_asset.as_str(),
+                    conversion.to_quantity,
+                    conversion.to_asset.as_str()
+                ),
+                (
+                    Decimal::new(1802442, 5),
+                    "BTC",
+                    Decimal::new(3370245, 4),
+                    "DOT"
+                )
+            );
+        }
+
+        #[test]
+        fn handle_asset_names_containing_to() {
+            let conversion =
+                Conversion::from_notes("Converted 5 Token to Moon to 0.1 ETH").unwrap();
+
+            assert_eq!(conversion.from_asset, "Token to Moon");
+            assert_eq!(conversion.to_asset, "ETH");
+        }
+
+        #[test]
+        fn return_typed_errors() {
+            assert_eq!(
+                Conversion::from_notes("Bought 2 BTC"),
+                Err(ConversionError::UnrecognizedNotes(
+                    "Bought 2 BTC".to_string()
+                ))
+            );
+            assert_eq!(
+                Conversion::from_notes("Converted 2 BTC"),
+                Err(ConversionError::MissingTarget(
+                    "Converted 2 BTC".to_string()
+                ))
+            );
+            assert_eq!(
+                Conversion::from_notes("Converted 2 BTC to lots DOT"),
+                Err(ConversionError::InvalidAmount("lots".to_string()))
+            );
+            assert_eq!(
+                Conversion::from_notes("Converted 2 BTC to 5"),
+                Err(ConversionError::MissingAsset("5".to_string()))
+            );
+        }
+    }
+}
+
+pub mod coinbase_pro {
+    pub use chrono::{DateTime, Utc};
+    use rust_decimal::Decimal;
+    use serde::{de, Deserialize, Deserializer, Serialize};
+
+    use crate::InputTransaction;
+
+    pub const CSV_HEADERS: &[&str] = &[
+        "portfolio",
+        "trade id",
+        "product",
+        "side",
+        "created at",
+        "size",
+        "size unit",
+        "price",
+        "fee",
+        "total",
+        "price/fee/total unit",
+    ];
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+    #[serde(rename_all = "camelCase")]
+    pub enum FillSide {
+        /// The size unit was bought with the price unit.
+        Buy,
+        /// The size unit was sold for the price unit.
+        Sell,
+    }
+
+    /// A row of the Coinbase Pro fills export, one per fill of an order.
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+    #[serde(rename_all(serialize = "camelCase"))]
+    pub struct CoinbaseProFill {
+        pub portfolio: String,
+        #[serde(rename(deserialize = "trade id"))]
+        pub trade_id: String,
+        /// The pair traded, such as `BTC-USD`.
+        pub product: String,
+        #[serde(deserialize_with = "parse_side")]
+        pub side: FillSide,
+        #[serde(rename(deserialize = "created at"))]
+        pub created_at: DateTime<Utc>,
+        #[serde(with = "rust_decimal::serde::str")]
+        pub size: Decimal,
+        #[serde(rename(deserialize = "size unit"))]
+        pub size_unit: String,
+        #[serde(with = "rust_decimal::serde::str")]
+        pub price: Decimal,
+        #[serde(with = "rust_decimal::serde::str")]
+        pub fee: Decimal,
+        /// Signed change to the price unit with the fee taken out, negative for buys.
+        #[serde(with = "rust_decimal::serde::str")]
+        pub total: Decimal,
+        #[serde(rename(deserialize = "price/fee/total unit"))]
+        pub price_unit: String,
+    }
+
+    impl InputTransaction for CoinbaseProFill {
+        fn is_input_transaction(&self) -> bool {
+            self.side == FillSide::Buy
+        }
+    }
+
+    fn parse_side<'de, D: Deserializer<'de>>(d: D) -> Result<FillSide, D::Error> {
+        let side = String::deserialize(d)?;
+
+        match side.trim().to_ascii_uppercase().as_str() {
+            "BUY" => Ok(FillSide::Buy),
+            "SELL" => Ok(FillSide::Sell),
+            _ => Err(de::Error::unknown_variant(&side, &["BUY", "SELL"])),
+        }
+    }
+}
+
+pub mod cost_basis {
+    pub use chrono::{DateTime, Utc};
+    use rust_decimal::Decimal;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+
+    /// Method used to pick which tax lots a disposal is matched against.
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+    #[serde(rename_all = "camelCase")]
+    pub enum CostBasisStrategy {
+        /// First in, first out. The oldest lots are consumed first.
+        #[default]
+        Fifo,
+        /// Last in, first out. The newest lots are consumed first.
+        Lifo,
+        /// Highest in, first out. The lots with the highest unit cost are consumed first.
+        Hifo,
+        /// Lots are chosen explicitly per disposal, keyed by disposal id.
+        /// Any quantity not covered by the selected lots falls back to FIFO.
+        SpecificId(HashMap<String, Vec<String>>),
+    }
+
+    /// A purchase or receipt of an asset that creates a new tax lot.
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Acquisition {
+        pub id: String,
+        pub asset: String,
+        pub quantity: Decimal,
+        /// Total USD cost of the acquisition, inclusive of fees.
+        pub cost: Decimal,
+        pub fees: Decimal,
+        pub time: DateTime<Utc>,
+    }
+
+    /// A sale, spend or conversion of an asset that realizes a gain or loss.
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Disposal {
+        pub id: String,
+        pub asset: String,
+        pub quantity: Decimal,
+        /// Gross USD proceeds of the disposal, before fees.
+        pub proceeds: Decimal,
+        pub fees: Decimal,
+        pub time: DateTime<Utc>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+    #[serde(rename_all = "camelCase")]
+    pub enum CostBasisEvent {
+        Acquisition(Acquisition),
+        Disposal(Disposal),
+    }
+
+    impl CostBasisEvent {
+        pub fn time(&self) -> &DateTime<Utc> {
+            match self {
+                CostBasisEvent::Acquisition(acquisition) => &acquisition.time,
+                CostBasisEvent::Disposal(disposal) => &disposal.time,
+            }
+        }
+    }
+
+    /// The unconsumed remainder of an acquisition.
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+    #[serde(rename_all = "camelCase")]
+    pub struct TaxLot {
+        pub id: String,
+        pub asset: String,
+        pub acquired_at: DateTime<Utc>,
+        pub quantity: Decimal,
+        pub remaining: Decimal,
+        pub cost: Decimal,
+    }
+
+    impl TaxLot {
+        pub fn unit_cost(&self) -> Decimal {
+            if self.quantity.is_zero() {
+                Decimal::ZERO
+            } else {
+                self.cost / self.quantity
+            }
+        }
+    }
+
+    impl From<&Acquisition> for TaxLot {
+        fn from(acquisition: &Acquisition) -> Self {
+            Self {
+                id: acquisition.id.to_string(),
+                asset: acquisition.asset.to_string(),
+                acquired_at: acquisition.time,
+                quantity: acquisition.quantity,
+                remaining: acquisition.quantity,
+                cost: acquisition.cost,
+            }
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Hash)]
+    #[serde(rename_all = "camelCase")]
+    pub enum HoldingPeriod {
+        ShortTerm,
+        LongTerm,
+    }
+
+    /// The gain or loss realized by matching part of a disposal against a single tax lot.
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+    #[serde(rename_all = "camelCase")]
+    pub struct RealizedGain {
+        pub asset: String,
+        pub lot_id: String,
+        pub disposal_id: String,
+        pub quantity: Decimal,
+        pub acquired_at: DateTime<Utc>,
+        pub disposed_at: DateTime<Utc>,
+        pub proceeds: Decimal,
+        pub cost_basis: Decimal,
+        pub fees: Decimal,
+        pub gain: Decimal,
+        pub holding_period: HoldingPeriod,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+    #[serde(rename_all = "camelCase")]
+    pub struct GainTotals {
+        pub proceeds: Decimal,
+        pub cost_basis: Decimal,
+        pub fees: Decimal,
+        pub gain: Decimal,
+    }
+
+    impl GainTotals {
+        pub fn add(&mut self, gain: &RealizedGain) {
+            self.proceeds += gain.proceeds;
+            self.cost_basis += gain.cost_basis;
+            self.fees += gain.fees;
+            self.gain += gain.gain;
+        }
+
+        pub fn combine(&mut self, totals: &GainTotals) {
+            self.proceeds += totals.proceeds;
+            self.cost_basis += totals.cost_basis;
+            self.fees += totals.fees;
+            self.gain += totals.gain;
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+    #[serde(rename_all = "camelCase")]
+    pub struct AssetGainSummary {
+        pub asset: String,
+        pub short_term: GainTotals,
+        pub long_term: GainTotals,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+    #[serde(rename_all = "camelCase")]
+    pub struct TaxYearSummary {
+        pub tax_year: i32,
+        pub short_term: GainTotals,
+        pub long_term: GainTotals,
+        pub assets: Vec<AssetGainSummary>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+    #[serde(rename_all = "camelCase")]
+    pub struct CostBasisReport {
+        pub realized_gains: Vec<RealizedGain>,
+        pub open_lots: Vec<TaxLot>,
+        /// Disposals, or the remainder of disposals, that had no lots left to match against.
+        pub unmatched_disposals: Vec<Disposal>,
+    }
+}
+
+pub mod gemini {
+    use std::{
+        collections::{BTreeMap, HashMap},
+        fmt,
+    };
+
     pub use chrono::{DateTime, Utc};
+    use chrono::{NaiveDateTime, TimeZone};
     use rust_decimal::Decimal;
     use serde::{Deserialize, Serialize};
 
-    use crate::InputTransaction;
+    use crate::{amount::unformat_amount, InputTransaction};
+
+    /// Headers every transaction history export has. Amount, fee and balance columns follow for each asset the
+    /// account has held, e.g. `BTC Amount BTC`, `Trading Fee (BTC) BTC` and `BTC Balance BTC`.
+    pub const CSV_HEADERS: &[&str] = &["Date", "Time (UTC)", "Type", "Symbol", "Specification"];
+
+    pub const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+    /// Every transaction type that is understood, anything else is [`GeminiTransactionType::Unknown`].
+    pub const TRANSACTION_TYPES: &[GeminiTransactionType] = &[
+        GeminiTransactionType::Buy,
+        GeminiTransactionType::Sell,
+        GeminiTransactionType::Credit,
+        GeminiTransactionType::Debit,
+    ];
+
+    /// Types that are read as another, auction fills are buys and sells like any other trade.
+    const TRANSACTION_TYPE_ALIASES: &[(&str, GeminiTransactionType)] = &[
+        ("Auction Buy", GeminiTransactionType::Buy),
+        ("Auction Sell", GeminiTransactionType::Sell),
+    ];
+
+    /// The Type column of a Gemini transaction history export. What a credit or debit was for is in the
+    /// specification.
+    ///
+    /// ```
+    /// # use models::gemini::GeminiTransactionType;
+    /// assert_eq!(GeminiTransactionType::from("Credit"), GeminiTransactionType::Credit);
+    /// assert_eq!(GeminiTransactionType::from("Auction Buy"), GeminiTransactionType::Buy);
+    /// assert!(GeminiTransactionType::from("Custody Transfer").is_unknown());
+    /// ```
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Hash)]
+    #[serde(from = "String", into = "String")]
+    pub enum GeminiTransactionType {
+        Buy,
+        Sell,
+        Credit,
+        Debit,
+        Unknown(String),
+    }
+
+    impl GeminiTransactionType {
+        /// The name Gemini uses for the type in its exports.
+        pub fn as_str(&self) -> &str {
+            match self {
+                Self::Buy => "Buy",
+                Self::Sell => "Sell",
+                Self::Credit => "Credit",
+                Self::Debit => "Debit",
+                Self::Unknown(transaction_type) => transaction_type,
+            }
+        }
+
+        pub fn is_unknown(&self) -> bool {
+            matches!(self, Self::Unknown(_))
+        }
+    }
+
+    impl From<&str> for GeminiTransactionType {
+        fn from(transaction_type: &str) -> Self {
+            let trimmed = transaction_type.trim();
+
+            TRANSACTION_TYPES
+                .iter()
+                .find(|known| known.as_str().eq_ignore_ascii_case(trimmed))
+                .or_else(|| {
+                    TRANSACTION_TYPE_ALIASES
+                        .iter()
+                        .find(|(alias, _)| alias.eq_ignore_ascii_case(trimmed))
+                        .map(|(_, known)| known)
+                })
+                .cloned()
+                .unwrap_or_else(|| Self::Unknown(transaction_type.to_string()))
+        }
+    }
+
+    impl From<String> for GeminiTransactionType {
+        fn from(transaction_type: String) -> Self {
+            Self::from(transaction_type.as_str())
+        }
+    }
+
+    impl From<GeminiTransactionType> for String {
+        fn from(transaction_type: GeminiTransactionType) -> Self {
+            transaction_type.to_string()
+        }
+    }
+
+    impl fmt::Display for GeminiTransactionType {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.as_str())
+        }
+    }
+
+    /// A row of the Gemini transaction history export. The per asset columns are read in to `amounts` and `fees`,
+    /// keyed by asset, leaving out assets the row did not change.
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+    #[serde(
+        rename_all(serialize = "camelCase"),
+        try_from = "HashMap<String, String>"
+    )]
+    pub struct GeminiTransactionRecord {
+        pub time: DateTime<Utc>,
+        pub transaction_type: GeminiTransactionType,
+        /// The Type column as Gemini wrote it, `Auction Buy` is read as a [`GeminiTransactionType::Buy`].
+        pub raw_transaction_type: String,
+        /// The pair traded, such as `BTCUSD`, or the asset credited or debited.
+        pub symbol: String,
+        /// What the row was for, such as `Earn Interest` or `Deposit (BTC)`.
+        pub specification: String,
+        /// Signed change to each asset, fees not included.
+        pub amounts: BTreeMap<String, Decimal>,
+        /// Fee paid in each asset, always positive.
+        pub fees: BTreeMap<String, Decimal>,
+    }
+
+    impl GeminiTransactionRecord {
+        /// The asset the row is about, the base of a pair or the asset credited or debited.
+        pub fn asset(&self) -> &str {
+            self.amounts
+                .keys()
+                .filter(|asset| self.symbol.starts_with(asset.as_str()))
+                .max_by_key(|asset| asset.len())
+                .map_or(self.symbol.as_str(), String::as_str)
+        }
+
+        /// Credits paid out for Earn or staking.
+        pub fn is_staking_reward(&self) -> bool {
+            let specification = self.specification.to_ascii_lowercase();
+
+            self.transaction_type == GeminiTransactionType::Credit
+                && (specification.contains("interest") || specification.contains("reward"))
+        }
+    }
+
+    impl InputTransaction for GeminiTransactionRecord {
+        fn is_input_transaction(&self) -> bool {
+            matches!(
+                self.transaction_type,
+                GeminiTransactionType::Buy | GeminiTransactionType::Credit
+            )
+        }
+    }
+
+    impl TryFrom<HashMap<String, String>> for GeminiTransactionRecord {
+        type Error = String;
+
+        fn try_from(row: HashMap<String, String>) -> Result<Self, Self::Error> {
+            let column = |header: &str| {
+                row.get(header)
+                    .map(|value| value.trim())
+                    .ok_or_else(|| format!("missing field `{header}`"))
+            };
+            let time = format!("{} {}", column("Date")?, column("Time (UTC)")?);
+            let time = NaiveDateTime::parse_from_str(&time, DATE_FORMAT)
+                .map(|time| Utc.from_utc_datetime(&time))
+                .map_err(|error| format!("{time:?} is not a time: {error}"))?;
+
+            let mut amounts = BTreeMap::new();
+            let mut fees = BTreeMap::new();
+            for (header, value) in row.iter().filter(|(_, value)| !value.trim().is_empty()) {
+                if let Some(asset) = amount_column_asset(header) {
+                    amounts.insert(asset.to_string(), unformat_amount(value)?);
+                } else if let Some(asset) = fee_column_asset(header) {
+                    fees.insert(asset.to_string(), unformat_amount(value)?.abs());
+                }
+            }
+
+            Ok(Self {
+                time,
+                transaction_type: GeminiTransactionType::from(column("Type")?),
+                raw_transaction_type: column("Type")?.to_string(),
+                symbol: column("Symbol")?.to_string(),
+                specification: column("Specification")?.to_string(),
+                amounts,
+                fees,
+            })
+        }
+    }
+
+    /// `BTC Amount BTC` is the amount column of BTC.
+    fn amount_column_asset(header: &str) -> Option<&str> {
+        let (asset, unit) = header.split_once(" Amount ")?;
+        (asset == unit.trim()).then_some(asset)
+    }
+
+    /// `Trading Fee (BTC) BTC` is the fee column of BTC.
+    fn fee_column_asset(header: &str) -> Option<&str> {
+        let (asset, unit) = header.strip_prefix("Trading Fee (")?.split_once(") ")?;
+        (asset == unit.trim()).then_some(asset)
+    }
+
+    #[cfg(test)]
+    mod gemini_transaction_record_should {
+        use std::collections::HashMap;
+
+        use rust_decimal::Decimal;
+
+        use super::{GeminiTransactionRecord, GeminiTransactionType};
+
+        fn row(columns: &[(&str, &str)]) -> HashMap<String, String> {
+            columns
+                .iter()
+                .map(|(header, value)| (header.to_string(), value.to_string()))
+                .collect()
+        }
+
+        #[test]
+        fn read_the_columns_of_each_asset() {
+            let record = GeminiTransactionRecord::try_from(row(&[
+                ("Date", "2021-03-01"),
+                ("Time (UTC)", "15:20:30.123"),
+                ("Type", "Buy"),
+                ("Symbol", "BTCUSD"),
+                ("Specification", "Exchange Limit"),
+                ("USD Amount USD", "($1,000.00)"),
+                ("Trading Fee (USD) USD", "($3.50)"),
+                ("USD Balance USD", "$500.00"),
+                ("BTC Amount BTC", "0.02 BTC"),
+                ("Trading Fee (BTC) BTC", ""),
+                ("ETH Amount ETH", ""),
+            ]))
+            .unwrap();
+
+            assert_eq!(record.transaction_type, GeminiTransactionType::Buy);
+            assert_eq!(record.asset(), "BTC");
+            assert_eq!(record.amounts.len(), 2);
+            assert_eq!(record.amounts["USD"], Decimal::new(-1000, 0));
+            assert_eq!(record.amounts["BTC"], Decimal::new(2, 2));
+            assert_eq!(record.fees["USD"], Decimal::new(35, 1));
+            assert!(!record.fees.contains_key("BTC"));
+        }
+
+        #[test]
+        fn keep_the_type_gemini_wrote() {
+            let record = GeminiTransactionRecord::try_from(row(&[
+                ("Date", "2021-03-03"),
+                ("Time (UTC)", "00:00:00.000"),
+                ("Type", "Auction Buy"),
+                ("Symbol", "ETHUSD"),
+                ("Specification", "Auction"),
+                ("USD Amount USD", "($10.00)"),
+                ("ETH Amount ETH", "0.004 ETH"),
+            ]))
+            .unwrap();
+
+            assert_eq!(record.transaction_type, GeminiTransactionType::Buy);
+            assert_eq!(record.raw_transaction_type, "Auction Buy");
+        }
 
-    pub const INCLUDE_TRANSACTIONS: &[&str] = &[
-        "Buy",
-        "Send",
-        "Receive",
-        "Convert",
-        "Rewards Income",
-        "CardSpend",
-        "CardBuyBack",
-        "Learning Reward",
-        "Sell",
-        "Advanced Trade Buy",
-    ];
+        #[test]
+        fn tell_earn_interest_from_deposits() {
+            let credit = |specification: &str| {
+                GeminiTransactionRecord::try_from(row(&[
+                    ("Date", "2021-03-01"),
+                    ("Time (UTC)", "15:20:30"),
+                    ("Type", "Credit"),
+                    ("Symbol", "ETH"),
+                    ("Specification", specification),
+                    ("ETH Amount ETH", "0.001 ETH"),
+                ]))
+                .unwrap()
+            };
 
-    pub const INPUT_TRANSACTIONS: &[&str] = &[
-        "Buy",
-        "Receive",
-        "Rewards Income",
-        "CardBuyBack",
-        "Learning Reward",
-        "Advanced Trade Buy",
-    ];
+            assert!(credit("Earn Interest").is_staking_reward());
+            assert!(!credit("Deposit (ETH)").is_staking_reward());
+            assert_eq!(credit("Deposit (ETH)").asset(), "ETH");
+        }
 
-    pub const OUTPUT_TRANSACTIONS: &[&str] = &["Sell", "Send", "CardSpend"];
+        #[test]
+        fn report_rows_without_a_time() {
+            let error = GeminiTransactionRecord::try_from(row(&[
+                ("Date", "2021-03-01"),
+                ("Type", "Credit"),
+                ("Symbol", "ETH"),
+                ("Specification", "Earn Interest"),
+            ]))
+            .unwrap_err();
 
-    pub const CSV_HEADERS: &[&str] = &[
-        "Timestamp",
-        "Transaction Type",
-        "Asset",
-        "Quantity Transacted",
-        "Spot Price Currency",
-        "Spot Price at Transaction",
-        "Subtotal",
-        "Total (inclusive of fees and/or spread)",
-        "Fees and/or Spread",
-        "Notes",
-    ];
+            assert_eq!(error, "missing field `Time (UTC)`");
+        }
+    }
+}
 
+pub mod income {
+    pub use chrono::{DateTime, Utc};
+    use rust_decimal::Decimal;
+    use serde::{Deserialize, Serialize};
+
+    use crate::cost_basis::Acquisition;
+
+    /// A single staking or learning reward with its fair market value when it was received.
     #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
-    pub struct CoinbaseTransactionRecord {
-        #[serde(rename(serialize = "timeOfTransaction", deserialize = "Timestamp"))]
-        pub time_of_transaction: DateTime<Utc>,
-        #[serde(rename(serialize = "transactionType", deserialize = "Transaction Type"))]
-        pub transaction_type: String,
-        #[serde(rename(serialize = "asset", deserialize = "Asset"))]
+    #[serde(rename_all = "camelCase")]
+    pub struct IncomeRecord {
+        pub id: String,
         pub asset: String,
-        #[serde(rename(serialize = "quantityTransacted", deserialize = "Quantity Transacted"))]
-        pub quantity_transacted: Decimal,
-        #[serde(rename(serialize = "quantityTransacted", deserialize = "Spot Price Currency"))]
-        pub spot_price_currency: String,
-        #[serde(rename(
-            serialize = "spotPriceAtTransaction",
-            deserialize = "Spot Price at Transaction"
-        ))]
-        pub spot_price_at_transaction: Option<Decimal>,
-        #[serde(rename(serialize = "subtotal", deserialize = "Subtotal"))]
-        pub subtotal: Option<Decimal>,
-        #[serde(rename(
-            serialize = "total",
-            deserialize = "Total (inclusive of fees and/or spread)"
-        ))]
-        pub total: Option<Decimal>,
-        #[serde(rename(serialize = "fees", deserialize = "Fees and/or Spread"))]
-        pub fees: Option<Decimal>,
-        #[serde(rename(serialize = "notes", deserialize = "Notes"))]
-        pub notes: String,
+        pub quantity: Decimal,
+        pub received_at: DateTime<Utc>,
+        pub usd_price: Option<Decimal>,
+        pub usd_value: Option<Decimal>,
     }
 
-    impl InputTransaction for CoinbaseTransactionRecord {
-        fn is_input_transaction(&self) -> bool {
-            INPUT_TRANSACTIONS.iter().any(|received_transaction_type| {
-                received_transaction_type.eq(&self.transaction_type)
+    impl TryFrom<&IncomeRecord> for Acquisition {
+        type Error = String;
+
+        /// Rewards become lots at their value on receipt. Rewards that could not be priced have no basis to give a
+        /// lot, so they are refused rather than given a zero basis that would overstate the gain when sold.
+        fn try_from(record: &IncomeRecord) -> Result<Self, Self::Error> {
+            let cost = record.usd_value.ok_or_else(|| {
+                format!(
+                    "Reward of {} {} received on {} has no USD value",
+                    record.quantity.normalize(),
+                    record.asset,
+                    record.received_at.format("%Y-%m-%d")
+                )
+            })?;
+
+            Ok(Self {
+                id: record.id.to_string(),
+                asset: record.asset.to_string(),
+                quantity: record.quantity,
+                cost,
+                fees: Decimal::ZERO,
+                time: record.received_at,
             })
         }
     }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+    #[serde(rename_all = "camelCase")]
+    pub struct IncomePeriodTotal {
+        /// The month as `YYYY-MM` or the year as `YYYY`.
+        pub period: String,
+        pub usd_value: Decimal,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+    #[serde(rename_all = "camelCase")]
+    pub struct IncomeReport {
+        pub records: Vec<IncomeRecord>,
+        pub monthly_totals: Vec<IncomePeriodTotal>,
+        pub yearly_totals: Vec<IncomePeriodTotal>,
+        /// Rewards that have no USD value and are left out of the totals.
+        pub unpriced: Vec<IncomeRecord>,
+    }
 }
 
 pub mod kraken {
     pub const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+    use std::{collections::HashMap, fmt, str::FromStr};
+
     use chrono::TimeZone;
     pub use chrono::{DateTime, Utc};
     use rust_decimal::{prelude::Zero, Decimal};
@@ -118,6 +1719,258 @@ pub mod kraken {
         "txid", "refid", "time", "type", "subtype", "aclass", "asset", "amount", "fee", "balance",
     ];
 
+    /// Headers of the trades export, which is separate from the ledger export.
+    pub const TRADE_CSV_HEADERS: &[&str] = &[
+        "txid",
+        "ordertxid",
+        "pair",
+        "time",
+        "type",
+        "ordertype",
+        "price",
+        "cost",
+        "fee",
+        "vol",
+        "margin",
+        "misc",
+        "ledgers",
+    ];
+
+    /// Trade times have fractional seconds, which are optional when parsing.
+    pub const TRADE_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+    /// Every ledger type that is understood, anything else is [`KrakenLedgerType::Unknown`].
+    pub const LEDGER_TYPES: &[KrakenLedgerType] = &[
+        KrakenLedgerType::Trade,
+        KrakenLedgerType::Spend,
+        KrakenLedgerType::Receive,
+        KrakenLedgerType::Deposit,
+        KrakenLedgerType::Withdrawal,
+        KrakenLedgerType::Transfer,
+        KrakenLedgerType::Staking,
+        KrakenLedgerType::Earn,
+        KrakenLedgerType::Margin,
+        KrakenLedgerType::Rollover,
+        KrakenLedgerType::Settled,
+        KrakenLedgerType::Adjustment,
+        KrakenLedgerType::Dividend,
+        KrakenLedgerType::Sale,
+        KrakenLedgerType::Credit,
+    ];
+
+    /// Every ledger subtype that is understood, anything else is [`KrakenLedgerSubtype::Unknown`].
+    pub const LEDGER_SUBTYPES: &[KrakenLedgerSubtype] = &[
+        KrakenLedgerSubtype::SpotToStaking,
+        KrakenLedgerSubtype::StakingFromSpot,
+        KrakenLedgerSubtype::StakingToSpot,
+        KrakenLedgerSubtype::SpotFromStaking,
+        KrakenLedgerSubtype::SpotToFutures,
+        KrakenLedgerSubtype::SpotFromFutures,
+        KrakenLedgerSubtype::Reward,
+        KrakenLedgerSubtype::Allocation,
+        KrakenLedgerSubtype::Deallocation,
+        KrakenLedgerSubtype::AutoAllocation,
+        KrakenLedgerSubtype::Migration,
+    ];
+
+    /// The type column of a Kraken ledger export. Types Kraken adds later are kept as
+    /// [`KrakenLedgerType::Unknown`] rather than failing the row.
+    ///
+    /// ```
+    /// # use models::kraken::KrakenLedgerType;
+    /// assert_eq!(KrakenLedgerType::from("rollover"), KrakenLedgerType::Rollover);
+    /// assert_eq!(
+    ///     KrakenLedgerType::from("nft"),
+    ///     KrakenLedgerType::Unknown("nft".to_string())
+    /// );
+    /// assert!(KrakenLedgerType::Margin.is_margin());
+    /// ```
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Hash)]
+    #[serde(from = "String", into = "String")]
+    pub enum KrakenLedgerType {
+        Trade,
+        /// The spent side of a Buy Crypto purchase.
+        Spend,
+        /// The received side of a Buy Crypto purchase.
+        Receive,
+        Deposit,
+        Withdrawal,
+        /// Moves between spot, staking and futures wallets, see [`KrakenLedgerSubtype`].
+        Transfer,
+        /// Staking rewards, also used for the staked side of some staking transfers.
+        Staking,
+        /// Kraken Earn rewards and allocations, see [`KrakenLedgerSubtype`].
+        Earn,
+        /// Profit or loss of a margin position along with its opening fee.
+        Margin,
+        /// Fee charged to keep a margin position open.
+        Rollover,
+        /// A margin position settled with the underlying asset.
+        Settled,
+        Adjustment,
+        Dividend,
+        Sale,
+        Credit,
+        Unknown(String),
+    }
+
+    impl KrakenLedgerType {
+        /// The name Kraken uses for the type in its exports.
+        pub fn as_str(&self) -> &str {
+            match self {
+                Self::Trade => "trade",
+                Self::Spend => "spend",
+                Self::Receive => "receive",
+                Self::Deposit => "deposit",
+                Self::Withdrawal => "withdrawal",
+                Self::Transfer => "transfer",
+                Self::Staking => "staking",
+                Self::Earn => "earn",
+                Self::Margin => "margin",
+                Self::Rollover => "rollover",
+                Self::Settled => "settled",
+                Self::Adjustment => "adjustment",
+                Self::Dividend => "dividend",
+                Self::Sale => "sale",
+                Self::Credit => "credit",
+                Self::Unknown(record_type) => record_type,
+            }
+        }
+
+        /// Rows that belong to a margin position rather than a spot trade.
+        pub fn is_margin(&self) -> bool {
+            matches!(self, Self::Margin | Self::Rollover | Self::Settled)
+        }
+
+        pub fn is_unknown(&self) -> bool {
+            matches!(self, Self::Unknown(_))
+        }
+    }
+
+    impl From<&str> for KrakenLedgerType {
+        fn from(record_type: &str) -> Self {
+            LEDGER_TYPES
+                .iter()
+                .find(|known| known.as_str().eq_ignore_ascii_case(record_type.trim()))
+                .cloned()
+                .unwrap_or_else(|| Self::Unknown(record_type.to_string()))
+        }
+    }
+
+    impl From<String> for KrakenLedgerType {
+        fn from(record_type: String) -> Self {
+            Self::from(record_type.as_str())
+        }
+    }
+
+    impl From<KrakenLedgerType> for String {
+        fn from(record_type: KrakenLedgerType) -> Self {
+            record_type.to_string()
+        }
+    }
+
+    impl fmt::Display for KrakenLedgerType {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.as_str())
+        }
+    }
+
+    /// The subtype column of a Kraken ledger export.
+    ///
+    /// ```
+    /// # use models::kraken::KrakenLedgerSubtype;
+    /// assert!(KrakenLedgerSubtype::from("spottostaking").is_staking_transfer());
+    /// assert!(!KrakenLedgerSubtype::from("spotfromfutures").is_staking_transfer());
+    /// ```
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Hash)]
+    #[serde(from = "String", into = "String")]
+    pub enum KrakenLedgerSubtype {
+        SpotToStaking,
+        StakingFromSpot,
+        StakingToSpot,
+        SpotFromStaking,
+        SpotToFutures,
+        SpotFromFutures,
+        /// An Earn reward paid out.
+        Reward,
+        /// Assets moved in to an Earn strategy.
+        Allocation,
+        /// Assets moved out of an Earn strategy.
+        Deallocation,
+        AutoAllocation,
+        /// Staked assets moved over when Kraken replaced staking with Earn.
+        Migration,
+        Unknown(String),
+    }
+
+    impl KrakenLedgerSubtype {
+        /// The name Kraken uses for the subtype in its exports.
+        pub fn as_str(&self) -> &str {
+            match self {
+                Self::SpotToStaking => "spottostaking",
+                Self::StakingFromSpot => "stakingfromspot",
+                Self::StakingToSpot => "stakingtospot",
+                Self::SpotFromStaking => "spotfromstaking",
+                Self::SpotToFutures => "spottofutures",
+                Self::SpotFromFutures => "spotfromfutures",
+                Self::Reward => "reward",
+                Self::Allocation => "allocation",
+                Self::Deallocation => "deallocation",
+                Self::AutoAllocation => "autoallocation",
+                Self::Migration => "migration",
+                Self::Unknown(subtype) => subtype,
+            }
+        }
+
+        /// Moves of the same holding between the spot wallet and staking or Earn. These change which variant
+        /// of the asset is held, they are never income.
+        pub fn is_staking_transfer(&self) -> bool {
+            matches!(
+                self,
+                Self::SpotToStaking
+                    | Self::StakingFromSpot
+                    | Self::StakingToSpot
+                    | Self::SpotFromStaking
+                    | Self::Allocation
+                    | Self::Deallocation
+                    | Self::AutoAllocation
+                    | Self::Migration
+            )
+        }
+
+        pub fn is_unknown(&self) -> bool {
+            matches!(self, Self::Unknown(_))
+        }
+    }
+
+    impl From<&str> for KrakenLedgerSubtype {
+        fn from(subtype: &str) -> Self {
+            LEDGER_SUBTYPES
+                .iter()
+                .find(|known| known.as_str().eq_ignore_ascii_case(subtype.trim()))
+                .cloned()
+                .unwrap_or_else(|| Self::Unknown(subtype.to_string()))
+        }
+    }
+
+    impl From<String> for KrakenLedgerSubtype {
+        fn from(subtype: String) -> Self {
+            Self::from(subtype.as_str())
+        }
+    }
+
+    impl From<KrakenLedgerSubtype> for String {
+        fn from(subtype: KrakenLedgerSubtype) -> Self {
+            subtype.to_string()
+        }
+    }
+
+    impl fmt::Display for KrakenLedgerSubtype {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.as_str())
+        }
+    }
+
     #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
     #[serde(rename_all(serialize = "camelCase"))]
     pub struct KrakenLedgerRecord {
@@ -126,8 +1979,8 @@ pub mod kraken {
         #[serde(deserialize_with = "parse_date_time")]
         pub time: DateTime<Utc>,
         #[serde(rename(deserialize = "type"))]
-        pub record_type: String,
-        pub subtype: Option<String>,
+        pub record_type: KrakenLedgerType,
+        pub subtype: Option<KrakenLedgerSubtype>,
         #[serde(rename(deserialize = "aclass"))]
         pub a_class: String,
         pub asset: String,
@@ -153,6 +2006,259 @@ pub mod kraken {
             .map_err(serde::de::Error::custom)
     }
 
+    /// A fill from the Kraken trades export. Unlike the ledger it carries the executed price, and `ledgers` lists
+    /// the txids of the ledger rows the fill booked.
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+    #[serde(rename_all(serialize = "camelCase"))]
+    pub struct KrakenTradeRecord {
+        pub txid: String,
+        pub ordertxid: String,
+        /// Kraken's name for the pair, such as `XXBTZUSD` or `DOTUSD`.
+        pub pair: String,
+        #[serde(deserialize_with = "parse_trade_date_time")]
+        pub time: DateTime<Utc>,
+        /// Whether the base asset of the pair was bought or sold.
+        #[serde(rename(deserialize = "type"), deserialize_with = "parse_trade_side")]
+        pub trade_type: TradeSide,
+        pub ordertype: String,
+        /// Quote asset paid per unit of the base asset.
+        #[serde(with = "rust_decimal::serde::str")]
+        pub price: Decimal,
+        /// Quote asset paid or received, before fees.
+        #[serde(with = "rust_decimal::serde::str")]
+        pub cost: Decimal,
+        #[serde(with = "rust_decimal::serde::str")]
+        pub fee: Decimal,
+        /// Base asset bought or sold.
+        #[serde(with = "rust_decimal::serde::str")]
+        pub vol: Decimal,
+        #[serde(with = "rust_decimal::serde::str")]
+        pub margin: Decimal,
+        pub misc: String,
+        /// Comma separated txids of the ledger rows of the fill.
+        pub ledgers: String,
+    }
+
+    impl KrakenTradeRecord {
+        /// The txids of the ledger rows booked by the fill.
+        /// ```
+        /// # use chrono::{TimeZone, Utc};
+        /// # use models::kraken::{KrakenTradeRecord, TradeSide, TRADE_DATE_FORMAT};
+        /// # use rust_decimal::Decimal;
+        /// let fill = KrakenTradeRecord {
+        ///     txid: "TKB7ODD-ILZGC5-LCRRBL".to_string(),
+        ///     ordertxid: "OQCLML-BW3P3-BUCMWZ".to_string(),
+        ///     pair: "DOTUSD".to_string(),
+        ///     time: Utc
+        ///         .datetime_from_str("2021-09-29 15:18:30.1234", TRADE_DATE_FORMAT)
+        ///         .unwrap(),
+        ///     trade_type: TradeSide::Buy,
+        ///     ordertype: "limit".to_string(),
+        ///     price: Decimal::new(25, 0),
+        ///     cost: Decimal::new(100, 0),
+        ///     fee: Decimal::new(26, 2),
+        ///     vol: Decimal::new(4, 0),
+        ///     margin: Decimal::ZERO,
+        ///     misc: "".to_string(),
+        ///     ledgers: "L7RLII-OFGWB-JTUO7J, L8RLII-OFGWB-JTUO7J".to_string(),
+        /// };
+        /// assert_eq!(
+        ///     fill.ledger_txids(),
+        ///     vec!["L7RLII-OFGWB-JTUO7J", "L8RLII-OFGWB-JTUO7J"]
+        /// );
+        /// ```
+        pub fn ledger_txids(&self) -> Vec<&str> {
+            self.ledgers
+                .split(',')
+                .map(str::trim)
+                .filter(|txid| !txid.is_empty())
+                .collect()
+        }
+    }
+
+    fn parse_trade_side<'de, D: Deserializer<'de>>(d: D) -> Result<TradeSide, D::Error> {
+        let s: String = Deserialize::deserialize(d)?;
+
+        s.trim()
+            .parse()
+            .map_err(|_| serde::de::Error::unknown_variant(s.trim(), &["buy", "sell"]))
+    }
+
+    fn parse_trade_date_time<'de, D: Deserializer<'de>>(d: D) -> Result<DateTime<Utc>, D::Error> {
+        // 2021-09-29 15:18:30.1234
+        let s: String = Deserialize::deserialize(d)?;
+
+        Utc.datetime_from_str(&s, TRADE_DATE_FORMAT)
+            .map_err(serde::de::Error::custom)
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+    #[serde(rename_all = "camelCase")]
+    pub enum TradeSide {
+        /// The base asset was received.
+        Buy,
+        /// The base asset was spent.
+        Sell,
+    }
+
+    impl TradeSide {
+        /// The name Kraken uses for the side in its trades export.
+        pub fn as_str(&self) -> &str {
+            match self {
+                Self::Buy => "buy",
+                Self::Sell => "sell",
+            }
+        }
+    }
+
+    impl FromStr for TradeSide {
+        type Err = String;
+
+        /// Reads the side as Kraken names it in its trades export.
+        fn from_str(side: &str) -> Result<Self, Self::Err> {
+            match side {
+                "buy" => Ok(Self::Buy),
+                "sell" => Ok(Self::Sell),
+                other => Err(format!(
+                    "\"{other}\" is not a trade side, expected buy or sell"
+                )),
+            }
+        }
+    }
+
+    /// The ledger rows of a single `refid` combined into one trade. Quantities are always positive and do not
+    /// include fees.
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+    #[serde(rename_all = "camelCase")]
+    pub struct KrakenTrade {
+        pub refid: String,
+        /// txid of the first ledger row of the base asset.
+        pub base_txid: Option<String>,
+        pub time: DateTime<Utc>,
+        pub side: TradeSide,
+        /// Canonical ticker of the asset that was bought or sold.
+        pub base: String,
+        /// Canonical ticker of the asset the base was priced in.
+        pub quote: String,
+        pub base_quantity: Decimal,
+        pub quote_quantity: Decimal,
+        /// Quote asset paid per unit of the base asset.
+        pub price: Decimal,
+        pub base_fee: Decimal,
+        pub quote_fee: Decimal,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+    #[serde(rename_all = "camelCase")]
+    pub enum UnbalancedReason {
+        /// Only one asset moved under the refid.
+        SingleAsset,
+        /// More than two assets moved under the refid.
+        TooManyAssets,
+        /// Both assets were received or both were spent.
+        SameDirection,
+    }
+
+    /// A refid whose ledger rows could not be combined into a trade.
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+    #[serde(rename_all = "camelCase")]
+    pub struct UnbalancedRefid {
+        pub refid: String,
+        pub reason: UnbalancedReason,
+        pub records: Vec<KrakenLedgerRecord>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+    #[serde(rename_all = "camelCase")]
+    pub struct KrakenTradeReport {
+        pub trades: Vec<KrakenTrade>,
+        pub unbalanced: Vec<UnbalancedRefid>,
+    }
+
+    /// A ledger row where the balance replayed from the ledger does not match the balance Kraken exported.
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+    #[serde(rename_all = "camelCase")]
+    pub struct BalanceMismatch {
+        pub txid: String,
+        pub refid: String,
+        pub time: DateTime<Utc>,
+        /// The asset code as exported, Kraken keeps a balance per code.
+        pub asset: String,
+        pub kraken_balance: Decimal,
+        pub computed_balance: Decimal,
+        /// Computed balance minus Kraken's balance.
+        pub difference: Decimal,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+    #[serde(rename_all = "camelCase")]
+    pub struct BalanceReconciliation {
+        pub mismatches: Vec<BalanceMismatch>,
+        /// Replayed balance of every asset code at the end of the ledger.
+        pub balances: HashMap<String, Decimal>,
+    }
+
+    #[cfg(test)]
+    mod kraken_ledger_type_should {
+        use super::{KrakenLedgerSubtype, KrakenLedgerType, LEDGER_SUBTYPES, LEDGER_TYPES};
+
+        #[test]
+        fn read_every_known_type_back_from_its_name() {
+            for record_type in LEDGER_TYPES {
+                assert_eq!(&KrakenLedgerType::from(record_type.as_str()), record_type);
+            }
+            for subtype in LEDGER_SUBTYPES {
+                assert_eq!(&KrakenLedgerSubtype::from(subtype.as_str()), subtype);
+            }
+        }
+
+        #[test]
+        fn keep_the_name_of_unknown_types() {
+            let record_type = KrakenLedgerType::from("nfttrade");
+            let subtype = KrakenLedgerSubtype::from("spottoearn");
+
+            assert!(record_type.is_unknown());
+            assert!(subtype.is_unknown());
+            assert_eq!(String::from(record_type), "nfttrade");
+            assert_eq!(String::from(subtype), "spottoearn");
+        }
+
+        #[test]
+        fn tell_margin_rows_apart() {
+            assert!(KrakenLedgerType::Rollover.is_margin());
+            assert!(KrakenLedgerType::Settled.is_margin());
+            assert!(!KrakenLedgerType::Trade.is_margin());
+        }
+
+        #[test]
+        fn tell_staking_transfers_from_rewards() {
+            assert!(KrakenLedgerSubtype::StakingFromSpot.is_staking_transfer());
+            assert!(KrakenLedgerSubtype::Deallocation.is_staking_transfer());
+            assert!(!KrakenLedgerSubtype::Reward.is_staking_transfer());
+            assert!(!KrakenLedgerSubtype::SpotFromFutures.is_staking_transfer());
+        }
+    }
+
+    #[cfg(test)]
+    mod kraken_trade_record_should {
+        use chrono::{TimeZone, Timelike, Utc};
+
+        use super::TRADE_DATE_FORMAT;
+
+        #[test]
+        fn read_times_with_and_without_fractional_seconds() {
+            let fractional = Utc
+                .datetime_from_str("2021-09-29 15:18:30.1234", TRADE_DATE_FORMAT)
+                .unwrap();
+            let whole = Utc
+                .datetime_from_str("2021-09-29 15:18:30", TRADE_DATE_FORMAT)
+                .unwrap();
+
+            assert_eq!(fractional.nanosecond(), 123_400_000);
+            assert_eq!(fractional.with_nanosecond(0), Some(whole));
+        }
+    }
+
     #[cfg(test)]
     mod input_transaction_should {
         use chrono::{TimeZone, Utc};
@@ -160,7 +2266,7 @@ pub mod kraken {
 
         use crate::InputTransaction;
 
-        use super::{KrakenLedgerRecord, DATE_FORMAT as KRAKEN_DATE_FORMAT};
+        use super::{KrakenLedgerRecord, KrakenLedgerType, DATE_FORMAT as KRAKEN_DATE_FORMAT};
 
         #[test]
         fn find_positive_amount_as_input() {
@@ -170,7 +2276,7 @@ pub mod kraken {
                 time: Utc
                     .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
                     .unwrap(),
-                record_type: "buy".to_string(),
+                record_type: KrakenLedgerType::from("buy"),
                 subtype: None,
                 a_class: "currency".to_string(),
                 asset: "BTC".to_string(),
@@ -193,7 +2299,7 @@ pub mod kraken {
                 time: Utc
                     .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
                     .unwrap(),
-                record_type: "sell".to_string(),
+                record_type: KrakenLedgerType::from("sell"),
                 subtype: None,
                 a_class: "currency".to_string(),
                 asset: "BTC".to_string(),
@@ -216,7 +2322,7 @@ pub mod kraken {
                 time: Utc
                     .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
                     .unwrap(),
-                record_type: "sell".to_string(),
+                record_type: KrakenLedgerType::from("sell"),
                 subtype: None,
                 a_class: "currency".to_string(),
                 asset: "BTC".to_string(),
@@ -232,3 +2338,353 @@ pub mod kraken {
         }
     }
 }
+
+pub mod mapping {
+    use std::{collections::HashMap, fmt};
+
+    pub use chrono::{DateTime, Utc};
+    use chrono::{NaiveDate, NaiveDateTime, TimeZone};
+    use rust_decimal::Decimal;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{amount::unformat_amount, InputTransaction};
+
+    /// Which columns of a csv that is not a known export hold each part of a transaction. Definitions are written in
+    /// JSON or TOML.
+    ///
+    /// ```
+    /// # use models::mapping::ColumnMapping;
+    /// let mapping = ColumnMapping::from_definition(
+    ///     r#"
+    ///     name = "hardware-wallet"
+    ///     timestamp = "Date"
+    ///     date_format = "%m/%d/%Y %H:%M"
+    ///     asset = "Currency"
+    ///     quantity = "Amount"
+    ///     fee = "Fee"
+    ///
+    ///     [side]
+    ///     column = "Direction"
+    ///     inflow = ["IN"]
+    ///     outflow = ["OUT"]
+    ///     "#,
+    /// )
+    /// .unwrap();
+    ///
+    /// assert_eq!(mapping.name, "hardware-wallet");
+    /// assert_eq!(mapping.columns(), ["Date", "Currency", "Amount", "Direction", "Fee"]);
+    /// assert_eq!(ColumnMapping::from_definition(&mapping.to_json()), Ok(mapping));
+    /// ```
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+    #[serde(rename_all = "camelCase")]
+    pub struct ColumnMapping {
+        /// Name the mapping is stored and selected by.
+        pub name: String,
+        pub timestamp: String,
+        /// Format of the timestamp column in chrono's syntax, RFC 3339 when left out. Formats without a time or
+        /// without an offset are read as UTC.
+        #[serde(default, alias = "date_format")]
+        pub date_format: Option<String>,
+        pub asset: String,
+        pub quantity: String,
+        /// When left out the sign of the quantity says which way the asset moved.
+        #[serde(default)]
+        pub side: Option<SideMapping>,
+        #[serde(default)]
+        pub fee: Option<String>,
+        /// Column naming the asset the fee was paid in, the fee is in the asset of the row when left out.
+        #[serde(default, alias = "fee_asset")]
+        pub fee_asset: Option<String>,
+        /// Column holding the USD value of the quantity.
+        #[serde(default, alias = "fiat_value")]
+        pub fiat_value: Option<String>,
+    }
+
+    /// A column saying which way the asset moved, with the values that mean it was received or sent. Values are
+    /// matched ignoring case.
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+    pub struct SideMapping {
+        pub column: String,
+        pub inflow: Vec<String>,
+        pub outflow: Vec<String>,
+    }
+
+    /// A row read with a [`ColumnMapping`].
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+    #[serde(rename_all = "camelCase")]
+    pub struct MappedTransaction {
+        /// Name of the mapping the row was read with.
+        pub mapping: String,
+        pub time: DateTime<Utc>,
+        pub asset: String,
+        /// Positive when the asset was received, negative when it was sent. Fees are not included.
+        pub quantity: Decimal,
+        /// Always positive.
+        pub fee: Decimal,
+        pub fee_asset: String,
+        pub fiat_value: Option<Decimal>,
+    }
+
+    impl InputTransaction for MappedTransaction {
+        fn is_input_transaction(&self) -> bool {
+            self.quantity >= Decimal::ZERO
+        }
+    }
+
+    /// Why a row could not be read with a mapping.
+    #[derive(Debug, PartialEq, Eq, Clone)]
+    pub struct ColumnError {
+        pub column: String,
+        pub raw_value: Option<String>,
+        pub reason: String,
+    }
+
+    impl fmt::Display for ColumnError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "column \"{}\": {}", self.column, self.reason)
+        }
+    }
+
+    impl ColumnMapping {
+        /// Reads a definition written in JSON, or TOML when it is not a JSON object.
+        pub fn from_definition(definition: &str) -> Result<Self, String> {
+            let mapping: Self = match definition.trim_start().starts_with('{') {
+                true => serde_json::from_str(definition).map_err(|error| error.to_string())?,
+                false => toml::from_str(definition).map_err(|error| error.to_string())?,
+            };
+
+            if mapping.name.trim().is_empty() {
+                return Err("a mapping needs a name".to_string());
+            }
+            match &mapping.side {
+                Some(side) if side.inflow.is_empty() || side.outflow.is_empty() => Err(format!(
+                    "side column \"{}\" needs both inflow and outflow values",
+                    side.column
+                )),
+                _ => Ok(mapping),
+            }
+        }
+
+        /// The definition as JSON, which is how mappings are stored.
+        pub fn to_json(&self) -> String {
+            serde_json::to_string(self).expect("column mappings only hold strings")
+        }
+
+        /// Every column the mapping reads, a csv needs all of them to be read with it.
+        pub fn columns(&self) -> Vec<&str> {
+            [
+                Some(&self.timestamp),
+                Some(&self.asset),
+                Some(&self.quantity),
+                self.side.as_ref().map(|side| &side.column),
+                self.fee.as_ref(),
+                self.fee_asset.as_ref(),
+                self.fiat_value.as_ref(),
+            ]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect()
+        }
+
+        /// Reads a row keyed by header. Amounts may be written with currency symbols, thousands separators or in
+        /// parentheses when negative.
+        pub fn map_row(
+            &self,
+            row: &HashMap<String, String>,
+        ) -> Result<MappedTransaction, ColumnError> {
+            let column = |header: &String| {
+                row.get(header)
+                    .map(|value| value.trim())
+                    .ok_or_else(|| ColumnError {
+                        column: header.to_string(),
+                        raw_value: None,
+                        reason: "missing from the csv".to_string(),
+                    })
+            };
+            let amount = |header: &String| {
+                column(header).and_then(|value| {
+                    unformat_amount(value).map_err(|reason| ColumnError {
+                        column: header.to_string(),
+                        raw_value: Some(value.to_string()),
+                        reason,
+                    })
+                })
+            };
+            let optional_amount = |header: &Option<String>| match header {
+                Some(header) if !column(header)?.is_empty() => amount(header).map(Some),
+                _ => Ok(None),
+            };
+
+            let asset = column(&self.asset)?.to_string();
+            let quantity = amount(&self.quantity)?;
+            let quantity = match &self.side {
+                Some(side) => {
+                    let value = column(&side.column)?;
+                    let is = |values: &[String]| {
+                        values
+                            .iter()
+                            .any(|known| known.trim().eq_ignore_ascii_case(value))
+                    };
+
+                    if is(&side.inflow) {
+                        quantity.abs()
+                    } else if is(&side.outflow) {
+                        -quantity.abs()
+                    } else {
+                        return Err(ColumnError {
+                            column: side.column.to_string(),
+                            raw_value: Some(value.to_string()),
+                            reason: "not an inflow or outflow value of the mapping".to_string(),
+                        });
+                    }
+                }
+                None => quantity,
+            };
+            let fee_asset = match &self.fee_asset {
+                Some(header) if !column(header)?.is_empty() => column(header)?.to_string(),
+                _ => asset.to_string(),
+            };
+
+            Ok(MappedTransaction {
+                mapping: self.name.to_string(),
+                time: self.parse_time(column(&self.timestamp)?)?,
+                asset,
+                quantity,
+                fee: optional_amount(&self.fee)?.unwrap_or_default().abs(),
+                fee_asset,
+                fiat_value: optional_amount(&self.fiat_value)?.map(|value| value.abs()),
+            })
+        }
+
+        fn parse_time(&self, time: &str) -> Result<DateTime<Utc>, ColumnError> {
+            let parsed = match &self.date_format {
+                Some(format) => DateTime::parse_from_str(time, format)
+                    .map(|time| time.with_timezone(&Utc))
+                    .or_else(|_| {
+                        NaiveDateTime::parse_from_str(time, format)
+                            .map(|time| Utc.from_utc_datetime(&time))
+                    })
+                    .or_else(|_| {
+                        NaiveDate::parse_from_str(time, format)
+                            .map(|date| Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap()))
+                    })
+                    .map_err(|error| format!("is not a time in the format \"{format}\": {error}")),
+                None => DateTime::parse_from_rfc3339(time)
+                    .map(|time| time.with_timezone(&Utc))
+                    .map_err(|error| format!("is not an RFC 3339 time: {error}")),
+            };
+
+            parsed.map_err(|reason| ColumnError {
+                column: self.timestamp.to_string(),
+                raw_value: Some(time.to_string()),
+                reason,
+            })
+        }
+    }
+
+    #[cfg(test)]
+    mod column_mapping_should {
+        use std::collections::HashMap;
+
+        use rust_decimal::Decimal;
+
+        use super::{ColumnMapping, SideMapping};
+
+        fn mapping() -> ColumnMapping {
+            ColumnMapping {
+                name: "wallet".to_string(),
+                timestamp: "When".to_string(),
+                date_format: Some("%d.%m.%Y".to_string()),
+                asset: "Coin".to_string(),
+                quantity: "Amount".to_string(),
+                side: Some(SideMapping {
+                    column: "Way".to_string(),
+                    inflow: vec!["received".to_string()],
+                    outflow: vec!["sent".to_string()],
+                }),
+                fee: Some("Fee".to_string()),
+                fee_asset: None,
+                fiat_value: Some("Value".to_string()),
+            }
+        }
+
+        fn row(columns: &[(&str, &str)]) -> HashMap<String, String> {
+            columns
+                .iter()
+                .map(|(header, value)| (header.to_string(), value.to_string()))
+                .collect()
+        }
+
+        #[test]
+        fn sign_the_quantity_by_the_side_column() {
+            let transaction = mapping()
+                .map_row(&row(&[
+                    ("When", "02.03.2021"),
+                    ("Coin", "ETH"),
+                    ("Amount", "0.5"),
+                    ("Way", "Sent"),
+                    ("Fee", "0.001"),
+                    ("Value", "$1,000.00"),
+                ]))
+                .unwrap();
+
+            assert_eq!(transaction.time.to_rfc3339(), "2021-03-02T00:00:00+00:00");
+            assert_eq!(transaction.quantity, Decimal::new(-5, 1));
+            assert_eq!(transaction.fee, Decimal::new(1, 3));
+            assert_eq!(transaction.fee_asset, "ETH");
+            assert_eq!(transaction.fiat_value, Some(Decimal::new(1000, 0)));
+        }
+
+        #[test]
+        fn keep_the_sign_of_the_quantity_without_a_side_column() {
+            let mapping = ColumnMapping {
+                side: None,
+                date_format: None,
+                ..mapping()
+            };
+            let transaction = mapping
+                .map_row(&row(&[
+                    ("When", "2021-03-02T10:00:00-05:00"),
+                    ("Coin", "ETH"),
+                    ("Amount", "(0.5)"),
+                    ("Fee", ""),
+                    ("Value", ""),
+                ]))
+                .unwrap();
+
+            assert_eq!(transaction.time.to_rfc3339(), "2021-03-02T15:00:00+00:00");
+            assert_eq!(transaction.quantity, Decimal::new(-5, 1));
+            assert_eq!(transaction.fee, Decimal::ZERO);
+            assert_eq!(transaction.fiat_value, None);
+        }
+
+        #[test]
+        fn report_the_column_that_could_not_be_read() {
+            let error = mapping()
+                .map_row(&row(&[
+                    ("When", "02.03.2021"),
+                    ("Coin", "ETH"),
+                    ("Amount", "0.5"),
+                    ("Way", "staked"),
+                ]))
+                .unwrap_err();
+
+            assert_eq!(error.column, "Way");
+            assert_eq!(error.raw_value.as_deref(), Some("staked"));
+        }
+
+        #[test]
+        fn read_json_definitions() {
+            let mapping = ColumnMapping::from_definition(
+                r#"{"name": "wallet", "timestamp": "When", "asset": "Coin", "quantity": "Amount", "feeAsset": "Fee Coin"}"#,
+            )
+            .unwrap();
+
+            assert_eq!(mapping.fee_asset.as_deref(), Some("Fee Coin"));
+            assert!(
+                ColumnMapping::from_definition(r#"{"name": "", "timestamp": "When"}"#).is_err()
+            );
+        }
+    }
+}
@@ -1,13 +1,30 @@
 pub mod schema;
 
-use crate::schema::{coinbase_transactions, kraken_transactions};
+use crate::schema::{
+    binance_transactions, coinbase_transactions, column_mappings, import_batches, kraken_trades,
+    kraken_transactions,
+};
 use chrono::prelude::*;
 use diesel::prelude::*;
-use models::{coinbase::INPUT_TRANSACTIONS, InputTransaction};
+use models::{
+    binance::{BinanceOperation, BinanceTransactionRecord},
+    coinbase::{
+        CoinbaseTransactionRecord, CoinbaseTransactionType, Conversion, ConversionError,
+        INPUT_TRANSACTIONS,
+    },
+    kraken::{KrakenLedgerRecord, KrakenLedgerSubtype, KrakenLedgerType, KrakenTradeRecord},
+    mapping::ColumnMapping,
+    InputTransaction,
+};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
 
-#[derive(Queryable, Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+/// A stored Coinbase transaction. Its `transaction_key` is left out, select it with
+/// `CoinbaseTransaction::as_select()`.
+#[derive(Queryable, Selectable, Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[diesel(table_name = coinbase_transactions)]
 #[serde(rename_all(serialize = "camelCase"))]
 pub struct CoinbaseTransaction {
     pub id: i32,
@@ -21,13 +38,49 @@ pub struct CoinbaseTransaction {
     pub total: Option<Decimal>,
     pub fees: Option<Decimal>,
     pub notes: String,
+    /// The id Coinbase gave the transaction, only transaction reports have one.
+    pub coinbase_id: Option<String>,
+    /// The import that stored the transaction, if it was imported.
+    pub import_batch_id: Option<i32>,
+}
+
+impl CoinbaseTransaction {
+    /// The stored transaction type, with types that are not understood kept as
+    /// [`CoinbaseTransactionType::Unknown`].
+    pub fn transaction_kind(&self) -> CoinbaseTransactionType {
+        CoinbaseTransactionType::from(self.transaction_type.as_str())
+    }
 }
 
 impl InputTransaction for CoinbaseTransaction {
     fn is_input_transaction(&self) -> bool {
-        INPUT_TRANSACTIONS
-            .iter()
-            .any(|received_transaction_type| received_transaction_type.eq(&self.transaction_type))
+        INPUT_TRANSACTIONS.contains(&self.transaction_kind())
+    }
+}
+
+impl From<&CoinbaseTransaction> for CoinbaseTransactionRecord {
+    fn from(transaction: &CoinbaseTransaction) -> Self {
+        Self {
+            id: transaction.coinbase_id.clone(),
+            time_of_transaction: transaction.time_of_transaction,
+            transaction_type: transaction.transaction_kind(),
+            asset: transaction.asset.to_string(),
+            quantity_transacted: transaction.quantity_transacted,
+            spot_price_currency: transaction.spot_price_currency.to_string(),
+            spot_price_at_transaction: transaction.spot_price_at_transaction,
+            subtotal: transaction.subtotal,
+            total: transaction.total,
+            fees: transaction.fees,
+            notes: transaction.notes.to_string(),
+        }
+    }
+}
+
+impl TryFrom<&CoinbaseTransaction> for Conversion {
+    type Error = ConversionError;
+
+    fn try_from(transaction: &CoinbaseTransaction) -> Result<Self, Self::Error> {
+        Conversion::try_from(&CoinbaseTransactionRecord::from(transaction))
     }
 }
 
@@ -44,6 +97,141 @@ pub struct NewCoinbaseTransaction {
     pub total: Option<Decimal>,
     pub fees: Option<Decimal>,
     pub notes: String,
+    #[serde(default)]
+    pub coinbase_id: Option<String>,
+    /// Tells a transaction apart from every other, imports skip transactions whose key is stored already. Always
+    /// [`NewCoinbaseTransaction::key`], it is filled in when the transaction is stored.
+    #[serde(skip_deserializing)]
+    pub transaction_key: String,
+    /// Only set by imports, see [`NewCoinbaseTransaction::in_import_batch`].
+    #[serde(skip_deserializing)]
+    pub import_batch_id: Option<i32>,
+}
+
+impl NewCoinbaseTransaction {
+    /// Key of a transaction, the id Coinbase gave it or, for legacy exports without ids, its
+    /// [`NewCoinbaseTransaction::content_key`]. Fills of the same order can share their time, asset and quantity, so
+    /// transactions with ids are only ever told apart by them. Transactions stored before keys existed were keyed the
+    /// same way by the migration that added them.
+    ///
+    /// ```
+    /// # use models_db::NewCoinbaseTransaction;
+    /// # use rust_decimal::Decimal;
+    /// let fill = NewCoinbaseTransaction {
+    ///     time_of_transaction: "2021-01-22T21:38:01Z".parse().unwrap(),
+    ///     transaction_type: "Advanced Trade Buy".to_string(),
+    ///     asset: "BTC".to_string(),
+    ///     quantity_transacted: Decimal::new(1, 2),
+    ///     spot_price_currency: "USD".to_string(),
+    ///     spot_price_at_transaction: None,
+    ///     subtotal: None,
+    ///     total: None,
+    ///     fees: None,
+    ///     notes: String::new(),
+    ///     coinbase_id: Some("64d0b1f2a3c4e5f6a7b8c9d0".to_string()),
+    ///     transaction_key: String::new(),
+    ///     import_batch_id: None,
+    /// };
+    /// let same_second = NewCoinbaseTransaction {
+    ///     coinbase_id: Some("64d0b1f2a3c4e5f6a7b8c9d1".to_string()),
+    ///     ..fill.clone()
+    /// };
+    /// let legacy = NewCoinbaseTransaction {
+    ///     coinbase_id: None,
+    ///     ..fill.clone()
+    /// };
+    ///
+    /// assert_eq!(fill.key(), "64d0b1f2a3c4e5f6a7b8c9d0");
+    /// assert_ne!(fill.key(), same_second.key());
+    /// assert_eq!(legacy.key(), legacy.content_key());
+    /// ```
+    pub fn key(&self) -> String {
+        match self.coinbase_id.as_deref().map(str::trim) {
+            Some(coinbase_id) if !coinbase_id.is_empty() => coinbase_id.to_string(),
+            _ => self.content_key(),
+        }
+    }
+
+    /// Key of a transaction without an id, made from when it happened, its type, asset and quantity.
+    ///
+    /// ```
+    /// # use models_db::NewCoinbaseTransaction;
+    /// # use rust_decimal::Decimal;
+    /// let transaction = NewCoinbaseTransaction {
+    ///     time_of_transaction: "2021-01-22T21:38:01Z".parse().unwrap(),
+    ///     transaction_type: "Buy".to_string(),
+    ///     asset: "BTC".to_string(),
+    ///     quantity_transacted: Decimal::new(16458000, 10),
+    ///     spot_price_currency: "USD".to_string(),
+    ///     spot_price_at_transaction: None,
+    ///     subtotal: None,
+    ///     total: None,
+    ///     fees: None,
+    ///     notes: String::new(),
+    ///     coinbase_id: None,
+    ///     transaction_key: String::new(),
+    ///     import_batch_id: None,
+    /// };
+    ///
+    /// // Trailing zeros of the quantity are not part of the key.
+    /// let mut trimmed = transaction.clone();
+    /// trimmed.quantity_transacted = Decimal::new(16458, 7);
+    /// assert_eq!(transaction.content_key(), trimmed.content_key());
+    /// assert_eq!(transaction.content_key().len(), 64);
+    /// ```
+    pub fn content_key(&self) -> String {
+        let content = format!(
+            "{}|{}|{}|{}",
+            self.time_of_transaction.format("%Y-%m-%dT%H:%M:%SZ"),
+            self.transaction_type,
+            self.asset,
+            self.quantity_transacted.normalize()
+        );
+
+        format!("{:x}", Sha256::digest(content.as_bytes()))
+    }
+
+    /// The transaction as stored by the import that created `import_batch_id`.
+    pub fn in_import_batch(self, import_batch_id: i32) -> Self {
+        Self {
+            import_batch_id: Some(import_batch_id),
+            ..self
+        }
+    }
+
+    /// The transaction with its key filled in.
+    pub fn keyed(self) -> Self {
+        Self {
+            transaction_key: self.key(),
+            ..self
+        }
+    }
+}
+
+impl From<&CoinbaseTransactionRecord> for NewCoinbaseTransaction {
+    fn from(record: &CoinbaseTransactionRecord) -> Self {
+        Self {
+            time_of_transaction: record.time_of_transaction,
+            transaction_type: record.transaction_type.to_string(),
+            asset: record.asset.to_string(),
+            quantity_transacted: record.quantity_transacted,
+            spot_price_currency: record.spot_price_currency.to_string(),
+            spot_price_at_transaction: record.spot_price_at_transaction,
+            subtotal: record.subtotal,
+            total: record.total,
+            fees: record.fees,
+            notes: record.notes.to_string(),
+            coinbase_id: record
+                .id
+                .as_deref()
+                .map(str::trim)
+                .filter(|id| !id.is_empty())
+                .map(str::to_string),
+            transaction_key: String::new(),
+            import_batch_id: None,
+        }
+        .keyed()
+    }
 }
 
 #[derive(Queryable, Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
@@ -60,6 +248,28 @@ pub struct KrakenTransaction {
     pub amount: Decimal,
     pub fee: Decimal,
     pub balance: Option<Decimal>,
+    /// The import that stored the transaction, if it was imported.
+    pub import_batch_id: Option<i32>,
+}
+
+impl From<&KrakenTransaction> for KrakenLedgerRecord {
+    fn from(transaction: &KrakenTransaction) -> Self {
+        Self {
+            txid: transaction.txid.clone(),
+            refid: transaction.refid.to_string(),
+            time: transaction.transaction_time,
+            record_type: KrakenLedgerType::from(transaction.record_type.as_str()),
+            subtype: transaction
+                .subtype
+                .as_deref()
+                .map(KrakenLedgerSubtype::from),
+            a_class: transaction.a_class.to_string(),
+            asset: transaction.asset.to_string(),
+            amount: transaction.amount,
+            fee: transaction.fee,
+            balance: transaction.balance,
+        }
+    }
 }
 
 #[derive(Insertable, Deserialize, PartialEq, Eq, Clone)]
@@ -75,6 +285,266 @@ pub struct NewKrakenTransaction {
     pub amount: Decimal,
     pub fee: Decimal,
     pub balance: Option<Decimal>,
+    /// Only set by imports, see [`NewKrakenTransaction::in_import_batch`].
+    #[serde(skip_deserializing)]
+    pub import_batch_id: Option<i32>,
+}
+
+impl NewKrakenTransaction {
+    /// The transaction as stored by the import that created `import_batch_id`.
+    pub fn in_import_batch(self, import_batch_id: i32) -> Self {
+        Self {
+            import_batch_id: Some(import_batch_id),
+            ..self
+        }
+    }
+}
+
+impl From<&KrakenLedgerRecord> for NewKrakenTransaction {
+    fn from(record: &KrakenLedgerRecord) -> Self {
+        Self {
+            txid: record.txid.clone(),
+            refid: record.refid.to_string(),
+            transaction_time: record.time,
+            record_type: record.record_type.to_string(),
+            subtype: record.subtype.as_ref().map(ToString::to_string),
+            a_class: record.a_class.to_string(),
+            asset: record.asset.to_string(),
+            amount: record.amount,
+            fee: record.fee,
+            balance: record.balance,
+            import_batch_id: None,
+        }
+    }
+}
+
+#[derive(Queryable, Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct KrakenTradeFill {
+    pub id: i32,
+    pub txid: String,
+    pub ordertxid: String,
+    pub pair: String,
+    pub trade_time: DateTime<Utc>,
+    pub trade_type: String,
+    pub ordertype: String,
+    pub price: Decimal,
+    pub cost: Decimal,
+    pub fee: Decimal,
+    pub vol: Decimal,
+    pub margin: Decimal,
+    pub misc: String,
+    pub ledgers: String,
+    /// The import that stored the fill, if it was imported.
+    pub import_batch_id: Option<i32>,
+}
+
+impl TryFrom<&KrakenTradeFill> for KrakenTradeRecord {
+    type Error = String;
+
+    /// Fails when the stored side is not one Kraken uses.
+    fn try_from(fill: &KrakenTradeFill) -> Result<Self, Self::Error> {
+        Ok(Self {
+            txid: fill.txid.to_string(),
+            ordertxid: fill.ordertxid.to_string(),
+            pair: fill.pair.to_string(),
+            time: fill.trade_time,
+            trade_type: fill.trade_type.parse()?,
+            ordertype: fill.ordertype.to_string(),
+            price: fill.price,
+            cost: fill.cost,
+            fee: fill.fee,
+            vol: fill.vol,
+            margin: fill.margin,
+            misc: fill.misc.to_string(),
+            ledgers: fill.ledgers.to_string(),
+        })
+    }
+}
+
+#[derive(Insertable, Deserialize, PartialEq, Eq, Clone)]
+#[diesel(table_name = kraken_trades)]
+pub struct NewKrakenTradeFill {
+    pub txid: String,
+    pub ordertxid: String,
+    pub pair: String,
+    pub trade_time: DateTime<Utc>,
+    pub trade_type: String,
+    pub ordertype: String,
+    pub price: Decimal,
+    pub cost: Decimal,
+    pub fee: Decimal,
+    pub vol: Decimal,
+    pub margin: Decimal,
+    pub misc: String,
+    pub ledgers: String,
+    /// Only set by imports, see [`NewKrakenTradeFill::in_import_batch`].
+    #[serde(skip_deserializing)]
+    pub import_batch_id: Option<i32>,
+}
+
+impl NewKrakenTradeFill {
+    /// The fill as stored by the import that created `import_batch_id`.
+    pub fn in_import_batch(self, import_batch_id: i32) -> Self {
+        Self {
+            import_batch_id: Some(import_batch_id),
+            ..self
+        }
+    }
+}
+
+impl From<&KrakenTradeRecord> for NewKrakenTradeFill {
+    fn from(record: &KrakenTradeRecord) -> Self {
+        Self {
+            txid: record.txid.to_string(),
+            ordertxid: record.ordertxid.to_string(),
+            pair: record.pair.to_string(),
+            trade_time: record.time,
+            trade_type: record.trade_type.as_str().to_string(),
+            ordertype: record.ordertype.to_string(),
+            price: record.price,
+            cost: record.cost,
+            fee: record.fee,
+            vol: record.vol,
+            margin: record.margin,
+            misc: record.misc.to_string(),
+            ledgers: record.ledgers.to_string(),
+            import_batch_id: None,
+        }
+    }
+}
+
+#[derive(Queryable, Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct BinanceTransaction {
+    pub id: i32,
+    pub user_id: String,
+    pub transaction_time: DateTime<Utc>,
+    pub account: String,
+    pub operation: String,
+    pub coin: String,
+    pub change: Decimal,
+    pub remark: String,
+}
+
+impl From<&BinanceTransaction> for BinanceTransactionRecord {
+    fn from(transaction: &BinanceTransaction) -> Self {
+        Self {
+            user_id: transaction.user_id.to_string(),
+            time: transaction.transaction_time,
+            account: transaction.account.to_string(),
+            operation: BinanceOperation::from(transaction.operation.as_str()),
+            coin: transaction.coin.to_string(),
+            change: transaction.change,
+            remark: transaction.remark.to_string(),
+        }
+    }
+}
+
+#[derive(Insertable, Deserialize, PartialEq, Eq, Clone)]
+#[diesel(table_name = binance_transactions)]
+pub struct NewBinanceTransaction {
+    pub user_id: String,
+    pub transaction_time: DateTime<Utc>,
+    pub account: String,
+    pub operation: String,
+    pub coin: String,
+    pub change: Decimal,
+    pub remark: String,
+}
+
+/// A user defined column mapping, stored as its JSON definition.
+#[derive(Queryable, Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct StoredColumnMapping {
+    pub id: i32,
+    pub name: String,
+    pub definition: String,
+}
+
+impl TryFrom<&StoredColumnMapping> for ColumnMapping {
+    type Error = String;
+
+    fn try_from(stored: &StoredColumnMapping) -> Result<Self, Self::Error> {
+        ColumnMapping::from_definition(&stored.definition)
+    }
+}
+
+#[derive(Insertable, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[diesel(table_name = column_mappings)]
+pub struct NewStoredColumnMapping {
+    pub name: String,
+    pub definition: String,
+}
+
+impl From<&ColumnMapping> for NewStoredColumnMapping {
+    fn from(mapping: &ColumnMapping) -> Self {
+        Self {
+            name: mapping.name.to_string(),
+            definition: mapping.to_json(),
+        }
+    }
+}
+
+/// An upload whose transactions were stored, each stored transaction refers back to the batch that stored it.
+#[derive(Queryable, Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct ImportBatch {
+    pub id: i32,
+    pub source_format: String,
+    pub filename: Option<String>,
+    /// Sha256 of the uploaded file, in hex.
+    pub file_hash: String,
+    pub rows_inserted: i32,
+    /// Rows that were stored before, by this or another batch.
+    pub rows_duplicate: i32,
+    pub rows_failed: i32,
+    pub uploaded_at: DateTime<Utc>,
+    /// Rows that failed to parse, and why.
+    pub parse_warnings: Vec<String>,
+}
+
+#[derive(Insertable, Deserialize, PartialEq, Eq, Debug, Clone, Default)]
+#[diesel(table_name = import_batches)]
+pub struct NewImportBatch {
+    pub source_format: String,
+    pub filename: Option<String>,
+    pub file_hash: String,
+    pub rows_inserted: i32,
+    pub rows_duplicate: i32,
+    pub rows_failed: i32,
+    pub parse_warnings: Vec<String>,
+}
+
+/// What is learned about a batch once its file has been read, the format and name it was recorded with are kept.
+#[derive(AsChangeset, PartialEq, Eq, Debug, Clone, Default)]
+#[diesel(table_name = import_batches)]
+pub struct ImportBatchUpdate {
+    pub file_hash: String,
+    pub rows_inserted: i32,
+    pub rows_duplicate: i32,
+    pub rows_failed: i32,
+    pub parse_warnings: Vec<String>,
+}
+
+/// A batch along with a page of the transactions it stored.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct ImportBatchTransactions {
+    pub import_batch: ImportBatch,
+    pub coinbase_transactions: Vec<CoinbaseTransaction>,
+    pub kraken_transactions: Vec<KrakenTransaction>,
+    pub kraken_trades: Vec<KrakenTradeFill>,
+}
+
+/// A batch that was removed, along with how many of the transactions it stored were removed with it.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct RolledBackImportBatch {
+    pub import_batch: ImportBatch,
+    pub coinbase_transactions: usize,
+    pub kraken_transactions: usize,
+    pub kraken_trades: usize,
 }
 
 #[derive(Deserialize)]
@@ -100,6 +570,8 @@ pub struct DBConfigOptions {
     pub username: Option<String>,
     pub password: Option<String>,
     pub database_name: Option<String>,
+    pub max_connections: Option<u32>,
+    pub connection_timeout: Option<Duration>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -109,6 +581,10 @@ pub struct DBConfig {
     username: String,
     password: String,
     database_name: String,
+    /// Most connections a pool holds open at once.
+    max_connections: u32,
+    /// How long a request waits for a pooled connection before giving up.
+    connection_timeout: Duration,
 }
 
 impl Default for DBConfig {
@@ -119,6 +595,8 @@ impl Default for DBConfig {
             username: "super_user".to_string(),
             password: "password".to_string(),
             database_name: "crypto_data".to_string(),
+            max_connections: 10,
+            connection_timeout: Duration::from_secs(5),
         }
     }
 }
@@ -136,6 +614,12 @@ impl DBConfig {
                 database_name: config_options
                     .database_name
                     .unwrap_or(default_config.database_name),
+                max_connections: config_options
+                    .max_connections
+                    .unwrap_or(default_config.max_connections),
+                connection_timeout: config_options
+                    .connection_timeout
+                    .unwrap_or(default_config.connection_timeout),
             },
             None => default_config,
         }
@@ -172,6 +656,14 @@ impl DBConfig {
         &self.database_name
     }
 
+    pub fn get_max_connections(&self) -> u32 {
+        self.max_connections
+    }
+
+    pub fn get_connection_timeout(&self) -> Duration {
+        self.connection_timeout
+    }
+
     pub fn init_from_env() -> Self {
         let default = Self::default();
 
@@ -181,13 +673,21 @@ impl DBConfig {
             username: std::env::var("DB_USER").unwrap_or(default.username),
             password: std::env::var("DB_PASSWORD").unwrap_or(default.password),
             database_name: std::env::var("DB_NAME").unwrap_or(default.database_name),
+            max_connections: std::env::var("DB_MAX_CONNECTIONS")
+                .ok()
+                .and_then(|max| max.parse().ok())
+                .unwrap_or(default.max_connections),
+            connection_timeout: std::env::var("DB_CONNECTION_TIMEOUT_SECS")
+                .ok()
+                .and_then(|seconds| seconds.parse().ok())
+                .map_or(default.connection_timeout, Duration::from_secs),
         }
     }
 }
 
 #[cfg(test)]
 mod db_config_should {
-    use super::DBConfig;
+    use super::{DBConfig, DBConfigOptions};
 
     #[test]
     fn return_connection_string() {
@@ -203,6 +703,7 @@ mod db_config_should {
             password: password.clone(),
             username: username.clone(),
             database_name: database_name.clone(),
+            ..DBConfig::default()
         };
 
         let expected = format!("postgres://{username}:{password}@{host}:{port}/{database_name}");
@@ -211,4 +712,18 @@ mod db_config_should {
 
     #[test]
     fn have_correct_assignments_from_new() {}
+
+    #[test]
+    fn keep_default_pool_settings_that_are_not_given() {
+        let config = DBConfig::new(Some(DBConfigOptions {
+            max_connections: Some(2),
+            ..Default::default()
+        }));
+
+        assert_eq!(config.get_max_connections(), 2);
+        assert_eq!(
+            config.get_connection_timeout(),
+            DBConfig::default().get_connection_timeout()
+        );
+    }
 }
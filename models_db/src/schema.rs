@@ -1,5 +1,18 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    binance_transactions (id) {
+        id -> Int4,
+        user_id -> Text,
+        transaction_time -> Timestamptz,
+        account -> Text,
+        operation -> Text,
+        coin -> Text,
+        change -> Numeric,
+        remark -> Text,
+    }
+}
+
 diesel::table! {
     coinbase_transactions (id) {
         id -> Int4,
@@ -13,6 +26,9 @@ diesel::table! {
         total -> Nullable<Numeric>,
         fees -> Nullable<Numeric>,
         notes -> Text,
+        transaction_key -> Text,
+        coinbase_id -> Nullable<Text>,
+        import_batch_id -> Nullable<Int4>,
     }
 }
 
@@ -29,7 +45,61 @@ diesel::table! {
         amount -> Numeric,
         fee -> Numeric,
         balance -> Nullable<Numeric>,
+        import_batch_id -> Nullable<Int4>,
+    }
+}
+
+diesel::table! {
+    kraken_trades (id) {
+        id -> Int4,
+        txid -> Text,
+        ordertxid -> Text,
+        pair -> Text,
+        trade_time -> Timestamptz,
+        trade_type -> Text,
+        ordertype -> Text,
+        price -> Numeric,
+        cost -> Numeric,
+        fee -> Numeric,
+        vol -> Numeric,
+        margin -> Numeric,
+        misc -> Text,
+        ledgers -> Text,
+        import_batch_id -> Nullable<Int4>,
     }
 }
 
-diesel::allow_tables_to_appear_in_same_query!(coinbase_transactions, kraken_transactions,);
+diesel::table! {
+    column_mappings (id) {
+        id -> Int4,
+        name -> Text,
+        definition -> Text,
+    }
+}
+
+diesel::table! {
+    import_batches (id) {
+        id -> Int4,
+        source_format -> Text,
+        filename -> Nullable<Text>,
+        file_hash -> Text,
+        rows_inserted -> Int4,
+        rows_duplicate -> Int4,
+        rows_failed -> Int4,
+        uploaded_at -> Timestamptz,
+        parse_warnings -> Array<Text>,
+    }
+}
+
+diesel::joinable!(coinbase_transactions -> import_batches (import_batch_id));
+diesel::joinable!(kraken_trades -> import_batches (import_batch_id));
+diesel::joinable!(kraken_transactions -> import_batches (import_batch_id));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    binance_transactions,
+    coinbase_transactions,
+    column_mappings,
+    import_batches,
+    kraken_trades,
+    kraken_transactions,
+);
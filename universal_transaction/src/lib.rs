@@ -1,119 +1,1169 @@
-extern crate coinbase_transactions;
-extern crate kraken_ledgers;
-extern crate rust_decimal;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
 
-use coinbase_parser::transaction_parser::CoinbaseTransactionRecord;
-use kraken_ledgers::ledger_parser::KrakenLedgerRecord;
+use chrono::{DateTime, Utc};
+use kraken_parser::{KrakenParser, KrakenTrade, TradeSide, USD_ASSETS};
+use models::{
+    asset::{Asset, AssetVariant},
+    coinbase::{CoinbaseTransactionRecord, CoinbaseTransactionType, Conversion},
+    cost_basis::{Acquisition, CostBasisEvent, Disposal},
+    income::IncomeRecord,
+    kraken::{KrakenLedgerRecord, KrakenLedgerSubtype, KrakenLedgerType},
+    mapping::MappedTransaction,
+    ActiveAssetValues, CostBasisEvents, HistoricalPrice, InputTransaction, InputTransactions,
+    RecordsByAsset, StakingIncome, StakingRewards,
+};
+use models_db::{CoinbaseTransaction, KrakenTransaction};
+pub use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
-use self::rust_decimal::Decimal;
-
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Hash)]
+#[serde(rename_all = "camelCase")]
 pub enum TransactionSource {
     Coinbase,
     Kraken,
+    /// A csv read with a user defined column mapping.
+    Mapped,
+}
+
+impl fmt::Display for TransactionSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionSource::Coinbase => write!(f, "coinbase"),
+            TransactionSource::Kraken => write!(f, "kraken"),
+            TransactionSource::Mapped => write!(f, "mapped"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum TransactionKind {
+    Buy,
+    Sell,
+    /// One side of a crypto to crypto conversion made by the exchange.
+    Convert,
+    /// One side of an order placed on the exchange's order book.
+    Trade,
+    Staking,
+    /// Rewards that are not staking, e.g. learning rewards.
+    Reward,
+    Send,
+    Receive,
+    Spend,
+    Deposit,
+    Withdrawal,
+    /// Movement between accounts of the same exchange, e.g. into staking.
+    Transfer,
+    /// Profit, loss or fees of a margin position.
+    Margin,
+    Other,
+}
+
+/// The ids an exchange or the database gave to a transaction.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalIds {
+    pub transaction_id: Option<String>,
+    /// Id shared by every row belonging to the same action, e.g. both sides of a Kraken trade.
+    pub reference_id: Option<String>,
+    pub database_id: Option<i32>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct PriceTransaction {
+/// A single change to the balance of one asset, regardless of the exchange it came from.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UniversalTransaction {
     pub source: TransactionSource,
-    pub currency: String,
-    pub quantity_of_currency: Decimal,
-    pub date_of_transaction: String,
-    pub currency_price_at_date: Option<Decimal>,
-    pub comparison_currency: String,
+    pub account: Option<String>,
+    /// Canonical ticker of the asset.
+    pub asset: String,
+    /// Where the exchange held the asset, e.g. staked.
+    pub variant: AssetVariant,
+    /// Positive when the asset was received, negative when it left the account. Fees are not included.
+    pub quantity: Decimal,
+    pub fee_asset: Option<String>,
+    pub fee_amount: Decimal,
+    /// USD value of the quantity, when the exchange reported it or it could be taken from the other side of a trade.
+    pub fiat_value: Option<Decimal>,
+    pub timestamp: DateTime<Utc>,
+    pub kind: TransactionKind,
+    pub external_ids: ExternalIds,
+}
+
+impl UniversalTransaction {
+    /// The change to the balance of the asset once fees paid in the same asset are taken out.
+    pub fn net_quantity(&self) -> Decimal {
+        self.quantity - self.fee_in(&self.asset)
+    }
+
+    pub fn is_fiat(&self) -> bool {
+        USD_ASSETS.contains(&self.asset.as_str())
+    }
+
+    fn fee_in(&self, asset: &str) -> Decimal {
+        match &self.fee_asset {
+            Some(fee_asset) if fee_asset.eq(asset) => self.fee_amount,
+            _ => Decimal::ZERO,
+        }
+    }
+
+    fn usd_fee(&self) -> Decimal {
+        USD_ASSETS
+            .iter()
+            .map(|usd_asset| self.fee_in(usd_asset))
+            .sum()
+    }
+}
+
+impl InputTransaction for UniversalTransaction {
+    fn is_input_transaction(&self) -> bool {
+        self.quantity >= Decimal::ZERO
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum UniversalTransactionError {
+    /// The transaction type is not known, so it is not known which way the balance moved.
+    UnknownTransactionType(String),
+    /// Kraken repeats some ledger rows without a txid. Those rows are duplicates and carry no balance change.
+    MissingTxid(String),
 }
 
-impl From<CoinbaseTransactionRecord> for PriceTransaction {
-    /// Converts a CoinbaseTransactionRecord into a PriceTransaction.
+impl fmt::Display for UniversalTransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UniversalTransactionError::UnknownTransactionType(transaction_type) => {
+                write!(f, "Unknown transaction type: {transaction_type}")
+            }
+            UniversalTransactionError::MissingTxid(refid) => {
+                write!(f, "Ledger row {refid} has no txid")
+            }
+        }
+    }
+}
+
+impl TryFrom<&CoinbaseTransactionRecord> for UniversalTransaction {
+    type Error = UniversalTransactionError;
+
+    /// Converts a CoinbaseTransactionRecord into a UniversalTransaction. Only the sent side of a Convert is kept,
+    /// use [`Ledger::from_coinbase_records`] to get both sides.
     ///
     /// ```
-    ///
-    /// # use coinbase_parser::transaction_parser::CoinbaseTransactionRecord;
-    /// # use universal_transaction::PriceTransaction;
-    /// # use universal_transaction::TransactionSource;
+    /// # use chrono::{DateTime, Utc};
+    /// # use models::coinbase::{CoinbaseTransactionRecord, CoinbaseTransactionType};
     /// # use rust_decimal::Decimal;
+    /// # use universal_transaction::{TransactionKind, TransactionSource, UniversalTransaction};
     /// #
-    /// let c_transaction = CoinbaseTransactionRecord {
-    /// time_of_transaction: "2021-04-01T21:38:01Z".to_string(),
-    /// transaction_type: "Buy".to_string(),
-    /// asset: "BTC".to_string(),
-    /// // ... the other properties
-    /// # quantity_transacted: Decimal::new(00016458, 7),
-    /// # spot_price_currency: "USD".to_string(),
-    /// # spot_price_at_transaction: Some(Decimal::new(5894398, 2)),
-    /// # subtotal: Some(Decimal::new(9701, 2)),
-    /// # total: Some(Decimal::new(100, 0)),
-    /// # fees: Some(Decimal::new(299, 2)),
-    /// # notes: "Bought 0.0016458 BTC for $100.00 USD".to_string(),
-    /// };
-    /// let expected = PriceTransaction {
-    ///     source: TransactionSource::Coinbase,
-    ///     currency: "BTC".to_string(),
-    ///     quantity_of_currency: Decimal::new(00016458, 7),
-    ///     date_of_transaction: "2021-04-01T21:38:01Z".to_string(),
-    ///     currency_price_at_date: None,
-    ///     comparison_currency: "usd".to_string()
+    /// let record = CoinbaseTransactionRecord {
+    ///     id: None,
+    ///     time_of_transaction: "2021-04-01T21:38:01Z".parse::<DateTime<Utc>>().unwrap(),
+    ///     transaction_type: CoinbaseTransactionType::Sell,
+    ///     asset: "BTC".to_string(),
+    ///     // ... the other properties
+    /// #   quantity_transacted: Decimal::new(16458, 7),
+    /// #   spot_price_currency: "USD".to_string(),
+    /// #   spot_price_at_transaction: Some(Decimal::new(5894398, 2)),
+    /// #   subtotal: Some(Decimal::new(9701, 2)),
+    /// #   total: Some(Decimal::new(100, 0)),
+    /// #   fees: Some(Decimal::new(299, 2)),
+    /// #   notes: "Sold 0.0016458 BTC for $97.01 USD".to_string(),
     /// };
     ///
-    /// assert_eq!(PriceTransaction::from(c_transaction), expected);
+    /// let transaction = UniversalTransaction::try_from(&record).unwrap();
+    /// assert_eq!(transaction.source, TransactionSource::Coinbase);
+    /// assert_eq!(transaction.kind, TransactionKind::Sell);
+    /// assert_eq!(transaction.quantity, Decimal::new(-16458, 7));
+    /// assert_eq!(transaction.fiat_value, Some(Decimal::new(9701, 2)));
     /// ```
-    fn from(transaction: CoinbaseTransactionRecord) -> Self {
-        PriceTransaction {
+    fn try_from(record: &CoinbaseTransactionRecord) -> Result<Self, Self::Error> {
+        let (kind, sign) = match &record.transaction_type {
+            CoinbaseTransactionType::Buy | CoinbaseTransactionType::AdvancedTradeBuy => {
+                (TransactionKind::Buy, Decimal::ONE)
+            }
+            CoinbaseTransactionType::Sell | CoinbaseTransactionType::AdvancedTradeSell => {
+                (TransactionKind::Sell, Decimal::NEGATIVE_ONE)
+            }
+            CoinbaseTransactionType::Convert => (TransactionKind::Convert, Decimal::NEGATIVE_ONE),
+            CoinbaseTransactionType::RewardsIncome
+            | CoinbaseTransactionType::StakingIncome
+            | CoinbaseTransactionType::InflationReward => (TransactionKind::Staking, Decimal::ONE),
+            CoinbaseTransactionType::LearningReward => (TransactionKind::Reward, Decimal::ONE),
+            CoinbaseTransactionType::Send => (TransactionKind::Send, Decimal::NEGATIVE_ONE),
+            CoinbaseTransactionType::Receive | CoinbaseTransactionType::CardBuyBack => {
+                (TransactionKind::Receive, Decimal::ONE)
+            }
+            CoinbaseTransactionType::CardSpend => (TransactionKind::Spend, Decimal::NEGATIVE_ONE),
+            CoinbaseTransactionType::Deposit => (TransactionKind::Deposit, Decimal::ONE),
+            CoinbaseTransactionType::Withdrawal => {
+                (TransactionKind::Withdrawal, Decimal::NEGATIVE_ONE)
+            }
+            CoinbaseTransactionType::Unknown(transaction_type) => {
+                return Err(UniversalTransactionError::UnknownTransactionType(
+                    transaction_type.to_string(),
+                ))
+            }
+        };
+
+        let asset = Asset::new(&record.asset);
+        let fiat_value = record
+            .subtotal
+            .or_else(|| {
+                record
+                    .spot_price_at_transaction
+                    .map(|price| price * record.quantity_transacted)
+            })
+            .filter(|_| USD_ASSETS.contains(&record.spot_price_currency.as_str()));
+
+        Ok(Self {
             source: TransactionSource::Coinbase,
-            currency: transaction.asset.to_string(),
-            quantity_of_currency: transaction.quantity_transacted,
-            date_of_transaction: transaction.time_of_transaction,
-            currency_price_at_date: None,
-            comparison_currency: String::from("usd"),
-        }
+            account: None,
+            asset: asset.ticker,
+            variant: asset.variant,
+            quantity: record.quantity_transacted * sign,
+            fee_asset: record.fees.map(|_| record.spot_price_currency.to_string()),
+            fee_amount: record.fees.unwrap_or_default(),
+            fiat_value,
+            timestamp: record.time_of_transaction,
+            kind,
+            external_ids: ExternalIds {
+                transaction_id: record.id.clone().filter(|id| !id.trim().is_empty()),
+                ..ExternalIds::default()
+            },
+        })
+    }
+}
+
+impl TryFrom<&CoinbaseTransaction> for UniversalTransaction {
+    type Error = UniversalTransactionError;
+
+    fn try_from(transaction: &CoinbaseTransaction) -> Result<Self, Self::Error> {
+        let mut universal = Self::try_from(&CoinbaseTransactionRecord::from(transaction))?;
+        universal.external_ids.database_id = Some(transaction.id);
+
+        Ok(universal)
     }
 }
 
-impl From<KrakenLedgerRecord> for PriceTransaction {
-    /// Converts a KrakenLedgerRecord into a PriceTransaction.
+impl TryFrom<&KrakenLedgerRecord> for UniversalTransaction {
+    type Error = UniversalTransactionError;
+
+    /// Converts a KrakenLedgerRecord into a UniversalTransaction. Kraken amounts are already signed and fees
+    /// are charged in the asset of the row.
     ///
     /// ```
-    /// # extern crate rust_decimal;
-    /// # extern crate kraken_ledgers;
-    /// # extern crate universal_transaction;
-    ///
-    /// # use rust_decimal::Decimal;
-    /// # use kraken_ledgers::ledger_parser::KrakenLedgerRecord;
-    /// # use universal_transaction::PriceTransaction;
-    /// # use universal_transaction::TransactionSource;
-    /// let k_transaction = KrakenLedgerRecord {
+    /// # use chrono::{TimeZone, Utc};
+    /// # use models::kraken::{KrakenLedgerRecord, KrakenLedgerType, DATE_FORMAT as KRAKEN_DATE_FORMAT};
+    /// # use rust_decimal::prelude::{Decimal, Zero};
+    /// # use universal_transaction::{TransactionKind, TransactionSource, UniversalTransaction};
+    /// #
+    /// let record = KrakenLedgerRecord {
     ///     txid: Some("L7RLII-4423D-JTUU7J".to_string()),
     ///     refid: "L7RLII-4423D-JTUU9E".to_string(),
-    ///     time: "2021-09-29 15:18:30".to_string(),
-    ///     record_type: "deposit".to_string(),
+    ///     time: Utc
+    ///         .datetime_from_str("2021-09-29 15:18:30", KRAKEN_DATE_FORMAT)
+    ///         .unwrap(),
+    ///     record_type: KrakenLedgerType::Withdrawal,
     ///     // ... other properties
-    ///     # subtype: None,
-    ///     # a_class: "currency".to_string(),
-    ///     # asset: "ADA".to_string(),
-    ///     # amount: Decimal::new(2323, 2),
-    ///     # fee: Decimal::new(0, 0),
-    ///     # balance: Some(Decimal::new(2323, 2)),
+    /// #   subtype: None,
+    /// #   a_class: "currency".to_string(),
+    /// #   asset: "ADA".to_string(),
+    /// #   amount: Decimal::new(-2323, 2),
+    /// #   fee: Decimal::new(1, 0),
+    /// #   balance: Some(Decimal::zero()),
     /// };
     ///
-    /// let expected = PriceTransaction {
-    ///     source: TransactionSource::Kraken,
-    ///     currency: String::from("ADA"),
-    ///     quantity_of_currency: Decimal::new(2323, 2),
-    ///     date_of_transaction: "2021-09-29 15:18:30".to_string(),
-    ///     currency_price_at_date: None,
-    ///     comparison_currency: String::from("usd"),
-    /// };
+    /// let transaction = UniversalTransaction::try_from(&record).unwrap();
+    /// assert_eq!(transaction.source, TransactionSource::Kraken);
+    /// assert_eq!(transaction.kind, TransactionKind::Withdrawal);
+    /// assert_eq!(transaction.net_quantity(), Decimal::new(-2423, 2));
     ///
-    /// assert_eq!(PriceTransaction::from(k_transaction), expected);
+    /// let duplicate = KrakenLedgerRecord { txid: None, ..record };
+    /// assert!(UniversalTransaction::try_from(&duplicate).is_err());
     /// ```
-    fn from(record: KrakenLedgerRecord) -> Self {
-        PriceTransaction {
+    fn try_from(record: &KrakenLedgerRecord) -> Result<Self, Self::Error> {
+        let Some(txid) = &record.txid else {
+            return Err(UniversalTransactionError::MissingTxid(
+                record.refid.to_string(),
+            ));
+        };
+
+        let kind = match (&record.record_type, &record.subtype) {
+            (KrakenLedgerType::Trade | KrakenLedgerType::Spend | KrakenLedgerType::Receive, _) => {
+                TransactionKind::Trade
+            }
+            (_, Some(subtype)) if subtype.is_staking_transfer() => TransactionKind::Transfer,
+            (KrakenLedgerType::Staking, _)
+            | (KrakenLedgerType::Earn, Some(KrakenLedgerSubtype::Reward)) => {
+                TransactionKind::Staking
+            }
+            (KrakenLedgerType::Deposit, _) => TransactionKind::Deposit,
+            (KrakenLedgerType::Withdrawal, _) => TransactionKind::Withdrawal,
+            (KrakenLedgerType::Transfer | KrakenLedgerType::Earn, _) => TransactionKind::Transfer,
+            (record_type, _) if record_type.is_margin() => TransactionKind::Margin,
+            _ => TransactionKind::Other,
+        };
+
+        let asset = Asset::new(&record.asset);
+
+        Ok(Self {
             source: TransactionSource::Kraken,
-            currency: record.asset,
-            quantity_of_currency: record.amount,
-            date_of_transaction: record.time,
-            currency_price_at_date: None,
-            comparison_currency: String::from("usd"),
+            account: None,
+            fee_asset: Some(asset.ticker.to_string()),
+            asset: asset.ticker,
+            variant: asset.variant,
+            quantity: record.amount,
+            fee_amount: record.fee,
+            fiat_value: Some(record.amount.abs())
+                .filter(|_| USD_ASSETS.contains(&record.asset.as_str())),
+            timestamp: record.time,
+            kind,
+            external_ids: ExternalIds {
+                transaction_id: Some(txid.to_string()),
+                reference_id: Some(record.refid.to_string()),
+                database_id: None,
+            },
+        })
+    }
+}
+
+impl TryFrom<&KrakenTransaction> for UniversalTransaction {
+    type Error = UniversalTransactionError;
+
+    fn try_from(transaction: &KrakenTransaction) -> Result<Self, Self::Error> {
+        let mut universal = Self::try_from(&KrakenLedgerRecord::from(transaction))?;
+        universal.external_ids.database_id = Some(transaction.id);
+
+        Ok(universal)
+    }
+}
+
+impl From<&MappedTransaction> for UniversalTransaction {
+    /// Mappings do not say what a row was for, rows with a USD value are taken to be buys and sells and the rest to
+    /// be deposits and withdrawals. The mapping name is kept as the account.
+    ///
+    /// ```
+    /// # use chrono::{TimeZone, Utc};
+    /// # use models::mapping::MappedTransaction;
+    /// # use rust_decimal::Decimal;
+    /// # use universal_transaction::{TransactionKind, TransactionSource, UniversalTransaction};
+    /// #
+    /// let mapped = MappedTransaction {
+    ///     mapping: "wallet".to_string(),
+    ///     time: Utc.with_ymd_and_hms(2021, 3, 1, 15, 20, 30).unwrap(),
+    ///     asset: "ETH".to_string(),
+    ///     quantity: Decimal::new(-5, 1),
+    ///     fee: Decimal::new(1, 3),
+    ///     fee_asset: "ETH".to_string(),
+    ///     fiat_value: None,
+    /// };
+    ///
+    /// let transaction = UniversalTransaction::from(&mapped);
+    /// assert_eq!(transaction.source, TransactionSource::Mapped);
+    /// assert_eq!(transaction.kind, TransactionKind::Withdrawal);
+    /// assert_eq!(transaction.net_quantity(), Decimal::new(-501, 3));
+    /// ```
+    fn from(transaction: &MappedTransaction) -> Self {
+        let kind = match (
+            transaction.fiat_value.is_some(),
+            transaction.is_input_transaction(),
+        ) {
+            (true, true) => TransactionKind::Buy,
+            (true, false) => TransactionKind::Sell,
+            (false, true) => TransactionKind::Deposit,
+            (false, false) => TransactionKind::Withdrawal,
+        };
+        let asset = Asset::new(&transaction.asset);
+
+        Self {
+            source: TransactionSource::Mapped,
+            account: Some(transaction.mapping.to_string()),
+            asset: asset.ticker,
+            variant: asset.variant,
+            quantity: transaction.quantity,
+            fee_asset: Some(Asset::new(&transaction.fee_asset).ticker),
+            fee_amount: transaction.fee,
+            fiat_value: transaction.fiat_value,
+            timestamp: transaction.time,
+            kind,
+            external_ids: ExternalIds::default(),
+        }
+    }
+}
+
+/// Transactions from every source, ordered by time.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct Ledger {
+    transactions: Vec<UniversalTransaction>,
+}
+
+impl Ledger {
+    pub fn new(mut transactions: Vec<UniversalTransaction>) -> Self {
+        transactions.sort_by_key(|transaction| transaction.timestamp);
+
+        Self { transactions }
+    }
+
+    /// Builds a ledger from a Coinbase export. Converts become a sent and a received transaction and rows with an
+    /// unknown transaction type are left out.
+    pub fn from_coinbase_records(records: &[CoinbaseTransactionRecord]) -> Self {
+        Self::new(with_content_ids(
+            records
+                .iter()
+                .map(|record| coinbase_transactions(record, None)),
+        ))
+    }
+
+    pub fn from_coinbase_transactions(transactions: &[CoinbaseTransaction]) -> Self {
+        Self::new(
+            transactions
+                .iter()
+                .flat_map(|transaction| {
+                    coinbase_transactions(
+                        &CoinbaseTransactionRecord::from(transaction),
+                        Some(transaction.id),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    /// Builds a ledger from a Kraken ledger export. Trades against USD are valued from their USD side and the
+    /// duplicate rows without a txid are left out.
+    pub fn from_kraken_records(records: &[KrakenLedgerRecord]) -> Self {
+        let kraken_parser = KrakenParser::new(records.to_vec());
+
+        Self::new(value_kraken_trades(
+            classify_kraken_staking(
+                records
+                    .iter()
+                    .filter_map(|record| UniversalTransaction::try_from(record).ok())
+                    .collect(),
+                &kraken_parser,
+            ),
+            kraken_parser.trades().trades,
+        ))
+    }
+
+    pub fn from_kraken_transactions(transactions: &[KrakenTransaction]) -> Self {
+        let kraken_parser =
+            KrakenParser::new(transactions.iter().map(KrakenLedgerRecord::from).collect());
+
+        Self::new(value_kraken_trades(
+            classify_kraken_staking(
+                transactions
+                    .iter()
+                    .filter_map(|transaction| UniversalTransaction::try_from(transaction).ok())
+                    .collect(),
+                &kraken_parser,
+            ),
+            kraken_parser.trades().trades,
+        ))
+    }
+
+    /// Builds a ledger from a csv read with a user defined column mapping.
+    pub fn from_mapped_transactions(transactions: &[MappedTransaction]) -> Self {
+        Self::new(with_content_ids(
+            transactions
+                .iter()
+                .map(|transaction| vec![UniversalTransaction::from(transaction)]),
+        ))
+    }
+
+    /// Combines two ledgers keeping the time order.
+    pub fn merge(self, other: Ledger) -> Self {
+        Self::new(
+            self.transactions
+                .into_iter()
+                .chain(other.transactions)
+                .collect(),
+        )
+    }
+
+    pub fn transactions(&self) -> &[UniversalTransaction] {
+        &self.transactions
+    }
+
+    /// Values the rewards that came without a USD value, such as every Kraken reward, at the given prices so they
+    /// become lots. Rewards that still can not be priced are left without a value.
+    pub fn with_reward_prices(mut self, prices: &dyn HistoricalPrice) -> Self {
+        self.transactions
+            .iter_mut()
+            .filter(|transaction| is_reward(transaction) && transaction.fiat_value.is_none())
+            .for_each(|transaction| {
+                transaction.fiat_value = prices
+                    .usd_price_at(&transaction.asset, &transaction.timestamp)
+                    .map(|price| price * transaction.net_quantity());
+            });
+
+        self
+    }
+
+    fn event_id(transaction: &UniversalTransaction) -> String {
+        let id = match &transaction.external_ids {
+            ExternalIds {
+                transaction_id: Some(transaction_id),
+                ..
+            } => transaction_id.to_string(),
+            ExternalIds {
+                database_id: Some(database_id),
+                ..
+            } => database_id.to_string(),
+            _ => content_id(transaction),
+        };
+
+        if transaction.kind == TransactionKind::Convert && transaction.is_input_transaction() {
+            format!("{}-{id}-to", transaction.source)
+        } else {
+            format!("{}-{id}", transaction.source)
         }
     }
 }
+
+impl StakingRewards for Ledger {
+    fn staking_rewards(&self) -> HashMap<String, Decimal> {
+        self.transactions
+            .iter()
+            .filter(|transaction| transaction.kind == TransactionKind::Staking)
+            .fold(HashMap::new(), |mut reward_map, transaction| {
+                *reward_map
+                    .entry(transaction.asset.to_string())
+                    .or_insert(Decimal::ZERO) += transaction.quantity;
+
+                reward_map
+            })
+    }
+}
+
+impl ActiveAssetValues for Ledger {
+    /// The balance of every asset across all sources, with fees paid in the asset taken out.
+    /// ```
+    /// # use chrono::{DateTime, Utc};
+    /// # use models::{
+    /// #     coinbase::{CoinbaseTransactionRecord, CoinbaseTransactionType},
+    /// #     ActiveAssetValues,
+    /// # };
+    /// # use rust_decimal::Decimal;
+    /// # use universal_transaction::Ledger;
+    /// #
+    /// let convert = CoinbaseTransactionRecord {
+    ///     id: None,
+    ///     time_of_transaction: "2021-04-01T21:38:01Z".parse::<DateTime<Utc>>().unwrap(),
+    ///     transaction_type: CoinbaseTransactionType::Convert,
+    ///     asset: "BTC".to_string(),
+    ///     quantity_transacted: Decimal::new(1, 0),
+    ///     spot_price_currency: "USD".to_string(),
+    ///     spot_price_at_transaction: Some(Decimal::new(50000, 0)),
+    ///     subtotal: Some(Decimal::new(50000, 0)),
+    ///     total: Some(Decimal::new(50000, 0)),
+    ///     fees: Some(Decimal::new(100, 0)),
+    ///     notes: "Converted 1 BTC to 2,000 DOT".to_string(),
+    /// };
+    ///
+    /// let ledger = Ledger::from_coinbase_records(&[convert]);
+    /// let active_assets = ledger.active_assets();
+    /// assert_eq!(active_assets.get("BTC"), Some(&Decimal::new(-1, 0)));
+    /// assert_eq!(active_assets.get("DOT"), Some(&Decimal::new(2000, 0)));
+    /// ```
+    fn active_assets(&self) -> HashMap<String, Decimal> {
+        self.transactions
+            .iter()
+            .fold(HashMap::new(), |mut asset_map, transaction| {
+                *asset_map
+                    .entry(transaction.asset.to_string())
+                    .or_insert(Decimal::ZERO) += transaction.net_quantity();
+
+                asset_map
+            })
+    }
+}
+
+impl RecordsByAsset<UniversalTransaction> for Ledger {
+    fn by_asset(&self) -> HashMap<String, Vec<&UniversalTransaction>> {
+        self.transactions
+            .iter()
+            .fold(HashMap::new(), |mut asset_map, transaction| {
+                asset_map
+                    .entry(transaction.asset.to_string())
+                    .or_insert_with(Vec::new)
+                    .push(transaction);
+
+                asset_map
+            })
+    }
+}
+
+impl InputTransactions<UniversalTransaction> for Ledger {
+    fn input_transactions(&self) -> Vec<&UniversalTransaction> {
+        self.transactions
+            .iter()
+            .filter(|transaction| transaction.is_input_transaction())
+            .collect()
+    }
+}
+
+impl CostBasisEvents for Ledger {
+    /// Creates acquisitions and disposals from every transaction with a USD value. Transfers between wallets and
+    /// trades that could not be valued are skipped. Rewards without a USD value make no lot, callers price them first
+    /// with [`Ledger::with_reward_prices`].
+    fn cost_basis_events(&self) -> Vec<CostBasisEvent> {
+        self.transactions
+            .iter()
+            .filter(|transaction| !transaction.is_fiat())
+            .filter_map(|transaction| {
+                let fiat_value = transaction.fiat_value?;
+                let id = Self::event_id(transaction);
+                let fees = transaction.usd_fee();
+
+                match transaction.kind {
+                    TransactionKind::Buy
+                    | TransactionKind::Staking
+                    | TransactionKind::Reward
+                    | TransactionKind::Convert
+                    | TransactionKind::Trade
+                        if transaction.is_input_transaction() =>
+                    {
+                        Some(CostBasisEvent::Acquisition(Acquisition {
+                            id,
+                            asset: transaction.asset.to_string(),
+                            quantity: transaction.net_quantity(),
+                            cost: fiat_value + fees,
+                            fees,
+                            time: transaction.timestamp,
+                        }))
+                    }
+                    TransactionKind::Sell
+                    | TransactionKind::Spend
+                    | TransactionKind::Convert
+                    | TransactionKind::Trade => Some(CostBasisEvent::Disposal(Disposal {
+                        id,
+                        asset: transaction.asset.to_string(),
+                        quantity: transaction.net_quantity().abs(),
+                        proceeds: fiat_value,
+                        fees,
+                        time: transaction.timestamp,
+                    })),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+}
+
+impl StakingIncome for Ledger {
+    /// Rewards are valued with the USD value the exchange reported, otherwise with the given prices.
+    fn staking_income(&self, prices: &dyn HistoricalPrice) -> Vec<IncomeRecord> {
+        self.transactions
+            .iter()
+            .filter(|transaction| is_reward(transaction))
+            .map(|transaction| {
+                let quantity = transaction.net_quantity();
+                let usd_price = transaction
+                    .fiat_value
+                    .filter(|_| !quantity.is_zero())
+                    .map(|fiat_value| fiat_value / quantity)
+                    .or_else(|| prices.usd_price_at(&transaction.asset, &transaction.timestamp));
+
+                IncomeRecord {
+                    id: Self::event_id(transaction),
+                    asset: transaction.asset.to_string(),
+                    quantity,
+                    received_at: transaction.timestamp,
+                    usd_price,
+                    usd_value: transaction
+                        .fiat_value
+                        .or_else(|| usd_price.map(|price| price * quantity)),
+                }
+            })
+            .collect()
+    }
+}
+
+fn is_reward(transaction: &UniversalTransaction) -> bool {
+    matches!(
+        transaction.kind,
+        TransactionKind::Staking | TransactionKind::Reward
+    )
+}
+
+/// An id made from what the transaction holds, for rows their source gave no id. It does not depend on the other
+/// rows of the ledger, so it stays the same when rows or sources are added.
+fn content_id(transaction: &UniversalTransaction) -> String {
+    let content = format!(
+        "{}|{}|{}|{:?}|{}|{}|{}",
+        transaction.source,
+        transaction.account.as_deref().unwrap_or_default(),
+        transaction.timestamp.format("%Y-%m-%dT%H:%M:%SZ"),
+        transaction.kind,
+        transaction.asset,
+        transaction.quantity.normalize(),
+        transaction.fee_amount.normalize()
+    );
+
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+/// Gives the transactions of each source row without an id one made from the row's first transaction. Identical rows
+/// are told apart by how many came before them in the same source.
+fn with_content_ids(
+    rows: impl Iterator<Item = Vec<UniversalTransaction>>,
+) -> Vec<UniversalTransaction> {
+    let mut occurrences: HashMap<String, usize> = HashMap::new();
+
+    rows.flat_map(|mut transactions| {
+        let Some(first) = transactions.first() else {
+            return transactions;
+        };
+        if first.external_ids.transaction_id.is_some() || first.external_ids.database_id.is_some() {
+            return transactions;
+        }
+
+        let id = content_id(first);
+        let occurrence = occurrences.entry(id.to_string()).or_default();
+        let id = match *occurrence {
+            0 => id,
+            occurrence => format!("{id}-{occurrence}"),
+        };
+        *occurrence += 1;
+
+        transactions
+            .iter_mut()
+            .for_each(|transaction| transaction.external_ids.transaction_id = Some(id.to_string()));
+        transactions
+    })
+    .collect()
+}
+
+fn coinbase_transactions(
+    record: &CoinbaseTransactionRecord,
+    database_id: Option<i32>,
+) -> Vec<UniversalTransaction> {
+    let Ok(mut sent) = UniversalTransaction::try_from(record) else {
+        return Vec::new();
+    };
+    sent.external_ids.database_id = database_id;
+
+    if sent.kind != TransactionKind::Convert {
+        return vec![sent];
+    }
+
+    match Conversion::try_from(record) {
+        Ok(conversion) => {
+            let asset = Asset::new(&conversion.to_asset);
+            let received = UniversalTransaction {
+                asset: asset.ticker,
+                variant: asset.variant,
+                quantity: conversion.to_quantity,
+                fee_asset: None,
+                fee_amount: Decimal::ZERO,
+                fiat_value: sent.fiat_value.map(|value| value - sent.fee_amount),
+                ..sent.clone()
+            };
+
+            vec![sent, received]
+        }
+        Err(_) => vec![sent],
+    }
+}
+
+/// A single row can not tell a staking reward from the staked side of a transfer, the rest of the ledger can.
+fn classify_kraken_staking(
+    mut transactions: Vec<UniversalTransaction>,
+    kraken_parser: &KrakenParser<KrakenLedgerRecord>,
+) -> Vec<UniversalTransaction> {
+    let rewards: HashSet<&str> = kraken_parser
+        .staking_reward_records()
+        .into_iter()
+        .filter_map(|record| record.txid.as_deref())
+        .collect();
+
+    transactions
+        .iter_mut()
+        .filter(|transaction| transaction.kind == TransactionKind::Staking)
+        .filter(|transaction| {
+            !transaction
+                .external_ids
+                .transaction_id
+                .as_deref()
+                .is_some_and(|txid| rewards.contains(txid))
+        })
+        .for_each(|transaction| transaction.kind = TransactionKind::Transfer);
+
+    transactions
+}
+
+/// Gives the crypto side of a Kraken trade the USD value of its other side, split over partial fills by quantity.
+fn value_kraken_trades(
+    mut transactions: Vec<UniversalTransaction>,
+    trades: Vec<KrakenTrade>,
+) -> Vec<UniversalTransaction> {
+    let usd_trades: HashMap<&str, &KrakenTrade> = trades
+        .iter()
+        .filter(|trade| USD_ASSETS.contains(&trade.quote.as_str()))
+        .map(|trade| (trade.refid.as_str(), trade))
+        .collect();
+
+    for transaction in transactions
+        .iter_mut()
+        .filter(|transaction| transaction.kind == TransactionKind::Trade)
+    {
+        let Some(trade) = transaction
+            .external_ids
+            .reference_id
+            .as_deref()
+            .and_then(|reference_id| usd_trades.get(reference_id))
+            .filter(|trade| trade.base.eq(&transaction.asset))
+        else {
+            continue;
+        };
+
+        let trade_value = match trade.side {
+            TradeSide::Buy => trade.quote_quantity + trade.quote_fee,
+            TradeSide::Sell => trade.quote_quantity - trade.quote_fee,
+        };
+        transaction.fiat_value =
+            Some(trade_value * transaction.quantity.abs() / trade.base_quantity);
+    }
+
+    transactions
+}
+
+#[cfg(test)]
+mod ledger_should {
+    use chrono::{DateTime, TimeZone, Utc};
+    use models::{
+        coinbase::{CoinbaseTransactionRecord, CoinbaseTransactionType},
+        cost_basis::CostBasisEvent,
+        kraken::{KrakenLedgerRecord, KrakenLedgerType, DATE_FORMAT as KRAKEN_DATE_FORMAT},
+        ActiveAssetValues, CostBasisEvents, HistoricalPrice, StakingRewards,
+    };
+    use rust_decimal::Decimal;
+
+    use crate::{Ledger, TransactionKind, TransactionSource};
+
+    fn coinbase_record(
+        time: &str,
+        transaction_type: &str,
+        quantity: Decimal,
+    ) -> CoinbaseTransactionRecord {
+        CoinbaseTransactionRecord {
+            id: None,
+            time_of_transaction: time.parse::<DateTime<Utc>>().unwrap(),
+            transaction_type: CoinbaseTransactionType::from(transaction_type),
+            asset: "DOT".to_string(),
+            quantity_transacted: quantity,
+            spot_price_currency: "USD".to_string(),
+            spot_price_at_transaction: Some(Decimal::new(10, 0)),
+            subtotal: Some(quantity * Decimal::new(10, 0)),
+            total: Some(quantity * Decimal::new(10, 0) + Decimal::ONE),
+            fees: Some(Decimal::ONE),
+            notes: String::new(),
+        }
+    }
+
+    fn kraken_record(
+        txid: Option<&str>,
+        refid: &str,
+        time: &str,
+        record_type: &str,
+        asset: &str,
+        amount: Decimal,
+        fee: Decimal,
+    ) -> KrakenLedgerRecord {
+        KrakenLedgerRecord {
+            txid: txid.map(str::to_string),
+            refid: refid.to_string(),
+            time: Utc.datetime_from_str(time, KRAKEN_DATE_FORMAT).unwrap(),
+            record_type: KrakenLedgerType::from(record_type),
+            subtype: None,
+            a_class: "currency".to_string(),
+            asset: asset.to_string(),
+            amount,
+            fee,
+            balance: None,
+        }
+    }
+
+    #[test]
+    fn order_merged_sources_by_time() {
+        let coinbase = Ledger::from_coinbase_records(&[
+            coinbase_record("2021-03-01T00:00:00Z", "Buy", Decimal::new(5, 0)),
+            coinbase_record("2021-01-01T00:00:00Z", "Buy", Decimal::new(5, 0)),
+        ]);
+        let kraken = Ledger::from_kraken_records(&[kraken_record(
+            Some("L1"),
+            "R1",
+            "2021-02-01 00:00:00",
+            "deposit",
+            "DOT",
+            Decimal::new(3, 0),
+            Decimal::ZERO,
+        )]);
+
+        let ledger = coinbase.merge(kraken);
+        let sources: Vec<TransactionSource> = ledger
+            .transactions()
+            .iter()
+            .map(|transaction| transaction.source)
+            .collect();
+
+        assert_eq!(
+            sources,
+            [
+                TransactionSource::Coinbase,
+                TransactionSource::Kraken,
+                TransactionSource::Coinbase
+            ]
+        );
+        assert_eq!(
+            ledger.active_assets().get("DOT"),
+            Some(&Decimal::new(13, 0))
+        );
+    }
+
+    #[test]
+    fn skip_unknown_coinbase_types_and_kraken_rows_without_txid() {
+        let coinbase = Ledger::from_coinbase_records(&[coinbase_record(
+            "2021-01-01T00:00:00Z",
+            "Something New",
+            Decimal::ONE,
+        )]);
+        let kraken = Ledger::from_kraken_records(&[kraken_record(
+            None,
+            "R1",
+            "2021-02-01 00:00:00",
+            "staking",
+            "DOT",
+            Decimal::ONE,
+            Decimal::ZERO,
+        )]);
+
+        assert!(coinbase.merge(kraken).transactions().is_empty());
+    }
+
+    #[test]
+    fn count_staking_rewards_from_every_source() {
+        let coinbase = Ledger::from_coinbase_records(&[coinbase_record(
+            "2021-01-01T00:00:00Z",
+            "Rewards Income",
+            Decimal::new(2, 0),
+        )]);
+        let kraken = Ledger::from_kraken_records(&[kraken_record(
+            Some("L1"),
+            "R1",
+            "2021-02-01 00:00:00",
+            "staking",
+            "DOT",
+            Decimal::new(3, 0),
+            Decimal::ZERO,
+        )]);
+
+        let rewards = coinbase.merge(kraken).staking_rewards();
+        assert_eq!(rewards.get("DOT"), Some(&Decimal::new(5, 0)));
+    }
+
+    #[test]
+    fn make_lots_of_kraken_rewards_once_they_are_priced() {
+        struct FixedPrice;
+        impl HistoricalPrice for FixedPrice {
+            fn usd_price_at(&self, _: &str, _: &DateTime<Utc>) -> Option<Decimal> {
+                Some(Decimal::new(5, 0))
+            }
+        }
+
+        let ledger = Ledger::from_kraken_records(&[kraken_record(
+            Some("L1"),
+            "R1",
+            "2021-02-01 00:00:00",
+            "staking",
+            "DOT",
+            Decimal::new(3, 0),
+            Decimal::ZERO,
+        )]);
+        assert!(ledger.cost_basis_events().is_empty());
+
+        match ledger
+            .with_reward_prices(&FixedPrice)
+            .cost_basis_events()
+            .as_slice()
+        {
+            [CostBasisEvent::Acquisition(acquisition)] => {
+                assert_eq!(acquisition.id, "kraken-L1");
+                assert_eq!(acquisition.cost, Decimal::new(15, 0));
+            }
+            events => panic!("Unexpected events {events:?}"),
+        }
+    }
+
+    #[test]
+    fn value_kraken_trades_from_the_usd_side() {
+        let ledger = Ledger::from_kraken_records(&[
+            kraken_record(
+                Some("L1"),
+                "T1",
+                "2021-02-01 00:00:00",
+                "trade",
+                "ZUSD",
+                Decimal::new(-100, 0),
+                Decimal::ONE,
+            ),
+            kraken_record(
+                Some("L2"),
+                "T1",
+                "2021-02-01 00:00:00",
+                "trade",
+                "DOT",
+                Decimal::new(10, 0),
+                Decimal::ZERO,
+            ),
+        ]);
+
+        let events = ledger.cost_basis_events();
+        assert_eq!(events.len(), 1);
+        match events.first() {
+            Some(CostBasisEvent::Acquisition(acquisition)) => {
+                assert_eq!(acquisition.id, "kraken-L2");
+                assert_eq!(acquisition.quantity, Decimal::new(10, 0));
+                assert_eq!(acquisition.cost, Decimal::new(101, 0));
+            }
+            _ => panic!("Trade was not seen as an acquisition"),
+        }
+    }
+
+    #[test]
+    fn treat_the_staked_side_of_a_transfer_as_a_transfer() {
+        let ledger = Ledger::from_kraken_records(&[
+            kraken_record(
+                Some("L1"),
+                "R1",
+                "2021-02-01 00:00:00",
+                "withdrawal",
+                "DOT",
+                Decimal::new(-10, 0),
+                Decimal::ZERO,
+            ),
+            kraken_record(
+                Some("L2"),
+                "R1",
+                "2021-02-01 00:00:00",
+                "staking",
+                "DOT.S",
+                Decimal::new(10, 0),
+                Decimal::ZERO,
+            ),
+            kraken_record(
+                Some("L3"),
+                "R2",
+                "2021-02-08 00:00:00",
+                "staking",
+                "DOT.S",
+                Decimal::new(1, 1),
+                Decimal::ZERO,
+            ),
+            kraken_record(
+                Some("L4"),
+                "R3",
+                "2021-02-09 00:00:00",
+                "rollover",
+                "ZUSD",
+                Decimal::ZERO,
+                Decimal::new(2, 2),
+            ),
+        ]);
+
+        let kinds: Vec<TransactionKind> = ledger
+            .transactions()
+            .iter()
+            .map(|transaction| transaction.kind)
+            .collect();
+
+        assert_eq!(
+            kinds,
+            vec![
+                TransactionKind::Withdrawal,
+                TransactionKind::Transfer,
+                TransactionKind::Staking,
+                TransactionKind::Margin
+            ]
+        );
+        assert_eq!(
+            *ledger.staking_rewards().get("DOT").unwrap(),
+            Decimal::new(1, 1)
+        );
+    }
+
+    #[test]
+    fn create_both_sides_of_a_coinbase_convert() {
+        let convert = CoinbaseTransactionRecord {
+            id: None,
+            notes: "Converted 5 DOT to 0.001 BTC".to_string(),
+            ..coinbase_record("2021-01-01T00:00:00Z", "Convert", Decimal::new(5, 0))
+        };
+
+        let events = Ledger::from_coinbase_records(&[convert]).cost_basis_events();
+        assert_eq!(events.len(), 2);
+        match events.as_slice() {
+            [CostBasisEvent::Disposal(disposal), CostBasisEvent::Acquisition(acquisition)] => {
+                assert!(disposal.id.starts_with("coinbase-"));
+                assert_eq!(disposal.proceeds, Decimal::new(50, 0));
+                assert_eq!(acquisition.id, format!("{}-to", disposal.id));
+                assert_eq!(acquisition.asset, "BTC");
+                assert_eq!(acquisition.cost, Decimal::new(49, 0));
+            }
+            _ => panic!("Convert was not split into a disposal and an acquisition"),
+        }
+    }
+
+    #[test]
+    fn keep_event_ids_when_ledgers_are_merged() {
+        let coinbase = Ledger::from_coinbase_records(&[
+            CoinbaseTransactionRecord {
+                id: Some("6f1c2a".to_string()),
+                ..coinbase_record("2021-01-02T00:00:00Z", "Buy", Decimal::new(5, 0))
+            },
+            coinbase_record("2021-01-03T00:00:00Z", "Buy", Decimal::new(2, 0)),
+            coinbase_record("2021-01-03T00:00:00Z", "Buy", Decimal::new(2, 0)),
+        ]);
+        let kraken = Ledger::from_kraken_records(&[
+            kraken_record(
+                Some("L1"),
+                "T1",
+                "2021-01-01 00:00:00",
+                "trade",
+                "ZUSD",
+                Decimal::new(-100, 0),
+                Decimal::ONE,
+            ),
+            kraken_record(
+                Some("L2"),
+                "T1",
+                "2021-01-01 00:00:00",
+                "trade",
+                "DOT",
+                Decimal::new(10, 0),
+                Decimal::ZERO,
+            ),
+        ]);
+
+        let event_ids = |ledger: &Ledger| {
+            ledger
+                .cost_basis_events()
+                .iter()
+                .map(|event| match event {
+                    CostBasisEvent::Acquisition(acquisition) => acquisition.id.to_string(),
+                    CostBasisEvent::Disposal(disposal) => disposal.id.to_string(),
+                })
+                .filter(|id| id.starts_with("coinbase-"))
+                .collect::<Vec<String>>()
+        };
+        let ids = event_ids(&coinbase);
+
+        assert_eq!(ids.len(), 3);
+        assert_eq!(ids[0], "coinbase-6f1c2a");
+        assert_eq!(ids[2], format!("{}-1", ids[1]));
+        assert_eq!(event_ids(&kraken.merge(coinbase)), ids);
+    }
+}